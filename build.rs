@@ -0,0 +1,12 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        std::env::set_var("PROTOC", protoc);
+        // the generated clients need the 2021 prelude, only the server is built
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/gateway.proto"], &["proto"])
+            .expect("compile gateway.proto");
+    }
+}
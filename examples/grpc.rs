@@ -0,0 +1,26 @@
+extern crate env_logger;
+extern crate rsex;
+
+use rsex::config::Config;
+use rsex::grpc;
+use std::env;
+
+// usage: grpc <config.toml> [addr]
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("usage: {} <config> [addr]", args[0]);
+        return;
+    }
+    let addr = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:50051");
+
+    let config = Config::from_file(&args[1]).unwrap();
+    let registry = config.build().unwrap();
+    // the blocking clients can't be built inside the runtime
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(grpc::serve(addr.parse().unwrap(), registry))
+        .unwrap();
+}
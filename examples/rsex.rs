@@ -0,0 +1,135 @@
+extern crate env_logger;
+extern crate rsex;
+
+use rsex::config::{Client, Config, ExchangeConfig, Registry};
+use rsex::errors::*;
+use rsex::models::OrderRequest;
+use std::env;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: rsex [-c config] <command>
+
+commands:
+    <exchange> ticker <symbol>
+    <exchange> orderbook <symbol> [depth]
+    <exchange> kline <symbol> [period] [limit]
+    <exchange> balance <asset>
+    <exchange> orders <symbol>
+    <exchange> history <symbol>
+    <exchange> get <symbol> <id>
+    <exchange> buy|sell <symbol> <price> <amount> [order_type]
+    <exchange> cancel <symbol> <id>
+    <exchange> cancel-all <symbol>
+    balances <asset>
+
+<exchange> is a name from the config file (-c, $RSEX_CONFIG or ./rsex.toml),
+without one binance, binance_swap and huobi are available for public data.";
+
+fn load_registry(config: Option<String>) -> APIResult<Registry> {
+    let path = config
+        .or_else(|| env::var("RSEX_CONFIG").ok())
+        .unwrap_or_else(|| "rsex.toml".into());
+    if Path::new(&path).exists() {
+        return Config::from_file(&path)?.build();
+    }
+    let mut registry = Registry::new();
+    for exchange in &["binance", "binance_swap", "huobi"] {
+        let config = ExchangeConfig {
+            name: exchange.to_string(),
+            exchange: exchange.to_string(),
+            ..Default::default()
+        };
+        registry.insert(exchange.to_string(), config.build()?);
+    }
+    Ok(registry)
+}
+
+fn arg<'a>(args: &'a [String], i: usize, name: &str) -> APIResult<&'a str> {
+    args.get(i)
+        .map(String::as_str)
+        .ok_or_else(|| Box::new(ExError::ApiError(format!("missing {}", name))).into())
+}
+
+fn print<T: serde::Serialize>(value: T) -> APIResult<()> {
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+fn run_client(client: &Client, args: &[String]) -> APIResult<()> {
+    let command = arg(args, 0, "command")?;
+    match command {
+        "ticker" => print(client.get_ticker(arg(args, 1, "symbol")?)?),
+        "orderbook" => {
+            let depth = arg(args, 2, "depth").unwrap_or("20").parse()?;
+            print(client.get_orderbook(arg(args, 1, "symbol")?, depth)?)
+        }
+        "kline" => {
+            let period = arg(args, 2, "period").unwrap_or("1m");
+            let limit = arg(args, 3, "limit").unwrap_or("20").parse()?;
+            print(client.get_kline(arg(args, 1, "symbol")?, period, limit)?)
+        }
+        "balance" => print(client.get_balance(arg(args, 1, "asset")?)?),
+        "orders" => print(client.get_open_orders(arg(args, 1, "symbol")?)?),
+        "history" => print(client.get_history_orders(arg(args, 1, "symbol")?)?),
+        "get" => print(client.get_order(arg(args, 1, "symbol")?, arg(args, 2, "id")?)?),
+        "buy" | "sell" => {
+            let symbol = arg(args, 1, "symbol")?;
+            let price = arg(args, 2, "price")?.parse()?;
+            let amount = arg(args, 3, "amount")?.parse()?;
+            let order_type = arg(args, 4, "order_type").unwrap_or("LIMIT");
+            let order = OrderRequest::new(symbol, command, order_type, price, amount);
+            let id = client.create_order(&order)?;
+            print(id)
+        }
+        "cancel" => print(client.cancel(arg(args, 1, "symbol")?, arg(args, 2, "id")?)?),
+        "cancel-all" => print(client.cancel_all(arg(args, 1, "symbol")?)?),
+        _ => Err(Box::new(ExError::ApiError(format!(
+            "unknown command: {}",
+            command
+        )))),
+    }
+}
+
+fn run(mut args: Vec<String>) -> APIResult<()> {
+    let mut config = None;
+    if args.first().map(String::as_str) == Some("-c") {
+        config = Some(arg(&args, 1, "config")?.to_string());
+        args.drain(..2);
+    }
+    let registry = load_registry(config)?;
+    let target = arg(&args, 0, "exchange")?;
+    if target == "balances" {
+        let asset = arg(&args, 1, "asset")?;
+        let mut names: Vec<&String> = registry.keys().collect();
+        names.sort();
+        for name in names {
+            match registry[name].get_balance(asset) {
+                Ok(balance) => println!("{}: {}", name, balance),
+                Err(err) => println!("{}: {}", name, err),
+            }
+        }
+        return Ok(());
+    }
+    match registry.get(target) {
+        Some(client) => run_client(client, &args[1..]),
+        None => Err(Box::new(ExError::ApiError(format!(
+            "unknown exchange: {}",
+            target
+        )))),
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "-h" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(err) = run(args) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
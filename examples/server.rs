@@ -0,0 +1,27 @@
+extern crate env_logger;
+extern crate rsex;
+
+use rsex::config::Config;
+use rsex::server;
+use std::env;
+
+// usage: RSEX_API_TOKEN=... server <config.toml> [addr]
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("usage: RSEX_API_TOKEN=<token> {} <config> [addr]", args[0]);
+        return;
+    }
+    let addr = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:8080");
+    let token = env::var("RSEX_API_TOKEN").unwrap_or_default();
+
+    let config = Config::from_file(&args[1]).unwrap();
+    let registry = config.build().unwrap();
+    // the blocking clients can't be built inside the runtime
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(server::serve(addr, registry, &token))
+        .unwrap();
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::result_large_err)]
+
 extern crate rsex;
 
 use rsex::binance::spot_ws::{BinanceWs, WsEvent};
@@ -15,17 +17,14 @@ fn main() {
     }
 
     let handler = |event: WsEvent| {
-        match event {
-            WsEvent::TickerEvent(e) => {
-                println!("{:?}", e);
-            }
-            _ => {}
+        if let WsEvent::TickerEvent(e) = event {
+            println!("{:?}", e);
         }
         Ok(())
     };
 
     let url = "wss://stream.binancezh.pro:9443/ws/btcusdt@depth20";
-    let mut ws = BinanceWs::new(url.into());
-    ws.connect(handler);
+    let mut ws = BinanceWs::new(url);
     ws.sub_ticker(&symbol);
+    ws.connect(handler);
 }
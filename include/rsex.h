@@ -0,0 +1,27 @@
+/* C interface of rsex, every call takes and returns json. */
+#ifndef RSEX_H
+#define RSEX_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct rsex_client rsex_client;
+
+/* config: {"name": "bn", "exchange": "binance", "api_key": "..", "secret_key": ".."},
+ * returns NULL if the config is invalid */
+rsex_client *rsex_client_new(const char *config);
+void rsex_client_free(rsex_client *client);
+
+/* method: get_ticker, get_orderbook, get_kline, get_balance, create_order,
+ * cancel, cancel_all, get_order, get_open_orders, get_history_orders.
+ * params: {"symbol": "BTCUSDT", "depth": 20, ...}, may be NULL.
+ * returns {"ok": result} or {"error": message}, release it with rsex_string_free */
+char *rsex_call(const rsex_client *client, const char *method, const char *params);
+void rsex_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
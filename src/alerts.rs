@@ -0,0 +1,244 @@
+use crate::models::*;
+use crate::notify::Notifier;
+
+use std::sync::mpsc::Receiver;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Condition {
+    // last trade price or ticker mid moves through the level, either way
+    PriceCrosses(f64),
+    PriceAbove(f64),
+    PriceBelow(f64),
+    // (ask - bid) / mid in basis points
+    SpreadAboveBps(f64),
+    // funding rate of a mark price update, e.g. 0.0005 for 0.05%
+    FundingAbove(f64),
+    FundingBelow(f64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub id: u64,
+    pub symbol: String,
+    pub condition: Condition,
+    // the price, spread or funding rate that triggered it
+    pub value: f64,
+    pub timestamp: u64,
+}
+
+
+struct Alert {
+    id: u64,
+    symbol: String,
+    condition: Condition,
+    // the condition held on the last value, alerts fire on the edge only
+    active: bool,
+    // last price was at or above the level of PriceCrosses
+    above: Option<bool>,
+}
+
+enum Input {
+    Price(f64),
+    Spread(f64),
+    Funding(f64),
+}
+
+impl Alert {
+    fn check(&mut self, input: &Input) -> Option<f64> {
+        let (holds, value) = match (&self.condition, input) {
+            (Condition::PriceCrosses(level), Input::Price(price)) => {
+                let above = price >= level;
+                let crossed = matches!(self.above, Some(was_above) if was_above != above);
+                self.above = Some(above);
+                // a cross is an event in itself, no need to re-arm
+                return if crossed { Some(*price) } else { None };
+            }
+            (Condition::PriceAbove(level), Input::Price(price)) => (price > level, *price),
+            (Condition::PriceBelow(level), Input::Price(price)) => (price < level, *price),
+            (Condition::SpreadAboveBps(bps), Input::Spread(spread)) => (spread > bps, *spread),
+            (Condition::FundingAbove(rate), Input::Funding(funding)) => (funding > rate, *funding),
+            (Condition::FundingBelow(rate), Input::Funding(funding)) => (funding < rate, *funding),
+            _ => return None,
+        };
+        let fire = holds && !self.active;
+        self.active = holds;
+        if fire {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+// price, spread and funding alerts checked against stream events. an alert
+// fires when its condition becomes true and again only after it was false
+// in between, events go to callbacks and channels
+//
+//     let mut alerts = Alerts::new();
+//     alerts.add("BTCUSDT", Condition::PriceCrosses(30000.0));
+//     let rx = alerts.subscribe();
+//     // for every TickerEvent
+//     alerts.on_ticker(&ticker);
+pub struct Alerts {
+    alerts: Vec<Alert>,
+    next_id: u64,
+    notifier: Notifier<AlertEvent>,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Alerts::new()
+    }
+}
+
+impl Alerts {
+    pub fn new() -> Self {
+        Alerts {
+            alerts: vec![],
+            next_id: 1,
+            notifier: Notifier::new(),
+        }
+    }
+
+    // returns the id to remove it with
+    pub fn add(&mut self, symbol: &str, condition: Condition) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.alerts.push(Alert {
+            id,
+            symbol: symbol.to_uppercase(),
+            condition,
+            active: false,
+            above: None,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> bool {
+        let len = self.alerts.len();
+        self.alerts.retain(|alert| alert.id != id);
+        self.alerts.len() != len
+    }
+
+    pub fn len(&self) -> usize {
+        self.alerts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alerts.is_empty()
+    }
+
+    pub fn on_trigger<F>(&mut self, callback: F)
+    where
+        F: FnMut(&AlertEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<AlertEvent> {
+        self.notifier.subscribe()
+    }
+
+    pub fn on_trade(&mut self, trade: &Trade) {
+        self.check(&trade.symbol, Input::Price(trade.price), trade.timestamp);
+    }
+
+    pub fn on_ticker(&mut self, ticker: &Ticker) {
+        let (bid, ask) = (ticker.bid.price, ticker.ask.price);
+        if bid <= 0.0 || ask <= 0.0 {
+            return;
+        }
+        let mid = (bid + ask) / 2.0;
+        self.check(&ticker.symbol, Input::Price(mid), ticker.timestamp);
+        let spread = (ask - bid) / mid * 10000.0;
+        self.check(&ticker.symbol, Input::Spread(spread), ticker.timestamp);
+    }
+
+    pub fn on_mark_price(&mut self, mark: &MarkPrice) {
+        self.check(
+            &mark.symbol,
+            Input::Funding(mark.funding_rate),
+            mark.timestamp,
+        );
+    }
+
+    fn check(&mut self, symbol: &str, input: Input, timestamp: u64) {
+        let symbol = symbol.to_uppercase();
+        let mut events = vec![];
+        for alert in self.alerts.iter_mut().filter(|a| a.symbol == symbol) {
+            if let Some(value) = alert.check(&input) {
+                events.push(AlertEvent {
+                    id: alert.id,
+                    symbol: alert.symbol.clone(),
+                    condition: alert.condition.clone(),
+                    value,
+                    timestamp,
+                });
+            }
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn trade(price: f64) -> Trade {
+        Trade {
+            symbol: "btcusdt".into(),
+            trade_id: 1,
+            timestamp: 1,
+            amount: 1.0,
+            price,
+            side: "buy".into(),
+        }
+    }
+
+    #[test]
+    fn test_alerts() {
+        let mut alerts = Alerts::new();
+        let cross = alerts.add("BTCUSDT", Condition::PriceCrosses(100.0));
+        let above = alerts.add("BTCUSDT", Condition::PriceAbove(105.0));
+        alerts.add("BTCUSDT", Condition::SpreadAboveBps(50.0));
+        alerts.add("BTCUSDT", Condition::FundingAbove(0.001));
+        let rx = alerts.subscribe();
+        let fired = Arc::new(Mutex::new(vec![]));
+        let log = fired.clone();
+        alerts.on_trigger(move |event| log.lock().unwrap().push(event.id));
+
+        for price in [99.0, 99.5, 100.0, 101.0, 106.0, 107.0, 104.0, 106.0, 98.0] {
+            alerts.on_trade(&trade(price));
+        }
+        // crossed up at 100 and down at 98, above 105 twice after dipping to 104
+        assert_eq!(*fired.lock().unwrap(), vec![cross, above, above, cross]);
+        assert_eq!(rx.try_iter().count(), 4);
+
+        let mut ticker = Ticker::new();
+        ticker.symbol = "BTCUSDT".into();
+        ticker.bid.price = 97.0;
+        ticker.ask.price = 99.0;
+        alerts.on_ticker(&ticker);
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.condition, Condition::SpreadAboveBps(50.0));
+        assert_eq!(event.value, 2.0 / 98.0 * 10000.0);
+
+        let mark = MarkPrice {
+            symbol: "BTCUSDT".into(),
+            timestamp: 2,
+            mark_price: 100.0,
+            index_price: 100.0,
+            funding_rate: 0.002,
+            next_funding_time: 3,
+        };
+        alerts.on_mark_price(&mark);
+        assert_eq!(rx.try_recv().unwrap().value, 0.002);
+
+        assert!(alerts.remove(cross));
+        assert!(!alerts.remove(cross));
+        assert_eq!(alerts.len(), 3);
+    }
+}
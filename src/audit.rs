@@ -0,0 +1,180 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::utils::Pending;
+
+use log::warn;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// credentials and signatures never written to the log
+const REDACTED_PARAMS: &[&str] = &["signature", "Signature", "AccessKeyId"];
+
+// one signed write request, i.e. an order create / cancel or a transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub exchange: String,
+    pub method: String,
+    pub endpoint: String,
+    // query string without REDACTED_PARAMS
+    pub params: String,
+    pub body: Option<String>,
+    // ms timestamps
+    pub sent_at: u64,
+    pub received_at: u64,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+// where audit records go, implement it to write to a database table
+pub trait AuditSink: Send + Sync + fmt::Debug {
+    fn record(&self, record: &AuditRecord) -> APIResult<()>;
+}
+
+// json lines appended to a file, existing lines are never rewritten
+//
+//     let audit = Arc::new(FileAuditLog::open("audit.jsonl")?);
+//     api.set_audit(audit.clone());
+pub struct FileAuditLog {
+    file: Mutex<File>,
+}
+
+impl FileAuditLog {
+    pub fn open<P: AsRef<Path>>(path: P) -> APIResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileAuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> APIResult<Vec<AuditRecord>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut records = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                records.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl fmt::Debug for FileAuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FileAuditLog")
+    }
+}
+
+impl AuditSink for FileAuditLog {
+    fn record(&self, record: &AuditRecord) -> APIResult<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        // one write per record so concurrent writers never interleave lines
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+fn redact(query: &str) -> String {
+    let pairs = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .filter(|(k, _)| !REDACTED_PARAMS.contains(&k.as_str()));
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish()
+}
+
+// runs the request and records it with its outcome. a failed audit write is
+// logged, the request already happened and its result is returned as is
+#[maybe_async::maybe_async]
+pub(crate) async fn audited<'a, F>(
+    audit: &Option<Arc<dyn AuditSink>>,
+    exchange: &str,
+    method: &str,
+    path: &str,
+    body: Option<String>,
+    send: F,
+) -> APIResult<String>
+where
+    F: FnOnce() -> Pending<'a, APIResult<String>>,
+{
+    let audit = match audit {
+        Some(audit) => audit,
+        None => return send().await,
+    };
+    let (endpoint, query) = path.split_once('?').unwrap_or((path, ""));
+    let sent_at = now_ms();
+    let ret = send().await;
+    let record = AuditRecord {
+        exchange: exchange.into(),
+        method: method.into(),
+        endpoint: endpoint.into(),
+        params: redact(query),
+        body,
+        sent_at,
+        received_at: now_ms(),
+        response: ret.as_ref().ok().cloned(),
+        error: ret.as_ref().err().map(|err| err.to_string()),
+    };
+    if let Err(err) = audit.record(&record) {
+        warn!("audit write failed: {:?}, record: {:?}", err, record);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_file_audit_log() {
+        let path = env::temp_dir().join(format!("rsex-audit-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let audit: Option<Arc<dyn AuditSink>> = Some(Arc::new(FileAuditLog::open(&path).unwrap()));
+        let ret = audited(
+            &audit,
+            "binance",
+            "POST",
+            "/api/v3/order?symbol=BTCUSDT&side=BUY&timestamp=1&signature=abc",
+            None,
+            || Box::pin(async { Ok(r#"{"orderId": 1}"#.to_string()) }),
+        )
+        .await;
+        assert!(ret.is_ok());
+        let ret = audited(&audit, "binance", "DELETE", "/api/v3/order", None, || {
+            Box::pin(async { Err(ExError::ApiError("unknown order".into()).into()) })
+        })
+        .await;
+        assert!(ret.is_err());
+
+        // reopening appends
+        let audit: Option<Arc<dyn AuditSink>> = Some(Arc::new(FileAuditLog::open(&path).unwrap()));
+        audited(
+            &audit,
+            "huobi",
+            "POST",
+            "/v1/order/orders/place?AccessKeyId=key&Signature=s",
+            Some("{}".into()),
+            || Box::pin(async { Ok("ok".into()) }),
+        )
+        .await
+        .unwrap();
+
+        let records = FileAuditLog::read(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].endpoint, "/api/v3/order");
+        assert_eq!(records[0].params, "symbol=BTCUSDT&side=BUY&timestamp=1");
+        assert_eq!(records[0].response.as_deref(), Some(r#"{"orderId": 1}"#));
+        assert!(records[1].error.as_ref().unwrap().contains("unknown order"));
+        assert_eq!(records[2].params, "");
+        assert!(records[2].received_at >= records[2].sent_at);
+        fs::remove_file(&path).unwrap();
+    }
+}
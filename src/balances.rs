@@ -0,0 +1,270 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+#[cfg(feature = "blocking")]
+use crate::traits::*;
+
+use log::warn;
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "blocking")]
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceEvent {
+    pub id: u64,
+    pub exchange: String,
+    pub asset: String,
+    pub available: f64,
+    pub threshold: f64,
+    // a top up hook ran and succeeded
+    pub topped_up: bool,
+    pub top_up_error: Option<String>,
+    pub timestamp: u64,
+}
+
+type FetchBalance = Box<dyn Fn() -> APIResult<f64> + Send>;
+type TopUp = Box<dyn FnMut(&BalanceEvent) -> APIResult<()> + Send>;
+
+struct Watch {
+    id: u64,
+    exchange: String,
+    asset: String,
+    threshold: f64,
+    fetch: FetchBalance,
+    top_up: Option<TopUp>,
+    // under the threshold on the last check, fires on the edge only
+    low: bool,
+}
+
+// alerts when the available balance of an asset falls under a threshold,
+// optionally running a hook that tops it up, e.g. a transfer from spot to
+// futures. a watch fires again only after the balance recovered, or on the
+// next check when its top up failed
+//
+//     let mut watcher = BalanceWatcher::new();
+//     let id = watcher.watch_future("binance-futures", swap.clone(), "USDT", 1000.0);
+//     watcher.top_up(id, move |event| {
+//         spot.transfer("USDT", 2000.0 - event.available)
+//     });
+//     watcher.on_low(|event| warn!("low balance: {:?}", event));
+//     watcher.run_every(Duration::from_secs(60));
+pub struct BalanceWatcher {
+    watches: Vec<Watch>,
+    next_id: u64,
+    notifier: Notifier<BalanceEvent>,
+    shutdown: Shutdown,
+}
+
+impl Default for BalanceWatcher {
+    fn default() -> Self {
+        BalanceWatcher::new()
+    }
+}
+
+impl BalanceWatcher {
+    pub fn new() -> Self {
+        BalanceWatcher {
+            watches: vec![],
+            next_id: 1,
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // fetch returns the available balance, returns the id of the watch
+    pub fn watch<F>(&mut self, exchange: &str, asset: &str, threshold: f64, fetch: F) -> u64
+    where
+        F: Fn() -> APIResult<f64> + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watches.push(Watch {
+            id,
+            exchange: exchange.into(),
+            asset: asset.into(),
+            threshold,
+            fetch: Box::new(fetch),
+            top_up: None,
+            low: false,
+        });
+        id
+    }
+
+    // the free spot balance
+    #[cfg(feature = "blocking")]
+    pub fn watch_spot<T>(&mut self, exchange: &str, api: Arc<T>, asset: &str, threshold: f64) -> u64
+    where
+        T: SpotRest + Send + Sync + 'static,
+    {
+        let name = asset.to_string();
+        self.watch(exchange, asset, threshold, move || {
+            Ok(api.get_balance(&name)?.free)
+        })
+    }
+
+    // the futures balance available for new positions
+    #[cfg(feature = "blocking")]
+    pub fn watch_future<T>(
+        &mut self,
+        exchange: &str,
+        api: Arc<T>,
+        asset: &str,
+        threshold: f64,
+    ) -> u64
+    where
+        T: FutureRest + Send + Sync + 'static,
+    {
+        let name = asset.to_string();
+        self.watch(exchange, asset, threshold, move || {
+            Ok(api.get_balance(&name)?.available_balance)
+        })
+    }
+
+    pub fn unwatch(&mut self, id: u64) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|w| w.id != id);
+        self.watches.len() != len
+    }
+
+    // runs before the callbacks when the watch fires
+    pub fn top_up<F>(&mut self, id: u64, hook: F) -> bool
+    where
+        F: FnMut(&BalanceEvent) -> APIResult<()> + Send + 'static,
+    {
+        match self.watches.iter_mut().find(|w| w.id == id) {
+            Some(watch) => {
+                watch.top_up = Some(Box::new(hook));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn on_low<F>(&mut self, callback: F)
+    where
+        F: FnMut(&BalanceEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<BalanceEvent> {
+        self.notifier.subscribe()
+    }
+
+    // run_every stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // fetches every balance, returns the events fired. failed fetches are
+    // logged and leave the watch as it was
+    pub fn check(&mut self) -> Vec<BalanceEvent> {
+        let mut events = vec![];
+        for watch in self.watches.iter_mut() {
+            let available = match (watch.fetch)() {
+                Ok(available) => available,
+                Err(err) => {
+                    warn!(
+                        "balance of {} on {} failed: {:?}",
+                        watch.asset, watch.exchange, err
+                    );
+                    continue;
+                }
+            };
+            let low = available < watch.threshold;
+            let fire = low && !watch.low;
+            watch.low = low;
+            if !fire {
+                continue;
+            }
+            let mut event = BalanceEvent {
+                id: watch.id,
+                exchange: watch.exchange.clone(),
+                asset: watch.asset.clone(),
+                available,
+                threshold: watch.threshold,
+                topped_up: false,
+                top_up_error: None,
+                timestamp: now_ms(),
+            };
+            if let Some(top_up) = watch.top_up.as_mut() {
+                match top_up(&event) {
+                    Ok(()) => event.topped_up = true,
+                    Err(err) => {
+                        warn!(
+                            "top up of {} on {} failed: {:?}",
+                            watch.asset, watch.exchange, err
+                        );
+                        event.top_up_error = Some(err.to_string());
+                        // tried again on the next check
+                        watch.low = false;
+                    }
+                }
+            }
+            events.push(event);
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+        events
+    }
+
+    // checks on a background thread, the first time right away
+    pub fn run_every(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.check();
+            if !self.shutdown.sleep(interval) {
+                return;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_balance_watcher() {
+        let balance = Arc::new(Mutex::new(500.0));
+        let mut watcher = BalanceWatcher::new();
+        let current = balance.clone();
+        let id = watcher.watch("binance-futures", "USDT", 100.0, move || {
+            Ok(*current.lock().unwrap())
+        });
+        let rx = watcher.subscribe();
+        let attempts = Arc::new(Mutex::new(0));
+        let count = attempts.clone();
+        let topped = balance.clone();
+        watcher.top_up(id, move |event| {
+            *count.lock().unwrap() += 1;
+            if *count.lock().unwrap() == 1 {
+                return Err(Box::new(ExError::ApiError("transfer failed".into())));
+            }
+            *topped.lock().unwrap() += 500.0 - event.available;
+            Ok(())
+        });
+
+        assert!(watcher.check().is_empty());
+        *balance.lock().unwrap() = 50.0;
+        let events = watcher.check();
+        assert_eq!(
+            events[0].top_up_error.as_deref(),
+            Some("ApiError: transfer failed")
+        );
+        // the failed top up is retried
+        let events = watcher.check();
+        assert!(events[0].topped_up);
+        assert_eq!(*balance.lock().unwrap(), 500.0);
+        assert!(watcher.check().is_empty());
+        assert_eq!(rx.try_iter().count(), 2);
+
+        *balance.lock().unwrap() = 10.0;
+        assert_eq!(watcher.check().len(), 1);
+        assert!(watcher.unwatch(id));
+    }
+}
@@ -1,24 +1,62 @@
+use crate::binance::mux::Shard;
 use crate::binance::types::*;
+use crate::constant::*;
 use crate::errors::*;
 use crate::models::*;
+use crate::queue::Backpressure;
+use crate::shutdown::{Shutdown, WsGuard};
+use crate::stream::EventStream;
 use crate::traits::*;
+use crate::utils::{decode_ws_message, from_ws_str, get_timestamp};
 
-use ws::{Handler, Handshake, Message, Result, Sender};
+use futures_channel::mpsc::UnboundedSender;
+use log::{info, warn};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use ws::util::Token;
+use ws::{CloseCode, Frame, Handler, Handshake, Message, Result, Sender};
 
-//static WEBSOCKET_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@depth20";
+const HEARTBEAT: Token = Token(1);
 
-#[derive(Debug)]
+//static WEBSOCKET_URL: &str = "wss://fstream.binance.com/ws/btcusdt@depth20";
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize)]
 pub enum WsEvent {
     // public stream
     OrderbookEvent(Orderbook),
-    KlineEvent(Kline),
+    // diff depth stream, levels with zero amount are removed
+    DepthUpdateEvent(Orderbook),
+    KlineEvent(KlineUpdate),
     TickerEvent(Ticker),
     TradeEvent(Trade),
+    MarkPriceEvent(MarkPrice),
+    LiquidationEvent(Liquidation),
     ResponseEvent(ResponseEvent),
 
     // private stream
-    AccountUpdateEvent(AccountUpdateEvent),
-    OrderTradeEvent(OrderTradeEvent),
+    AccountUpdateEvent(FutureAccountUpdate),
+    OrderTradeEvent(OrderUpdate),
+}
+
+impl WsEvent {
+    // default policy for an EventQueue: latest ticker and open kline win,
+    // snapshots and trades may be dropped, diffs and private events never are
+    pub fn backpressure(&self) -> Backpressure {
+        match self {
+            WsEvent::TickerEvent(e) => Backpressure::Coalesce(format!("ticker@{}", e.symbol)),
+            WsEvent::KlineEvent(e) if !e.is_closed => {
+                Backpressure::Coalesce(format!("kline@{}@{}", e.symbol, e.period))
+            }
+            WsEvent::OrderbookEvent(_) | WsEvent::TradeEvent(_) => Backpressure::DropOldest,
+            WsEvent::MarkPriceEvent(e) => Backpressure::Coalesce(format!("markprice@{}", e.symbol)),
+            WsEvent::LiquidationEvent(_) => Backpressure::DropOldest,
+            _ => Backpressure::Block,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,96 +65,355 @@ pub struct ResponseEvent {
     result: Option<String>,
 }
 
-pub struct BinanceWs<'a> {
+pub struct BinanceSwapWs<'a> {
     host: String,
     subs: Vec<String>,
     out: Option<Sender>,
+    reconnect: bool,
+    // keepalive ping interval, the connection is dropped after stale_after without any frame
+    heartbeat: Duration,
+    stale_after: Duration,
+    last_activity: Instant,
+    // symbols subscribed to the partial book, futures push both partial
+    // and diff depth as depthUpdate
+    partial_depth: Vec<String>,
+    // open time of the last closed kline per stream, kept across reconnects
+    last_closed: Rc<RefCell<HashMap<String, u64>>>,
+    // closes the connection and stops reconnecting once shut down
+    shutdown: Option<Shutdown>,
+    registered: Option<WsGuard>,
 
     handler: Box<dyn FnMut(WsEvent) -> Result<()> + 'a>,
 }
 
-impl<'a> BinanceWs<'a> {
+impl<'a> BinanceSwapWs<'a> {
     pub fn new(host: &str) -> Self {
-        BinanceWs {
+        BinanceSwapWs {
             host: host.into(),
             subs: vec![],
             out: None,
+            reconnect: true,
+            heartbeat: Duration::from_secs(20),
+            stale_after: Duration::from_secs(60),
+            last_activity: Instant::now(),
+            partial_depth: vec![],
+            last_closed: Rc::new(RefCell::new(HashMap::new())),
+            shutdown: None,
+            registered: None,
             handler: Box::new(|event| {
-                println!("event: {:?}", event);
+                info!("event: {:?}", event);
                 Ok(())
             }),
         }
     }
 
-    pub fn connect<Callback: Clone>(&mut self, handler: Callback)
+    pub fn with_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => BinanceSwapWs::new(BINANCE_SWAP_WS_HOST),
+            Network::Testnet => BinanceSwapWs::new(BINANCE_SWAP_TESTNET_WS_HOST),
+        }
+    }
+
+    pub fn set_reconnect(&mut self, reconnect: bool) {
+        self.reconnect = reconnect;
+    }
+
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    pub fn set_heartbeat(&mut self, interval: Duration, stale_after: Duration) {
+        self.heartbeat = interval;
+        self.stale_after = stale_after;
+    }
+
+    // blocks; subscriptions made before connect are sent again after every reconnect
+    pub fn connect<Callback>(&mut self, handler: Callback)
     where
-        Callback: FnMut(WsEvent) -> Result<()> + 'a,
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
     {
-        ws::connect(self.host.clone(), |out| BinanceWs {
-            host: self.host.clone(),
-            subs: self.subs.clone(),
-            out: Some(out),
-            handler: Box::new(handler.clone()),
+        self.connect_until(handler, &|| false);
+    }
+
+    // runs the client on its own thread and yields its events
+    pub fn into_stream(self) -> EventStream<WsEvent> {
+        let host = self.host;
+        let subs = self.subs;
+        let partial_depth = self.partial_depth;
+        let reconnect = self.reconnect;
+        let shutdown = self.shutdown;
+        let heartbeat = self.heartbeat;
+        let stale_after = self.stale_after;
+        EventStream::spawn(move |tx| {
+            let mut client = BinanceSwapWs::new(&host);
+            client.subs = subs;
+            client.partial_depth = partial_depth;
+            client.reconnect = reconnect;
+            client.shutdown = shutdown;
+            client.heartbeat = heartbeat;
+            client.stale_after = stale_after;
+            let done = tx.clone();
+            client.connect_until(
+                move |event| {
+                    tx.unbounded_send(event)
+                        .map_err(|err| ws::Error::new(ws::ErrorKind::Internal, err.to_string()))
+                },
+                &|| done.is_closed(),
+            );
         })
-        .unwrap();
+    }
+
+    fn connect_until<Callback>(&mut self, handler: Callback, done: &dyn Fn() -> bool)
+    where
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
+    {
+        loop {
+            let ret = ws::connect(self.host.clone(), |out| BinanceSwapWs {
+                host: self.host.clone(),
+                subs: self.subs.clone(),
+                out: Some(out),
+                reconnect: self.reconnect,
+                heartbeat: self.heartbeat,
+                stale_after: self.stale_after,
+                last_activity: Instant::now(),
+                partial_depth: self.partial_depth.clone(),
+                last_closed: self.last_closed.clone(),
+                shutdown: self.shutdown.clone(),
+                registered: None,
+                handler: Box::new(handler.clone()),
+            });
+            if let Err(err) = ret {
+                warn!("ws error: {:?}", err);
+            }
+            let stopped = matches!(&self.shutdown, Some(s) if s.is_shutdown());
+            if !self.reconnect || stopped || done() {
+                break;
+            }
+            warn!("ws disconnected, reconnecting");
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    // mark price, index price and funding rate pushed every second
+    pub fn sub_mark_price(&mut self, symbol: &str) {
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@markPrice@1s\"], \"id\": {}}}",
+            symbol.to_string().to_lowercase(),
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // liquidation orders of one symbol
+    pub fn sub_liquidation(&mut self, symbol: &str) {
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@forceOrder\"], \"id\": {}}}",
+            symbol.to_string().to_lowercase(),
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // liquidation orders of the whole market
+    pub fn sub_all_liquidations(&mut self) {
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"!forceOrder@arr\"], \"id\": {}}}",
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // levels 5, 10 or 20 subscribes to the partial book, None to the diff stream;
+    // speed is one of 100, 250, 500 ms, None keeps the exchange default
+    pub fn sub_depth(
+        &mut self,
+        symbol: &str,
+        levels: Option<u16>,
+        speed: Option<u16>,
+    ) -> APIResult<()> {
+        if let Some(levels) = levels {
+            if ![5, 10, 20].contains(&levels) {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid levels: {}, must be one of 5, 10, 20",
+                    levels
+                ))));
+            }
+        }
+        if let Some(speed) = speed {
+            if ![100, 250, 500].contains(&speed) {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid speed: {}, must be one of 100, 250, 500",
+                    speed
+                ))));
+            }
+        }
+        let mut stream = format!("{}@depth", symbol.to_lowercase());
+        if let Some(levels) = levels {
+            stream.push_str(&levels.to_string());
+            self.partial_depth.push(symbol.to_uppercase());
+        }
+        if let Some(speed) = speed {
+            stream.push_str(&format!("@{}ms", speed));
+        }
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}\"], \"id\": {}}}",
+            stream,
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+        Ok(())
+    }
+
+    // best bid/ask of several symbols in a single subscription
+    pub fn sub_tickers(&mut self, symbols: &[&str]) {
+        let params = symbols
+            .iter()
+            .map(|symbol| format!("\"{}@bookTicker\"", symbol.to_lowercase()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [{}], \"id\": {}}}",
+            params,
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // drops closed klines that were already delivered before a reconnect
+    fn is_duplicate(&self, event: &WsEvent) -> bool {
+        match event {
+            WsEvent::KlineEvent(update) if update.is_closed => {
+                let key = format!("{}@{}", update.symbol, update.period);
+                let mut last_closed = self.last_closed.borrow_mut();
+                match last_closed.get(&key) {
+                    Some(ts) if *ts >= update.kline.timestamp => true,
+                    _ => {
+                        last_closed.insert(key, update.kline.timestamp);
+                        false
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // a handler error closes the connection
+    fn dispatch(&mut self, event: WsEvent) -> Result<()> {
+        if let Err(err) = (self.handler)(event) {
+            warn!("handler error: {:?}", err);
+            if let Some(out) = &self.out {
+                out.close(CloseCode::Normal)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, msg: &str) {
+        match &self.out {
+            Some(out) => {
+                let _ = out.send(msg);
+            }
+            None => {
+                warn!("self.out is None");
+            }
+        }
     }
 
     fn deseralize(&self, s: &str) -> APIResult<WsEvent> {
-        if s.find("result") != None {
-            let resp: ResponseEvent = serde_json::from_str(s)?;
+        if s.contains("result") {
+            let resp: ResponseEvent = from_ws_str(s)?;
             return Ok(WsEvent::ResponseEvent(resp));
         }
-        //let val: Value = serde_json::from_str(s)?;
-        if s.find("kline") != None {
-            let resp: KlineEvent = serde_json::from_str(&s)?;
-            Ok(WsEvent::KlineEvent(resp.kline.into()))
-        } else if s.find("lastUpdateId") != None {
-            let resp: RawOrderbook = serde_json::from_str(&s)?;
-            Ok(WsEvent::OrderbookEvent(resp.into()))
-        } else if s.find("aggTrade") != None {
-            let resp: TradeEvent = serde_json::from_str(&s)?;
+        //let val: Value = from_ws_str(s)?;
+        if s.contains("ORDER_TRADE_UPDATE") {
+            let resp: FutureOrderTradeEvent = from_ws_str(s)?;
+            Ok(WsEvent::OrderTradeEvent(resp.into()))
+        } else if s.contains("ACCOUNT_UPDATE") {
+            let resp: FutureAccountUpdateEvent = from_ws_str(s)?;
+            Ok(WsEvent::AccountUpdateEvent(resp.into()))
+        } else if s.contains("kline") {
+            let resp: KlineEvent = from_ws_str(s)?;
+            Ok(WsEvent::KlineEvent(KlineUpdate {
+                symbol: resp.symbol,
+                period: resp.kline.interval.clone(),
+                is_closed: resp.kline.is_final_bar,
+                kline: resp.kline.into(),
+            }))
+        } else if s.contains("depthUpdate") {
+            let resp: DepthOrderbookEvent = from_ws_str(s)?;
+            if self.partial_depth.contains(&resp.symbol) {
+                Ok(WsEvent::OrderbookEvent(resp.into()))
+            } else {
+                Ok(WsEvent::DepthUpdateEvent(resp.into()))
+            }
+        } else if s.contains("aggTrade") {
+            let resp: TradeEvent = from_ws_str(s)?;
             Ok(WsEvent::TradeEvent(resp.into()))
-        } else if s.find("A") != None && s.find("B") != None {
-            let resp: BookTickerEvent = serde_json::from_str(&s)?;
-            Ok(WsEvent::TickerEvent(resp.into()))
+        } else if s.contains("forceOrder") {
+            let resp: ForceOrderEvent = from_ws_str(s)?;
+            Ok(WsEvent::LiquidationEvent(resp.into()))
+        } else if s.contains("markPriceUpdate") {
+            let resp: MarkPriceEvent = from_ws_str(s)?;
+            Ok(WsEvent::MarkPriceEvent(resp.into()))
+        } else if s.contains("A") && s.contains("B") {
+            let resp: BookTickerEvent = from_ws_str(s)?;
+            let mut ticker: Ticker = resp.into();
+            if ticker.timestamp == 0 {
+                ticker.timestamp = get_timestamp()?;
+            }
+            Ok(WsEvent::TickerEvent(ticker))
         } else {
             Err(Box::new(ExError::ApiError("msg channel not found".into())))
         }
     }
 }
 
-impl<'a> SpotWs for BinanceWs<'a> {
+impl<'a> SpotWs for BinanceSwapWs<'a> {
     fn sub_kline(&mut self, symbol: &str, period: &str) {
-        self.subs.push(format!(
+        let msg = format!(
             "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@kline_{}\"], \"id\": {}}}",
             symbol.to_string().to_lowercase(),
             period,
             self.subs.len() + 1,
-        ));
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
     }
 
     fn sub_orderbook(&mut self, symbol: &str) {
-        self.subs.push(format!(
+        self.partial_depth.push(symbol.to_uppercase());
+        let msg = format!(
             "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@depth20\"], \"id\": {}}}",
             symbol.to_string().to_lowercase(),
             self.subs.len() + 1,
-        ));
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
     }
 
     fn sub_trade(&mut self, symbol: &str) {
-        self.subs.push(format!(
+        let msg = format!(
             "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@aggTrade\"], \"id\": {}}}",
             symbol.to_string().to_lowercase(),
             self.subs.len() + 1,
-        ));
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
     }
 
     fn sub_ticker(&mut self, symbol: &str) {
-        self.subs.push(format!(
+        let msg = format!(
             "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@bookTicker\"], \"id\": {}}}",
             symbol.to_string().to_lowercase(),
             self.subs.len() + 1,
-        ));
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
     }
 
     fn sub_order_update(&mut self, _symbol: &str) {
@@ -124,27 +421,99 @@ impl<'a> SpotWs for BinanceWs<'a> {
     }
 }
 
-impl<'a> Handler for BinanceWs<'a> {
+// runs one connection of a BinanceStreams until its stream is dropped
+pub(crate) fn run_shard(shard: Shard, tx: UnboundedSender<WsEvent>) {
+    let mut client = shard_client(shard);
+    let done = tx.clone();
+    client.connect_until(
+        move |event| {
+            tx.unbounded_send(event)
+                .map_err(|err| ws::Error::new(ws::ErrorKind::Internal, err.to_string()))
+        },
+        &|| done.is_closed(),
+    );
+}
+
+fn shard_client(shard: Shard) -> BinanceSwapWs<'static> {
+    let mut client = BinanceSwapWs::new(&shard.host);
+    client.subs = shard.subscribe_messages();
+    client.partial_depth = shard
+        .streams
+        .iter()
+        .filter_map(|stream| partial_depth_symbol(stream))
+        .collect();
+    client.reconnect = shard.reconnect;
+    client.shutdown = shard.shutdown;
+    client
+}
+
+// the symbol of a partial book stream, e.g. btcusdt@depth20@100ms, diff
+// depth streams have no levels
+fn partial_depth_symbol(stream: &str) -> Option<String> {
+    let (symbol, stream) = stream.split_once('@')?;
+    let levels = stream.strip_prefix("depth")?.split('@').next()?;
+    levels.parse::<u16>().ok()?;
+    Some(symbol.to_uppercase())
+}
+
+impl<'a> Handler for BinanceSwapWs<'a> {
     fn on_open(&mut self, _shake: Handshake) -> Result<()> {
         match &self.out {
-            Some(out) => self.subs.iter().for_each(|s| {
-                let _ = out.send(s.as_str());
-            }),
+            Some(out) => {
+                if let Some(shutdown) = &self.shutdown {
+                    self.registered = Some(shutdown.register_ws(out.clone()));
+                }
+                info!("ws connected");
+                self.subs.iter().for_each(|s| {
+                    let _ = out.send(s.as_str());
+                });
+                out.timeout(self.heartbeat.as_millis() as u64, HEARTBEAT)?;
+            }
             None => {
-                println!("self.out is None");
+                warn!("self.out is None");
             }
         }
         Ok(())
     }
 
+    fn on_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        self.last_activity = Instant::now();
+        Ok(Some(frame))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> Result<()> {
+        if event != HEARTBEAT {
+            return Ok(());
+        }
+        if let Some(out) = &self.out {
+            if self.last_activity.elapsed() > self.stale_after {
+                // stops this connection, connect() reconnects if enabled
+                warn!("ws stale for {:?}, dropping connection", self.stale_after);
+                return out.shutdown();
+            }
+            out.ping(vec![])?;
+            out.timeout(self.heartbeat.as_millis() as u64, HEARTBEAT)?;
+        }
+        Ok(())
+    }
+
     fn on_message(&mut self, msg: Message) -> Result<()> {
         //println!("{:?}", msg);
-        match self.deseralize(&msg.to_string()) {
+        let s = match decode_ws_message(msg) {
+            Ok(s) => s,
+            Err(err) => {
+                warn!("decode msg error: {:?}", err);
+                return Ok(());
+            }
+        };
+        match self.deseralize(&s) {
             Ok(event) => {
-                let _ = (self.handler)(event);
+                if !self.is_duplicate(&event) {
+                    self.dispatch(event)?;
+                }
             }
             Err(err) => {
-                println!("deseralize msg error: {:?}", err);
+                warn!("deseralize msg error: {:?}", err);
             }
         }
         Ok(())
@@ -153,29 +522,138 @@ impl<'a> Handler for BinanceWs<'a> {
 
 #[cfg(test)]
 mod test {
+    #![allow(dead_code)]
     use super::*;
-    use crate::utils::get_timestamp;
+
+    static WEBSOCKET_URL: &str = "wss://fstream.binance.com/ws/btcusdt@depth20";
+
+    #[test]
+    fn test_mark_price_event() {
+        let mut ws = BinanceSwapWs::new(WEBSOCKET_URL);
+        ws.sub_mark_price("BTCUSDT");
+        assert!(ws.subs[0].contains("btcusdt@markPrice@1s"));
+
+        let msg = r#"{"e":"markPriceUpdate","E":1562305380000,"s":"BTCUSDT","p":"11794.15000000","i":"11784.62659091","P":"11784.25641265","r":"0.00038167","T":1562306400000}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::MarkPriceEvent(e) => {
+                assert_eq!(e.symbol, "BTCUSDT");
+                assert_eq!(e.timestamp, 1562305380000);
+                assert_eq!(e.mark_price, 11794.15);
+                assert_eq!(e.index_price, 11784.62659091);
+                assert_eq!(e.funding_rate, 0.00038167);
+                assert_eq!(e.next_funding_time, 1562306400000);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_liquidation_event() {
+        let mut ws = BinanceSwapWs::new(WEBSOCKET_URL);
+        ws.sub_all_liquidations();
+        assert!(ws.subs[0].contains("!forceOrder@arr"));
+
+        let msg = r#"{"e":"forceOrder","E":1568014460893,"o":{"s":"BTCUSDT","S":"SELL","o":"LIMIT","f":"IOC","q":"0.014","p":"9910","ap":"9910","X":"FILLED","l":"0.014","z":"0.014","T":1568014460893}}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::LiquidationEvent(e) => {
+                assert_eq!(e.symbol, "BTCUSDT");
+                assert_eq!(e.side, "sell");
+                assert_eq!(e.price, 9910.0);
+                assert_eq!(e.filled, 0.014);
+                assert_eq!(e.status, OrderStatus::Filled);
+                assert_eq!(e.timestamp, 1568014460893);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_depth_event() {
+        let mut ws = BinanceSwapWs::new(WEBSOCKET_URL);
+        assert!(ws.sub_depth("btcusdt", Some(5), Some(1000)).is_err());
+        ws.sub_depth("btcusdt", Some(5), Some(500)).unwrap();
+        assert!(ws.subs[0].contains("\"btcusdt@depth5@500ms\""));
+
+        let msg = r#"{"e":"depthUpdate","E":123456789,"T":123456788,"s":"BTCUSDT","U":157,"u":160,"pu":149,"b":[["7403.89","0.002"]],"a":[["7405.96","3.340"]]}"#;
+        assert!(matches!(
+            ws.deseralize(msg).unwrap(),
+            WsEvent::OrderbookEvent(_)
+        ));
+        let msg = msg.replace("BTCUSDT", "ETHUSDT");
+        assert!(matches!(
+            ws.deseralize(&msg).unwrap(),
+            WsEvent::DepthUpdateEvent(_)
+        ));
+    }
+
+    #[test]
+    fn test_shard_depth() {
+        let shard = Shard {
+            host: WEBSOCKET_URL.into(),
+            streams: vec![
+                "btcusdt@depth20@100ms".into(),
+                "ethusdt@depth@100ms".into(),
+                "bnbusdt@depth5".into(),
+                "solusdt@aggTrade".into(),
+            ],
+            reconnect: false,
+            shutdown: None,
+        };
+        let ws = shard_client(shard);
+        assert_eq!(ws.partial_depth, vec!["BTCUSDT", "BNBUSDT"]);
+        assert!(ws.subs[0].contains("\"ethusdt@depth@100ms\""));
+
+        let msg = r#"{"e":"depthUpdate","E":123456789,"T":123456788,"s":"BTCUSDT","U":157,"u":160,"pu":149,"b":[["7403.89","0.002"]],"a":[["7405.96","3.340"]]}"#;
+        assert!(matches!(
+            ws.deseralize(msg).unwrap(),
+            WsEvent::OrderbookEvent(_)
+        ));
+        let msg = msg.replace("BTCUSDT", "ETHUSDT");
+        assert!(matches!(
+            ws.deseralize(&msg).unwrap(),
+            WsEvent::DepthUpdateEvent(_)
+        ));
+    }
 
     #[test]
-    fn test_binancews() {
+    fn test_user_data_event() {
+        let ws = BinanceSwapWs::new(WEBSOCKET_URL);
+        let msg = r#"{"e":"ORDER_TRADE_UPDATE","E":1568879465651,"T":1568879465650,"o":{"s":"BTCUSDT","c":"TEST","S":"SELL","o":"TRAILING_STOP_MARKET","f":"GTC","q":"0.001","p":"0","ap":"7000","sp":"7103.04","x":"TRADE","X":"FILLED","i":8886774,"l":"0.001","z":"0.001","L":"7000","N":"USDT","n":"0.0028","T":1568879465650,"t":1,"b":"0","a":"9.91","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"TRAILING_STOP_MARKET","ps":"LONG","cp":false,"AP":"7476.89","cr":"5.0","rp":"0"}}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::OrderTradeEvent(e) => {
+                assert_eq!(e.symbol, "BTCUSDT");
+                assert_eq!(e.status, OrderStatus::Filled);
+                assert_eq!(e.avg_price, 7000.0);
+                assert_eq!(e.last_filled_price, 7000.0);
+                assert_eq!(e.commission, 0.0028);
+                assert_eq!(e.commission_asset, "USDT");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let msg = r#"{"e":"ACCOUNT_UPDATE","E":1564745798939,"T":1564745798938,"a":{"m":"ORDER","B":[{"a":"USDT","wb":"122624.12345678","cw":"100.12345678","bc":"50.12345678"}],"P":[{"s":"BTCUSDT","pa":"0","ep":"0.00000","bep":"0","cr":"200","up":"0","mt":"isolated","iw":"0.00000000","ps":"BOTH"}]}}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::AccountUpdateEvent(e) => {
+                assert_eq!(e.reason, "ORDER");
+                assert_eq!(e.balances[0].wallet_balance, 122624.12345678);
+                assert_eq!(e.balances[0].change, 50.12345678);
+                assert_eq!(e.positions[0].position_side, "BOTH");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    //#[test]
+    fn test_binance_swap_ws() {
         env_logger::init();
 
         let handler = |event: WsEvent| {
-            match event {
-                WsEvent::OrderbookEvent(e) => {
-                    println!("orderbook: {:?}", e);
-                }
-                _ => {
-                    println!("event: {:?}", event);
-                }
-            }
+            println!("event: {:?}", event);
             Ok(())
         };
-        let mut binance = BinanceWs::new(WEBSOCKET_URL);
-        //binance.sub_orderbook("btcusdt");
+        let mut binance = BinanceSwapWs::new(WEBSOCKET_URL);
+        binance.sub_mark_price("btcusdt");
         binance.sub_ticker("btcusdt");
-        binance.sub_kline("btcusdt", "5m");
-        binance.sub_trade("btcusdt");
         binance.connect(handler);
     }
 }
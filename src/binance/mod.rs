@@ -1,4 +1,10 @@
-pub mod spot_rest;
-pub mod spot_ws;
-//pub mod future_rest;
 pub mod types;
+
+native! {
+    pub mod future_rest;
+    pub mod future_ws;
+    pub mod mux;
+    pub mod portfolio_rest;
+    pub mod spot_rest;
+    pub mod spot_ws;
+}
@@ -0,0 +1,180 @@
+use crate::binance::{future_ws, spot_ws};
+use crate::constant::*;
+use crate::shutdown::Shutdown;
+use crate::stream::EventStream;
+
+use futures_channel::mpsc::UnboundedSender;
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+
+// streams a single connection may carry
+pub const BINANCE_SPOT_MAX_STREAMS: usize = 1024;
+pub const BINANCE_SWAP_MAX_STREAMS: usize = 200;
+// streams per SUBSCRIBE message, a full spot shard is subscribed in 4
+// messages, under the 5 incoming messages per second limit
+const SUBSCRIBE_BATCH: usize = 256;
+
+// one connection of a BinanceStreams
+pub struct Shard {
+    pub host: String,
+    pub streams: Vec<String>,
+    pub reconnect: bool,
+    pub shutdown: Option<Shutdown>,
+}
+
+impl Shard {
+    // SUBSCRIBE messages sent on every (re)connect
+    pub fn subscribe_messages(&self) -> Vec<String> {
+        self.streams
+            .chunks(SUBSCRIBE_BATCH)
+            .enumerate()
+            .map(|(i, streams)| {
+                json!({"method": "SUBSCRIBE", "params": streams, "id": i + 1}).to_string()
+            })
+            .collect()
+    }
+}
+
+// any number of streams over as few connections as binance allows, every
+// connection runs on its own thread and all events come out of one stream
+//
+//     let mut streams = BinanceStreams::spot(BINANCE_SPOT_WS_HOST);
+//     streams.subscribe_symbols(&symbols, "bookTicker");
+//     let events = streams.into_stream();
+pub struct BinanceStreams<E> {
+    host: String,
+    max_streams: usize,
+    streams: Vec<String>,
+    reconnect: bool,
+    shutdown: Option<Shutdown>,
+    run: fn(Shard, UnboundedSender<E>),
+}
+
+impl BinanceStreams<spot_ws::WsEvent> {
+    pub fn spot(host: &str) -> Self {
+        BinanceStreams::new(host, BINANCE_SPOT_MAX_STREAMS, spot_ws::run_shard)
+    }
+}
+
+impl BinanceStreams<future_ws::WsEvent> {
+    pub fn swap(host: &str) -> Self {
+        BinanceStreams::new(host, BINANCE_SWAP_MAX_STREAMS, future_ws::run_shard)
+    }
+}
+
+impl Default for BinanceStreams<spot_ws::WsEvent> {
+    fn default() -> Self {
+        BinanceStreams::spot(BINANCE_SPOT_WS_HOST)
+    }
+}
+
+impl<E: Send + 'static> BinanceStreams<E> {
+    fn new(host: &str, max_streams: usize, run: fn(Shard, UnboundedSender<E>)) -> Self {
+        BinanceStreams {
+            host: host.into(),
+            max_streams,
+            streams: vec![],
+            reconnect: true,
+            shutdown: None,
+            run,
+        }
+    }
+
+    // lower than the exchange limit spreads the streams over more connections
+    pub fn set_max_streams(&mut self, max_streams: usize) {
+        self.max_streams = max_streams.max(1);
+    }
+
+    pub fn set_reconnect(&mut self, reconnect: bool) {
+        self.reconnect = reconnect;
+    }
+
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    // a raw stream name such as btcusdt@aggTrade or ethusdt@kline_1m,
+    // subscribing twice is a no-op
+    pub fn subscribe(&mut self, stream: &str) {
+        if !self.streams.iter().any(|s| s == stream) {
+            self.streams.push(stream.into());
+        }
+    }
+
+    // e.g. subscribe_symbols(&["BTCUSDT", "ETHUSDT"], "depth@100ms")
+    pub fn subscribe_symbols(&mut self, symbols: &[&str], channel: &str) {
+        for symbol in symbols.iter() {
+            self.subscribe(&format!("{}@{}", symbol.to_lowercase(), channel));
+        }
+    }
+
+    pub fn streams(&self) -> &[String] {
+        &self.streams
+    }
+
+    // the connections into_stream opens, in subscription order
+    pub fn shards(&self) -> Vec<Shard> {
+        self.streams
+            .chunks(self.max_streams)
+            .map(|streams| Shard {
+                host: self.host.clone(),
+                streams: streams.to_vec(),
+                reconnect: self.reconnect,
+                shutdown: self.shutdown.clone(),
+            })
+            .collect()
+    }
+
+    // dropping the stream closes every connection on its next event
+    pub fn into_stream(self) -> EventStream<E> {
+        let shards = self.shards();
+        let run = self.run;
+        EventStream::spawn(move |tx| {
+            let mut handles = vec![];
+            for (i, shard) in shards.into_iter().enumerate() {
+                if i > 0 {
+                    // spreads the handshakes, binance limits new connections per ip
+                    thread::sleep(Duration::from_millis(200));
+                }
+                let tx = tx.clone();
+                handles.push(thread::spawn(move || run(shard, tx)));
+            }
+            for handle in handles {
+                let _ = handle.join();
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shards() {
+        let mut streams = BinanceStreams::spot(BINANCE_SPOT_WS_HOST);
+        let symbols: Vec<String> = (0..1500).map(|i| format!("SYM{}USDT", i)).collect();
+        let symbols: Vec<&str> = symbols.iter().map(|s| s.as_str()).collect();
+        streams.subscribe_symbols(&symbols, "bookTicker");
+        streams.subscribe("sym0usdt@bookTicker");
+        assert_eq!(streams.streams().len(), 1500);
+
+        let shards = streams.shards();
+        assert_eq!(shards.len(), 2);
+        assert_eq!(shards[0].streams.len(), 1024);
+        assert_eq!(shards[1].streams[0], "sym1024usdt@bookTicker");
+        let messages = shards[0].subscribe_messages();
+        assert_eq!(messages.len(), 4);
+        let first: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(first["method"], "SUBSCRIBE");
+        assert_eq!(first["params"].as_array().unwrap().len(), 256);
+        assert_eq!(first["params"][0], "sym0usdt@bookTicker");
+
+        let mut swap = BinanceStreams::swap(BINANCE_SWAP_WS_HOST);
+        swap.subscribe_symbols(&symbols[..450], "markPrice");
+        assert_eq!(swap.shards().len(), 3);
+        swap.set_max_streams(450);
+        assert_eq!(swap.shards().len(), 1);
+    }
+}
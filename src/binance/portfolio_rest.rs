@@ -0,0 +1,314 @@
+use crate::audit::AuditSink;
+use crate::binance::future_rest::BinanceSwap;
+use crate::binance::types::*;
+use crate::constant::*;
+use crate::errors::*;
+use crate::expiry::OrderExpiry;
+use crate::models::*;
+use crate::traits::*;
+use crate::utils::*;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// the futures market orders go to, usd margined or coin margined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PortfolioMarket {
+    Um,
+    Cm,
+}
+
+impl PortfolioMarket {
+    fn path(&self) -> &'static str {
+        match self {
+            PortfolioMarket::Um => "um",
+            PortfolioMarket::Cm => "cm",
+        }
+    }
+
+    // public market data is not served by papi
+    fn data_prefix(&self) -> &'static str {
+        match self {
+            PortfolioMarket::Um => "/fapi/v1",
+            PortfolioMarket::Cm => "/dapi/v1",
+        }
+    }
+}
+
+// portfolio margin account, one client per market. signing, keys and rate
+// limits are those of BinanceSwap, only the hosts and paths differ
+#[derive(Clone)]
+pub struct BinancePortfolio {
+    papi: BinanceSwap,
+    data: BinanceSwap,
+    market: PortfolioMarket,
+}
+
+impl BinancePortfolio {
+    pub fn new(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        market: PortfolioMarket,
+    ) -> Self {
+        let data_host = match market {
+            PortfolioMarket::Um => BINANCE_SWAP_HOST,
+            PortfolioMarket::Cm => BINANCE_COIN_SWAP_HOST,
+        };
+        BinancePortfolio::with_hosts(
+            api_key,
+            secret_key,
+            BINANCE_PORTFOLIO_HOST.into(),
+            data_host.into(),
+            market,
+        )
+    }
+
+    pub fn with_hosts(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        host: String,
+        data_host: String,
+        market: PortfolioMarket,
+    ) -> Self {
+        BinancePortfolio {
+            papi: BinanceSwap::new(api_key, secret_key, host),
+            data: BinanceSwap::new(None, None, data_host),
+            market,
+        }
+    }
+
+    pub fn set_proxy(&mut self, proxy: &str) -> APIResult<()> {
+        self.papi.set_proxy(proxy)?;
+        self.data.set_proxy(proxy)
+    }
+
+    pub fn set_rate_limit(&mut self, per_minute: u32) {
+        self.papi.set_rate_limit(per_minute);
+    }
+
+    // calls outside the scope fail with PermissionDenied before any request
+    pub fn set_scope(&mut self, scope: Scope) {
+        self.papi.set_scope(scope);
+    }
+
+    // signed writes (orders, cancels, transfers) are recorded there
+    pub fn set_audit(&mut self, audit: Arc<dyn AuditSink>) {
+        self.papi.set_audit(audit);
+    }
+
+    // orders set to good_till_emulated are cancelled there
+    pub fn set_expiry(&mut self, expiry: OrderExpiry) {
+        self.papi.set_expiry(expiry);
+    }
+
+    pub fn market(&self) -> PortfolioMarket {
+        self.market
+    }
+
+    // e.g. /papi/v1/um/order
+    fn uri(&self, endpoint: &str) -> String {
+        format!("/papi/v1/{}/{}", self.market.path(), endpoint)
+    }
+
+    fn signed(&self, params: BTreeMap<String, String>) -> APIResult<String> {
+        self.papi.build_signed_request(params)
+    }
+
+    #[maybe_async::maybe_async]
+    pub async fn get_account(&self) -> APIResult<PortfolioAccount> {
+        let req = self.signed(BTreeMap::new())?;
+        let ret = self.papi.get_signed("/papi/v1/account", &req).await?;
+        let resp: RawPortfolioAccount = serde_json::from_str(&ret)?;
+
+        Ok(resp.into())
+    }
+}
+
+#[maybe_async::maybe_async]
+impl FutureRest for BinancePortfolio {
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            post_only: true,
+            hedge_mode: true,
+            good_till_date: true,
+            self_trade_prevention: true,
+            ..ExchangeCapabilities::default()
+        }
+    }
+
+    async fn ping(&self) -> APIResult<()> {
+        self.papi.get("/papi/v1/ping", "").await?;
+        Ok(())
+    }
+
+    async fn get_server_time(&self) -> APIResult<u64> {
+        let uri = format!("{}/time", self.market.data_prefix());
+        let ret = self.data.get(&uri, "").await?;
+        let resp: ServerTime = serde_json::from_str(&ret)?;
+        Ok(resp.server_time)
+    }
+
+    async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
+        if ![5, 10, 20, 50, 100, 500, 1000].contains(&depth) {
+            return Err(Box::new(ExError::ApiError(format!(
+                "invalid depth: {}, must be one of 5, 10, 20, 50, 100, 500, 1000",
+                depth
+            ))));
+        }
+        let uri = format!("{}/depth", self.market.data_prefix());
+        let params = to_query(&DepthParams {
+            symbol,
+            limit: depth,
+        })?;
+        let ret = self.data.get(&uri, &params).await?;
+        let resp: RawOrderbook = serde_json::from_str(&ret)?;
+        Ok(resp.into())
+    }
+
+    async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+        let uri = format!("{}/ticker/bookTicker", self.market.data_prefix());
+        let params = to_query(&SymbolParams { symbol })?;
+        let ret = self.data.get(&uri, &params).await?;
+        // coin margined tickers come as a list
+        let resp: RawTicker = if ret.trim_start().starts_with('[') {
+            let tickers: Vec<RawTicker> = serde_json::from_str(&ret)?;
+            tickers
+                .into_iter()
+                .next()
+                .ok_or_else(|| Box::new(ExError::ApiError(format!("no ticker for {}", symbol))))?
+        } else {
+            serde_json::from_str(&ret)?
+        };
+
+        Ok(resp.into())
+    }
+
+    async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+        let uri = format!("{}/klines", self.market.data_prefix());
+        let params = to_query(&KlineParams {
+            symbol,
+            interval: period,
+            limit,
+            start_time: None,
+            end_time: None,
+        })?;
+        let ret = self.data.get(&uri, &params).await?;
+        let resp: Vec<RawKlineRow> = serde_json::from_str(&ret)?;
+
+        Ok(resp.into_iter().map(|kline| kline.into()).collect())
+    }
+
+    async fn get_balance(&self, asset: &str) -> APIResult<FutureBalance> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("asset".into(), asset.into());
+        let req = self.signed(params)?;
+        let ret = self.papi.get_signed("/papi/v1/balance", &req).await?;
+        let resp: RawPortfolioBalance = serde_json::from_str(&ret)?;
+
+        Ok(resp.into())
+    }
+
+    async fn create_order(&self, order: &OrderRequest) -> APIResult<String> {
+        self.papi.scope().check(Capability::Trade, "create_order")?;
+        let uri = self.uri("order");
+        let params = order_params(order, true)?;
+        let (uri, params) = (&uri, &params);
+        let place = |client_id: String| -> Pending<APIResult<String>> {
+            Box::pin(async move {
+                let mut params = params.clone();
+                params.insert("newClientOrderId".into(), client_id);
+                let req = self.signed(params)?;
+                let ret = self.papi.post_signed(uri, &req).await?;
+                let resp: OrderResult = serde_json::from_str(&ret)?;
+                resp.placed(order)
+            })
+        };
+        let lookup = |client_id: String| -> Pending<APIResult<String>> {
+            Box::pin(async move {
+                let mut params: BTreeMap<String, String> = BTreeMap::new();
+                params.insert("symbol".into(), order.symbol.clone());
+                params.insert("origClientOrderId".into(), client_id);
+                let req = self.signed(params)?;
+                let ret = self.papi.get_signed(uri, &req).await?;
+                let resp: RawOrder = serde_json::from_str(&ret)?;
+                Ok(resp.order_id.to_string())
+            })
+        };
+        OrderExpiry::place(self.papi.expiry(), order, true, || {
+            Box::pin(async { place_order_once(order.client_id.as_deref(), place, lookup).await })
+        })
+        .await
+    }
+
+    async fn cancel(&self, symbol: &str, id: &str) -> APIResult<bool> {
+        self.papi.scope().check(Capability::Trade, "cancel")?;
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbol".into(), symbol.into());
+        params.insert("orderId".into(), id.into());
+        let req = self.signed(params)?;
+        let _ret = self.papi.delete_signed(&self.uri("order"), &req).await?;
+        Ok(true)
+    }
+
+    async fn cancel_all(&self, symbol: &str) -> APIResult<bool> {
+        self.papi.scope().check(Capability::Trade, "cancel_all")?;
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbol".into(), symbol.into());
+        let req = self.signed(params)?;
+        let _ret = self
+            .papi
+            .delete_signed(&self.uri("allOpenOrders"), &req)
+            .await?;
+        Ok(true)
+    }
+
+    async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbol".into(), symbol.into());
+        params.insert("orderId".into(), id.into());
+        let req = self.signed(params)?;
+        let ret = self.papi.get_signed(&self.uri("order"), &req).await?;
+        let resp: RawOrder = serde_json::from_str(&ret)?;
+
+        Ok(resp.into())
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbol".into(), symbol.into());
+        let req = self.signed(params)?;
+        let ret = self.papi.get_signed(&self.uri("openOrders"), &req).await?;
+        let resp: Vec<RawOrder> = serde_json::from_str(&ret)?;
+
+        Ok(resp.into_iter().map(|order| order.into()).collect())
+    }
+
+    async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbol".into(), symbol.into());
+        let req = self.signed(params)?;
+        let ret = self.papi.get_signed(&self.uri("allOrders"), &req).await?;
+        let resp: Vec<RawOrder> = serde_json::from_str(&ret)?;
+        let mut orders = resp
+            .into_iter()
+            .filter(|order| order.status == "FILLED" || order.status == "CANCELED")
+            .collect::<Vec<RawOrder>>();
+        orders.sort_by_key(|order| std::cmp::Reverse(order.time));
+
+        Ok(orders.into_iter().map(|order| order.into()).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_uri() {
+        let api = BinancePortfolio::new(None, None, PortfolioMarket::Cm);
+        assert_eq!(api.uri("order"), "/papi/v1/cm/order");
+        assert_eq!(api.market().data_prefix(), "/dapi/v1");
+        #[cfg(feature = "blocking")]
+        assert!(api.get_orderbook("BTCUSD_PERP", 7).is_err());
+    }
+}
@@ -1,25 +1,59 @@
+use crate::binance::mux::Shard;
 use crate::binance::types::*;
+use crate::constant::*;
 use crate::errors::*;
 use crate::models::*;
+use crate::queue::Backpressure;
+use crate::shutdown::{Shutdown, WsGuard};
+use crate::stream::EventStream;
 use crate::traits::*;
+use crate::utils::{decode_ws_message, from_ws_str, get_timestamp};
 
+use futures_channel::mpsc::UnboundedSender;
 use log::{info, warn};
-use ws::{Handler, Handshake, Message, Result, Sender};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use ws::util::Token;
+use ws::{CloseCode, Frame, Handler, Handshake, Message, Result, Sender};
+
+const HEARTBEAT: Token = Token(1);
 
 //static WEBSOCKET_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@depth20";
 
-#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize)]
 pub enum WsEvent {
     // public stream
     OrderbookEvent(Orderbook),
-    KlineEvent(Kline),
+    // diff depth stream, levels with zero amount are removed
+    DepthUpdateEvent(Orderbook),
+    KlineEvent(KlineUpdate),
     TickerEvent(Ticker),
     TradeEvent(Trade),
     ResponseEvent(ResponseEvent),
 
     // private stream
-    AccountUpdateEvent(AccountUpdateEvent),
-    OrderTradeEvent(OrderTradeEvent),
+    AccountUpdateEvent(AccountUpdate),
+    BalanceUpdateEvent(BalanceDelta),
+    OrderTradeEvent(OrderUpdate),
+}
+
+impl WsEvent {
+    // default policy for an EventQueue: latest ticker and open kline win,
+    // snapshots and trades may be dropped, diffs and private events never are
+    pub fn backpressure(&self) -> Backpressure {
+        match self {
+            WsEvent::TickerEvent(e) => Backpressure::Coalesce(format!("ticker@{}", e.symbol)),
+            WsEvent::KlineEvent(e) if !e.is_closed => {
+                Backpressure::Coalesce(format!("kline@{}@{}", e.symbol, e.period))
+            }
+            WsEvent::OrderbookEvent(_) | WsEvent::TradeEvent(_) => Backpressure::DropOldest,
+            _ => Backpressure::Block,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -32,6 +66,16 @@ pub struct BinanceWs<'a> {
     host: String,
     subs: Vec<String>,
     out: Option<Sender>,
+    reconnect: bool,
+    // keepalive ping interval, the connection is dropped after stale_after without any frame
+    heartbeat: Duration,
+    stale_after: Duration,
+    last_activity: Instant,
+    // open time of the last closed kline per stream, kept across reconnects
+    last_closed: Rc<RefCell<HashMap<String, u64>>>,
+    // closes the connection and stops reconnecting once shut down
+    shutdown: Option<Shutdown>,
+    registered: Option<WsGuard>,
 
     handler: Box<dyn FnMut(WsEvent) -> Result<()> + 'a>,
 }
@@ -42,6 +86,13 @@ impl<'a> BinanceWs<'a> {
             host: host.into(),
             subs: vec![],
             out: None,
+            reconnect: true,
+            heartbeat: Duration::from_secs(20),
+            stale_after: Duration::from_secs(60),
+            last_activity: Instant::now(),
+            last_closed: Rc::new(RefCell::new(HashMap::new())),
+            shutdown: None,
+            registered: None,
             handler: Box::new(|event| {
                 info!("event: {:?}", event);
                 Ok(())
@@ -49,17 +100,185 @@ impl<'a> BinanceWs<'a> {
         }
     }
 
-    pub fn connect<Callback: Clone>(&mut self, handler: Callback)
+    pub fn with_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => BinanceWs::new(BINANCE_SPOT_WS_HOST),
+            Network::Testnet => BinanceWs::new(BINANCE_SPOT_TESTNET_WS_HOST),
+        }
+    }
+
+    pub fn set_reconnect(&mut self, reconnect: bool) {
+        self.reconnect = reconnect;
+    }
+
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    pub fn set_heartbeat(&mut self, interval: Duration, stale_after: Duration) {
+        self.heartbeat = interval;
+        self.stale_after = stale_after;
+    }
+
+    // blocks; subscriptions made before connect are sent again after every reconnect
+    pub fn connect<Callback>(&mut self, handler: Callback)
     where
-        Callback: FnMut(WsEvent) -> Result<()> + 'a,
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
     {
-        ws::connect(self.host.clone(), |out| BinanceWs {
-            host: self.host.clone(),
-            subs: self.subs.clone(),
-            out: Some(out),
-            handler: Box::new(handler.clone()),
+        self.connect_until(handler, &|| false);
+    }
+
+    // runs the client on its own thread and yields its events
+    pub fn into_stream(self) -> EventStream<WsEvent> {
+        let host = self.host;
+        let subs = self.subs;
+        let reconnect = self.reconnect;
+        let shutdown = self.shutdown;
+        let heartbeat = self.heartbeat;
+        let stale_after = self.stale_after;
+        EventStream::spawn(move |tx| {
+            let mut client = BinanceWs::new(&host);
+            client.subs = subs;
+            client.reconnect = reconnect;
+            client.shutdown = shutdown;
+            client.heartbeat = heartbeat;
+            client.stale_after = stale_after;
+            let done = tx.clone();
+            client.connect_until(
+                move |event| {
+                    tx.unbounded_send(event)
+                        .map_err(|err| ws::Error::new(ws::ErrorKind::Internal, err.to_string()))
+                },
+                &|| done.is_closed(),
+            );
         })
-        .unwrap();
+    }
+
+    fn connect_until<Callback>(&mut self, handler: Callback, done: &dyn Fn() -> bool)
+    where
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
+    {
+        loop {
+            let ret = ws::connect(self.host.clone(), |out| BinanceWs {
+                host: self.host.clone(),
+                subs: self.subs.clone(),
+                out: Some(out),
+                reconnect: self.reconnect,
+                heartbeat: self.heartbeat,
+                stale_after: self.stale_after,
+                last_activity: Instant::now(),
+                last_closed: self.last_closed.clone(),
+                shutdown: self.shutdown.clone(),
+                registered: None,
+                handler: Box::new(handler.clone()),
+            });
+            if let Err(err) = ret {
+                warn!("ws error: {:?}", err);
+            }
+            let stopped = matches!(&self.shutdown, Some(s) if s.is_shutdown());
+            if !self.reconnect || stopped || done() {
+                break;
+            }
+            warn!("ws disconnected, reconnecting");
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    // every individual trade, sub_trade subscribes to aggregated trades
+    pub fn sub_raw_trade(&mut self, symbol: &str) {
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}@trade\"], \"id\": {}}}",
+            symbol.to_string().to_lowercase(),
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // levels 5, 10 or 20 subscribes to the partial book, None to the diff stream;
+    // speed is one of 100, 1000 ms, None keeps the exchange default
+    pub fn sub_depth(
+        &mut self,
+        symbol: &str,
+        levels: Option<u16>,
+        speed: Option<u16>,
+    ) -> APIResult<()> {
+        if let Some(levels) = levels {
+            if ![5, 10, 20].contains(&levels) {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid levels: {}, must be one of 5, 10, 20",
+                    levels
+                ))));
+            }
+        }
+        if let Some(speed) = speed {
+            if ![100, 1000].contains(&speed) {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid speed: {}, must be one of 100, 1000",
+                    speed
+                ))));
+            }
+        }
+        let mut stream = format!("{}@depth", symbol.to_lowercase());
+        if let Some(levels) = levels {
+            stream.push_str(&levels.to_string());
+        }
+        if let Some(speed) = speed {
+            stream.push_str(&format!("@{}ms", speed));
+        }
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [\"{}\"], \"id\": {}}}",
+            stream,
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+        Ok(())
+    }
+
+    // best bid/ask of several symbols in a single subscription
+    pub fn sub_tickers(&mut self, symbols: &[&str]) {
+        let params = symbols
+            .iter()
+            .map(|symbol| format!("\"{}@bookTicker\"", symbol.to_lowercase()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let msg = format!(
+            "{{\"method\": \"SUBSCRIBE\", \"params\": [{}], \"id\": {}}}",
+            params,
+            self.subs.len() + 1,
+        );
+        self.send(msg.as_str());
+        self.subs.push(msg);
+    }
+
+    // drops closed klines that were already delivered before a reconnect
+    fn is_duplicate(&self, event: &WsEvent) -> bool {
+        match event {
+            WsEvent::KlineEvent(update) if update.is_closed => {
+                let key = format!("{}@{}", update.symbol, update.period);
+                let mut last_closed = self.last_closed.borrow_mut();
+                match last_closed.get(&key) {
+                    Some(ts) if *ts >= update.kline.timestamp => true,
+                    _ => {
+                        last_closed.insert(key, update.kline.timestamp);
+                        false
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // a handler error closes the connection
+    fn dispatch(&mut self, event: WsEvent) -> Result<()> {
+        if let Err(err) = (self.handler)(event) {
+            warn!("handler error: {:?}", err);
+            if let Some(out) = &self.out {
+                out.close(CloseCode::Normal)?;
+            }
+        }
+        Ok(())
     }
 
     fn send(&self, msg: &str) {
@@ -74,23 +293,51 @@ impl<'a> BinanceWs<'a> {
     }
 
     fn deseralize(&self, s: &str) -> APIResult<WsEvent> {
-        if s.find("result") != None {
-            let resp: ResponseEvent = serde_json::from_str(s)?;
+        if s.contains("result") {
+            let resp: ResponseEvent = from_ws_str(s)?;
             return Ok(WsEvent::ResponseEvent(resp));
         }
-        //let val: Value = serde_json::from_str(s)?;
-        if s.find("kline") != None {
-            let resp: KlineEvent = serde_json::from_str(&s)?;
-            Ok(WsEvent::KlineEvent(resp.kline.into()))
-        } else if s.find("lastUpdateId") != None {
-            let resp: RawOrderbook = serde_json::from_str(&s)?;
-            Ok(WsEvent::OrderbookEvent(resp.into()))
-        } else if s.find("aggTrade") != None {
-            let resp: TradeEvent = serde_json::from_str(&s)?;
+        //let val: Value = from_ws_str(s)?;
+        if s.contains("executionReport") {
+            let resp: OrderTradeEvent = from_ws_str(s)?;
+            Ok(WsEvent::OrderTradeEvent(resp.into()))
+        } else if s.contains("outboundAccountPosition") {
+            let resp: AccountUpdateEvent = from_ws_str(s)?;
+            Ok(WsEvent::AccountUpdateEvent(resp.into()))
+        } else if s.contains("balanceUpdate") {
+            let resp: BalanceUpdateEvent = from_ws_str(s)?;
+            Ok(WsEvent::BalanceUpdateEvent(resp.into()))
+        } else if s.contains("kline") {
+            let resp: KlineEvent = from_ws_str(s)?;
+            Ok(WsEvent::KlineEvent(KlineUpdate {
+                symbol: resp.symbol,
+                period: resp.kline.interval.clone(),
+                is_closed: resp.kline.is_final_bar,
+                kline: resp.kline.into(),
+            }))
+        } else if s.contains("lastUpdateId") {
+            let resp: RawOrderbook = from_ws_str(s)?;
+            let mut orderbook: Orderbook = resp.into();
+            if orderbook.timestamp == 0 {
+                orderbook.timestamp = get_timestamp()?;
+            }
+            Ok(WsEvent::OrderbookEvent(orderbook))
+        } else if s.contains("depthUpdate") {
+            let resp: DepthOrderbookEvent = from_ws_str(s)?;
+            Ok(WsEvent::DepthUpdateEvent(resp.into()))
+        } else if s.contains("aggTrade") {
+            let resp: TradeEvent = from_ws_str(s)?;
             Ok(WsEvent::TradeEvent(resp.into()))
-        } else if s.find("A") != None && s.find("B") != None {
-            let resp: BookTickerEvent = serde_json::from_str(&s)?;
-            Ok(WsEvent::TickerEvent(resp.into()))
+        } else if s.contains("\"e\":\"trade\"") {
+            let resp: RawTradeEvent = from_ws_str(s)?;
+            Ok(WsEvent::TradeEvent(resp.into()))
+        } else if s.contains("A") && s.contains("B") {
+            let resp: BookTickerEvent = from_ws_str(s)?;
+            let mut ticker: Ticker = resp.into();
+            if ticker.timestamp == 0 {
+                ticker.timestamp = get_timestamp()?;
+            }
+            Ok(WsEvent::TickerEvent(ticker))
         } else {
             Err(Box::new(ExError::ApiError("msg channel not found".into())))
         }
@@ -144,16 +391,34 @@ impl<'a> SpotWs for BinanceWs<'a> {
     }
 }
 
+// runs one connection of a BinanceStreams until its stream is dropped
+pub(crate) fn run_shard(shard: Shard, tx: UnboundedSender<WsEvent>) {
+    let mut client = BinanceWs::new(&shard.host);
+    client.subs = shard.subscribe_messages();
+    client.reconnect = shard.reconnect;
+    client.shutdown = shard.shutdown;
+    let done = tx.clone();
+    client.connect_until(
+        move |event| {
+            tx.unbounded_send(event)
+                .map_err(|err| ws::Error::new(ws::ErrorKind::Internal, err.to_string()))
+        },
+        &|| done.is_closed(),
+    );
+}
+
 impl<'a> Handler for BinanceWs<'a> {
     fn on_open(&mut self, _shake: Handshake) -> Result<()> {
         match &self.out {
-            /*
-            Some(out) => self.subs.iter().for_each(|s| {
-                let _ = out.send(s.as_str());
-            }),
-            */
-            Some(_) => {
+            Some(out) => {
+                if let Some(shutdown) = &self.shutdown {
+                    self.registered = Some(shutdown.register_ws(out.clone()));
+                }
                 info!("ws connected");
+                self.subs.iter().for_each(|s| {
+                    let _ = out.send(s.as_str());
+                });
+                out.timeout(self.heartbeat.as_millis() as u64, HEARTBEAT)?;
             }
             None => {
                 warn!("self.out is None");
@@ -162,11 +427,41 @@ impl<'a> Handler for BinanceWs<'a> {
         Ok(())
     }
 
+    fn on_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        self.last_activity = Instant::now();
+        Ok(Some(frame))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> Result<()> {
+        if event != HEARTBEAT {
+            return Ok(());
+        }
+        if let Some(out) = &self.out {
+            if self.last_activity.elapsed() > self.stale_after {
+                // stops this connection, connect() reconnects if enabled
+                warn!("ws stale for {:?}, dropping connection", self.stale_after);
+                return out.shutdown();
+            }
+            out.ping(vec![])?;
+            out.timeout(self.heartbeat.as_millis() as u64, HEARTBEAT)?;
+        }
+        Ok(())
+    }
+
     fn on_message(&mut self, msg: Message) -> Result<()> {
         //println!("{:?}", msg);
-        match self.deseralize(&msg.to_string()) {
+        let s = match decode_ws_message(msg) {
+            Ok(s) => s,
+            Err(err) => {
+                warn!("decode msg error: {:?}", err);
+                return Ok(());
+            }
+        };
+        match self.deseralize(&s) {
             Ok(event) => {
-                let _ = (self.handler)(event);
+                if !self.is_duplicate(&event) {
+                    self.dispatch(event)?;
+                }
             }
             Err(err) => {
                 warn!("deseralize msg error: {:?}", err);
@@ -178,9 +473,126 @@ impl<'a> Handler for BinanceWs<'a> {
 
 #[cfg(test)]
 mod test {
+    #![allow(dead_code)]
     use super::*;
 
     static WEBSOCKET_URL: &str = "wss://stream.binance.com:9443/ws/btcusdt@depth20";
+
+    #[test]
+    fn test_kline_event() {
+        let ws = BinanceWs::new(WEBSOCKET_URL);
+        let msg = r#"{"e":"kline","E":123456789,"s":"BTCUSDT","k":{"t":123400000,"T":123460000,"s":"BTCUSDT","i":"1m","f":100,"L":200,"o":"0.0010","c":"0.0020","h":"0.0025","l":"0.0015","v":"1000","n":100,"x":true,"q":"1.0000","V":"500","Q":"0.500","B":"123456"}}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::KlineEvent(update) => {
+                assert_eq!(update.symbol, "BTCUSDT");
+                assert_eq!(update.period, "1m");
+                assert!(update.is_closed);
+                assert_eq!(update.kline.close, 0.002);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+        // the same closed candle replayed after a reconnect is dropped
+        let event = ws.deseralize(msg).unwrap();
+        assert!(!ws.is_duplicate(&event));
+        assert!(ws.is_duplicate(&event));
+    }
+
+    #[test]
+    fn test_book_ticker_event() {
+        let mut ws = BinanceWs::new(WEBSOCKET_URL);
+        ws.sub_tickers(&["BNBUSDT", "btcusdt"]);
+        assert!(ws.subs[0].contains("[\"bnbusdt@bookTicker\", \"btcusdt@bookTicker\"]"));
+
+        let msg = r#"{"u":400900217,"s":"BNBUSDT","b":"25.35190000","B":"31.21000000","a":"25.36520000","A":"40.66000000"}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::TickerEvent(ticker) => {
+                assert_eq!(ticker.symbol, "BNBUSDT");
+                assert!(ticker.timestamp > 0);
+                assert_eq!(ticker.bid.price, 25.3519);
+                assert_eq!(ticker.ask.amount, 40.66);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_user_data_event() {
+        let ws = BinanceWs::new(WEBSOCKET_URL);
+        let msg = r#"{"e":"executionReport","E":1499405658658,"s":"ETHBTC","c":"mUvoqJxFIILMdfAW5iGSOW","S":"BUY","o":"LIMIT","f":"GTC","q":"1.00000000","p":"0.10264410","P":"0.00000000","F":"0.00000000","g":-1,"C":"","x":"TRADE","X":"PARTIALLY_FILLED","r":"NONE","i":4293153,"l":"0.40000000","z":"0.50000000","L":"0.10264410","n":"0.00010000","N":"BNB","T":1499405658657,"t":12,"I":8641984,"w":false,"m":false,"M":false,"O":1499405658657,"Z":"0.05000000","Y":"0.04105764","Q":"0.00000000"}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::OrderTradeEvent(e) => {
+                assert_eq!(e.order_id, "4293153");
+                assert_eq!(e.status, OrderStatus::PartiallyFilled);
+                assert_eq!(e.execution_type, "TRADE");
+                assert_eq!(e.filled, 0.5);
+                assert_eq!(e.avg_price, 0.1);
+                assert_eq!(e.last_filled_amount, 0.4);
+                assert_eq!(e.commission, 0.0001);
+                assert_eq!(e.commission_asset, "BNB");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let msg = r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,"B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::AccountUpdateEvent(e) => {
+                assert_eq!(e.timestamp, 1564034571073);
+                assert_eq!(e.balances[0].asset, "ETH");
+                assert_eq!(e.balances[0].free, 10000.0);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let msg = r#"{"e":"balanceUpdate","E":1573200697110,"a":"BTC","d":"100.00000000","T":1573200697068}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::BalanceUpdateEvent(e) => {
+                assert_eq!(e.asset, "BTC");
+                assert_eq!(e.change, 100.0);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_depth_event() {
+        let mut ws = BinanceWs::new(WEBSOCKET_URL);
+        ws.sub_depth("BNBBTC", Some(10), Some(100)).unwrap();
+        ws.sub_depth("BNBBTC", None, None).unwrap();
+        assert!(ws.sub_depth("BNBBTC", Some(50), None).is_err());
+        assert!(ws.sub_depth("BNBBTC", None, Some(250)).is_err());
+        assert!(ws.subs[0].contains("\"bnbbtc@depth10@100ms\""));
+        assert!(ws.subs[1].contains("\"bnbbtc@depth\""));
+
+        let msg = r#"{"e":"depthUpdate","E":123456789,"s":"BNBBTC","U":157,"u":160,"b":[["0.0024","10"]],"a":[["0.0026","100"]]}"#;
+        match ws.deseralize(msg).unwrap() {
+            WsEvent::DepthUpdateEvent(e) => {
+                assert_eq!(e.last_update_id, 160);
+                assert_eq!(e.bids[0].amount, 10.0);
+                assert_eq!(e.asks[0].price, 0.0026);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_trade_event() {
+        let ws = BinanceWs::new(WEBSOCKET_URL);
+        let agg = r#"{"e":"aggTrade","E":123456789,"s":"BNBBTC","a":12345,"p":"0.001","q":"100","f":100,"l":105,"T":123456785,"m":true,"M":true}"#;
+        let raw = r#"{"e":"trade","E":123456789,"s":"BNBBTC","t":12345,"p":"0.001","q":"100","b":88,"a":50,"T":123456785,"m":false,"M":true}"#;
+        for (msg, side) in [(agg, "sell"), (raw, "buy")].iter() {
+            match ws.deseralize(msg).unwrap() {
+                WsEvent::TradeEvent(trade) => {
+                    assert_eq!(trade.symbol, "BNBBTC");
+                    assert_eq!(trade.trade_id, 12345);
+                    assert_eq!(trade.timestamp, 123456785);
+                    assert_eq!(trade.amount, 100.0);
+                    assert_eq!(trade.side, *side);
+                }
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+    }
+
     //#[test]
     fn test_binancews() {
         env_logger::init();
@@ -197,10 +609,10 @@ mod test {
             Ok(())
         };
         let mut binance = BinanceWs::new(WEBSOCKET_URL);
-        binance.connect(handler);
         //binance.sub_orderbook("btcusdt");
         binance.sub_ticker("btcusdt");
         binance.sub_kline("btcusdt", "5m");
         binance.sub_trade("btcusdt");
+        binance.connect(handler);
     }
 }
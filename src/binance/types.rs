@@ -1,7 +1,40 @@
-use crate::constant::*;
+use crate::errors::*;
 use crate::models::*;
 use crate::utils::*;
 
+use std::collections::{BTreeMap, HashMap};
+
+// query params of the public endpoints
+#[derive(Serialize)]
+pub struct SymbolParams<'a> {
+    pub symbol: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct DepthParams<'a> {
+    pub symbol: &'a str,
+    pub limit: u16,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KlineParams<'a> {
+    pub symbol: &'a str,
+    pub interval: &'a str,
+    pub limit: u16,
+    // open times in ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenKeyParams<'a> {
+    pub listen_key: &'a str,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerTime {
@@ -22,6 +55,7 @@ pub struct ExchangeInfo {
 pub struct RateLimit {
     pub rate_limit_type: String,
     pub interval: String,
+    pub interval_num: u64,
     pub limit: u64,
 }
 
@@ -35,17 +69,57 @@ pub struct Symbol {
     pub quote_asset: String,
     pub quote_precision: u64,
     pub order_types: Vec<String>,
+    // spot only, futures symbols leave them out
+    #[serde(default)]
     pub iceberg_allowed: bool,
+    #[serde(default)]
     pub is_spot_trading_allowed: bool,
+    #[serde(default)]
     pub is_margin_trading_allowed: bool,
     pub filters: Vec<Filters>,
 }
 
+impl Symbol {
+    pub fn rules(&self) -> SymbolRules {
+        let num = |v: &str| v.parse::<f64>().unwrap_or(0.0);
+        let mut rules = SymbolRules {
+            symbol: self.symbol.clone(),
+            ..Default::default()
+        };
+        for filter in self.filters.iter() {
+            match filter {
+                Filters::PriceFilter { tick_size, .. } => rules.tick_size = num(tick_size),
+                Filters::LotSize {
+                    step_size, min_qty, ..
+                } => {
+                    rules.step_size = num(step_size);
+                    rules.min_qty = num(min_qty);
+                }
+                Filters::MinNotional { min_notional, .. }
+                | Filters::Notional { min_notional, .. } => rules.min_notional = num(min_notional),
+                _ => {}
+            }
+        }
+        rules
+    }
+}
+
+// spot and futures statuses
+pub fn parse_symbol_status(status: &str) -> SymbolStatus {
+    match status {
+        "TRADING" => SymbolStatus::Trading,
+        "PRE_TRADING" | "PENDING_TRADING" => SymbolStatus::PreTrading,
+        "BREAK" | "CLOSE" | "DELIVERED" => SymbolStatus::Delisted,
+        _ => SymbolStatus::Suspended,
+    }
+}
+
 impl From<Symbol> for SymbolInfo {
     fn from(item: Symbol) -> SymbolInfo {
         SymbolInfo {
             base: item.base_asset,
             quote: item.quote_asset,
+            status: parse_symbol_status(&item.status),
             symbol: item.symbol,
             price_precision: item.quote_precision as u8,
             amount_precision: item.base_asset_precision as u8,
@@ -70,6 +144,7 @@ pub enum Filters {
     PercentPrice {
         multiplier_up: String,
         multiplier_down: String,
+        #[serde(default)]
         avg_price_mins: f64,
     },
     #[serde(rename = "LOT_SIZE")]
@@ -79,13 +154,24 @@ pub enum Filters {
         max_qty: String,
         step_size: String,
     },
+    // futures name the min notional notional
     #[serde(rename = "MIN_NOTIONAL")]
     #[serde(rename_all = "camelCase")]
     MinNotional {
+        #[serde(alias = "notional")]
         min_notional: String,
+        #[serde(default)]
         apply_to_market: bool,
+        #[serde(default)]
         avg_price_mins: f64,
     },
+    #[serde(rename = "NOTIONAL")]
+    #[serde(rename_all = "camelCase")]
+    Notional {
+        min_notional: String,
+        #[serde(default)]
+        max_notional: String,
+    },
     #[serde(rename = "ICEBERG_PARTS")]
     #[serde(rename_all = "camelCase")]
     IcebergParts { limit: u16 },
@@ -102,6 +188,9 @@ pub enum Filters {
         max_qty: String,
         step_size: String,
     },
+    // filters added after these, e.g. TRAILING_DELTA
+    #[serde(other)]
+    Other,
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -177,19 +266,43 @@ pub struct RawOrder {
     #[serde(rename = "type")]
     pub type_name: String,
     pub side: String,
-    #[serde(with = "string_or_float")]
+    // not in portfolio margin orders
+    #[serde(default, with = "string_or_float")]
     pub stop_price: f64,
+    #[serde(default)]
     pub iceberg_qty: String,
     pub time: u64,
+    #[serde(default)]
+    pub update_time: u64,
+    // spot only
+    #[serde(default)]
+    pub cummulative_quote_qty: String,
+    // futures only
+    #[serde(default)]
+    pub avg_price: String,
+}
+// spot PENDING_NEW and the futures liquidation statuses NEW_INSURANCE /
+// NEW_ADL are orders still working
+pub fn parse_order_status(status: &str) -> OrderStatus {
+    match status {
+        "NEW" | "PENDING_NEW" | "PENDING_CANCEL" | "NEW_INSURANCE" | "NEW_ADL" => OrderStatus::New,
+        "PARTIALLY_FILLED" => OrderStatus::PartiallyFilled,
+        "FILLED" => OrderStatus::Filled,
+        "CANCELED" => OrderStatus::Canceled,
+        "REJECTED" => OrderStatus::Rejected,
+        "EXPIRED" | "EXPIRED_IN_MATCH" => OrderStatus::Expired,
+        _ => OrderStatus::Unknown,
+    }
 }
 impl From<RawOrder> for Order {
     fn from(item: RawOrder) -> Order {
-        let status: u8 = match item.status.as_str() {
-            "NEW" => ORDER_STATUS_SUBMITTED,
-            "FILLED" => ORDER_STATUS_FILLED,
-            "PARTIALLY_FILLED" => ORDER_STATUS_PART_FILLED,
-            "CANCELLED" => ORDER_STATUS_CANCELLED,
-            _ => ORDER_STATUS_FAILED,
+        let filled = item.executed_qty.parse::<f64>().unwrap_or(0.0);
+        let avg_price = match item.avg_price.parse::<f64>() {
+            Ok(avg_price) => avg_price,
+            Err(_) if filled > 0.0 => {
+                item.cummulative_quote_qty.parse::<f64>().unwrap_or(0.0) / filled
+            }
+            Err(_) => 0.0,
         };
         Order {
             symbol: item.symbol,
@@ -197,8 +310,11 @@ impl From<RawOrder> for Order {
             amount: item.orig_qty.parse::<f64>().unwrap_or(0.0),
             price: item.price,
             side: item.side.to_uppercase(),
-            filled: item.executed_qty.parse::<f64>().unwrap_or(0.0),
-            status,
+            filled,
+            avg_price,
+            status: parse_order_status(&item.status),
+            create_time: item.time,
+            update_time: item.update_time.max(item.time),
         }
     }
 }
@@ -210,313 +326,1312 @@ pub struct OrderCanceled {
     pub order_id: u64,
     pub client_order_id: String,
 }
+// /api/v3/myTrades
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct OrderResult {
+pub struct RawMyTrade {
     pub symbol: String,
+    pub id: u64,
     pub order_id: u64,
-    pub client_order_id: String,
-    pub transact_time: u64,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub qty: f64,
+    #[serde(with = "string_or_float")]
+    pub commission: f64,
+    pub commission_asset: String,
+    pub time: u64,
+    pub is_buyer: bool,
+    pub is_maker: bool,
 }
-/// Response to a test order (endpoint /api/v3/order/test).
-///
-/// Currently, the API responds {} on a successfull test transaction,
-/// hence this struct has no fields.
+
+impl From<RawMyTrade> for MyTrade {
+    fn from(item: RawMyTrade) -> MyTrade {
+        MyTrade {
+            symbol: item.symbol,
+            trade_id: item.id,
+            order_id: item.order_id.to_string(),
+            price: item.price,
+            amount: item.qty,
+            fee: item.commission,
+            fee_asset: item.commission_asset,
+            side: if item.is_buyer { "buy" } else { "sell" }.into(),
+            is_maker: item.is_maker,
+            timestamp: item.time,
+        }
+    }
+}
+
+// /fapi/v1/income
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct TestResponse {}
+pub struct RawIncome {
+    #[serde(default)]
+    pub symbol: String,
+    pub income_type: String,
+    #[serde(with = "string_or_float")]
+    pub income: f64,
+    pub asset: String,
+    pub time: u64,
+    #[serde(with = "string_or_u64")]
+    pub tran_id: u64,
+}
+
+impl From<RawIncome> for Income {
+    fn from(item: RawIncome) -> Income {
+        Income {
+            symbol: item.symbol,
+            kind: item.income_type,
+            amount: item.income,
+            asset: item.asset,
+            tran_id: item.tran_id,
+            timestamp: item.time,
+        }
+    }
+}
+
+// /sapi/v1/capital/deposit/hisrec
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct RawOrderbook {
-    pub last_update_id: u64,
-    pub bids: Vec<RawBid>,
-    pub asks: Vec<RawAsk>,
+pub struct RawDeposit {
+    #[serde(default)]
+    pub id: String,
+    pub coin: String,
+    pub network: String,
+    #[serde(with = "string_or_float")]
+    pub amount: f64,
+    pub address: String,
+    pub tx_id: String,
+    // 0 pending, 6 credited, 1 success, 7 wrong deposit, 8 waiting user confirm
+    pub status: u8,
+    pub insert_time: u64,
+    // e.g. "3/12"
+    #[serde(default)]
+    pub confirm_times: String,
 }
-impl From<RawOrderbook> for Orderbook {
-    fn from(item: RawOrderbook) -> Orderbook {
-        let bids = item
-            .bids
-            .iter()
-            .map(|bid| Bid {
-                price: bid.price,
-                amount: bid.qty,
-            })
-            .collect::<Vec<Bid>>();
-        let asks = item
-            .asks
-            .iter()
-            .map(|ask| Ask {
-                price: ask.price,
-                amount: ask.qty,
-            })
-            .collect::<Vec<Ask>>();
-        Orderbook {
-            timestamp: item.last_update_id,
-            bids,
-            asks,
+
+impl From<RawDeposit> for Deposit {
+    fn from(item: RawDeposit) -> Deposit {
+        let status = match item.status {
+            1 => DepositStatus::Success,
+            6 => DepositStatus::Credited,
+            7 => DepositStatus::Failed,
+            _ => DepositStatus::Pending,
+        };
+        let mut confirms = item
+            .confirm_times
+            .split('/')
+            .map(|n| n.trim().parse::<u32>().unwrap_or(0));
+        let confirmations = confirms.next().unwrap_or(0);
+        let required_confirmations = confirms.next().unwrap_or(0);
+        Deposit {
+            id: item.id,
+            coin: item.coin,
+            network: item.network,
+            amount: item.amount,
+            address: item.address,
+            tx_id: item.tx_id,
+            status,
+            confirmations,
+            required_confirmations,
+            timestamp: item.insert_time,
         }
     }
 }
 
+// /sapi/v1/capital/config/getall
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RawBid {
-    #[serde(with = "string_or_float")]
-    pub price: f64,
-    #[serde(with = "string_or_float")]
-    pub qty: f64,
-    // Never serialized.
-    #[serde(skip)]
-    ignore: Vec<String>,
+#[serde(rename_all = "camelCase")]
+pub struct RawCoinConfig {
+    pub coin: String,
+    pub network_list: Vec<RawCoinNetwork>,
 }
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RawAsk {
+#[serde(rename_all = "camelCase")]
+pub struct RawCoinNetwork {
+    pub coin: String,
+    pub network: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub is_default: bool,
+    pub deposit_enable: bool,
+    pub withdraw_enable: bool,
     #[serde(with = "string_or_float")]
-    pub price: f64,
+    pub withdraw_fee: f64,
     #[serde(with = "string_or_float")]
-    pub qty: f64,
-    // Never serialized.
-    #[serde(skip)]
-    ignore: Vec<String>,
+    pub withdraw_min: f64,
+    #[serde(with = "string_or_float")]
+    pub withdraw_max: f64,
+    #[serde(default)]
+    pub min_confirm: u32,
+    // deposits share one address told apart by the memo
+    #[serde(default)]
+    pub same_address: bool,
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct UserDataStream {
-    pub listen_key: String,
+
+impl From<RawCoinNetwork> for CoinNetwork {
+    fn from(item: RawCoinNetwork) -> CoinNetwork {
+        CoinNetwork {
+            coin: item.coin,
+            network: item.network,
+            name: item.name,
+            is_default: item.is_default,
+            deposit_enabled: item.deposit_enable,
+            withdraw_enabled: item.withdraw_enable,
+            withdraw_fee: item.withdraw_fee,
+            withdraw_min: item.withdraw_min,
+            withdraw_max: item.withdraw_max,
+            memo_required: item.same_address,
+            min_confirmations: item.min_confirm,
+        }
+    }
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Success {}
+
+// /sapi/v1/capital/deposit/address
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-#[serde(untagged)]
-pub enum Prices {
-    AllPrices(Vec<SymbolPrice>),
+pub struct RawDepositAddress {
+    pub coin: String,
+    pub address: String,
+    #[serde(default)]
+    pub tag: String,
+}
+
+impl From<RawDepositAddress> for DepositAddress {
+    fn from(item: RawDepositAddress) -> DepositAddress {
+        DepositAddress {
+            coin: item.coin,
+            // not in the response, set from the request
+            network: String::new(),
+            address: item.address,
+            memo: Some(item.tag).filter(|tag| !tag.is_empty()),
+        }
+    }
 }
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SymbolPrice {
-    pub symbol: String,
-    #[serde(with = "string_or_float")]
-    pub price: f64,
+pub struct WithdrawResult {
+    pub id: String,
 }
+
+// /sapi/v1/capital/withdraw/history
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AveragePrice {
-    pub mins: u64,
+#[serde(rename_all = "camelCase")]
+pub struct RawWithdrawal {
+    pub id: String,
+    pub coin: String,
+    #[serde(default)]
+    pub network: String,
     #[serde(with = "string_or_float")]
-    pub price: f64,
+    pub amount: f64,
+    #[serde(with = "string_or_float")]
+    pub transaction_fee: f64,
+    pub address: String,
+    #[serde(default)]
+    pub tx_id: String,
+    // 0 email sent, 1 cancelled, 2 awaiting approval, 3 rejected,
+    // 4 processing, 5 failure, 6 completed
+    pub status: u8,
+    // utc, e.g. 2019-10-12 11:12:02
+    pub apply_time: String,
+}
+
+impl From<RawWithdrawal> for Withdrawal {
+    fn from(item: RawWithdrawal) -> Withdrawal {
+        let status = match item.status {
+            1 => WithdrawStatus::Canceled,
+            3 | 5 => WithdrawStatus::Failed,
+            4 => WithdrawStatus::Processing,
+            6 => WithdrawStatus::Completed,
+            _ => WithdrawStatus::Pending,
+        };
+        let timestamp =
+            chrono::NaiveDateTime::parse_from_str(&item.apply_time, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc().timestamp_millis() as u64)
+                .unwrap_or(0);
+        Withdrawal {
+            id: item.id,
+            coin: item.coin,
+            network: item.network,
+            amount: item.amount,
+            fee: item.transaction_fee,
+            address: item.address,
+            tx_id: item.tx_id,
+            status,
+            timestamp,
+        }
+    }
 }
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-#[serde(untagged)]
-pub enum BookTickers {
-    AllBookTickers(Vec<RawTicker>),
+pub struct OrderResult {
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+    // spot only
+    #[serde(default)]
+    pub transact_time: u64,
+    #[serde(default)]
+    pub status: String,
 }
-#[derive(Debug, Clone)]
-pub enum KlineSummaries {
-    AllKlineSummaries(Vec<KlineSummary>),
+
+impl OrderResult {
+    // the order id, futures may accept a GTX order that would take and
+    // expire it right away instead of rejecting it
+    pub fn placed(&self, order: &OrderRequest) -> APIResult<String> {
+        if order.post_only && self.status == "EXPIRED" {
+            return Err(Box::new(ExError::PostOnlyRejected(format!(
+                "order {} expired as it would take",
+                self.order_id
+            ))));
+        }
+        Ok(self.order_id.to_string())
+    }
+}
+
+// {"code": -2010, "msg": "Order would immediately match and take."}
+#[derive(Debug, Deserialize)]
+pub struct RawError {
+    pub code: i64,
+    pub msg: String,
+}
+
+// the error of a failed request from its status and body
+pub fn response_error(status: u16, body: &str) -> Box<ExError> {
+    let err: RawError = match serde_json::from_str(body) {
+        Ok(err) => err,
+        Err(_) => return Box::new(ExError::ApiError(format!("response: {}", status))),
+    };
+    match err.code {
+        // spot rejects LIMIT_MAKER orders that would take with the generic
+        // new order code, futures have one for GTX
+        -2010 if err.msg.contains("immediately match") => {
+            Box::new(ExError::PostOnlyRejected(err.msg))
+        }
+        -5022 => Box::new(ExError::PostOnlyRejected(err.msg)),
+        // cancel rejected and no such order, both sent for filled orders too
+        code @ (-2011 | -2013) => Box::new(ExError::UnknownOrder(format!(
+            "response: {} {} {}",
+            status, code, err.msg
+        ))),
+        code => Box::new(ExError::ApiError(format!(
+            "response: {} {} {}",
+            status, code, err.msg
+        ))),
+    }
+}
+
+// params of /api/v3/order and /fapi/v1/order, without the client order id
+pub fn order_params(order: &OrderRequest, futures: bool) -> APIResult<BTreeMap<String, String>> {
+    let mut params: BTreeMap<String, String> = BTreeMap::new();
+    params.insert("symbol".into(), order.symbol.clone());
+    params.insert("side".into(), order.side.clone());
+    params.insert("type".into(), order.order_type.clone());
+    match order.quote_amount {
+        Some(quote_amount) if order.is_market() && !futures => {
+            params.insert("quoteOrderQty".into(), quote_amount.to_string());
+        }
+        Some(_) => {
+            return Err(Box::new(ExError::ApiError(
+                "quote amount is for spot market orders only".into(),
+            )));
+        }
+        None => {
+            params.insert("quantity".into(), order.amount.to_string());
+        }
+    }
+    // spot has no GTD, those are cancelled from the client
+    let good_till = order.expire_at.filter(|_| futures && !order.emulate_expiry);
+    if order.is_market() {
+        if order.post_only {
+            return Err(Box::new(ExError::ApiError("post only market order".into())));
+        }
+        if order.expire_at.is_some() {
+            return Err(Box::new(ExError::ApiError("good till market order".into())));
+        }
+    } else {
+        params.insert("price".into(), order.price.to_string());
+        match (order.post_only, futures) {
+            // GTX and GTD are both a time in force
+            (true, true) if good_till.is_some() => {
+                return Err(Box::new(ExError::ApiError(
+                    "post only orders can only be good_till_emulated".into(),
+                )));
+            }
+            (false, true) if good_till.is_some() => {
+                params.insert("timeInForce".into(), "GTD".into());
+                params.insert(
+                    "goodTillDate".into(),
+                    good_till.unwrap_or_default().to_string(),
+                );
+            }
+            // spot has a separate order type for makers
+            (true, false) if order.order_type == "LIMIT" => {
+                params.insert("type".into(), "LIMIT_MAKER".into());
+            }
+            (true, false) => {
+                return Err(Box::new(ExError::ApiError(
+                    "post only is LIMIT only on spot".into(),
+                )));
+            }
+            (true, true) => {
+                params.insert("timeInForce".into(), "GTX".into());
+            }
+            _ => {
+                params.insert("timeInForce".into(), order.time_in_force.clone());
+            }
+        }
+    }
+    if !futures && (order.reduce_only || order.position_side.is_some()) {
+        return Err(Box::new(ExError::ApiError(
+            "reduce only and position side are futures only".into(),
+        )));
+    }
+    if let Some(mode) = order.self_trade_prevention {
+        params.insert("selfTradePreventionMode".into(), mode.as_str().into());
+    }
+    match order.position_side.as_deref() {
+        // hedge mode rejects reduceOnly, the side already says it closes
+        Some(side) if side != "BOTH" => {
+            params.insert("positionSide".into(), side.into());
+        }
+        _ if order.reduce_only => {
+            params.insert("reduceOnly".into(), "true".into());
+        }
+        _ => {}
+    }
+    Ok(params)
 }
+// /sapi/v1/asset/tradeFee
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct RawTicker {
+pub struct RawTradeFee {
     pub symbol: String,
     #[serde(with = "string_or_float")]
-    pub bid_price: f64,
-    #[serde(with = "string_or_float")]
-    pub bid_qty: f64,
-    #[serde(with = "string_or_float")]
-    pub ask_price: f64,
+    pub maker_commission: f64,
     #[serde(with = "string_or_float")]
-    pub ask_qty: f64,
+    pub taker_commission: f64,
 }
 
-impl From<RawTicker> for Ticker {
-    fn from(item: RawTicker) -> Ticker {
-        Ticker {
-            timestamp: 0u64,
-            bid: Bid {
-                price: item.bid_price,
-                amount: item.bid_qty,
-            },
-            ask: Ask {
-                price: item.ask_price,
-                amount: item.ask_qty,
-            },
+impl From<RawTradeFee> for FeeRate {
+    fn from(item: RawTradeFee) -> FeeRate {
+        FeeRate {
+            symbol: item.symbol,
+            maker: item.maker_commission,
+            taker: item.taker_commission,
         }
     }
 }
 
+// /fapi/v1/commissionRate
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct TradeHistory {
-    pub id: u64,
+pub struct RawCommissionRate {
+    pub symbol: String,
     #[serde(with = "string_or_float")]
-    pub price: f64,
+    pub maker_commission_rate: f64,
     #[serde(with = "string_or_float")]
-    pub qty: f64,
-    pub commission: String,
-    pub commission_asset: String,
-    pub time: u64,
-    pub is_buyer: bool,
-    pub is_maker: bool,
-    pub is_best_match: bool,
+    pub taker_commission_rate: f64,
 }
 
+impl From<RawCommissionRate> for FeeRate {
+    fn from(item: RawCommissionRate) -> FeeRate {
+        FeeRate {
+            symbol: item.symbol,
+            maker: item.maker_commission_rate,
+            taker: item.taker_commission_rate,
+        }
+    }
+}
+
+// /sapi/v1/convert/getQuote
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct PriceStats {
-    pub price_change: String,
-    pub price_change_percent: String,
-    pub weighted_avg_price: String,
-    #[serde(with = "string_or_float")]
-    pub prev_close_price: f64,
-    #[serde(with = "string_or_float")]
-    pub last_price: f64,
-    #[serde(with = "string_or_float")]
-    pub bid_price: f64,
-    #[serde(with = "string_or_float")]
-    pub ask_price: f64,
+pub struct RawConvertQuote {
+    pub quote_id: String,
     #[serde(with = "string_or_float")]
-    pub open_price: f64,
+    pub ratio: f64,
     #[serde(with = "string_or_float")]
-    pub high_price: f64,
+    pub inverse_ratio: f64,
+    pub valid_timestamp: u64,
     #[serde(with = "string_or_float")]
-    pub low_price: f64,
+    pub to_amount: f64,
     #[serde(with = "string_or_float")]
-    pub volume: f64,
-    pub open_time: u64,
-    pub close_time: u64,
-    pub first_id: u64,
-    pub last_id: u64,
-    pub count: u64,
+    pub from_amount: f64,
+}
+
+impl From<RawConvertQuote> for ConvertQuote {
+    fn from(item: RawConvertQuote) -> ConvertQuote {
+        ConvertQuote {
+            quote_id: item.quote_id,
+            ratio: item.ratio,
+            inverse_ratio: item.inverse_ratio,
+            from_amount: item.from_amount,
+            to_amount: item.to_amount,
+            valid_until: item.valid_timestamp,
+        }
+    }
 }
 
+// /sapi/v1/convert/acceptQuote and /sapi/v1/convert/orderStatus, the
+// order id is a string in the first and a number in the second
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct AccountUpdateEvent {
-    #[serde(rename = "e")]
-    pub event_type: String,
-    #[serde(rename = "E")]
-    pub event_time: u64,
-    m: u64,
-    t: u64,
-    b: u64,
-    s: u64,
-    #[serde(rename = "T")]
-    t_ignore: bool,
-    #[serde(rename = "W")]
-    w_ignore: bool,
-    #[serde(rename = "D")]
-    d_ignore: bool,
-    #[serde(rename = "B")]
-    pub balance: Vec<EventBalance>,
+pub struct RawConvertOrder {
+    #[serde(with = "string_or_u64")]
+    pub order_id: u64,
+    pub order_status: String,
+    pub create_time: u64,
 }
 
+impl From<RawConvertOrder> for ConvertOrder {
+    fn from(item: RawConvertOrder) -> ConvertOrder {
+        ConvertOrder {
+            order_id: item.order_id.to_string(),
+            status: item.order_status,
+            create_time: item.create_time,
+        }
+    }
+}
+
+// /sapi/v1/asset/dust-btc
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct EventBalance {
-    #[serde(rename = "a")]
-    pub asset: String,
+pub struct RawDustAssets {
+    pub details: Vec<RawDustAsset>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawDustAsset {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub amount_free: f64,
+    #[serde(rename = "toBNBOffExchange", with = "string_or_float")]
+    pub to_bnb_off_exchange: f64,
+}
+
+impl From<RawDustAsset> for DustAsset {
+    fn from(item: RawDustAsset) -> DustAsset {
+        DustAsset {
+            asset: item.asset,
+            amount: item.amount_free,
+            to_bnb: item.to_bnb_off_exchange,
+        }
+    }
+}
+
+// /sapi/v1/asset/dust
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawDustResult {
+    pub transfer_result: Vec<RawDustTransfer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawDustTransfer {
+    pub tran_id: u64,
+    pub from_asset: String,
+    #[serde(with = "string_or_float")]
+    pub amount: f64,
+    #[serde(with = "string_or_float")]
+    pub transfered_amount: f64,
+    #[serde(with = "string_or_float")]
+    pub service_charge_amount: f64,
+    pub operate_time: u64,
+}
+
+impl From<RawDustTransfer> for DustTransfer {
+    fn from(item: RawDustTransfer) -> DustTransfer {
+        DustTransfer {
+            tran_id: item.tran_id,
+            from_asset: item.from_asset,
+            amount: item.amount,
+            transferred_amount: item.transfered_amount,
+            service_charge: item.service_charge_amount,
+            timestamp: item.operate_time,
+        }
+    }
+}
+
+// simple earn list and position responses
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rows<T> {
+    pub rows: Vec<T>,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawEarnProduct {
+    pub product_id: String,
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub latest_annual_percentage_rate: f64,
+    #[serde(with = "string_or_float")]
+    pub min_purchase_amount: f64,
+    pub can_purchase: bool,
+    pub can_redeem: bool,
+}
+
+impl From<RawEarnProduct> for EarnProduct {
+    fn from(item: RawEarnProduct) -> EarnProduct {
+        EarnProduct {
+            product_id: item.product_id,
+            asset: item.asset,
+            apr: item.latest_annual_percentage_rate,
+            min_purchase_amount: item.min_purchase_amount,
+            can_purchase: item.can_purchase,
+            can_redeem: item.can_redeem,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawEarnPosition {
+    pub product_id: String,
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub total_amount: f64,
+    #[serde(with = "string_or_float")]
+    pub latest_annual_percentage_rate: f64,
+    pub can_redeem: bool,
+}
+
+impl From<RawEarnPosition> for EarnPosition {
+    fn from(item: RawEarnPosition) -> EarnPosition {
+        EarnPosition {
+            product_id: item.product_id,
+            asset: item.asset,
+            amount: item.total_amount,
+            apr: item.latest_annual_percentage_rate,
+            can_redeem: item.can_redeem,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawEarnResult {
+    // purchaseId or redeemId
+    #[serde(rename = "purchaseId", alias = "redeemId")]
+    pub id: u64,
+    pub success: bool,
+}
+
+// /sapi/v1/account/apiRestrictions
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawApiRestrictions {
+    pub ip_restrict: bool,
+    pub enable_reading: bool,
+    pub enable_spot_and_margin_trading: bool,
+    pub enable_margin: bool,
+    pub enable_futures: bool,
+    pub enable_withdrawals: bool,
+    pub enable_internal_transfer: bool,
+    pub permits_universal_transfer: bool,
+    #[serde(default)]
+    pub enable_vanilla_options: bool,
+    pub trading_authority_expiration_time: Option<u64>,
+}
+
+impl From<RawApiRestrictions> for ApiKeyPermissions {
+    fn from(item: RawApiRestrictions) -> ApiKeyPermissions {
+        ApiKeyPermissions {
+            ip_restrict: item.ip_restrict,
+            reading: item.enable_reading,
+            spot_and_margin_trading: item.enable_spot_and_margin_trading,
+            margin: item.enable_margin,
+            futures: item.enable_futures,
+            withdrawals: item.enable_withdrawals,
+            internal_transfer: item.enable_internal_transfer,
+            universal_transfer: item.permits_universal_transfer,
+            vanilla_options: item.enable_vanilla_options,
+            trading_expires_at: item.trading_authority_expiration_time,
+        }
+    }
+}
+
+// /sapi/v1/system/status, 0 normal and 1 maintenance
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawSystemStatus {
+    pub status: u8,
+    pub msg: String,
+}
+
+impl From<RawSystemStatus> for SystemStatus {
+    fn from(item: RawSystemStatus) -> SystemStatus {
+        match item.status {
+            0 => SystemStatus::Normal,
+            _ => SystemStatus::Maintenance(item.msg),
+        }
+    }
+}
+
+/// Response to a test order (endpoint /api/v3/order/test).
+///
+/// Currently, the API responds {} on a successfull test transaction,
+/// hence this struct has no fields.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResponse {}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawOrderbook {
+    pub last_update_id: u64,
+    // futures only
+    #[serde(default, rename = "E")]
+    pub event_time: u64,
+    pub bids: Vec<RawBid>,
+    pub asks: Vec<RawAsk>,
+}
+impl From<RawOrderbook> for Orderbook {
+    fn from(item: RawOrderbook) -> Orderbook {
+        let bids = item
+            .bids
+            .iter()
+            .map(|bid| Bid {
+                price: bid.price,
+                amount: bid.qty,
+            })
+            .collect::<Vec<Bid>>();
+        let asks = item
+            .asks
+            .iter()
+            .map(|ask| Ask {
+                price: ask.price,
+                amount: ask.qty,
+            })
+            .collect::<Vec<Ask>>();
+        Orderbook {
+            timestamp: item.event_time,
+            last_update_id: item.last_update_id,
+            bids,
+            asks,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawBid {
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub qty: f64,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawAsk {
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub qty: f64,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDataStream {
+    pub listen_key: String,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Success {}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(untagged)]
+pub enum Prices {
+    AllPrices(Vec<SymbolPrice>),
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SymbolPrice {
+    pub symbol: String,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AveragePrice {
+    pub mins: u64,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(untagged)]
+pub enum BookTickers {
+    AllBookTickers(Vec<RawTicker>),
+}
+#[derive(Debug, Clone)]
+pub enum KlineSummaries {
+    AllKlineSummaries(Vec<KlineSummary>),
+}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawTicker {
+    pub symbol: String,
+    #[serde(with = "string_or_float")]
+    pub bid_price: f64,
+    #[serde(with = "string_or_float")]
+    pub bid_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub ask_price: f64,
+    #[serde(with = "string_or_float")]
+    pub ask_qty: f64,
+}
+
+impl From<RawTicker> for Ticker {
+    fn from(item: RawTicker) -> Ticker {
+        Ticker {
+            symbol: item.symbol,
+            timestamp: 0u64,
+            bid: Bid {
+                price: item.bid_price,
+                amount: item.bid_qty,
+            },
+            ask: Ask {
+                price: item.ask_price,
+                amount: item.ask_qty,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeHistory {
+    pub id: u64,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub qty: f64,
+    pub commission: String,
+    pub commission_asset: String,
+    pub time: u64,
+    pub is_buyer: bool,
+    pub is_maker: bool,
+    pub is_best_match: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceStats {
+    pub price_change: String,
+    pub price_change_percent: String,
+    pub weighted_avg_price: String,
+    #[serde(with = "string_or_float")]
+    pub prev_close_price: f64,
+    #[serde(with = "string_or_float")]
+    pub last_price: f64,
+    #[serde(with = "string_or_float")]
+    pub bid_price: f64,
+    #[serde(with = "string_or_float")]
+    pub ask_price: f64,
+    #[serde(with = "string_or_float")]
+    pub open_price: f64,
+    #[serde(with = "string_or_float")]
+    pub high_price: f64,
+    #[serde(with = "string_or_float")]
+    pub low_price: f64,
+    #[serde(with = "string_or_float")]
+    pub volume: f64,
+    pub open_time: u64,
+    pub close_time: u64,
+    pub first_id: u64,
+    pub last_id: u64,
+    pub count: u64,
+}
+
+// spot outboundAccountPosition
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "u")]
+    pub last_update_time: u64,
+    #[serde(rename = "B")]
+    pub balance: Vec<EventBalance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EventBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
     #[serde(rename = "f")]
     pub free: String,
     #[serde(rename = "l")]
     pub locked: String,
 }
 
+impl From<AccountUpdateEvent> for AccountUpdate {
+    fn from(item: AccountUpdateEvent) -> AccountUpdate {
+        AccountUpdate {
+            timestamp: item.last_update_time,
+            balances: item
+                .balance
+                .into_iter()
+                .map(|b| Balance {
+                    asset: b.asset,
+                    free: str_to_f64(&b.free),
+                    locked: str_to_f64(&b.locked),
+                })
+                .collect(),
+        }
+    }
+}
+
+// spot balanceUpdate, sent on deposits, withdrawals and transfers
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BalanceUpdateEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "a")]
+    pub asset: String,
+    #[serde(rename = "d")]
+    pub delta: String,
+    #[serde(rename = "T")]
+    pub clear_time: u64,
+}
+
+impl From<BalanceUpdateEvent> for BalanceDelta {
+    fn from(item: BalanceUpdateEvent) -> BalanceDelta {
+        BalanceDelta {
+            asset: item.asset,
+            change: str_to_f64(&item.delta),
+            timestamp: item.clear_time,
+        }
+    }
+}
+
+// spot executionReport
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderTradeEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub new_client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "o")]
+    pub order_type: String,
+    #[serde(rename = "f")]
+    pub time_in_force: String,
+    #[serde(rename = "q")]
+    pub qty: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "x")]
+    pub execution_type: String,
+    #[serde(rename = "X")]
+    pub order_status: String,
+    #[serde(rename = "r")]
+    pub order_reject_reason: String,
+    #[serde(rename = "i")]
+    pub order_id: u64,
+    #[serde(rename = "l")]
+    pub qty_last_filled_trade: String,
+    #[serde(rename = "z")]
+    pub accumulated_qty_filled_trades: String,
+    #[serde(rename = "L")]
+    pub price_last_filled_trade: String,
+    #[serde(rename = "n")]
+    pub commission: String,
+    #[serde(default, rename = "N")]
+    pub asset_commisioned: Option<String>,
+    #[serde(rename = "T")]
+    pub trade_order_time: u64,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+    #[serde(default, rename = "Z")]
+    pub cummulative_quote_qty: String,
+}
+
+impl From<OrderTradeEvent> for OrderUpdate {
+    fn from(item: OrderTradeEvent) -> OrderUpdate {
+        let filled = str_to_f64(&item.accumulated_qty_filled_trades);
+        let avg_price = if filled > 0.0 {
+            str_to_f64(&item.cummulative_quote_qty) / filled
+        } else {
+            0.0
+        };
+        OrderUpdate {
+            symbol: item.symbol,
+            order_id: item.order_id.to_string(),
+            client_order_id: item.new_client_order_id,
+            side: item.side,
+            order_type: item.order_type,
+            execution_type: item.execution_type,
+            status: parse_order_status(&item.order_status),
+            price: str_to_f64(&item.price),
+            amount: str_to_f64(&item.qty),
+            filled,
+            avg_price,
+            last_filled_amount: str_to_f64(&item.qty_last_filled_trade),
+            last_filled_price: str_to_f64(&item.price_last_filled_trade),
+            commission: str_to_f64(&item.commission),
+            commission_asset: item.asset_commisioned.unwrap_or_default(),
+            trade_id: item.trade_id,
+            timestamp: item.trade_order_time,
+        }
+    }
+}
+
+// futures ORDER_TRADE_UPDATE
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FutureOrderTradeEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+    #[serde(rename = "o")]
+    pub order: RawFutureOrderUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawFutureOrderUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "c")]
+    pub client_order_id: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "o")]
+    pub order_type: String,
+    #[serde(rename = "q")]
+    pub qty: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "ap")]
+    pub avg_price: String,
+    #[serde(rename = "x")]
+    pub execution_type: String,
+    #[serde(rename = "X")]
+    pub order_status: String,
+    #[serde(rename = "i")]
+    pub order_id: u64,
+    #[serde(rename = "l")]
+    pub last_filled_qty: String,
+    #[serde(rename = "z")]
+    pub filled_qty: String,
+    #[serde(rename = "L")]
+    pub last_filled_price: String,
+    #[serde(default, rename = "N")]
+    pub commission_asset: Option<String>,
+    #[serde(default, rename = "n")]
+    pub commission: Option<String>,
+    #[serde(rename = "T")]
+    pub trade_time: u64,
+    #[serde(rename = "t")]
+    pub trade_id: i64,
+}
+
+impl From<FutureOrderTradeEvent> for OrderUpdate {
+    fn from(item: FutureOrderTradeEvent) -> OrderUpdate {
+        let order = item.order;
+        OrderUpdate {
+            symbol: order.symbol,
+            order_id: order.order_id.to_string(),
+            client_order_id: order.client_order_id,
+            side: order.side,
+            order_type: order.order_type,
+            execution_type: order.execution_type,
+            status: parse_order_status(&order.order_status),
+            price: str_to_f64(&order.price),
+            amount: str_to_f64(&order.qty),
+            filled: str_to_f64(&order.filled_qty),
+            avg_price: str_to_f64(&order.avg_price),
+            last_filled_amount: str_to_f64(&order.last_filled_qty),
+            last_filled_price: str_to_f64(&order.last_filled_price),
+            commission: order.commission.map(|c| str_to_f64(&c)).unwrap_or(0.0),
+            commission_asset: order.commission_asset.unwrap_or_default(),
+            trade_id: order.trade_id,
+            timestamp: order.trade_time,
+        }
+    }
+}
+
+// futures ACCOUNT_UPDATE
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FutureAccountUpdateEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+    #[serde(rename = "a")]
+    pub account: RawFutureAccountUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawFutureAccountUpdate {
+    #[serde(rename = "m")]
+    pub reason: String,
+    #[serde(rename = "B")]
+    pub balances: Vec<RawFutureBalanceUpdate>,
+    #[serde(default, rename = "P")]
+    pub positions: Vec<RawPositionUpdate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawFutureBalanceUpdate {
+    #[serde(rename = "a")]
+    pub asset: String,
+    #[serde(rename = "wb")]
+    pub wallet_balance: String,
+    #[serde(rename = "cw")]
+    pub cross_wallet_balance: String,
+    #[serde(rename = "bc")]
+    pub balance_change: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawPositionUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "pa")]
+    pub position_amt: String,
+    #[serde(rename = "ep")]
+    pub entry_price: String,
+    #[serde(rename = "up")]
+    pub unrealized_pnl: String,
+    #[serde(rename = "ps")]
+    pub position_side: String,
+}
+
+impl From<FutureAccountUpdateEvent> for FutureAccountUpdate {
+    fn from(item: FutureAccountUpdateEvent) -> FutureAccountUpdate {
+        FutureAccountUpdate {
+            timestamp: item.transaction_time,
+            reason: item.account.reason,
+            balances: item
+                .account
+                .balances
+                .into_iter()
+                .map(|b| FutureBalanceUpdate {
+                    asset: b.asset,
+                    wallet_balance: str_to_f64(&b.wallet_balance),
+                    cross_wallet_balance: str_to_f64(&b.cross_wallet_balance),
+                    change: str_to_f64(&b.balance_change),
+                })
+                .collect(),
+            positions: item
+                .account
+                .positions
+                .into_iter()
+                .map(|p| PositionUpdate {
+                    symbol: p.symbol,
+                    amount: str_to_f64(&p.position_amt),
+                    entry_price: str_to_f64(&p.entry_price),
+                    unrealized_pnl: str_to_f64(&p.unrealized_pnl),
+                    position_side: p.position_side,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "a")]
+    pub aggregated_trade_id: u64,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub qty: String,
+    #[serde(rename = "f")]
+    pub first_break_trade_id: u64,
+    #[serde(rename = "l")]
+    pub last_break_trade_id: u64,
+    #[serde(rename = "T")]
+    pub trade_order_time: u64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+    #[serde(skip, rename = "M")]
+    pub m_ignore: bool,
+}
+
+impl From<TradeEvent> for Trade {
+    fn from(item: TradeEvent) -> Trade {
+        let side = if item.is_buyer_maker { "sell" } else { "buy" };
+
+        Trade {
+            symbol: item.symbol,
+            trade_id: item.aggregated_trade_id,
+            timestamp: item.trade_order_time,
+            amount: str_to_f64(&item.qty),
+            price: str_to_f64(&item.price),
+            side: side.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RawTradeEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub qty: String,
+    #[serde(rename = "T")]
+    pub trade_time: u64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+}
+
+impl From<RawTradeEvent> for Trade {
+    fn from(item: RawTradeEvent) -> Trade {
+        let side = if item.is_buyer_maker { "sell" } else { "buy" };
+
+        Trade {
+            symbol: item.symbol,
+            trade_id: item.trade_id,
+            timestamp: item.trade_time,
+            amount: str_to_f64(&item.qty),
+            price: str_to_f64(&item.price),
+            side: side.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarkPriceEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub mark_price: String,
+    #[serde(rename = "i")]
+    pub index_price: String,
+    #[serde(rename = "r")]
+    pub funding_rate: String,
+    #[serde(rename = "T")]
+    pub next_funding_time: u64,
+}
+
+impl From<MarkPriceEvent> for MarkPrice {
+    fn from(item: MarkPriceEvent) -> MarkPrice {
+        MarkPrice {
+            symbol: item.symbol,
+            timestamp: item.event_time,
+            mark_price: str_to_f64(&item.mark_price),
+            index_price: str_to_f64(&item.index_price),
+            funding_rate: str_to_f64(&item.funding_rate),
+            next_funding_time: item.next_funding_time,
+        }
+    }
+}
+
+// GET /fapi/v1/premiumIndex
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPremiumIndex {
+    pub symbol: String,
+    #[serde(with = "string_or_float")]
+    pub mark_price: f64,
+    #[serde(with = "string_or_float")]
+    pub index_price: f64,
+    #[serde(with = "string_or_float")]
+    pub last_funding_rate: f64,
+    pub next_funding_time: u64,
+    pub time: u64,
+}
+
+impl From<RawPremiumIndex> for MarkPrice {
+    fn from(item: RawPremiumIndex) -> MarkPrice {
+        MarkPrice {
+            symbol: item.symbol,
+            timestamp: item.time,
+            mark_price: item.mark_price,
+            index_price: item.index_price,
+            funding_rate: item.last_funding_rate,
+            next_funding_time: item.next_funding_time,
+        }
+    }
+}
+
+// GET /fapi/v1/openInterest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawOpenInterest {
+    pub symbol: String,
+    #[serde(with = "string_or_float")]
+    pub open_interest: f64,
+    pub time: u64,
+}
+
+impl From<RawOpenInterest> for OpenInterest {
+    fn from(item: RawOpenInterest) -> OpenInterest {
+        OpenInterest {
+            symbol: item.symbol,
+            timestamp: item.time,
+            open_interest: item.open_interest,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForceOrderEvent {
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "o")]
+    pub order: RawForceOrder,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct OrderTradeEvent {
-    #[serde(rename = "e")]
-    pub event_type: String,
-    #[serde(rename = "E")]
-    pub event_time: u64,
+pub struct RawForceOrder {
     #[serde(rename = "s")]
     pub symbol: String,
-    #[serde(rename = "c")]
-    pub new_client_order_id: String,
     #[serde(rename = "S")]
     pub side: String,
-    #[serde(rename = "o")]
-    pub order_type: String,
-    #[serde(rename = "f")]
-    pub time_in_force: String,
     #[serde(rename = "q")]
     pub qty: String,
     #[serde(rename = "p")]
     pub price: String,
-    #[serde(skip, rename = "P")]
-    pub p_ignore: String,
-    #[serde(skip, rename = "F")]
-    pub f_ignore: String,
-    #[serde(skip)]
-    pub g: i32,
-    #[serde(skip, rename = "C")]
-    pub c_ignore: Option<String>,
-    #[serde(rename = "x")]
-    pub execution_type: String,
+    #[serde(rename = "ap")]
+    pub avg_price: String,
     #[serde(rename = "X")]
-    pub order_status: String,
-    #[serde(rename = "r")]
-    pub order_reject_reason: String,
-    #[serde(rename = "i")]
-    pub order_id: u64,
-    #[serde(rename = "l")]
-    pub qty_last_filled_trade: String,
+    pub status: String,
     #[serde(rename = "z")]
-    pub accumulated_qty_filled_trades: String,
-    #[serde(rename = "L")]
-    pub price_last_filled_trade: String,
-    #[serde(rename = "n")]
-    pub commission: String,
-    #[serde(skip, rename = "N")]
-    pub asset_commisioned: Option<String>,
-    #[serde(rename = "T")]
-    pub trade_order_time: u64,
-    #[serde(rename = "t")]
-    pub trade_id: i64,
-    #[serde(skip, rename = "I")]
-    pub i_ignore: u64,
-    #[serde(skip)]
-    pub w: bool,
-    #[serde(rename = "m")]
-    pub is_buyer_maker: bool,
-    #[serde(skip, rename = "M")]
-    pub m_ignore: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct TradeEvent {
-    #[serde(rename = "e")]
-    pub event_type: String,
-    #[serde(rename = "E")]
-    pub event_time: u64,
-    #[serde(rename = "s")]
-    pub symbol: String,
-    #[serde(rename = "a")]
-    pub aggregated_trade_id: u64,
-    #[serde(rename = "p")]
-    pub price: String,
-    #[serde(rename = "q")]
-    pub qty: String,
-    #[serde(rename = "f")]
-    pub first_break_trade_id: u64,
-    #[serde(rename = "l")]
-    pub last_break_trade_id: u64,
+    pub filled_qty: String,
     #[serde(rename = "T")]
-    pub trade_order_time: u64,
-    #[serde(rename = "m")]
-    pub is_buyer_maker: bool,
-    #[serde(skip, rename = "M")]
-    pub m_ignore: bool,
+    pub trade_time: u64,
 }
 
-impl From<TradeEvent> for Trade {
-    fn from(item: TradeEvent) -> Trade {
-        let side = if item.is_buyer_maker { "sell" } else { "buy" };
-
-        Trade {
-            timestamp: item.event_time,
-            amount: str_to_f64(&item.qty),
-            price: str_to_f64(&item.price),
-            side: side.into(),
+impl From<ForceOrderEvent> for Liquidation {
+    fn from(item: ForceOrderEvent) -> Liquidation {
+        let order = item.order;
+        Liquidation {
+            symbol: order.symbol,
+            side: order.side.to_lowercase(),
+            price: str_to_f64(&order.price),
+            avg_price: str_to_f64(&order.avg_price),
+            amount: str_to_f64(&order.qty),
+            filled: str_to_f64(&order.filled_qty),
+            status: parse_order_status(&order.status),
+            timestamp: order.trade_time,
         }
     }
 }
@@ -524,6 +1639,9 @@ impl From<TradeEvent> for Trade {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct BookTickerEvent {
+    // only sent on the futures stream
+    #[serde(default, rename = "E")]
+    pub event_time: u64,
     #[serde(rename = "u")]
     pub update_id: u64,
     #[serde(rename = "s")]
@@ -541,7 +1659,8 @@ pub struct BookTickerEvent {
 impl From<BookTickerEvent> for Ticker {
     fn from(item: BookTickerEvent) -> Ticker {
         Ticker {
-            timestamp: item.update_id,
+            symbol: item.symbol,
+            timestamp: item.event_time,
             bid: Bid {
                 price: str_to_f64(&item.best_bid),
                 amount: str_to_f64(&item.best_bid_qty),
@@ -724,14 +1843,72 @@ impl From<DepthOrderbookEvent> for Orderbook {
             .collect::<Vec<Ask>>();
         Orderbook {
             timestamp: item.event_time,
+            last_update_id: item.final_update_id,
             bids,
             asks,
         }
     }
 }
 
+// one row of /api/v3/klines and /fapi/v1/klines:
+// [open_time, "open", "high", "low", "close", "volume", close_time, ...],
+// columns after volume are skipped so new ones don't break parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawKlineRow(pub u64, pub f64, pub f64, pub f64, pub f64, pub f64);
+
+#[derive(Deserialize)]
+struct KlineField(#[serde(with = "string_or_float")] f64);
+
+impl<'de> serde::Deserialize<'de> for RawKlineRow {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct RowVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RowVisitor {
+            type Value = RawKlineRow;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a kline row")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<RawKlineRow, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let missing = |i| serde::de::Error::invalid_length(i, &self);
+                let timestamp: u64 = seq.next_element()?.ok_or_else(|| missing(0))?;
+                let mut values = [0.0; 5];
+                for (i, value) in values.iter_mut().enumerate() {
+                    let field: KlineField = seq.next_element()?.ok_or_else(|| missing(i + 1))?;
+                    *value = field.0;
+                }
+                while seq.next_element::<serde::de::IgnoredAny>()?.is_some() {}
+                let [open, high, low, close, volume] = values;
+                Ok(RawKlineRow(timestamp, open, high, low, close, volume))
+            }
+        }
+
+        deserializer.deserialize_seq(RowVisitor)
+    }
+}
+
+impl From<RawKlineRow> for Kline {
+    fn from(row: RawKlineRow) -> Kline {
+        Kline {
+            timestamp: row.0,
+            open: row.1,
+            high: row.2,
+            low: row.3,
+            close: row.4,
+            volume: row.5,
+        }
+    }
+}
+
 mod string_or_float {
-    use serde::{de, Deserialize, Deserializer, Serializer};
+    use serde::{de, Deserializer, Serializer};
     use std::fmt;
     pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -740,25 +1917,92 @@ mod string_or_float {
     {
         serializer.collect_str(value)
     }
+    // a visitor instead of an untagged enum, so prices are parsed straight
+    // from the borrowed input without buffering or allocating a String,
+    // this runs for every level of every depth message
     pub fn deserialize<'de, D>(deserializer: D) -> Result<f64, D::Error>
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        #[serde(untagged)]
-        enum StringOrFloat {
-            String(String),
-            Float(f64),
-        }
-        match StringOrFloat::deserialize(deserializer)? {
-            StringOrFloat::String(s) => s.parse().map_err(de::Error::custom),
-            StringOrFloat::Float(i) => Ok(i),
+        struct F64Visitor;
+
+        impl<'de> de::Visitor<'de> for F64Visitor {
+            type Value = f64;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number or a numeric string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+                v.parse().map_err(de::Error::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+                Ok(v)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+                Ok(v as f64)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+                Ok(v as f64)
+            }
         }
+
+        deserializer.deserialize_any(F64Visitor)
     }
 }
 
+mod string_or_u64 {
+    use serde::{de, Deserializer, Serializer};
+    use std::fmt;
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(*value)
+    }
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct U64Visitor;
+
+        impl<'de> de::Visitor<'de> for U64Visitor {
+            type Value = u64;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an integer or an integer string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+                v.parse().map_err(de::Error::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+                Ok(v)
+            }
+        }
+
+        deserializer.deserialize_any(U64Visitor)
+    }
+}
 
 // swap types
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiAssetsMargin {
+    pub multi_assets_margin: bool,
+}
+
+// answer of countdownCancelAll
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CountdownCancelAll {
+    pub symbol: String,
+    // ms, 0 when the countdown was cancelled
+    #[serde(with = "string_or_u64")]
+    pub countdown_time: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RawSwapBalance {
@@ -766,13 +2010,32 @@ pub struct RawSwapBalance {
     pub wallet_balance: String,
     pub unrealized_profit: String,
     pub margin_balance: String,
+    pub maint_margin: String,
     pub initial_margin: String,
     pub position_initial_margin: String,
     pub open_order_initial_margin: String,
-    pub cross_walet_balance: String,
+    pub cross_wallet_balance: String,
     pub cross_un_pnl: String,
     pub available_balance: String,
     pub max_withdraw_amount: String,
+    #[serde(default)]
+    pub margin_available: bool,
+}
+
+impl From<RawSwapBalance> for FutureBalance {
+    fn from(item: RawSwapBalance) -> FutureBalance {
+        FutureBalance {
+            asset: item.asset,
+            wallet_balance: str_to_f64(&item.wallet_balance),
+            margin_balance: str_to_f64(&item.margin_balance),
+            unrealized_pnl: str_to_f64(&item.unrealized_profit),
+            available_balance: str_to_f64(&item.available_balance),
+            max_withdraw_amount: str_to_f64(&item.max_withdraw_amount),
+            initial_margin: str_to_f64(&item.initial_margin),
+            maint_margin: str_to_f64(&item.maint_margin),
+            margin_available: item.margin_available,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -791,6 +2054,180 @@ pub struct RawPosition {
     pub position_side: String,
 }
 
+// /fapi/v2/positionRisk
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPositionRisk {
+    pub symbol: String,
+    #[serde(with = "string_or_float")]
+    pub position_amt: f64,
+    #[serde(with = "string_or_float")]
+    pub entry_price: f64,
+    #[serde(with = "string_or_float")]
+    pub mark_price: f64,
+    #[serde(with = "string_or_float")]
+    pub liquidation_price: f64,
+    #[serde(rename = "unRealizedProfit", with = "string_or_float")]
+    pub unrealized_profit: f64,
+    #[serde(with = "string_or_float")]
+    pub leverage: f64,
+    // cross or isolated
+    pub margin_type: String,
+    #[serde(with = "string_or_float")]
+    pub isolated_wallet: f64,
+    #[serde(default, with = "string_or_float")]
+    pub notional: f64,
+    pub position_side: String,
+}
+
+impl RawPositionRisk {
+    // the maintenance margin of the position and the cross margin ratio
+    // come from /fapi/v2/account, the adl quantile from /fapi/v1/adlQuantile
+    pub fn into_position_risk(
+        self,
+        maint_margin: f64,
+        cross_margin_ratio: f64,
+        adl_quantile: u8,
+    ) -> PositionRisk {
+        let isolated = self.margin_type == "isolated";
+        let margin_ratio = if !isolated {
+            cross_margin_ratio
+        } else if self.isolated_wallet + self.unrealized_profit > 0.0 {
+            maint_margin / (self.isolated_wallet + self.unrealized_profit)
+        } else {
+            1.0
+        };
+        PositionRisk {
+            symbol: self.symbol,
+            position_side: self.position_side,
+            amount: self.position_amt,
+            entry_price: self.entry_price,
+            mark_price: self.mark_price,
+            liquidation_price: self.liquidation_price,
+            unrealized_pnl: self.unrealized_profit,
+            notional: self.notional,
+            leverage: self.leverage as u32,
+            isolated,
+            margin_ratio,
+            adl_quantile,
+        }
+    }
+}
+
+// /fapi/v1/leverageBracket, a single object when the symbol is given
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RawLeverageBrackets {
+    One(RawSymbolBrackets),
+    All(Vec<RawSymbolBrackets>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawSymbolBrackets {
+    pub symbol: String,
+    pub brackets: Vec<RawLeverageBracket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawLeverageBracket {
+    pub bracket: u32,
+    pub initial_leverage: u32,
+    pub notional_cap: f64,
+    pub notional_floor: f64,
+    pub maint_margin_ratio: f64,
+    pub cum: f64,
+}
+
+impl From<RawLeverageBracket> for LeverageBracket {
+    fn from(item: RawLeverageBracket) -> LeverageBracket {
+        LeverageBracket {
+            bracket: item.bracket,
+            initial_leverage: item.initial_leverage,
+            notional_floor: item.notional_floor,
+            notional_cap: item.notional_cap,
+            maint_margin_ratio: item.maint_margin_ratio,
+            cum: item.cum,
+        }
+    }
+}
+
+// /papi/v1/account
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPortfolioAccount {
+    #[serde(rename = "uniMMR", with = "string_or_float")]
+    pub uni_mmr: f64,
+    #[serde(with = "string_or_float")]
+    pub account_equity: f64,
+    #[serde(with = "string_or_float")]
+    pub actual_equity: f64,
+    #[serde(with = "string_or_float")]
+    pub account_initial_margin: f64,
+    #[serde(with = "string_or_float")]
+    pub account_maint_margin: f64,
+    pub account_status: String,
+    #[serde(with = "string_or_float")]
+    pub virtual_max_withdraw_amount: f64,
+}
+
+impl From<RawPortfolioAccount> for PortfolioAccount {
+    fn from(item: RawPortfolioAccount) -> PortfolioAccount {
+        PortfolioAccount {
+            uni_mmr: item.uni_mmr,
+            account_equity: item.account_equity,
+            actual_equity: item.actual_equity,
+            initial_margin: item.account_initial_margin,
+            maint_margin: item.account_maint_margin,
+            status: item.account_status,
+            max_withdraw_amount: item.virtual_max_withdraw_amount,
+        }
+    }
+}
+
+// /papi/v1/balance
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawPortfolioBalance {
+    pub asset: String,
+    #[serde(with = "string_or_float")]
+    pub total_wallet_balance: f64,
+    #[serde(with = "string_or_float")]
+    pub cross_margin_free: f64,
+    #[serde(rename = "umUnrealizedPNL", with = "string_or_float")]
+    pub um_unrealized_pnl: f64,
+    #[serde(rename = "cmUnrealizedPNL", with = "string_or_float")]
+    pub cm_unrealized_pnl: f64,
+}
+
+impl From<RawPortfolioBalance> for FutureBalance {
+    fn from(item: RawPortfolioBalance) -> FutureBalance {
+        let unrealized_pnl = item.um_unrealized_pnl + item.cm_unrealized_pnl;
+        FutureBalance {
+            asset: item.asset,
+            wallet_balance: item.total_wallet_balance,
+            margin_balance: item.total_wallet_balance + unrealized_pnl,
+            unrealized_pnl,
+            available_balance: item.cross_margin_free,
+            max_withdraw_amount: item.cross_margin_free,
+            // margin is only known for the whole account
+            initial_margin: 0.0,
+            maint_margin: 0.0,
+            margin_available: true,
+        }
+    }
+}
+
+// /fapi/v1/adlQuantile, keyed by BOTH in one-way mode and LONG, SHORT
+// and HEDGE in hedge mode
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawAdlQuantile {
+    pub symbol: String,
+    pub adl_quantile: HashMap<String, u8>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RawSwapAccount {
@@ -813,3 +2250,463 @@ pub struct RawSwapAccount {
     pub assets: Vec<RawSwapBalance>,
     pub positions: Vec<RawPosition>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kline_rows() {
+        let raw = r#"[
+            [1499040000000, "0.01634790", "0.80000000", "0.01575800", "0.01577100",
+             "148976.11427815", 1499644799999, "2434.19055334", 308, "1756.87402397",
+             "28.46694368", "0"],
+            [1499040060000, 1.5, "2", "1", "1.8", "10", 1499040119999]
+        ]"#;
+        let klines: Vec<Kline> = serde_json::from_str::<Vec<RawKlineRow>>(raw)
+            .unwrap()
+            .into_iter()
+            .map(Kline::from)
+            .collect();
+        assert_eq!(klines.len(), 2);
+        assert_eq!(klines[0].timestamp, 1499040000000);
+        assert_eq!(klines[0].high, 0.8);
+        assert_eq!(klines[0].volume, 148976.11427815);
+        assert_eq!(klines[1].open, 1.5);
+
+        // malformed rows are errors instead of panics
+        assert!(serde_json::from_str::<Vec<RawKlineRow>>(r#"[[1, "1", "2"]]"#).is_err());
+        assert!(
+            serde_json::from_str::<Vec<RawKlineRow>>(r#"[["1", "1", "2", "1", "1", "1"]]"#)
+                .is_err()
+        );
+        assert!(
+            serde_json::from_str::<Vec<RawKlineRow>>(r#"[[1, "x", "2", "1", "1", "1"]]"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_symbol_rules() {
+        let raw = r#"{"symbol": "BTCUSDT", "status": "TRADING", "baseAsset": "BTC",
+            "baseAssetPrecision": 8, "quoteAsset": "USDT", "quotePrecision": 8,
+            "orderTypes": ["LIMIT"], "filters": [
+            {"filterType": "PRICE_FILTER", "minPrice": "556.80", "maxPrice": "4529764",
+             "tickSize": "0.10"},
+            {"filterType": "LOT_SIZE", "stepSize": "0.001", "maxQty": "1000",
+             "minQty": "0.001"},
+            {"filterType": "MIN_NOTIONAL", "notional": "100"},
+            {"filterType": "PERCENT_PRICE", "multiplierUp": "1.0500",
+             "multiplierDown": "0.9500", "multiplierDecimal": "4"},
+            {"filterType": "TRAILING_DELTA", "minTrailingAboveDelta": 10}]}"#;
+        let symbol: Symbol = serde_json::from_str(raw).unwrap();
+        let rules = symbol.rules();
+        assert_eq!(rules.tick_size, 0.1);
+        assert_eq!(rules.step_size, 0.001);
+        assert_eq!(rules.min_notional, 100.0);
+        let info: SymbolInfo = symbol.into();
+        assert_eq!(info.status, SymbolStatus::Trading);
+    }
+
+    #[test]
+    fn test_swap_balance() {
+        let raw = r#"{"asset": "BNB", "walletBalance": "1.0", "unrealizedProfit": "0.0",
+            "marginBalance": "1.0", "maintMargin": "0.0", "initialMargin": "0.0",
+            "positionInitialMargin": "0.0", "openOrderInitialMargin": "0.0",
+            "crossWalletBalance": "1.0", "crossUnPnl": "0.0", "availableBalance": "0.5",
+            "maxWithdrawAmount": "0.5", "marginAvailable": true}"#;
+        let balance: FutureBalance = serde_json::from_str::<RawSwapBalance>(raw).unwrap().into();
+        assert!(balance.margin_available);
+        assert_eq!(balance.available_balance, 0.5);
+
+        let mode: MultiAssetsMargin =
+            serde_json::from_str(r#"{"multiAssetsMargin": true}"#).unwrap();
+        assert!(mode.multi_assets_margin);
+
+        let countdown: CountdownCancelAll =
+            serde_json::from_str(r#"{"symbol": "BTCUSDT", "countdownTime": "100000"}"#).unwrap();
+        assert_eq!(countdown.countdown_time, 100000);
+    }
+
+    #[test]
+    fn test_premium_index() {
+        let raw = r#"{"symbol": "BTCUSDT", "markPrice": "11793.63104562", "indexPrice": "11781.80495970",
+            "estimatedSettlePrice": "11781.16138815", "lastFundingRate": "0.00038246",
+            "interestRate": "0.00010000", "nextFundingTime": 1597392000000, "time": 1597370495002}"#;
+        let mark: MarkPrice = serde_json::from_str::<RawPremiumIndex>(raw).unwrap().into();
+        assert_eq!(mark.funding_rate, 0.00038246);
+        assert_eq!(mark.next_funding_time, 1597392000000);
+
+        let raw = r#"{"openInterest": "10659.509", "symbol": "BTCUSDT", "time": 1589437530011}"#;
+        let oi: OpenInterest = serde_json::from_str::<RawOpenInterest>(raw).unwrap().into();
+        assert_eq!(oi.open_interest, 10659.509);
+    }
+
+    #[test]
+    fn test_position_risk() {
+        let raw = r#"[{"entryPrice": "6563.66500", "marginType": "isolated",
+            "isAutoAddMargin": "false", "isolatedMargin": "15.51", "leverage": "10",
+            "liquidationPrice": "5930.78", "markPrice": "6679.50671178",
+            "maxNotionalValue": "20000000", "positionAmt": "0.020",
+            "symbol": "BTCUSDT", "unRealizedProfit": "2.3", "positionSide": "LONG",
+            "notional": "133.59", "isolatedWallet": "13.2", "updateTime": 1625474304765}]"#;
+        let raws: Vec<RawPositionRisk> = serde_json::from_str(raw).unwrap();
+        let risk = raws[0].clone().into_position_risk(0.62, 0.3, 2);
+        assert!(risk.isolated);
+        assert_eq!(risk.leverage, 10);
+        assert_eq!(risk.liquidation_price, 5930.78);
+        assert!((risk.margin_ratio - 0.04).abs() < 1e-9);
+        assert_eq!(risk.adl_quantile, 2);
+
+        let mut raw = raws[0].clone();
+        raw.margin_type = "cross".into();
+        assert_eq!(raw.into_position_risk(0.62, 0.3, 2).margin_ratio, 0.3);
+
+        let adl: Vec<RawAdlQuantile> = serde_json::from_str(
+            r#"[{"symbol": "ETHUSDT", "adlQuantile": {"LONG": 3, "SHORT": 3, "HEDGE": 0}}]"#,
+        )
+        .unwrap();
+        assert_eq!(adl[0].adl_quantile["LONG"], 3);
+    }
+
+    #[test]
+    fn test_leverage_brackets() {
+        let raw = r#"[{"symbol": "ETHUSDT", "notionalCoef": 1.5, "brackets": [
+            {"bracket": 1, "initialLeverage": 75, "notionalCap": 10000, "notionalFloor": 0,
+             "maintMarginRatio": 0.0065, "cum": 0},
+            {"bracket": 2, "initialLeverage": 50, "notionalCap": 50000, "notionalFloor": 10000,
+             "maintMarginRatio": 0.01, "cum": 35}]}]"#;
+        let brackets = match serde_json::from_str::<RawLeverageBrackets>(raw).unwrap() {
+            RawLeverageBrackets::All(mut all) => all.remove(0).brackets,
+            RawLeverageBrackets::One(_) => panic!("expected a list"),
+        };
+        let brackets: Vec<LeverageBracket> = brackets.into_iter().map(|b| b.into()).collect();
+        assert_eq!(brackets[1].initial_leverage, 50);
+        assert_eq!(LeverageBracket::maint_margin(&brackets, 1000.0), Some(6.5));
+        // continuous at the tier boundary
+        assert_eq!(
+            LeverageBracket::maint_margin(&brackets, -10000.0),
+            Some(65.0)
+        );
+        assert_eq!(LeverageBracket::maint_margin(&brackets, 60000.0), None);
+    }
+
+    #[test]
+    fn test_convert() {
+        let raw = r#"{"quoteId": "12415572564", "ratio": "38163.7", "inverseRatio": "0.0000262",
+            "validTimestamp": 1623319461670, "toAmount": "3816.37", "fromAmount": "0.1"}"#;
+        let quote: ConvertQuote = serde_json::from_str::<RawConvertQuote>(raw).unwrap().into();
+        assert_eq!(quote.quote_id, "12415572564");
+        assert_eq!(quote.to_amount, 3816.37);
+
+        let accepted = r#"{"orderId": "933256278426274426", "createTime": 1623381330472,
+            "orderStatus": "PROCESS"}"#;
+        let status = r#"{"orderId": 933256278426274426, "orderStatus": "SUCCESS",
+            "fromAsset": "BTC", "fromAmount": "0.1", "toAsset": "USDT", "toAmount": "3816.37",
+            "ratio": "38163.7", "inverseRatio": "0.0000262", "createTime": 1623381330472}"#;
+        for raw in [accepted, status].iter() {
+            let order: ConvertOrder = serde_json::from_str::<RawConvertOrder>(raw).unwrap().into();
+            assert_eq!(order.order_id, "933256278426274426");
+        }
+    }
+
+    #[test]
+    fn test_dust() {
+        let raw = r#"{"details": [{"asset": "ADA", "assetFullName": "ADA", "amountFree": "6.21",
+            "toBTC": "0.00016848", "toBNB": "0.01777302", "toBNBOffExchange": "0.01741756",
+            "exchange": "0.00035546"}], "totalTransferBtc": "0.00016848",
+            "totalTransferBNB": "0.01777302", "dribbletPercentage": "0.02"}"#;
+        let assets: RawDustAssets = serde_json::from_str(raw).unwrap();
+        let asset: DustAsset = assets.details[0].clone().into();
+        assert_eq!((asset.amount, asset.to_bnb), (6.21, 0.01741756));
+
+        let raw = r#"{"totalServiceCharge": "0.02102542", "totalTransfered": "1.05127099",
+            "transferResult": [{"amount": "0.03000000", "fromAsset": "ETH",
+            "operateTime": 1563368549307, "serviceChargeAmount": "0.00500000",
+            "tranId": 2970932918, "transferedAmount": "0.25000000"}]}"#;
+        let result: RawDustResult = serde_json::from_str(raw).unwrap();
+        let transfer: DustTransfer = result.transfer_result[0].clone().into();
+        assert_eq!(transfer.from_asset, "ETH");
+        assert_eq!(transfer.transferred_amount, 0.25);
+    }
+
+    #[test]
+    fn test_simple_earn() {
+        let raw = r#"{"rows": [{"asset": "BTC", "latestAnnualPercentageRate": "0.05",
+            "canPurchase": true, "canRedeem": true, "isSoldOut": false, "hot": false,
+            "minPurchaseAmount": "0.01", "productId": "BTC001",
+            "subscriptionStartTime": 1646182276000, "status": "PURCHASING"}], "total": 1}"#;
+        let products: Rows<RawEarnProduct> = serde_json::from_str(raw).unwrap();
+        let product: EarnProduct = products.rows[0].clone().into();
+        assert_eq!(product.product_id, "BTC001");
+        assert_eq!(product.apr, 0.05);
+
+        let raw = r#"{"rows": [{"totalAmount": "75.46", "latestAnnualPercentageRate": "0.02",
+            "asset": "USDT", "canRedeem": true, "collateralAmount": "0",
+            "productId": "USDT001", "yesterdayRealTimeRewards": "0.1"}], "total": 1}"#;
+        let positions: Rows<RawEarnPosition> = serde_json::from_str(raw).unwrap();
+        assert_eq!(positions.rows[0].total_amount, 75.46);
+
+        let redeemed: RawEarnResult =
+            serde_json::from_str(r#"{"redeemId": 40607, "success": true}"#).unwrap();
+        assert_eq!(redeemed.id, 40607);
+    }
+
+    #[test]
+    fn test_api_restrictions() {
+        let raw = r#"{"ipRestrict": false, "createTime": 1623840271000,
+            "enableWithdrawals": false, "enableInternalTransfer": true,
+            "permitsUniversalTransfer": true, "enableVanillaOptions": false,
+            "enableReading": true, "enableFutures": true, "enableMargin": false,
+            "enableSpotAndMarginTrading": true, "tradingAuthorityExpirationTime": 1628985600000}"#;
+        let permissions: ApiKeyPermissions = serde_json::from_str::<RawApiRestrictions>(raw)
+            .unwrap()
+            .into();
+        assert!(permissions.futures && permissions.spot_and_margin_trading);
+        assert!(!permissions.withdrawals);
+        assert_eq!(permissions.trading_expires_at, Some(1628985600000));
+    }
+
+    #[test]
+    fn test_portfolio_account() {
+        let raw = r#"{"uniMMR": "5167.92", "accountEquity": "122607.35",
+            "actualEquity": "73.47", "accountInitialMargin": "23.72",
+            "accountMaintMargin": "23.72", "accountStatus": "NORMAL",
+            "virtualMaxWithdrawAmount": "1627523.32", "totalAvailableBalance": "",
+            "totalMarginOpenLoss": "", "updateTime": 1657707212154}"#;
+        let account: PortfolioAccount = serde_json::from_str::<RawPortfolioAccount>(raw)
+            .unwrap()
+            .into();
+        assert_eq!(account.uni_mmr, 5167.92);
+        assert_eq!(account.status, "NORMAL");
+
+        let raw = r#"{"asset": "USDT", "totalWalletBalance": "122607.35",
+            "crossMarginAsset": "92.27", "crossMarginBorrowed": "10.00",
+            "crossMarginFree": "100.00", "crossMarginInterest": "0.00",
+            "crossMarginLocked": "3.00", "umWalletBalance": "0.00",
+            "umUnrealizedPNL": "23.72", "cmWalletBalance": "23.72",
+            "cmUnrealizedPNL": "-1.72", "updateTime": 1617939110373, "negativeBalance": "0"}"#;
+        let balance: FutureBalance = serde_json::from_str::<RawPortfolioBalance>(raw)
+            .unwrap()
+            .into();
+        assert_eq!(balance.unrealized_pnl, 22.0);
+        assert_eq!(balance.available_balance, 100.0);
+    }
+
+    #[test]
+    fn test_parse_order_status() {
+        for status in [
+            "NEW",
+            "PENDING_NEW",
+            "PENDING_CANCEL",
+            "NEW_INSURANCE",
+            "NEW_ADL",
+        ] {
+            assert_eq!(parse_order_status(status), OrderStatus::New, "{}", status);
+        }
+        assert_eq!(parse_order_status("REJECTED"), OrderStatus::Rejected);
+        assert_eq!(parse_order_status("EXPIRED_IN_MATCH"), OrderStatus::Expired);
+        let unknown = parse_order_status("SOMETHING_NEW");
+        assert_eq!(unknown, OrderStatus::Unknown);
+        assert!(!unknown.is_final());
+    }
+
+    #[test]
+    fn test_response_error() {
+        let body = r#"{"code": -2010, "msg": "Order would immediately match and take."}"#;
+        assert!(matches!(
+            *response_error(400, body),
+            ExError::PostOnlyRejected(_)
+        ));
+        let body = r#"{"code": -5022, "msg": "Due to the order could not be executed as maker, the Post Only order will be rejected."}"#;
+        assert!(matches!(
+            *response_error(400, body),
+            ExError::PostOnlyRejected(_)
+        ));
+        let body = r#"{"code": -2011, "msg": "Unknown order sent."}"#;
+        assert!(matches!(
+            *response_error(400, body),
+            ExError::UnknownOrder(_)
+        ));
+        let body =
+            r#"{"code": -2010, "msg": "Account has insufficient balance for requested action."}"#;
+        assert_eq!(
+            response_error(400, body).to_string(),
+            "ApiError: response: 400 -2010 Account has insufficient balance for requested action."
+        );
+        assert_eq!(
+            response_error(502, "<html>").to_string(),
+            "ApiError: response: 502"
+        );
+
+        let raw = r#"{"symbol": "BTCUSDT", "orderId": 22542179, "clientOrderId": "grid-1",
+            "status": "EXPIRED"}"#;
+        let resp: OrderResult = serde_json::from_str(raw).unwrap();
+        let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01);
+        assert_eq!(resp.placed(&order).unwrap(), "22542179");
+        assert!(resp.placed(&order.post_only()).is_err());
+    }
+
+    #[test]
+    fn test_order_params() {
+        let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01);
+        let params = order_params(&order, false).unwrap();
+        assert_eq!(params["type"], "LIMIT");
+        assert_eq!(params["timeInForce"], "GTC");
+        assert_eq!(params["price"], "9000");
+
+        let order = order.post_only();
+        let params = order_params(&order, false).unwrap();
+        assert_eq!(params["type"], "LIMIT_MAKER");
+        assert!(!params.contains_key("timeInForce"));
+        let params = order_params(&order, true).unwrap();
+        assert_eq!(params["timeInForce"], "GTX");
+        let order = OrderRequest::new("BTCUSDT", "BUY", "TAKE_PROFIT_LIMIT", 9000.0, 0.01);
+        assert!(order_params(&order.post_only(), false).is_err());
+
+        let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01).good_till(1700000000000);
+        let params = order_params(&order, true).unwrap();
+        assert_eq!(params["timeInForce"], "GTD");
+        assert_eq!(params["goodTillDate"], "1700000000000");
+        let params = order_params(&order, false).unwrap();
+        assert_eq!(params["timeInForce"], "GTC");
+        assert!(!params.contains_key("goodTillDate"));
+        assert!(order_params(&order.clone().post_only(), true).is_err());
+        let order = order.post_only().good_till_emulated(1700000000000);
+        let params = order_params(&order, true).unwrap();
+        assert_eq!(params["timeInForce"], "GTX");
+
+        let order = OrderRequest::limit_sell("BTCUSDT", 9000.0, 0.01)
+            .self_trade_prevention(SelfTradePrevention::ExpireMaker);
+        let params = order_params(&order, false).unwrap();
+        assert_eq!(params["selfTradePreventionMode"], "EXPIRE_MAKER");
+
+        let order = OrderRequest::market_buy_quote("BTCUSDT", 500.0);
+        let params = order_params(&order, false).unwrap();
+        assert_eq!(params["quoteOrderQty"], "500");
+        assert!(!params.contains_key("quantity"));
+        assert!(order_params(&order, true).is_err());
+        let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01).quote_amount(500.0);
+        assert!(order_params(&order, false).is_err());
+
+        let order = OrderRequest::market_sell("BTCUSDT", 0.01).reduce_only();
+        let params = order_params(&order, true).unwrap();
+        assert!(!params.contains_key("price"));
+        assert_eq!(params["reduceOnly"], "true");
+        assert!(order_params(&order, false).is_err());
+
+        let order = OrderRequest::close("BTCUSDT", "SHORT", -0.01).unwrap();
+        let params = order_params(&order, true).unwrap();
+        assert_eq!(params["side"], "BUY");
+        assert_eq!(params["quantity"], "0.01");
+        assert_eq!(params["positionSide"], "SHORT");
+        assert!(!params.contains_key("reduceOnly"));
+        let order = OrderRequest::close("BTCUSDT", "BOTH", -0.01).unwrap();
+        let params = order_params(&order, true).unwrap();
+        assert_eq!(params["side"], "BUY");
+        assert_eq!(params["reduceOnly"], "true");
+        assert!(OrderRequest::close("BTCUSDT", "LONG", 0.0).is_none());
+    }
+
+    #[test]
+    fn test_history_records() {
+        let raw = r#"[{"symbol": "BNBBTC", "id": 28457, "orderId": 100234,
+            "orderListId": -1, "price": "4.00000100", "qty": "12.00000000",
+            "quoteQty": "48.000012", "commission": "10.10000000",
+            "commissionAsset": "BNB", "time": 1499865549590, "isBuyer": true,
+            "isMaker": false, "isBestMatch": true}]"#;
+        let trades: Vec<RawMyTrade> = serde_json::from_str(raw).unwrap();
+        let trade: MyTrade = trades[0].clone().into();
+        assert_eq!(trade.side, "buy");
+        assert_eq!(trade.fee, 10.1);
+
+        let raw = r#"[{"symbol": "", "incomeType": "TRANSFER", "income": "-0.37500000",
+            "asset": "USDT", "info": "TRANSFER", "time": 1570608000000,
+            "tranId": 9689322392, "tradeId": ""}]"#;
+        let incomes: Vec<RawIncome> = serde_json::from_str(raw).unwrap();
+        assert_eq!(incomes[0].tran_id, 9689322392);
+
+        let raw = r#"[{"id": "769800519366885376", "amount": "0.001", "coin": "BNB",
+            "network": "BNB", "status": 6, "address": "bnb136ns6lfw4zs5hg4n85vdthaad7hq5m4gtkgf23",
+            "addressTag": "101764890", "txId": "98A3EA560C6B3336D348B6C83F0F95ECE4F1F5919E94BD006E5BF3BF264FACFC",
+            "insertTime": 1661493146000, "transferType": 0, "confirmTimes": "1/1",
+            "unlockConfirm": 0, "walletType": 0}]"#;
+        let deposits: Vec<RawDeposit> = serde_json::from_str(raw).unwrap();
+        let deposit: Deposit = deposits[0].clone().into();
+        assert_eq!(deposit.status, DepositStatus::Credited);
+        assert_eq!(deposit.amount, 0.001);
+        assert_eq!(deposit.confirmations, 1);
+        assert_eq!(deposit.required_confirmations, 1);
+
+        let raw = r#"[{"coin": "USDT", "depositAllEnable": true, "free": "0", "name": "TetherUS",
+            "networkList": [{"addressRegex": "^T[1-9A-HJ-NP-Za-km-z]{33}$", "coin": "USDT",
+            "depositEnable": true, "isDefault": false, "memoRegex": "", "minConfirm": 1,
+            "name": "Tron (TRC20)", "network": "TRX", "resetAddressStatus": false,
+            "specialTips": "", "unLockConfirm": 0, "withdrawEnable": true, "withdrawFee": "1",
+            "withdrawIntegerMultiple": "0.000001", "withdrawMax": "10000000",
+            "withdrawMin": "10", "sameAddress": false}]}]"#;
+        let coins: Vec<RawCoinConfig> = serde_json::from_str(raw).unwrap();
+        let network: CoinNetwork = coins[0].network_list[0].clone().into();
+        assert_eq!(network.network, "TRX");
+        assert_eq!(network.withdraw_fee, 1.0);
+        assert_eq!(network.withdraw_min, 10.0);
+        assert!(!network.memo_required);
+
+        let raw = r#"[{"id": "b6ae22b3aa844210a7041aee7589627c", "amount": "8.91000000",
+            "transactionFee": "0.004", "coin": "USDT", "status": 6,
+            "address": "0x94df8b352de7f46f64b01d3666bf6e936e44ce60",
+            "txId": "0xb5ef8c13b968a406cc62a93a8bd80f9e9a906ef1b3fcf20a2e48573c17659268",
+            "applyTime": "2019-10-12 11:12:02", "network": "ETH", "transferType": 0}]"#;
+        let withdrawals: Vec<RawWithdrawal> = serde_json::from_str(raw).unwrap();
+        let withdrawal: Withdrawal = withdrawals[0].clone().into();
+        assert_eq!(withdrawal.status, WithdrawStatus::Completed);
+        assert_eq!(withdrawal.fee, 0.004);
+        assert_eq!(withdrawal.timestamp, 1570878722000);
+    }
+
+    #[test]
+    fn test_fee_rates() {
+        let raw =
+            r#"[{"symbol": "ADABNB", "makerCommission": "0.001", "takerCommission": "0.0015"}]"#;
+        let fees: Vec<RawTradeFee> = serde_json::from_str(raw).unwrap();
+        let fee: FeeRate = fees[0].clone().into();
+        assert_eq!((fee.maker, fee.taker), (0.001, 0.0015));
+
+        let raw = r#"{"symbol": "BTCUSDT", "makerCommissionRate": "0.0002", "takerCommissionRate": "0.0004"}"#;
+        let fee: FeeRate = serde_json::from_str::<RawCommissionRate>(raw)
+            .unwrap()
+            .into();
+        assert_eq!(fee.symbol, "BTCUSDT");
+        assert_eq!((fee.maker, fee.taker), (0.0002, 0.0004));
+    }
+
+    #[test]
+    fn test_depth_update_event() {
+        let raw = r#"{"e": "depthUpdate", "E": 123456789, "s": "BNBBTC", "U": 157, "u": 160,
+            "b": [["0.0024", "10"]], "a": [["0.0026", 100], [0.0027, "1.5"]]}"#;
+        let orderbook: Orderbook = serde_json::from_str::<DepthOrderbookEvent>(raw)
+            .unwrap()
+            .into();
+        assert_eq!(orderbook.last_update_id, 160);
+        assert_eq!(orderbook.bids[0].price, 0.0024);
+        assert_eq!(orderbook.asks[0].amount, 100.0);
+        assert_eq!(orderbook.asks[1].price, 0.0027);
+        assert!(serde_json::from_str::<RawBid>(r#"["0.1", null]"#).is_err());
+    }
+
+    #[test]
+    fn test_raw_order_into_order() {
+        let raw = r#"{
+            "symbol": "LTCBTC", "orderId": 1, "orderListId": -1, "clientOrderId": "myOrder1",
+            "price": "0.1", "origQty": "1.0", "executedQty": "0.5",
+            "cummulativeQuoteQty": "0.04", "status": "PARTIALLY_FILLED", "timeInForce": "GTC",
+            "type": "LIMIT", "side": "BUY", "stopPrice": "0.0", "icebergQty": "0.0",
+            "time": 1499827319559, "updateTime": 1499827319600, "isWorking": true
+        }"#;
+        let order: Order = serde_json::from_str::<RawOrder>(raw).unwrap().into();
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(order.filled, 0.5);
+        assert!((order.avg_price - 0.08).abs() < 1e-9);
+        assert_eq!(order.create_time, 1499827319559);
+        assert_eq!(order.update_time, 1499827319600);
+    }
+}
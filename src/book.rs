@@ -0,0 +1,564 @@
+use crate::checksum::BookChecksum;
+use crate::errors::*;
+use crate::models::*;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// f64 price usable as a map key, exchanges never send NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+type Levels = BTreeMap<Price, f64>;
+
+// (price, amount) exactly as received, keyed like Levels
+type RawLevels = BTreeMap<Price, (String, String)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeltaKind {
+    Added,
+    Changed,
+    Removed,
+}
+
+// normalized change of one level, amount is 0 for a removed level
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BookDelta {
+    pub side: BookSide,
+    pub kind: DeltaKind,
+    pub price: f64,
+    pub amount: f64,
+}
+
+// immutable view of a LocalBook, taking one is two Arc clones. the book only
+// copies a side when it is written while a snapshot still holds it
+#[derive(Debug, Clone, Default)]
+pub struct BookSnapshot {
+    pub last_update_id: u64,
+    pub timestamp: u64,
+    bids: Arc<Levels>,
+    asks: Arc<Levels>,
+}
+
+impl BookSnapshot {
+    pub fn best_bid(&self) -> Option<Bid> {
+        self.bids.iter().next_back().map(|(price, amount)| Bid {
+            price: price.0,
+            amount: *amount,
+        })
+    }
+
+    pub fn best_ask(&self) -> Option<Ask> {
+        self.asks.iter().next().map(|(price, amount)| Ask {
+            price: price.0,
+            amount: *amount,
+        })
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    // (price, amount) from the best bid down
+    pub fn bids(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.bids
+            .iter()
+            .rev()
+            .map(|(price, amount)| (price.0, *amount))
+    }
+
+    // (price, amount) from the best ask up
+    pub fn asks(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.asks.iter().map(|(price, amount)| (price.0, *amount))
+    }
+
+    pub fn bid_depth(&self) -> usize {
+        self.bids.len()
+    }
+
+    pub fn ask_depth(&self) -> usize {
+        self.asks.len()
+    }
+
+    // best `depth` levels of each side
+    pub fn to_orderbook(&self, depth: usize) -> Orderbook {
+        Orderbook {
+            timestamp: self.timestamp,
+            last_update_id: self.last_update_id,
+            bids: self
+                .bids()
+                .take(depth)
+                .map(|(price, amount)| Bid { price, amount })
+                .collect(),
+            asks: self
+                .asks()
+                .take(depth)
+                .map(|(price, amount)| Ask { price, amount })
+                .collect(),
+        }
+    }
+}
+
+// depth snapshot or update with the levels as the exchange sent them, needed
+// by exchanges whose checksum is computed over the original strings
+#[derive(Debug, Clone, Default)]
+pub struct RawDepth {
+    pub last_update_id: u64,
+    pub timestamp: u64,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+    pub checksum: Option<i64>,
+}
+
+impl RawDepth {
+    fn to_orderbook(&self) -> APIResult<Orderbook> {
+        let mut bids = vec![];
+        for (price, amount) in self.bids.iter() {
+            bids.push(Bid {
+                price: price.parse()?,
+                amount: amount.parse()?,
+            });
+        }
+        let mut asks = vec![];
+        for (price, amount) in self.asks.iter() {
+            asks.push(Ask {
+                price: price.parse()?,
+                amount: amount.parse()?,
+            });
+        }
+        Ok(Orderbook {
+            timestamp: self.timestamp,
+            last_update_id: self.last_update_id,
+            bids,
+            asks,
+        })
+    }
+}
+
+// orderbook kept up to date from a snapshot and diff depth updates, a level
+// update is O(log n) whatever the depth
+//
+//     let mut book = LocalBook::from_snapshot(&api.get_depth_snapshot("BTCUSDT", 1000)?);
+//     // for every DepthUpdateEvent
+//     book.apply(&update);
+//
+// books fed with reset_raw and apply_raw also keep the original strings of
+// every level so the exchange checksum can be verified
+//
+//     let mut book = LocalBook::new();
+//     book.reset_raw(&snapshot)?;
+//     // for every update, refetches the snapshot when the checksum mismatches
+//     book.apply_checked(&update, BookChecksum::Okx, || fetch_snapshot())?;
+#[derive(Debug, Clone, Default)]
+pub struct LocalBook {
+    book: BookSnapshot,
+    raw_bids: RawLevels,
+    raw_asks: RawLevels,
+}
+
+impl LocalBook {
+    pub fn new() -> Self {
+        LocalBook::default()
+    }
+
+    pub fn from_snapshot(orderbook: &Orderbook) -> Self {
+        let mut book = LocalBook::new();
+        book.reset(orderbook);
+        book
+    }
+
+    // replaces every level, e.g. after a sequence gap
+    pub fn reset(&mut self, orderbook: &Orderbook) {
+        let bids = orderbook
+            .bids
+            .iter()
+            .filter(|bid| bid.amount > 0.0)
+            .map(|bid| (Price(bid.price), bid.amount))
+            .collect();
+        let asks = orderbook
+            .asks
+            .iter()
+            .filter(|ask| ask.amount > 0.0)
+            .map(|ask| (Price(ask.price), ask.amount))
+            .collect();
+        self.book = BookSnapshot {
+            last_update_id: orderbook.last_update_id,
+            timestamp: orderbook.timestamp,
+            bids: Arc::new(bids),
+            asks: Arc::new(asks),
+        };
+        self.raw_bids.clear();
+        self.raw_asks.clear();
+    }
+
+    // like reset, keeping the original strings
+    pub fn reset_raw(&mut self, snapshot: &RawDepth) -> APIResult<()> {
+        let orderbook = snapshot.to_orderbook()?;
+        self.reset(&orderbook);
+        for (bid, raw) in orderbook.bids.iter().zip(snapshot.bids.iter()) {
+            update_raw_level(&mut self.raw_bids, bid.price, bid.amount, raw);
+        }
+        for (ask, raw) in orderbook.asks.iter().zip(snapshot.asks.iter()) {
+            update_raw_level(&mut self.raw_asks, ask.price, ask.amount, raw);
+        }
+        Ok(())
+    }
+
+    // like apply_with_deltas, keeping the original strings. nothing is
+    // applied when a level doesn't parse
+    pub fn apply_raw(&mut self, update: &RawDepth) -> APIResult<Option<Vec<BookDelta>>> {
+        let orderbook = update.to_orderbook()?;
+        let deltas = match self.apply_with_deltas(&orderbook) {
+            Some(deltas) => deltas,
+            None => return Ok(None),
+        };
+        for (bid, raw) in orderbook.bids.iter().zip(update.bids.iter()) {
+            update_raw_level(&mut self.raw_bids, bid.price, bid.amount, raw);
+        }
+        for (ask, raw) in orderbook.asks.iter().zip(update.asks.iter()) {
+            update_raw_level(&mut self.raw_asks, ask.price, ask.amount, raw);
+        }
+        Ok(Some(deltas))
+    }
+
+    // checksum of the levels kept by reset_raw and apply_raw
+    pub fn checksum(&self, kind: BookChecksum) -> i64 {
+        let depth = kind.depth();
+        let bids: Vec<(&str, &str)> = self
+            .raw_bids
+            .values()
+            .rev()
+            .take(depth)
+            .map(|(price, amount)| (price.as_str(), amount.as_str()))
+            .collect();
+        let asks: Vec<(&str, &str)> = self
+            .raw_asks
+            .values()
+            .take(depth)
+            .map(|(price, amount)| (price.as_str(), amount.as_str()))
+            .collect();
+        kind.compute(&bids, &asks)
+    }
+
+    // apply_raw then compares the book with the checksum the update carries,
+    // on a mismatch the book is rebuilt from the snapshot `resync` returns.
+    // returns whether it resynced
+    pub fn apply_checked<F>(
+        &mut self,
+        update: &RawDepth,
+        kind: BookChecksum,
+        resync: F,
+    ) -> APIResult<bool>
+    where
+        F: FnOnce() -> APIResult<RawDepth>,
+    {
+        if self.apply_raw(update)?.is_none() {
+            return Ok(false);
+        }
+        match update.checksum {
+            Some(expected) if self.checksum(kind) != expected => {
+                self.reset_raw(&resync()?)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // like reset, returning how every level changed
+    pub fn reset_with_deltas(&mut self, orderbook: &Orderbook) -> Vec<BookDelta> {
+        let before = self.snapshot();
+        self.reset(orderbook);
+        let mut deltas = diff_levels(BookSide::Bid, &before.bids, &self.book.bids);
+        deltas.extend(diff_levels(BookSide::Ask, &before.asks, &self.book.asks));
+        deltas
+    }
+
+    // applies a diff where a zero amount removes the level. updates already
+    // covered by the book (last update id not newer) are skipped, returns
+    // whether it was applied
+    pub fn apply(&mut self, update: &Orderbook) -> bool {
+        self.apply_with_deltas(update).is_some()
+    }
+
+    // like apply, returning the levels that actually changed or None when
+    // the update was skipped
+    pub fn apply_with_deltas(&mut self, update: &Orderbook) -> Option<Vec<BookDelta>> {
+        if update.last_update_id != 0 && update.last_update_id <= self.book.last_update_id {
+            return None;
+        }
+        let mut deltas = vec![];
+        for bid in update.bids.iter() {
+            deltas.extend(self.update_bid(bid.price, bid.amount));
+        }
+        for ask in update.asks.iter() {
+            deltas.extend(self.update_ask(ask.price, ask.amount));
+        }
+        self.book.last_update_id = update.last_update_id;
+        self.book.timestamp = update.timestamp;
+        Some(deltas)
+    }
+
+    pub fn update_bid(&mut self, price: f64, amount: f64) -> Option<BookDelta> {
+        let levels = Arc::make_mut(&mut self.book.bids);
+        let kind = update_level(levels, price, amount)?;
+        Some(BookDelta {
+            side: BookSide::Bid,
+            kind,
+            price,
+            amount: amount.max(0.0),
+        })
+    }
+
+    pub fn update_ask(&mut self, price: f64, amount: f64) -> Option<BookDelta> {
+        let levels = Arc::make_mut(&mut self.book.asks);
+        let kind = update_level(levels, price, amount)?;
+        Some(BookDelta {
+            side: BookSide::Ask,
+            kind,
+            price,
+            amount: amount.max(0.0),
+        })
+    }
+
+    pub fn snapshot(&self) -> BookSnapshot {
+        self.book.clone()
+    }
+}
+
+impl std::ops::Deref for LocalBook {
+    type Target = BookSnapshot;
+
+    fn deref(&self) -> &BookSnapshot {
+        &self.book
+    }
+}
+
+// None when the level is unchanged
+fn update_level(levels: &mut Levels, price: f64, amount: f64) -> Option<DeltaKind> {
+    if amount > 0.0 {
+        match levels.insert(Price(price), amount) {
+            None => Some(DeltaKind::Added),
+            Some(previous) if previous != amount => Some(DeltaKind::Changed),
+            Some(_) => None,
+        }
+    } else {
+        levels.remove(&Price(price)).map(|_| DeltaKind::Removed)
+    }
+}
+
+fn update_raw_level(levels: &mut RawLevels, price: f64, amount: f64, raw: &(String, String)) {
+    if amount > 0.0 {
+        levels.insert(Price(price), raw.clone());
+    } else {
+        levels.remove(&Price(price));
+    }
+}
+
+fn diff_levels(side: BookSide, before: &Levels, after: &Levels) -> Vec<BookDelta> {
+    let delta = |kind, price: &Price, amount| BookDelta {
+        side,
+        kind,
+        price: price.0,
+        amount,
+    };
+    let mut deltas = vec![];
+    for (price, amount) in after.iter() {
+        match before.get(price) {
+            None => deltas.push(delta(DeltaKind::Added, price, *amount)),
+            Some(previous) if previous != amount => {
+                deltas.push(delta(DeltaKind::Changed, price, *amount))
+            }
+            Some(_) => {}
+        }
+    }
+    for price in before.keys().filter(|price| !after.contains_key(price)) {
+        deltas.push(delta(DeltaKind::Removed, price, 0.0));
+    }
+    deltas
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn orderbook(last_update_id: u64, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> Orderbook {
+        Orderbook {
+            timestamp: last_update_id * 10,
+            last_update_id,
+            bids: bids
+                .iter()
+                .map(|&(price, amount)| Bid { price, amount })
+                .collect(),
+            asks: asks
+                .iter()
+                .map(|&(price, amount)| Ask { price, amount })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_local_book() {
+        let mut book = LocalBook::from_snapshot(&orderbook(
+            100,
+            &[(99.0, 1.0), (98.0, 2.0), (97.0, 3.0)],
+            &[(101.0, 1.0), (102.0, 2.0)],
+        ));
+        assert_eq!(book.best_bid().unwrap().price, 99.0);
+        assert_eq!(book.best_ask().unwrap().price, 101.0);
+        assert_eq!(book.mid(), Some(100.0));
+        let before = book.snapshot();
+
+        // already in the snapshot
+        assert!(!book.apply(&orderbook(100, &[(99.0, 0.0)], &[])));
+        assert!(book.apply(&orderbook(
+            101,
+            &[(99.0, 0.0), (98.5, 4.0)],
+            &[(100.5, 1.5), (102.0, 0.0)]
+        )));
+        assert_eq!(book.last_update_id, 101);
+        assert_eq!(
+            book.bids().collect::<Vec<(f64, f64)>>(),
+            vec![(98.5, 4.0), (98.0, 2.0), (97.0, 3.0)]
+        );
+        assert_eq!(book.spread(), Some(2.0));
+        let top = book.to_orderbook(1);
+        assert_eq!(top.bids.len(), 1);
+        assert_eq!(top.asks[0].price, 100.5);
+
+        // snapshots don't see later updates
+        assert_eq!(before.last_update_id, 100);
+        assert_eq!(before.best_bid().unwrap().price, 99.0);
+        assert_eq!(before.ask_depth(), 2);
+    }
+
+    #[test]
+    fn test_book_deltas() {
+        let mut book = LocalBook::from_snapshot(&orderbook(1, &[(99.0, 1.0)], &[(101.0, 1.0)]));
+        let deltas = book
+            .apply_with_deltas(&orderbook(
+                2,
+                &[(99.0, 2.0), (98.0, 1.0), (97.0, 0.0)],
+                &[(101.0, 0.0), (102.0, 1.0)],
+            ))
+            .unwrap();
+        let kinds: Vec<(BookSide, DeltaKind, f64)> =
+            deltas.iter().map(|d| (d.side, d.kind, d.price)).collect();
+        // removing the absent 97 level is not a change
+        assert_eq!(
+            kinds,
+            vec![
+                (BookSide::Bid, DeltaKind::Changed, 99.0),
+                (BookSide::Bid, DeltaKind::Added, 98.0),
+                (BookSide::Ask, DeltaKind::Removed, 101.0),
+                (BookSide::Ask, DeltaKind::Added, 102.0),
+            ]
+        );
+        assert!(book.apply_with_deltas(&orderbook(2, &[], &[])).is_none());
+
+        let deltas = book.reset_with_deltas(&orderbook(3, &[(99.0, 2.0)], &[(102.0, 3.0)]));
+        assert_eq!(
+            deltas,
+            vec![
+                BookDelta {
+                    side: BookSide::Bid,
+                    kind: DeltaKind::Removed,
+                    price: 98.0,
+                    amount: 0.0
+                },
+                BookDelta {
+                    side: BookSide::Ask,
+                    kind: DeltaKind::Changed,
+                    price: 102.0,
+                    amount: 3.0
+                },
+            ]
+        );
+    }
+
+    fn raw_depth(last_update_id: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> RawDepth {
+        let levels = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(price, amount)| (price.to_string(), amount.to_string()))
+                .collect()
+        };
+        RawDepth {
+            last_update_id,
+            timestamp: last_update_id * 10,
+            bids: levels(bids),
+            asks: levels(asks),
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_checked() {
+        let mut book = LocalBook::new();
+        book.reset_raw(&raw_depth(
+            1,
+            &[("3366.1", "7"), ("3366", "6"), ("3365", "1")],
+            &[("3366.8", "9"), ("3368", "8")],
+        ))
+        .unwrap();
+
+        // the checksum covers the strings as received, 3366 not 3366.0
+        let mut update = raw_depth(2, &[("3365", "0")], &[]);
+        update.checksum = Some(-1881014294);
+        let resynced = book
+            .apply_checked(&update, BookChecksum::Okx, || panic!("no resync"))
+            .unwrap();
+        assert!(!resynced);
+        assert_eq!(book.checksum(BookChecksum::Okx), -1881014294);
+        assert_eq!(book.bid_depth(), 2);
+
+        // a mismatch rebuilds the book from a fresh snapshot
+        let mut update = raw_depth(3, &[("3366", "5")], &[]);
+        update.checksum = Some(1);
+        let resynced = book
+            .apply_checked(&update, BookChecksum::Okx, || {
+                Ok(raw_depth(4, &[("3366", "4")], &[("3367", "2")]))
+            })
+            .unwrap();
+        assert!(resynced);
+        assert_eq!(book.last_update_id, 4);
+        assert_eq!(
+            book.bids().collect::<Vec<(f64, f64)>>(),
+            vec![(3366.0, 4.0)]
+        );
+        assert_eq!(
+            book.checksum(BookChecksum::Okx),
+            BookChecksum::Okx.compute(&[("3366", "4")], &[("3367", "2")])
+        );
+
+        // a level that doesn't parse leaves the book untouched
+        assert!(book.apply_raw(&raw_depth(5, &[("x", "1")], &[])).is_err());
+        assert_eq!(book.last_update_id, 4);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::errors::*;
+use crate::utils::Pending;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// what a public GET returned, NotModified answers an If-None-Match
+pub enum Fetched {
+    Body { body: String, etag: Option<String> },
+    NotModified,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    // answered without a request
+    pub hits: u64,
+    pub misses: u64,
+    // stale entries the server confirmed unchanged
+    pub not_modified: u64,
+}
+
+struct Entry {
+    body: String,
+    etag: Option<String>,
+    fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    // by endpoint path, endpoints without a ttl are never cached
+    ttls: HashMap<String, Duration>,
+    // by client name and path with query
+    entries: HashMap<String, Entry>,
+    stats: CacheStats,
+}
+
+// caches the responses of public GETs for a while, so the components of a
+// process sharing one cache don't refetch exchange info or tickers they
+// all need. once stale an entry with an ETag is revalidated with
+// If-None-Match, the body is kept when the server answers 304. clones share
+// the entries
+//
+//     let mut cache = ResponseCache::new();
+//     cache.set_ttl("/api/v3/exchangeInfo", Duration::from_secs(300));
+//     cache.set_ttl("/api/v3/ticker/bookTicker", Duration::from_millis(500));
+//     spot.set_cache(cache.clone());
+//     margin.set_cache(cache);
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ResponseCache({:?})", self.stats())
+    }
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        ResponseCache::default()
+    }
+
+    // endpoint is the path without the query, e.g. "/api/v3/exchangeInfo"
+    pub fn set_ttl(&mut self, endpoint: &str, ttl: Duration) {
+        self.inner.lock().unwrap().ttls.insert(endpoint.into(), ttl);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.inner.lock().unwrap().stats
+    }
+
+    // drops every entry of the endpoint, of all clients
+    pub fn invalidate(&self, endpoint: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.retain(|key, _| {
+            let path = key.split_once(' ').map_or(key.as_str(), |(_, path)| path);
+            path.split('?').next() != Some(endpoint)
+        });
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    // the cached body of path while fresh, fetch otherwise. fetch gets the
+    // ETag of the stale entry to send as If-None-Match. errors are not
+    // cached. the lock is not held while fetching
+    #[maybe_async::maybe_async]
+    pub async fn get_or_fetch<'a, F>(
+        &self,
+        client: &str,
+        endpoint: &str,
+        path: &str,
+        fetch: F,
+    ) -> APIResult<String>
+    where
+        F: FnOnce(Option<String>) -> Pending<'a, APIResult<Fetched>>,
+    {
+        let key = format!("{} {}", client, path);
+        // the etag to revalidate with, None for endpoints without a ttl
+        let etag = {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.ttls.get(endpoint).copied() {
+                Some(ttl) => match inner.entries.get(&key) {
+                    Some(entry) if entry.fetched_at.elapsed() < ttl => {
+                        let body = entry.body.clone();
+                        inner.stats.hits += 1;
+                        return Ok(body);
+                    }
+                    Some(entry) => Some(entry.etag.clone()),
+                    None => Some(None),
+                },
+                None => None,
+            }
+        };
+        let etag = match etag {
+            Some(etag) => etag,
+            None => {
+                return match fetch(None).await? {
+                    Fetched::Body { body, .. } => Ok(body),
+                    Fetched::NotModified => Err(not_modified_error(path)),
+                }
+            }
+        };
+        let fetched = fetch(etag).await?;
+        let mut inner = self.inner.lock().unwrap();
+        match fetched {
+            Fetched::Body { body, etag } => {
+                inner.stats.misses += 1;
+                inner.entries.insert(
+                    key,
+                    Entry {
+                        body: body.clone(),
+                        etag,
+                        fetched_at: Instant::now(),
+                    },
+                );
+                Ok(body)
+            }
+            Fetched::NotModified => {
+                let entry = match inner.entries.get_mut(&key) {
+                    Some(entry) => entry,
+                    // cleared while fetching
+                    None => return Err(not_modified_error(path)),
+                };
+                entry.fetched_at = Instant::now();
+                let body = entry.body.clone();
+                inner.stats.not_modified += 1;
+                Ok(body)
+            }
+        }
+    }
+}
+
+fn not_modified_error(path: &str) -> Box<dyn std::error::Error> {
+    Box::new(ExError::ApiError(format!(
+        "304 without a cached body: {}",
+        path
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::thread;
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_response_cache() {
+        let mut cache = ResponseCache::new();
+        cache.set_ttl("/api/v3/exchangeInfo", Duration::from_millis(30));
+        let shared = cache.clone();
+        let fetches = Cell::new(0);
+        let fetch = |etag: Option<String>| -> Pending<APIResult<Fetched>> {
+            fetches.set(fetches.get() + 1);
+            Box::pin(async move {
+                match etag.as_deref() {
+                    Some("v1") => Ok(Fetched::NotModified),
+                    _ => Ok(Fetched::Body {
+                        body: "info".into(),
+                        etag: Some("v1".into()),
+                    }),
+                }
+            })
+        };
+
+        let path = "/api/v3/exchangeInfo";
+        let body = cache
+            .get_or_fetch("binance", path, path, fetch)
+            .await
+            .unwrap();
+        assert_eq!(body, "info");
+        let body = shared
+            .get_or_fetch("binance", path, path, fetch)
+            .await
+            .unwrap();
+        assert_eq!(body, "info");
+        assert_eq!(fetches.get(), 1);
+        // another client, another entry
+        shared
+            .get_or_fetch("binance_swap", path, path, fetch)
+            .await
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+
+        thread::sleep(Duration::from_millis(40));
+        let body = cache
+            .get_or_fetch("binance", path, path, fetch)
+            .await
+            .unwrap();
+        assert_eq!(body, "info");
+        assert_eq!(fetches.get(), 3);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                not_modified: 1
+            }
+        );
+
+        // no ttl, always fetched
+        let depth = "/api/v3/depth?symbol=BTCUSDT";
+        cache
+            .get_or_fetch("binance", "/api/v3/depth", depth, fetch)
+            .await
+            .unwrap();
+        cache
+            .get_or_fetch("binance", "/api/v3/depth", depth, fetch)
+            .await
+            .unwrap();
+        assert_eq!(fetches.get(), 5);
+
+        let failed = cache
+            .get_or_fetch("huobi", path, path, |_| {
+                Box::pin(async { Err(ExError::ApiError("down".into()).into()) })
+            })
+            .await;
+        assert!(failed.is_err());
+        cache.invalidate(path);
+        cache
+            .get_or_fetch("binance", path, path, fetch)
+            .await
+            .unwrap();
+        assert_eq!(fetches.get(), 6);
+        assert_eq!(cache.stats().misses, 3);
+    }
+}
@@ -0,0 +1,99 @@
+// orderbook checksums pushed by okx and kraken with every depth update,
+// levels are (price, amount) exactly as received since the checksum is
+// computed over the original strings. there are no okx or kraken clients
+// yet, LocalBook::apply_checked validates a locally maintained book with them
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookChecksum {
+    Okx,
+    Kraken,
+}
+
+impl BookChecksum {
+    // okx sends a signed and kraken an unsigned 32 bit value, both fit in i64
+    pub fn compute(&self, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> i64 {
+        match self {
+            BookChecksum::Okx => okx_checksum(bids, asks) as i64,
+            BookChecksum::Kraken => kraken_checksum(bids, asks) as i64,
+        }
+    }
+
+    // levels of each side the checksum covers
+    pub fn depth(&self) -> usize {
+        match self {
+            BookChecksum::Okx => 25,
+            BookChecksum::Kraken => 10,
+        }
+    }
+}
+
+// okx: up to 25 levels, bid and ask interleaved as `bid_px:bid_sz:ask_px:ask_sz`,
+// crc32 interpreted as a signed 32 bit integer
+pub fn okx_checksum(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> i32 {
+    let mut fields = vec![];
+    for i in 0..25 {
+        if let Some((price, amount)) = bids.get(i) {
+            fields.push(*price);
+            fields.push(*amount);
+        }
+        if let Some((price, amount)) = asks.get(i) {
+            fields.push(*price);
+            fields.push(*amount);
+        }
+    }
+    crc32fast::hash(fields.join(":").as_bytes()) as i32
+}
+
+// kraken: top 10 asks then top 10 bids, each price and amount with the
+// decimal point and leading zeros removed
+pub fn kraken_checksum(bids: &[(&str, &str)], asks: &[(&str, &str)]) -> u32 {
+    let strip = |s: &str| s.replace('.', "").trim_start_matches('0').to_string();
+    let payload = asks
+        .iter()
+        .take(10)
+        .chain(bids.iter().take(10))
+        .map(|(price, amount)| format!("{}{}", strip(price), strip(amount)))
+        .collect::<String>();
+    crc32fast::hash(payload.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_okx_checksum() {
+        let bids = [("3366.1", "7"), ("3366", "6")];
+        let asks = [("3366.8", "9"), ("3368", "8")];
+        assert_eq!(okx_checksum(&bids, &asks), -1881014294);
+    }
+
+    #[test]
+    fn test_kraken_checksum() {
+        let asks = [
+            ("0.05005", "0.00000500"),
+            ("0.05010", "0.00000500"),
+            ("0.05015", "0.00000500"),
+            ("0.05020", "0.00000500"),
+            ("0.05025", "0.00000500"),
+            ("0.05030", "0.00000500"),
+            ("0.05035", "0.00000500"),
+            ("0.05040", "0.00000500"),
+            ("0.05045", "0.00000500"),
+            ("0.05050", "0.00000500"),
+        ];
+        let bids = [
+            ("0.05000", "0.00000500"),
+            ("0.04995", "0.00000500"),
+            ("0.04990", "0.00000500"),
+            ("0.04980", "0.00000500"),
+            ("0.04975", "0.00000500"),
+            ("0.04970", "0.00000500"),
+            ("0.04965", "0.00000500"),
+            ("0.04960", "0.00000500"),
+            ("0.04955", "0.00000500"),
+            ("0.04950", "0.00000500"),
+        ];
+        assert_eq!(kraken_checksum(&bids, &asks), 974947235);
+    }
+}
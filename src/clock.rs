@@ -0,0 +1,190 @@
+use crate::errors::*;
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// milliseconds since the unix epoch, read by every timestamp the crate
+// puts in a request
+pub trait TimeSource: Send + Sync {
+    fn now_ms(&self) -> u64;
+}
+
+lazy_static! {
+    static ref SOURCE: RwLock<Arc<dyn TimeSource>> = RwLock::new(Arc::new(SystemClock));
+}
+
+// the source used by signed requests of every client, the system clock by
+// default. a backtest sets a ManualClock, a bot an OffsetClock synced with
+// the exchange
+pub fn set_time_source(source: Arc<dyn TimeSource>) {
+    *SOURCE.write().unwrap() = source;
+}
+
+pub fn time_source() -> Arc<dyn TimeSource> {
+    SOURCE.read().unwrap().clone()
+}
+
+pub fn now_ms() -> u64 {
+    SOURCE.read().unwrap().now_ms()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+
+    // std has no clock on wasm32-unknown-unknown
+    #[cfg(target_arch = "wasm32")]
+    fn now_ms(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+// system time when created, advanced by the monotonic clock so it never goes
+// backwards when the wall clock is stepped
+#[derive(Debug, Clone)]
+pub struct MonotonicClock {
+    base_ms: u64,
+    base: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        MonotonicClock {
+            base_ms: SystemClock.now_ms(),
+            base: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+impl TimeSource for MonotonicClock {
+    fn now_ms(&self) -> u64 {
+        self.base_ms + self.base.elapsed().as_millis() as u64
+    }
+}
+
+// another clock shifted to the exchange's time, avoids "timestamp outside
+// recvWindow" rejections on hosts with a drifting clock
+pub struct OffsetClock<T> {
+    inner: T,
+    offset_ms: AtomicI64,
+}
+
+impl<T: TimeSource> OffsetClock<T> {
+    pub fn new(inner: T) -> Self {
+        OffsetClock {
+            inner,
+            offset_ms: AtomicI64::new(0),
+        }
+    }
+
+    pub fn offset_ms(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset_ms(&self, offset_ms: i64) {
+        self.offset_ms.store(offset_ms, Ordering::Relaxed);
+    }
+
+    // sets the offset from a server time request, assuming the server read
+    // its clock halfway through the round trip
+    //
+    //     clock.sync(|| api.get_server_time())?;
+    pub fn sync<F>(&self, server_time: F) -> APIResult<i64>
+    where
+        F: FnOnce() -> APIResult<u64>,
+    {
+        let sent = self.inner.now_ms();
+        let server = server_time()?;
+        let received = self.inner.now_ms();
+        let offset = server as i64 - (sent + (received.saturating_sub(sent)) / 2) as i64;
+        self.set_offset_ms(offset);
+        Ok(offset)
+    }
+}
+
+impl<T: TimeSource> TimeSource for OffsetClock<T> {
+    fn now_ms(&self) -> u64 {
+        (self.inner.now_ms() as i64 + self.offset_ms()).max(0) as u64
+    }
+}
+
+// set by hand, for tests and backtests replaying history
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now_ms: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now_ms: u64) -> Self {
+        ManualClock {
+            now_ms: AtomicU64::new(now_ms),
+        }
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for Arc<T> {
+    fn now_ms(&self) -> u64 {
+        (**self).now_ms()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clocks() {
+        let system = SystemClock.now_ms();
+        let monotonic = MonotonicClock::new();
+        assert!(monotonic.now_ms() >= system);
+        assert!(monotonic.now_ms() - system < 1000);
+
+        let manual = Arc::new(ManualClock::new(1000));
+        manual.advance(500);
+        assert_eq!(manual.now_ms(), 1500);
+
+        let clock = OffsetClock::new(manual.clone());
+        // the server answers 2000 while the local clock moves 1500 -> 1540
+        let offset = clock
+            .sync(|| {
+                manual.advance(40);
+                Ok(2000)
+            })
+            .unwrap();
+        assert_eq!(offset, 480);
+        assert_eq!(clock.now_ms(), 2020);
+        clock.set_offset_ms(-5000);
+        assert_eq!(clock.now_ms(), 0);
+    }
+}
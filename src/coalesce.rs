@@ -0,0 +1,217 @@
+use crate::errors::*;
+use crate::http;
+use crate::utils::Pending;
+
+#[cfg(not(feature = "blocking"))]
+use futures_channel::oneshot;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+type Shared = Result<String, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Default)]
+struct Call {
+    result: Mutex<Option<Shared>>,
+    done: Condvar,
+    // woken with done, the async flavor can't block on the condvar
+    #[cfg(not(feature = "blocking"))]
+    waiters: Mutex<Vec<oneshot::Sender<()>>>,
+}
+
+impl Call {
+    fn shared(&self) -> APIResult<String> {
+        match self.result.lock().unwrap().as_ref().unwrap() {
+            Ok(body) => Ok(body.clone()),
+            Err(err) => Err(copy_error(err.as_ref())),
+        }
+    }
+}
+
+#[maybe_async::sync_impl]
+fn wait(call: &Call) -> APIResult<String> {
+    let mut result = call.result.lock().unwrap();
+    while result.is_none() {
+        result = call.done.wait(result).unwrap();
+    }
+    drop(result);
+    call.shared()
+}
+
+#[maybe_async::async_impl]
+async fn wait(call: &Call) -> APIResult<String> {
+    let done = {
+        let result = call.result.lock().unwrap();
+        if result.is_some() {
+            None
+        } else {
+            let (tx, rx) = oneshot::channel();
+            call.waiters.lock().unwrap().push(tx);
+            Some(rx)
+        }
+    };
+    if let Some(done) = done {
+        let _ = done.await;
+    }
+    call.shared()
+}
+
+#[derive(Default)]
+struct Inner {
+    // by client name and path with query
+    calls: Mutex<HashMap<String, Arc<Call>>>,
+    coalesced: Mutex<u64>,
+}
+
+// merges identical public GETs in flight at the same time into one request,
+// the callers that arrive while it is running wait and get its result.
+// nothing is kept once it finishes, see cache::ResponseCache for that.
+// clones share the calls in flight
+//
+//     let coalescer = Coalescer::new();
+//     spot.set_coalescer(coalescer.clone());
+//     // ten threads asking for the BTCUSDT book at once cost one request
+//     let api = Arc::new(spot);
+#[derive(Clone, Default)]
+pub struct Coalescer {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for Coalescer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Coalescer({})", self.coalesced())
+    }
+}
+
+// the first caller of a key runs it, removes the call on the way out even if
+// the request panics so the waiters are not stuck
+struct Leader<'a> {
+    inner: &'a Inner,
+    key: &'a str,
+    call: Arc<Call>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.inner.calls.lock().unwrap().remove(self.key);
+        let mut result = self.call.result.lock().unwrap();
+        if result.is_none() {
+            *result = Some(Err(Box::new(ExError::ApiError(format!(
+                "coalesced request failed: {}",
+                self.key
+            )))));
+        }
+        self.call.done.notify_all();
+        #[cfg(not(feature = "blocking"))]
+        for waiter in self.call.waiters.lock().unwrap().drain(..) {
+            let _ = waiter.send(());
+        }
+    }
+}
+
+impl Coalescer {
+    pub fn new() -> Self {
+        Coalescer::default()
+    }
+
+    // callers that got the result of another one's request
+    pub fn coalesced(&self) -> u64 {
+        *self.inner.coalesced.lock().unwrap()
+    }
+
+    // runs send unless a call of the same key is in flight, then waits for
+    // its result. errors are shared too, as copies
+    #[maybe_async::maybe_async]
+    pub async fn run<'a, F>(&self, key: &str, send: F) -> APIResult<String>
+    where
+        F: FnOnce() -> Pending<'a, APIResult<String>>,
+    {
+        let (call, leader) = {
+            let mut calls = self.inner.calls.lock().unwrap();
+            match calls.get(key) {
+                Some(call) => (call.clone(), false),
+                None => {
+                    let call = Arc::new(Call::default());
+                    calls.insert(key.into(), call.clone());
+                    (call, true)
+                }
+            }
+        };
+        if leader {
+            let leader = Leader {
+                inner: &self.inner,
+                key,
+                call,
+            };
+            let ret = send().await;
+            *leader.call.result.lock().unwrap() = Some(match &ret {
+                Ok(body) => Ok(body.clone()),
+                Err(err) => Err(copy_error(err.as_ref())),
+            });
+            return ret;
+        }
+        *self.inner.coalesced.lock().unwrap() += 1;
+        wait(&call).await
+    }
+}
+
+// keeps the type of the errors callers match on, the rest become ApiError
+fn copy_error(err: &(dyn std::error::Error + 'static)) -> Box<dyn std::error::Error + Send + Sync> {
+    if let Some(err) = err.downcast_ref::<ExError>() {
+        return Box::new(err.clone());
+    }
+    if let Some(err) = http::http_error(err) {
+        return Box::new(err.clone());
+    }
+    Box::new(ExError::ApiError(err.to_string()))
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_coalescer() {
+        let coalescer = Coalescer::new();
+        let sends = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(4));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let coalescer = coalescer.clone();
+                let sends = sends.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    coalescer
+                        .run("binance /api/v3/depth?symbol=BTCUSDT", || {
+                            sends.fetch_add(1, Ordering::SeqCst);
+                            thread::sleep(Duration::from_millis(50));
+                            Ok("book".to_string())
+                        })
+                        .map_err(|err| err.to_string())
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), "book");
+        }
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+        assert_eq!(coalescer.coalesced(), 3);
+
+        // done calls are not reused
+        let ret = coalescer.run("binance /api/v3/depth?symbol=BTCUSDT", || {
+            Err(Box::new(ExError::RateLimitExceeded("429".into())))
+        });
+        assert!(matches!(
+            ret.unwrap_err().downcast_ref::<ExError>(),
+            Some(ExError::RateLimitExceeded(_))
+        ));
+        assert_eq!(
+            copy_error(&ExError::IpBanned("418".into())).to_string(),
+            "IpBanned: 418"
+        );
+    }
+}
@@ -0,0 +1,539 @@
+use crate::binance::future_rest::BinanceSwap;
+use crate::binance::spot_rest::Binance;
+use crate::errors::*;
+use crate::hosts::{HostPool, Selection};
+use crate::huobi::spot_rest::Huobi;
+use crate::keystore::Keystore;
+use crate::limits::{shared_limiter, RateLimiter};
+use crate::models::*;
+use crate::traits::{FutureRest, SpotRest};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub exchanges: Vec<ExchangeConfig>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ExchangeConfig {
+    // key of the client in the registry
+    pub name: String,
+    // binance | binance_margin | binance_swap | huobi
+    pub exchange: String,
+    pub api_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub host: Option<String>,
+    // binance only, tried in order when the host times out
+    #[serde(default)]
+    pub fallback_hosts: Vec<String>,
+    // preference (default) or latency, see hosts::Selection
+    pub host_selection: Option<Selection>,
+    #[serde(default)]
+    pub network: Network,
+    pub proxy: Option<String>,
+    // name of the keystore entry holding api_key/secret_key
+    pub keystore: Option<String>,
+    // max requests per minute
+    pub rate_limit: Option<u32>,
+    // clients with the same group share one rate_limit budget, e.g. all
+    // clients behind one egress ip
+    pub rate_limit_group: Option<String>,
+    // additional key pairs to rotate signed requests over
+    #[serde(default)]
+    pub extra_keys: Vec<KeyConfig>,
+    // capabilities on top of reading, e.g. ["trade"], all when unset
+    pub permissions: Option<Vec<Capability>>,
+    // huobi only
+    pub account_type: Option<String>,
+    pub account_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct KeyConfig {
+    pub api_key: String,
+    pub secret_key: String,
+    // max requests per minute for this key
+    pub rate_limit: Option<u32>,
+}
+
+#[derive(Clone)]
+pub enum Client {
+    Binance(Binance),
+    BinanceSwap(BinanceSwap),
+    Huobi(Huobi),
+}
+
+pub type Registry = HashMap<String, Client>;
+
+// same calls on every client, balances differ between spot and futures
+// so get_balance returns json. every call is async without the blocking
+// feature
+#[maybe_async::maybe_async]
+impl Client {
+    pub async fn get_balance(&self, asset: &str) -> APIResult<serde_json::Value> {
+        Ok(match self {
+            Client::Binance(c) => serde_json::to_value(SpotRest::get_balance(c, asset).await?)?,
+            Client::BinanceSwap(c) => {
+                serde_json::to_value(FutureRest::get_balance(c, asset).await?)?
+            }
+            Client::Huobi(c) => serde_json::to_value(c.get_balance(asset).await?)?,
+        })
+    }
+
+    pub async fn create_order(&self, order: &OrderRequest) -> APIResult<String> {
+        match self {
+            Client::Binance(c) => SpotRest::create_order(c, order).await,
+            Client::BinanceSwap(c) => FutureRest::create_order(c, order).await,
+            Client::Huobi(c) => c.create_order(order).await,
+        }
+    }
+
+    pub async fn cancel(&self, symbol: &str, id: &str) -> APIResult<bool> {
+        match self {
+            Client::Binance(c) => SpotRest::cancel(c, symbol, id).await,
+            Client::BinanceSwap(c) => FutureRest::cancel(c, symbol, id).await,
+            Client::Huobi(c) => c.cancel(symbol, id).await,
+        }
+    }
+
+    pub async fn cancel_all(&self, symbol: &str) -> APIResult<bool> {
+        match self {
+            Client::Binance(c) => SpotRest::cancel_all(c, symbol).await,
+            Client::BinanceSwap(c) => FutureRest::cancel_all(c, symbol).await,
+            Client::Huobi(c) => c.cancel_all(symbol).await,
+        }
+    }
+
+    pub async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order> {
+        match self {
+            Client::Binance(c) => SpotRest::get_order(c, symbol, id).await,
+            Client::BinanceSwap(c) => FutureRest::get_order(c, symbol, id).await,
+            Client::Huobi(c) => c.get_order(symbol, id).await,
+        }
+    }
+
+    pub async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        match self {
+            Client::Binance(c) => SpotRest::get_open_orders(c, symbol).await,
+            Client::BinanceSwap(c) => FutureRest::get_open_orders(c, symbol).await,
+            Client::Huobi(c) => c.get_open_orders(symbol).await,
+        }
+    }
+
+    pub async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        match self {
+            Client::Binance(c) => SpotRest::get_history_orders(c, symbol).await,
+            Client::BinanceSwap(c) => FutureRest::get_history_orders(c, symbol).await,
+            Client::Huobi(c) => c.get_history_orders(symbol).await,
+        }
+    }
+
+    pub async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
+        match self {
+            Client::Binance(c) => SpotRest::get_orderbook(c, symbol, depth).await,
+            Client::BinanceSwap(c) => FutureRest::get_orderbook(c, symbol, depth).await,
+            Client::Huobi(c) => c.get_orderbook(symbol, depth).await,
+        }
+    }
+
+    pub async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+        match self {
+            Client::Binance(c) => SpotRest::get_ticker(c, symbol).await,
+            Client::BinanceSwap(c) => FutureRest::get_ticker(c, symbol).await,
+            Client::Huobi(c) => c.get_ticker(symbol).await,
+        }
+    }
+
+    pub async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+        match self {
+            Client::Binance(c) => SpotRest::get_kline(c, symbol, period, limit).await,
+            Client::BinanceSwap(c) => FutureRest::get_kline(c, symbol, period, limit).await,
+            Client::Huobi(c) => c.get_kline(symbol, period, limit).await,
+        }
+    }
+
+    pub async fn ping(&self) -> APIResult<()> {
+        match self {
+            Client::Binance(c) => SpotRest::ping(c).await,
+            Client::BinanceSwap(c) => FutureRest::ping(c).await,
+            Client::Huobi(c) => c.ping().await,
+        }
+    }
+
+    pub async fn get_server_time(&self) -> APIResult<u64> {
+        match self {
+            Client::Binance(c) => SpotRest::get_server_time(c).await,
+            Client::BinanceSwap(c) => FutureRest::get_server_time(c).await,
+            Client::Huobi(c) => c.get_server_time().await,
+        }
+    }
+
+    pub async fn get_system_status(&self) -> APIResult<SystemStatus> {
+        match self {
+            Client::Binance(c) => c.get_system_status().await,
+            Client::BinanceSwap(c) => c.get_system_status().await,
+            Client::Huobi(c) => c.get_system_status().await,
+        }
+    }
+
+    pub async fn get_fee_rates(&self, symbol: &str) -> APIResult<FeeRate> {
+        match self {
+            Client::Binance(c) => c.get_fee_rates(symbol).await,
+            Client::BinanceSwap(c) => c.get_fee_rates(symbol).await,
+            Client::Huobi(c) => c.get_fee_rates(symbol).await,
+        }
+    }
+
+    // huobi limits are not tracked
+    pub fn rate_limits(&self) -> Vec<RateLimitStatus> {
+        match self {
+            Client::Binance(c) => c.rate_limits(),
+            Client::BinanceSwap(c) => c.rate_limits(),
+            Client::Huobi(_) => vec![],
+        }
+    }
+}
+
+impl Config {
+    // file format is picked from the extension, toml by default
+    pub fn from_file<P: AsRef<Path>>(path: P) -> APIResult<Config> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => Config::from_yaml(&content),
+            _ => Config::from_toml(&content),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> APIResult<Config> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_yaml(content: &str) -> APIResult<Config> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    pub fn build(&self) -> APIResult<Registry> {
+        self.build_with(None)
+    }
+
+    pub fn build_with_keystore(&self, keystore: &Keystore) -> APIResult<Registry> {
+        self.build_with(Some(keystore))
+    }
+
+    fn build_with(&self, keystore: Option<&Keystore>) -> APIResult<Registry> {
+        let mut registry = Registry::new();
+        for item in &self.exchanges {
+            registry.insert(item.name.clone(), item.build_with(keystore)?);
+        }
+        Ok(registry)
+    }
+}
+
+// `env:NAME` reads the credential from the environment variable NAME,
+// anything else is used as is
+pub fn resolve_credential(value: &str) -> APIResult<String> {
+    match value.strip_prefix("env:") {
+        Some(name) => env::var(name).map_err(|_| {
+            Box::new(ExError::ApiError(format!("env var {} not set", name))) as Box<_>
+        }),
+        None => Ok(value.into()),
+    }
+}
+
+// also a builder for one client without a config file
+//
+//     let api = ExchangeConfig::new("binance_swap")
+//         .credentials("env:BN_KEY", "env:BN_SECRET")
+//         .network(Network::Testnet)
+//         .build()?;
+impl ExchangeConfig {
+    pub fn new(exchange: &str) -> Self {
+        ExchangeConfig {
+            name: exchange.into(),
+            exchange: exchange.into(),
+            ..ExchangeConfig::default()
+        }
+    }
+
+    pub fn credentials(mut self, api_key: &str, secret_key: &str) -> Self {
+        self.api_key = Some(api_key.into());
+        self.secret_key = Some(secret_key.into());
+        self
+    }
+
+    // picks the default host of the network, unless a host is set
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn build(&self) -> APIResult<Client> {
+        self.build_with(None)
+    }
+
+    // the configured or default host followed by the fallbacks
+    fn hosts(&self, host: &str) -> HostPool {
+        let mut hosts = vec![host];
+        hosts.extend(self.fallback_hosts.iter().map(|h| h.as_str()));
+        let pool = HostPool::new(&hosts);
+        if let Some(selection) = self.host_selection {
+            pool.set_selection(selection);
+        }
+        pool
+    }
+
+    fn scope(&self) -> Scope {
+        match &self.permissions {
+            Some(capabilities) => Scope::only(capabilities),
+            None => Scope::all(),
+        }
+    }
+
+    fn limiter(&self) -> Option<RateLimiter> {
+        let rate_limit = self.rate_limit?;
+        Some(match &self.rate_limit_group {
+            Some(group) => shared_limiter(&format!("group:{}", group), rate_limit),
+            None => RateLimiter::per_minute(rate_limit),
+        })
+    }
+
+    fn build_with(&self, keystore: Option<&Keystore>) -> APIResult<Client> {
+        let (api_key, secret_key) = match (&self.keystore, keystore) {
+            (Some(name), Some(keystore)) => match keystore.get(name) {
+                Some(cred) => (Some(cred.api_key.clone()), Some(cred.secret_key.clone())),
+                None => {
+                    return Err(Box::new(ExError::ApiError(format!(
+                        "keystore entry not found: {}",
+                        name
+                    ))))
+                }
+            },
+            (Some(_), None) => {
+                return Err(Box::new(ExError::ApiError("keystore not loaded".into())))
+            }
+            _ => (
+                self.api_key
+                    .as_deref()
+                    .map(resolve_credential)
+                    .transpose()?,
+                self.secret_key
+                    .as_deref()
+                    .map(resolve_credential)
+                    .transpose()?,
+            ),
+        };
+        match self.exchange.as_str() {
+            "binance" | "binance_margin" => {
+                let mut api = match &self.host {
+                    Some(host) => Binance::new(api_key, secret_key, host.clone()),
+                    None => Binance::with_network(api_key, secret_key, self.network),
+                };
+                if !self.fallback_hosts.is_empty() {
+                    api.set_hosts(self.hosts(api.hosts().active()));
+                }
+                if self.exchange == "binance_margin" {
+                    api.set_margin();
+                }
+                if let Some(proxy) = &self.proxy {
+                    api.set_proxy(proxy)?;
+                }
+                if let Some(limiter) = self.limiter() {
+                    api.set_limiter(limiter);
+                }
+                api.set_scope(self.scope());
+                for key in &self.extra_keys {
+                    api.add_key(
+                        &resolve_credential(&key.api_key)?,
+                        &resolve_credential(&key.secret_key)?,
+                        key.rate_limit,
+                    )?;
+                }
+                Ok(Client::Binance(api))
+            }
+            "binance_swap" => {
+                let mut api = match &self.host {
+                    Some(host) => BinanceSwap::new(api_key, secret_key, host.clone()),
+                    None => BinanceSwap::with_network(api_key, secret_key, self.network),
+                };
+                if !self.fallback_hosts.is_empty() {
+                    api.set_hosts(self.hosts(api.hosts().active()));
+                }
+                if let Some(proxy) = &self.proxy {
+                    api.set_proxy(proxy)?;
+                }
+                if let Some(limiter) = self.limiter() {
+                    api.set_limiter(limiter);
+                }
+                api.set_scope(self.scope());
+                for key in &self.extra_keys {
+                    api.add_key(
+                        &resolve_credential(&key.api_key)?,
+                        &resolve_credential(&key.secret_key)?,
+                        key.rate_limit,
+                    )?;
+                }
+                Ok(Client::BinanceSwap(api))
+            }
+            "huobi" => {
+                if !self.extra_keys.is_empty() {
+                    return Err(Box::new(ExError::ApiError(
+                        "huobi does not support extra_keys".into(),
+                    )));
+                }
+                if self.network == Network::Testnet {
+                    return Err(Box::new(ExError::ApiError("huobi has no testnet".into())));
+                }
+                let host = self
+                    .host
+                    .clone()
+                    .unwrap_or_else(|| crate::constant::HUOBI_HOST.into());
+                let mut api = Huobi::new(api_key, secret_key, host);
+                if let Some(proxy) = &self.proxy {
+                    api.set_proxy(proxy)?;
+                }
+                if let Some(limiter) = self.limiter() {
+                    api.set_limiter(limiter);
+                }
+                api.set_scope(self.scope());
+                if let (Some(ty), Some(id)) = (&self.account_type, &self.account_id) {
+                    api.set_account(ty, id);
+                }
+                Ok(Client::Huobi(api))
+            }
+            ex => Err(Box::new(ExError::ApiError(format!(
+                "unknown exchange: {}",
+                ex
+            )))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let content = r#"
+            [[exchanges]]
+            name = "bn"
+            exchange = "binance_swap"
+            api_key = "key"
+            secret_key = "secret"
+            network = "testnet"
+            fallback_hosts = ["https://testnet-fallback.example.com"]
+            host_selection = "latency"
+            rate_limit = 1200
+
+            [[exchanges.extra_keys]]
+            api_key = "key2"
+            secret_key = "secret2"
+            rate_limit = 600
+
+            [[exchanges]]
+            name = "hb"
+            exchange = "huobi"
+            proxy = "http://127.0.0.1:1080"
+            rate_limit = 600
+            rate_limit_group = "egress"
+            permissions = []
+        "#;
+        let config = Config::from_toml(content).unwrap();
+        assert_eq!(config.exchanges.len(), 2);
+        assert_eq!(config.exchanges[0].network, Network::Testnet);
+        let registry = config.build().unwrap();
+        match registry.get("bn") {
+            Some(Client::BinanceSwap(api)) => assert_eq!(
+                api.hosts().hosts(),
+                [
+                    "https://testnet.binancefuture.com",
+                    "https://testnet-fallback.example.com"
+                ]
+            ),
+            _ => panic!("expected binance_swap"),
+        }
+        assert!(matches!(registry.get("hb"), Some(Client::Huobi(_))));
+        // refused before the proxy is ever reached
+        #[cfg(feature = "blocking")]
+        {
+            let err = registry["hb"]
+                .create_order(&OrderRequest::limit_buy("btcusdt", 1.0, 1.0))
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ExError>(),
+                Some(ExError::PermissionDenied(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let api = ExchangeConfig::new("binance")
+            .credentials("key", "secret")
+            .network(Network::Testnet)
+            .build()
+            .unwrap();
+        match api {
+            Client::Binance(api) => {
+                assert_eq!(api.hosts().active(), "https://testnet.binance.vision")
+            }
+            _ => panic!("not a binance client"),
+        }
+        let api = ExchangeConfig::new("binance_swap")
+            .network(Network::Testnet)
+            .host("http://127.0.0.1:1")
+            .build()
+            .unwrap();
+        match api {
+            Client::BinanceSwap(api) => assert_eq!(api.hosts().active(), "http://127.0.0.1:1"),
+            _ => panic!("not a binance swap client"),
+        }
+        assert!(ExchangeConfig::new("huobi")
+            .network(Network::Testnet)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_credential() {
+        env::set_var("RSEX_TEST_API_KEY", "key-from-env");
+        assert_eq!(
+            resolve_credential("env:RSEX_TEST_API_KEY").unwrap(),
+            "key-from-env"
+        );
+        assert_eq!(resolve_credential("plain").unwrap(), "plain");
+        assert!(resolve_credential("env:RSEX_TEST_NOT_SET").is_err());
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let content = "
+exchanges:
+  - name: bn
+    exchange: binance
+    host: https://www.binancezh.pro
+";
+        let config = Config::from_yaml(content).unwrap();
+        assert_eq!(config.exchanges[0].network, Network::Mainnet);
+        assert!(matches!(
+            config.build().unwrap().get("bn"),
+            Some(Client::Binance(_))
+        ));
+    }
+}
@@ -4,8 +4,23 @@ pub const ORDER_ACTION_SELL: &str = "SELL";
 pub const ORDER_TYPE_LIMIT: &str = "LIMIT";
 pub const ORDER_TYPE_MARKET: &str = "MARKET";
 
-pub const ORDER_STATUS_FILLED: u8 = 1;
-pub const ORDER_STATUS_PART_FILLED: u8 = 2;
-pub const ORDER_STATUS_CANCELLED: u8 = 3;
-pub const ORDER_STATUS_SUBMITTED: u8 = 4;
-pub const ORDER_STATUS_FAILED: u8 = 5;
+pub const BINANCE_SPOT_HOST: &str = "https://api.binance.com";
+// api.binance.com and its alternates, mirrors can be added to a HostPool
+pub const BINANCE_SPOT_HOSTS: &[&str] = &[
+    "https://api.binance.com",
+    "https://api1.binance.com",
+    "https://api2.binance.com",
+    "https://api3.binance.com",
+    "https://api4.binance.com",
+];
+pub const BINANCE_SPOT_TESTNET_HOST: &str = "https://testnet.binance.vision";
+pub const BINANCE_SPOT_WS_HOST: &str = "wss://stream.binance.com:9443/ws";
+pub const BINANCE_SPOT_TESTNET_WS_HOST: &str = "wss://testnet.binance.vision/ws";
+pub const BINANCE_SWAP_HOST: &str = "https://fapi.binance.com";
+pub const BINANCE_SWAP_TESTNET_HOST: &str = "https://testnet.binancefuture.com";
+pub const BINANCE_SWAP_WS_HOST: &str = "wss://fstream.binance.com/ws";
+pub const BINANCE_SWAP_TESTNET_WS_HOST: &str = "wss://stream.binancefuture.com/ws";
+pub const BINANCE_COIN_SWAP_HOST: &str = "https://dapi.binance.com";
+pub const BINANCE_PORTFOLIO_HOST: &str = "https://papi.binance.com";
+pub const HUOBI_HOST: &str = "https://api.huobi.pro";
+pub const HUOBI_WS_HOST: &str = "wss://api.huobi.pro/ws";
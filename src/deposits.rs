@@ -0,0 +1,332 @@
+use crate::clock::now_ms;
+use crate::models::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+use crate::transfer::Wallet;
+
+use log::warn;
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepositEventKind {
+    // first seen in the deposit history
+    Seen,
+    // more block confirmations
+    Confirming,
+    // credited, can be traded
+    Tradable,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DepositEvent {
+    pub exchange: String,
+    pub kind: DepositEventKind,
+    pub deposit: Deposit,
+    // id of the expectation the deposit matched
+    pub expected: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Expected {
+    id: u64,
+    exchange: String,
+    coin: String,
+    amount: f64,
+    tx_id: Option<String>,
+    // deposit id once matched
+    matched: Option<String>,
+}
+
+struct Watch {
+    exchange: String,
+    wallet: Wallet,
+    coin: String,
+    since: u64,
+}
+
+
+// last status and confirmations seen of a deposit
+type Seen = (DepositStatus, u32);
+
+// watches the deposit history of coins on exchanges and reports deposits
+// as they show up, confirm and become tradable. expected deposits, e.g.
+// from a withdrawal elsewhere, are matched by tx id, or by amount within
+// the tolerance when the tx id is not known
+//
+//     let mut poller = DepositPoller::new();
+//     poller.watch("huobi", Arc::new(huobi), "USDT");
+//     let id = poller.expect("huobi", "USDT", 999.0, None);
+//     poller.on_event(move |event| {
+//         if event.expected == Some(id) && event.kind == DepositEventKind::Tradable {
+//             info!("funds arrived: {:?}", event.deposit);
+//         }
+//     });
+//     poller.run_every(Duration::from_secs(30));
+pub struct DepositPoller {
+    watches: Vec<Watch>,
+    expected: Vec<Expected>,
+    next_id: u64,
+    // by (exchange, deposit id)
+    seen: HashMap<(String, String), Seen>,
+    // relative amount difference an expectation still matches
+    tolerance: f64,
+    lookback: Duration,
+    notifier: Notifier<DepositEvent>,
+    shutdown: Shutdown,
+}
+
+impl Default for DepositPoller {
+    fn default() -> Self {
+        DepositPoller::new()
+    }
+}
+
+impl DepositPoller {
+    pub fn new() -> Self {
+        DepositPoller {
+            watches: vec![],
+            expected: vec![],
+            next_id: 1,
+            seen: HashMap::new(),
+            tolerance: 0.0,
+            lookback: Duration::from_secs(3600),
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // e.g. 0.01 to match an amount off by a fee of up to 1%
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    // how far before watch the history is read, 1 hour by default
+    pub fn set_lookback(&mut self, lookback: Duration) {
+        self.lookback = lookback;
+    }
+
+    // run_every stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    pub fn watch(&mut self, exchange: &str, wallet: Wallet, coin: &str) {
+        let since = now_ms().saturating_sub(self.lookback.as_millis() as u64);
+        self.watches.push(Watch {
+            exchange: exchange.into(),
+            wallet,
+            coin: coin.to_uppercase(),
+            since,
+        });
+    }
+
+    // returns the id events of the matching deposit carry
+    pub fn expect(&mut self, exchange: &str, coin: &str, amount: f64, tx_id: Option<&str>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.expected.push(Expected {
+            id,
+            exchange: exchange.into(),
+            coin: coin.to_uppercase(),
+            amount,
+            tx_id: tx_id.map(|t| t.to_string()),
+            matched: None,
+        });
+        id
+    }
+
+    // expectations no deposit matched yet
+    pub fn unmatched(&self) -> Vec<u64> {
+        self.expected
+            .iter()
+            .filter(|e| e.matched.is_none())
+            .map(|e| e.id)
+            .collect()
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(&DepositEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<DepositEvent> {
+        self.notifier.subscribe()
+    }
+
+    // reads every watched history once, returns the events fired. failed
+    // queries are logged and retried on the next poll
+    pub fn poll(&mut self) -> Vec<DepositEvent> {
+        let mut events = vec![];
+        for i in 0..self.watches.len() {
+            let watch = &self.watches[i];
+            let deposits = match watch.wallet.get_deposits(&watch.coin, watch.since) {
+                Ok(deposits) => deposits,
+                Err(err) => {
+                    warn!(
+                        "deposits of {} on {} failed: {:?}",
+                        watch.coin, watch.exchange, err
+                    );
+                    continue;
+                }
+            };
+            let exchange = watch.exchange.clone();
+            for deposit in deposits {
+                events.extend(self.update(&exchange, deposit));
+            }
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+        events
+    }
+
+    // polls on a background thread, the first time right away
+    pub fn run_every(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.poll();
+            if !self.shutdown.sleep(interval) {
+                return;
+            }
+        })
+    }
+
+    fn update(&mut self, exchange: &str, deposit: Deposit) -> Vec<DepositEvent> {
+        let key = (exchange.to_string(), deposit.id.clone());
+        let current = (deposit.status, deposit.confirmations);
+        let previous = self.seen.insert(key, current);
+        if previous == Some(current) {
+            return vec![];
+        }
+        let expected = self.match_expected(exchange, &deposit);
+        let mut kinds = vec![];
+        if previous.is_none() {
+            kinds.push(DepositEventKind::Seen);
+        }
+        let was = previous.map(|(status, _)| status);
+        match deposit.status {
+            DepositStatus::Credited | DepositStatus::Success => {
+                // credited then withdrawable is tradable once
+                if !matches!(was, Some(DepositStatus::Credited | DepositStatus::Success)) {
+                    kinds.push(DepositEventKind::Tradable);
+                }
+            }
+            DepositStatus::Failed => kinds.push(DepositEventKind::Failed),
+            DepositStatus::Pending => {
+                if previous.is_some() {
+                    kinds.push(DepositEventKind::Confirming);
+                }
+            }
+        }
+        kinds
+            .into_iter()
+            .map(|kind| DepositEvent {
+                exchange: exchange.into(),
+                kind,
+                deposit: deposit.clone(),
+                expected,
+            })
+            .collect()
+    }
+
+    fn match_expected(&mut self, exchange: &str, deposit: &Deposit) -> Option<u64> {
+        if let Some(e) = self
+            .expected
+            .iter()
+            .find(|e| e.exchange == exchange && e.matched.as_ref() == Some(&deposit.id))
+        {
+            return Some(e.id);
+        }
+        let tolerance = self.tolerance;
+        let expected = self.expected.iter_mut().find(|e| {
+            if e.matched.is_some() || e.exchange != exchange || e.coin != deposit.coin {
+                return false;
+            }
+            match e.tx_id.as_ref() {
+                Some(tx_id) => tx_id.eq_ignore_ascii_case(&deposit.tx_id),
+                None => (deposit.amount - e.amount).abs() <= e.amount * tolerance + 1e-9,
+            }
+        })?;
+        expected.matched = Some(deposit.id.clone());
+        Some(expected.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fakes::FakeWallet;
+    use std::sync::Arc;
+
+    fn deposit(id: &str, amount: f64, tx_id: &str) -> Deposit {
+        Deposit {
+            id: id.into(),
+            coin: "USDT".into(),
+            network: "TRX".into(),
+            amount,
+            address: "addr".into(),
+            tx_id: tx_id.into(),
+            status: DepositStatus::Pending,
+            confirmations: 0,
+            required_confirmations: 3,
+            timestamp: now_ms(),
+        }
+    }
+
+    #[test]
+    fn test_deposit_poller() {
+        let wallet = Arc::new(FakeWallet::default());
+        let mut poller = DepositPoller::new();
+        poller.set_tolerance(0.01);
+        poller.watch("huobi", wallet.clone(), "usdt");
+        let by_amount = poller.expect("huobi", "USDT", 100.0, None);
+        let by_tx = poller.expect("huobi", "USDT", 50.0, Some("0xABC"));
+        let rx = poller.subscribe();
+
+        wallet.deposits.lock().unwrap().extend(vec![
+            deposit("1", 99.5, "0x1"),
+            deposit("2", 20.0, "0xabc"),
+            deposit("3", 7.0, "0x3"),
+        ]);
+        let events = poller.poll();
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|e| e.kind == DepositEventKind::Seen));
+        assert_eq!(events[0].expected, Some(by_amount));
+        assert_eq!(events[1].expected, Some(by_tx));
+        assert_eq!(events[2].expected, None);
+        assert!(poller.unmatched().is_empty());
+        assert!(poller.poll().is_empty());
+
+        {
+            let mut deposits = wallet.deposits.lock().unwrap();
+            deposits[0].confirmations = 2;
+            deposits[1].status = DepositStatus::Credited;
+            deposits[2].status = DepositStatus::Failed;
+        }
+        let kinds: Vec<DepositEventKind> = poller.poll().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DepositEventKind::Confirming,
+                DepositEventKind::Tradable,
+                DepositEventKind::Failed
+            ]
+        );
+
+        // credited to success is not tradable twice
+        wallet.deposits.lock().unwrap()[1].status = DepositStatus::Success;
+        assert!(poller.poll().is_empty());
+        assert_eq!(rx.try_iter().count(), 6);
+        wallet.deposits.lock().unwrap()[0].status = DepositStatus::Success;
+        let events = poller.poll();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, DepositEventKind::Tradable);
+        assert_eq!(events[0].expected, Some(by_amount));
+    }
+}
@@ -8,6 +8,17 @@ pub enum ExError {
     ApiError(String),
     RateLimitExceeded(String),
     IpBanned(String),
+    // scheduled downtime, pause instead of retrying
+    ExchangeInMaintenance(String),
+    // refused locally by the client's scope, nothing was sent
+    PermissionDenied(String),
+    // a post only order that would have taken liquidity, it was not placed.
+    // quoting loops reprice and try again
+    PostOnlyRejected(String),
+    // the exchange or this client has no such endpoint
+    NotSupported(String),
+    // no such order, or it is done already, e.g. when cancelling a filled one
+    UnknownOrder(String),
 }
 
 impl fmt::Display for ExError {
@@ -16,6 +27,11 @@ impl fmt::Display for ExError {
             ExError::ApiError(why) => write!(f, "ApiError: {}", why),
             ExError::RateLimitExceeded(why) => write!(f, "RateLimitExceeded: {}", why),
             ExError::IpBanned(why) => write!(f, "IpBanned: {}", why),
+            ExError::ExchangeInMaintenance(why) => write!(f, "ExchangeInMaintenance: {}", why),
+            ExError::PermissionDenied(why) => write!(f, "PermissionDenied: {}", why),
+            ExError::PostOnlyRejected(why) => write!(f, "PostOnlyRejected: {}", why),
+            ExError::NotSupported(why) => write!(f, "NotSupported: {}", why),
+            ExError::UnknownOrder(why) => write!(f, "UnknownOrder: {}", why),
         }
     }
 }
@@ -0,0 +1,210 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::shutdown::Shutdown;
+use crate::utils::Pending;
+
+use log::warn;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingExpiry {
+    pub symbol: String,
+    pub order_id: String,
+    pub expire_at: u64,
+}
+
+// cancels orders at their expire_at from the client, for exchanges without
+// GTD orders or orders set to good_till_emulated. clients given one with
+// set_expiry add the orders they place, clones share the pending orders.
+// orders are left open if the process is down when they expire
+//
+//     let expiry = OrderExpiry::new();
+//     spot.set_expiry(expiry.clone());
+//     let api = Arc::new(spot);
+//     let a = api.clone();
+//     expiry.run(move |symbol, id| a.cancel(symbol, id));
+//     let at = now_ms() + 3_600_000;
+//     api.create_order(&OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01).good_till(at))?;
+#[derive(Clone, Default)]
+pub struct OrderExpiry {
+    pending: Arc<Mutex<Vec<PendingExpiry>>>,
+    shutdown: Option<Shutdown>,
+}
+
+impl std::fmt::Debug for OrderExpiry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "OrderExpiry({} pending)",
+            self.pending.lock().unwrap().len()
+        )
+    }
+}
+
+impl OrderExpiry {
+    pub fn new() -> Self {
+        OrderExpiry::default()
+    }
+
+    pub fn add(&self, symbol: &str, order_id: &str, expire_at: u64) {
+        self.pending.lock().unwrap().push(PendingExpiry {
+            symbol: symbol.into(),
+            order_id: order_id.into(),
+            expire_at,
+        });
+    }
+
+    // e.g. once the order is filled or cancelled otherwise
+    pub fn remove(&self, order_id: &str) {
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|p| p.order_id != order_id);
+    }
+
+    pub fn pending(&self) -> Vec<PendingExpiry> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    // run stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    // cancels the orders expired at now, returns them. orders the exchange
+    // doesn't know, e.g. filled ones, are dropped, any other failure is
+    // logged and tried again next time
+    pub fn cancel_due<F>(&self, now: u64, cancel: F) -> Vec<PendingExpiry>
+    where
+        F: Fn(&str, &str) -> APIResult<bool>,
+    {
+        let due: Vec<PendingExpiry> = {
+            let mut pending = self.pending.lock().unwrap();
+            let (due, rest) = pending.drain(..).partition(|p| p.expire_at <= now);
+            *pending = rest;
+            due
+        };
+        let mut cancelled = vec![];
+        for p in due {
+            match cancel(&p.symbol, &p.order_id) {
+                Ok(_) => cancelled.push(p),
+                Err(err) if matches!(err.downcast_ref(), Some(ExError::UnknownOrder(_))) => {}
+                Err(err) => {
+                    warn!("expiring order {} failed: {:?}", p.order_id, err);
+                    self.pending.lock().unwrap().push(p);
+                }
+            }
+        }
+        cancelled
+    }
+
+    // cancels on a background thread until the shutdown
+    pub fn run<F>(self, cancel: F) -> JoinHandle<()>
+    where
+        F: Fn(&str, &str) -> APIResult<bool> + Send + 'static,
+    {
+        thread::spawn(move || loop {
+            self.cancel_due(now_ms(), &cancel);
+            if matches!(&self.shutdown, Some(s) if s.is_shutdown()) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        })
+    }
+
+    // places the order with place, then adds it when it expires from the
+    // client. native tells whether the client sends GTD to the exchange
+    #[maybe_async::maybe_async]
+    pub async fn place<'a, F>(
+        expiry: Option<&OrderExpiry>,
+        order: &OrderRequest,
+        native: bool,
+        place: F,
+    ) -> APIResult<String>
+    where
+        F: FnOnce() -> Pending<'a, APIResult<String>>,
+    {
+        let expire_at = match order.expire_at {
+            Some(expire_at) if order.emulate_expiry || !native => expire_at,
+            _ => return place().await,
+        };
+        let expiry = match expiry {
+            Some(expiry) => expiry,
+            None => {
+                return Err(Box::new(ExError::ApiError(
+                    "good till orders need set_expiry on this client".into(),
+                )))
+            }
+        };
+        let id = place().await?;
+        expiry.add(&order.symbol, &id, expire_at);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http;
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_order_expiry() {
+        let expiry = OrderExpiry::new();
+        let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01).good_till(2000);
+        let ret =
+            OrderExpiry::place(None, &order, false, || Box::pin(async { Ok("1".into()) })).await;
+        assert!(ret.is_err());
+        // sent as GTD, nothing to do
+        OrderExpiry::place(Some(&expiry), &order, true, || {
+            Box::pin(async { Ok("1".into()) })
+        })
+        .await
+        .unwrap();
+        assert!(expiry.pending().is_empty());
+        OrderExpiry::place(Some(&expiry), &order, false, || {
+            Box::pin(async { Ok("2".into()) })
+        })
+        .await
+        .unwrap();
+        let order = order.good_till_emulated(3000);
+        OrderExpiry::place(Some(&expiry), &order, true, || {
+            Box::pin(async { Ok("3".into()) })
+        })
+        .await
+        .unwrap();
+        expiry.add("BTCUSDT", "4", 3000);
+        expiry.remove("4");
+        assert_eq!(expiry.pending().len(), 2);
+
+        assert!(expiry.cancel_due(1000, |_, _| Ok(true)).is_empty());
+        let cancelled = expiry.cancel_due(2000, |_, _| Ok(true));
+        assert_eq!(cancelled[0].order_id, "2");
+
+        // timeouts and other failures are retried
+        let cancelled = expiry.cancel_due(3000, |_, _| {
+            Err(Box::new(http::Error::new(
+                http::ErrorKind::Timeout,
+                "timed out".into(),
+            )))
+        });
+        assert!(cancelled.is_empty());
+        assert_eq!(expiry.pending().len(), 1);
+        expiry.cancel_due(3000, |_, _| {
+            Err(Box::new(ExError::RateLimitExceeded(
+                "rate limit exceeded: 429".into(),
+            )))
+        });
+        assert_eq!(expiry.pending().len(), 1);
+        // filled or gone, nothing left to cancel
+        let cancelled = expiry.cancel_due(3000, |_, _| {
+            Err(Box::new(ExError::UnknownOrder(
+                "response: 400 -2011 Unknown order sent.".into(),
+            )))
+        });
+        assert!(cancelled.is_empty());
+        assert!(expiry.pending().is_empty());
+    }
+}
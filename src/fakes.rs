@@ -0,0 +1,74 @@
+// test doubles shared by the unit tests of several modules
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::traits::WalletRest;
+
+use std::sync::Mutex;
+
+// a wallet on two networks, TRX and ETH. withdrawals are recorded pending,
+// deposits are whatever the test pushes
+#[derive(Default)]
+pub struct FakeWallet {
+    pub withdrawals: Mutex<Vec<Withdrawal>>,
+    pub deposits: Mutex<Vec<Deposit>>,
+}
+
+impl WalletRest for FakeWallet {
+    fn get_coin_networks(&self, coin: &str) -> APIResult<Vec<CoinNetwork>> {
+        let network = |name: &str| CoinNetwork {
+            coin: coin.into(),
+            network: name.into(),
+            name: name.into(),
+            is_default: name == "TRX",
+            deposit_enabled: true,
+            withdraw_enabled: true,
+            withdraw_fee: 1.0,
+            withdraw_min: 10.0,
+            withdraw_max: 0.0,
+            memo_required: false,
+            min_confirmations: 1,
+        };
+        Ok(vec![network("TRX"), network("ETH")])
+    }
+
+    fn get_deposit_address(&self, coin: &str, network: Option<&str>) -> APIResult<DepositAddress> {
+        Ok(DepositAddress {
+            coin: coin.into(),
+            network: network.unwrap_or_default().into(),
+            address: "addr-b".into(),
+            memo: None,
+        })
+    }
+
+    fn get_deposits(&self, _coin: &str, _since: u64) -> APIResult<Vec<Deposit>> {
+        Ok(self.deposits.lock().unwrap().clone())
+    }
+
+    fn withdraw(&self, req: &WithdrawRequest) -> APIResult<String> {
+        let mut withdrawals = self.withdrawals.lock().unwrap();
+        let id = format!("w{}", withdrawals.len() + 1);
+        withdrawals.push(Withdrawal {
+            id: id.clone(),
+            coin: req.coin.clone(),
+            network: req.network.clone().unwrap_or_default(),
+            amount: req.amount,
+            fee: 1.0,
+            address: req.address.clone(),
+            tx_id: String::new(),
+            status: WithdrawStatus::Pending,
+            timestamp: now_ms(),
+        });
+        Ok(id)
+    }
+
+    fn get_withdrawal(&self, _coin: &str, id: &str) -> APIResult<Withdrawal> {
+        match self.withdrawals.lock().unwrap().iter().find(|w| w.id == id) {
+            Some(withdrawal) => Ok(withdrawal.clone()),
+            None => Err(Box::new(ExError::ApiError(format!(
+                "unknown withdrawal: {}",
+                id
+            )))),
+        }
+    }
+}
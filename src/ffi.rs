@@ -0,0 +1,229 @@
+// extern "C" interface over config::Client, clients are opaque handles and
+// every call takes and returns json, see include/rsex.h
+use crate::config::{Client, ExchangeConfig};
+use crate::errors::*;
+use crate::models::OrderRequest;
+
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+fn str_param<'a>(params: &'a Value, key: &str) -> APIResult<&'a str> {
+    params
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Box::new(ExError::ApiError(format!("missing {}", key))).into())
+}
+
+fn num_param(params: &Value, key: &str) -> APIResult<f64> {
+    params
+        .get(key)
+        .and_then(Value::as_f64)
+        .ok_or_else(|| Box::new(ExError::ApiError(format!("missing {}", key))).into())
+}
+
+// integers only, anything out of range is an error rather than wrapped
+fn u16_param(params: &Value, key: &str, default: u16) -> APIResult<u16> {
+    match params.get(key) {
+        Some(v) => v
+            .as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| Box::new(ExError::ApiError(format!("invalid {}: {}", key, v))).into()),
+        None => Ok(default),
+    }
+}
+
+fn call(client: &Client, method: &str, params: &Value) -> APIResult<Value> {
+    Ok(match method {
+        "get_ticker" => serde_json::to_value(client.get_ticker(str_param(params, "symbol")?)?)?,
+        "get_orderbook" => {
+            let depth = u16_param(params, "depth", 20)?;
+            serde_json::to_value(client.get_orderbook(str_param(params, "symbol")?, depth)?)?
+        }
+        "get_kline" => {
+            let period = str_param(params, "period").unwrap_or("1m");
+            let limit = u16_param(params, "limit", 100)?;
+            serde_json::to_value(client.get_kline(str_param(params, "symbol")?, period, limit)?)?
+        }
+        "get_balance" => client.get_balance(str_param(params, "asset")?)?,
+        "get_fee_rates" => {
+            serde_json::to_value(client.get_fee_rates(str_param(params, "symbol")?)?)?
+        }
+        "create_order" => json!(client.create_order(&OrderRequest::new(
+            str_param(params, "symbol")?,
+            str_param(params, "action")?,
+            str_param(params, "order_type").unwrap_or("LIMIT"),
+            num_param(params, "price")?,
+            num_param(params, "amount")?,
+        ))?),
+        "cancel" => json!(client.cancel(str_param(params, "symbol")?, str_param(params, "id")?)?),
+        "cancel_all" => json!(client.cancel_all(str_param(params, "symbol")?)?),
+        "get_order" => serde_json::to_value(
+            client.get_order(str_param(params, "symbol")?, str_param(params, "id")?)?,
+        )?,
+        "get_open_orders" => {
+            serde_json::to_value(client.get_open_orders(str_param(params, "symbol")?)?)?
+        }
+        "get_history_orders" => {
+            serde_json::to_value(client.get_history_orders(str_param(params, "symbol")?)?)?
+        }
+        _ => {
+            return Err(Box::new(ExError::ApiError(format!(
+                "unknown method: {}",
+                method
+            ))))
+        }
+    })
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> APIResult<&'a str> {
+    if s.is_null() {
+        return Err(Box::new(ExError::ApiError("null pointer".into())));
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+// {"ok": result} or {"error": message}, a panic must not unwind into the
+// caller so it is returned as an error too
+fn to_response<F>(run: F) -> Value
+where
+    F: FnOnce() -> APIResult<Value>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(run)) {
+        Ok(Ok(value)) => json!({ "ok": value }),
+        Ok(Err(err)) => json!({ "error": err.to_string() }),
+        Err(payload) => {
+            let why = match (
+                payload.downcast_ref::<&str>(),
+                payload.downcast_ref::<String>(),
+            ) {
+                (Some(why), _) => why.to_string(),
+                (_, Some(why)) => why.clone(),
+                _ => "unknown".into(),
+            };
+            json!({ "error": format!("panic: {}", why) })
+        }
+    }
+}
+
+fn to_c_string(value: Value) -> *mut c_char {
+    CString::new(value.to_string())
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Builds a client from an exchange config in json, e.g.
+/// `{"name": "bn", "exchange": "binance", "api_key": "..", "secret_key": ".."}`.
+/// Returns null if the config is invalid.
+///
+/// # Safety
+/// `config` must be a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rsex_client_new(config: *const c_char) -> *mut Client {
+    let build = || -> APIResult<Client> {
+        let config: ExchangeConfig = serde_json::from_str(to_str(config)?)?;
+        config.build()
+    };
+    match build() {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `client` must come from `rsex_client_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rsex_client_free(client: *mut Client) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Calls `method` (get_ticker, get_orderbook, create_order ...) with json
+/// params, returns `{"ok": result}` or `{"error": message}`. The returned
+/// string must be released with `rsex_string_free`.
+///
+/// # Safety
+/// `client` must come from `rsex_client_new`, `method` and `params` must be
+/// valid nul terminated strings, `params` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rsex_call(
+    client: *const Client,
+    method: *const c_char,
+    params: *const c_char,
+) -> *mut c_char {
+    let run = || -> APIResult<Value> {
+        let client = client
+            .as_ref()
+            .ok_or_else(|| Box::new(ExError::ApiError("null client".into())))?;
+        let params = if params.is_null() {
+            Value::Null
+        } else {
+            serde_json::from_str(to_str(params)?)?
+        };
+        call(client, to_str(method)?, &params)
+    };
+    to_c_string(to_response(run))
+}
+
+/// # Safety
+/// `s` must come from `rsex_call` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rsex_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let config = CString::new(r#"{"name": "bn", "exchange": "unknown"}"#).unwrap();
+            assert!(rsex_client_new(config.as_ptr()).is_null());
+
+            let config = CString::new(r#"{"name": "bn", "exchange": "binance"}"#).unwrap();
+            let client = rsex_client_new(config.as_ptr());
+            assert!(!client.is_null());
+
+            let method = CString::new("get_ticker").unwrap();
+            let ret = rsex_call(client, method.as_ptr(), ptr::null());
+            let s = CStr::from_ptr(ret).to_str().unwrap().to_string();
+            rsex_string_free(ret);
+            assert_eq!(s, r#"{"error":"ApiError: missing symbol"}"#);
+
+            let method = CString::new("get_orderbook").unwrap();
+            for depth in &["70000", "-1", "2.5"] {
+                let params = format!(r#"{{"symbol": "BTCUSDT", "depth": {}}}"#, depth);
+                let params = CString::new(params).unwrap();
+                let ret = rsex_call(client, method.as_ptr(), params.as_ptr());
+                let s = CStr::from_ptr(ret).to_str().unwrap().to_string();
+                rsex_string_free(ret);
+                assert_eq!(
+                    s,
+                    format!(r#"{{"error":"ApiError: invalid depth: {}"}}"#, depth)
+                );
+            }
+
+            let method = CString::new("transfer").unwrap();
+            let params = CString::new("{}").unwrap();
+            let ret = rsex_call(client, method.as_ptr(), params.as_ptr());
+            assert!(CStr::from_ptr(ret)
+                .to_str()
+                .unwrap()
+                .contains("unknown method"));
+            rsex_string_free(ret);
+
+            rsex_client_free(client);
+        }
+
+        let ret = to_response(|| panic!("balance of {} missing", "BTC"));
+        assert_eq!(ret, json!({ "error": "panic: balance of BTC missing" }));
+    }
+}
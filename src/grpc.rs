@@ -0,0 +1,401 @@
+use crate::config::{Client, Registry};
+use crate::errors::*;
+use crate::models::{self, OrderRequest};
+
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("rsex.gateway");
+}
+
+use proto::market_data_server::{MarketData, MarketDataServer};
+use proto::trading_server::{Trading, TradingServer};
+
+// gRPC gateway exposing the clients of a Registry, see proto/gateway.proto.
+// the clients are blocking so every call runs on tokio's blocking pool.
+// trading calls are unauthenticated, bind it to localhost. the blocking
+// clients run their own runtime, so build the registry before entering tokio's
+//
+//     let registry = Config::from_file("config.toml")?.build()?;
+//     grpc::serve("127.0.0.1:50051".parse()?, registry).await?;
+#[derive(Clone)]
+pub struct Gateway {
+    registry: Arc<Registry>,
+}
+
+impl Gateway {
+    pub fn new(registry: Registry) -> Self {
+        Gateway {
+            registry: Arc::new(registry),
+        }
+    }
+
+    pub fn market_data(&self) -> MarketDataServer<Gateway> {
+        MarketDataServer::new(self.clone())
+    }
+
+    pub fn trading(&self) -> TradingServer<Gateway> {
+        TradingServer::new(self.clone())
+    }
+
+    async fn call<T, F>(&self, exchange: String, f: F) -> Result<Response<T>, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Client) -> APIResult<T> + Send + 'static,
+    {
+        let registry = self.registry.clone();
+        let ret = tokio::task::spawn_blocking(move || match registry.get(&exchange) {
+            Some(client) => f(client).map_err(to_status),
+            None => Err(Status::not_found(format!("unknown exchange: {}", exchange))),
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?;
+        ret.map(Response::new)
+    }
+}
+
+pub async fn serve(addr: SocketAddr, registry: Registry) -> APIResult<()> {
+    let gateway = Gateway::new(registry);
+    tonic::transport::Server::builder()
+        .add_service(gateway.market_data())
+        .add_service(gateway.trading())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl MarketData for Gateway {
+    async fn get_orderbook(
+        &self,
+        request: Request<proto::OrderbookRequest>,
+    ) -> Result<Response<proto::Orderbook>, Status> {
+        let req = request.into_inner();
+        let depth = match req.depth {
+            0 => 20,
+            depth => depth.min(u16::MAX as u32) as u16,
+        };
+        self.call(req.exchange.clone(), move |c| {
+            Ok(c.get_orderbook(&req.symbol, depth)?.into())
+        })
+        .await
+    }
+
+    async fn get_ticker(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::Ticker>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(c.get_ticker(&req.symbol)?.into())
+        })
+        .await
+    }
+
+    async fn get_kline(
+        &self,
+        request: Request<proto::KlineRequest>,
+    ) -> Result<Response<proto::KlineList>, Status> {
+        let req = request.into_inner();
+        let period = match req.period.as_str() {
+            "" => "1m".to_string(),
+            period => period.to_string(),
+        };
+        let limit = match req.limit {
+            0 => 100,
+            limit => limit.min(u16::MAX as u32) as u16,
+        };
+        self.call(req.exchange.clone(), move |c| {
+            let klines = c.get_kline(&req.symbol, &period, limit)?;
+            Ok(proto::KlineList {
+                klines: klines.into_iter().map(Into::into).collect(),
+            })
+        })
+        .await
+    }
+}
+
+#[tonic::async_trait]
+impl Trading for Gateway {
+    async fn get_balance(
+        &self,
+        request: Request<proto::BalanceRequest>,
+    ) -> Result<Response<proto::Balance>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            let value = c.get_balance(&req.asset)?;
+            Ok(to_balance(&req.asset, &value))
+        })
+        .await
+    }
+
+    async fn create_order(
+        &self,
+        request: Request<proto::CreateOrderRequest>,
+    ) -> Result<Response<proto::OrderId>, Status> {
+        let req = request.into_inner();
+        if req.action.is_empty() {
+            return Err(Status::invalid_argument("missing action"));
+        }
+        let order_type = match req.order_type.as_str() {
+            "" => "LIMIT",
+            order_type => order_type,
+        };
+        let order = OrderRequest::new(&req.symbol, &req.action, order_type, req.price, req.amount);
+        self.call(req.exchange.clone(), move |c| {
+            Ok(proto::OrderId {
+                order_id: c.create_order(&order)?,
+            })
+        })
+        .await
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<proto::OrderRequest>,
+    ) -> Result<Response<proto::CancelResponse>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(proto::CancelResponse {
+                success: c.cancel(&req.symbol, &req.order_id)?,
+            })
+        })
+        .await
+    }
+
+    async fn cancel_all(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::CancelResponse>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(proto::CancelResponse {
+                success: c.cancel_all(&req.symbol)?,
+            })
+        })
+        .await
+    }
+
+    async fn get_order(
+        &self,
+        request: Request<proto::OrderRequest>,
+    ) -> Result<Response<proto::Order>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(c.get_order(&req.symbol, &req.order_id)?.into())
+        })
+        .await
+    }
+
+    async fn get_open_orders(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::OrderList>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(to_order_list(c.get_open_orders(&req.symbol)?))
+        })
+        .await
+    }
+
+    async fn get_history_orders(
+        &self,
+        request: Request<proto::SymbolRequest>,
+    ) -> Result<Response<proto::OrderList>, Status> {
+        let req = request.into_inner();
+        self.call(req.exchange.clone(), move |c| {
+            Ok(to_order_list(c.get_history_orders(&req.symbol)?))
+        })
+        .await
+    }
+}
+
+fn to_status(err: Box<dyn std::error::Error>) -> Status {
+    match err.downcast_ref::<ExError>() {
+        Some(ExError::ExchangeInMaintenance(_)) => Status::unavailable(err.to_string()),
+        Some(ExError::NotSupported(_)) => Status::unimplemented(err.to_string()),
+        _ => Status::unknown(err.to_string()),
+    }
+}
+
+// spot balances are free / locked, futures ones are mapped from the
+// available balance and the initial margin
+fn to_balance(asset: &str, value: &Value) -> proto::Balance {
+    let field = |spot: &str, future: &str| {
+        value
+            .get(spot)
+            .or_else(|| value.get(future))
+            .and_then(Value::as_f64)
+            .unwrap_or_default()
+    };
+    proto::Balance {
+        asset: asset.into(),
+        free: field("free", "available_balance"),
+        locked: field("locked", "initial_margin"),
+    }
+}
+
+fn to_order_list(orders: Vec<models::Order>) -> proto::OrderList {
+    proto::OrderList {
+        orders: orders.into_iter().map(Into::into).collect(),
+    }
+}
+
+impl From<models::Orderbook> for proto::Orderbook {
+    fn from(orderbook: models::Orderbook) -> Self {
+        proto::Orderbook {
+            timestamp: orderbook.timestamp,
+            last_update_id: orderbook.last_update_id,
+            bids: orderbook
+                .bids
+                .iter()
+                .map(|bid| proto::Level {
+                    price: bid.price,
+                    amount: bid.amount,
+                })
+                .collect(),
+            asks: orderbook
+                .asks
+                .iter()
+                .map(|ask| proto::Level {
+                    price: ask.price,
+                    amount: ask.amount,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<models::Ticker> for proto::Ticker {
+    fn from(ticker: models::Ticker) -> Self {
+        proto::Ticker {
+            symbol: ticker.symbol,
+            timestamp: ticker.timestamp,
+            bid: Some(proto::Level {
+                price: ticker.bid.price,
+                amount: ticker.bid.amount,
+            }),
+            ask: Some(proto::Level {
+                price: ticker.ask.price,
+                amount: ticker.ask.amount,
+            }),
+        }
+    }
+}
+
+impl From<models::Kline> for proto::Kline {
+    fn from(kline: models::Kline) -> Self {
+        proto::Kline {
+            timestamp: kline.timestamp,
+            open: kline.open,
+            high: kline.high,
+            low: kline.low,
+            close: kline.close,
+            volume: kline.volume,
+        }
+    }
+}
+
+impl From<models::OrderStatus> for proto::OrderStatus {
+    fn from(status: models::OrderStatus) -> Self {
+        match status {
+            models::OrderStatus::New => proto::OrderStatus::New,
+            models::OrderStatus::PartiallyFilled => proto::OrderStatus::PartiallyFilled,
+            models::OrderStatus::Filled => proto::OrderStatus::Filled,
+            models::OrderStatus::Canceled => proto::OrderStatus::Canceled,
+            models::OrderStatus::Rejected => proto::OrderStatus::Rejected,
+            models::OrderStatus::Expired => proto::OrderStatus::Expired,
+            models::OrderStatus::Unknown => proto::OrderStatus::Unknown,
+        }
+    }
+}
+
+impl From<models::Order> for proto::Order {
+    fn from(order: models::Order) -> Self {
+        proto::Order {
+            symbol: order.symbol,
+            order_id: order.order_id,
+            amount: order.amount,
+            price: order.price,
+            side: order.side,
+            filled: order.filled,
+            avg_price: order.avg_price,
+            status: proto::OrderStatus::from(order.status) as i32,
+            create_time: order.create_time,
+            update_time: order.update_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_gateway() {
+        let config = Config::from_toml(
+            r#"
+            [[exchanges]]
+            name = "binance"
+            exchange = "binance"
+            "#,
+        )
+        .unwrap();
+        let registry = tokio::task::spawn_blocking(move || config.build().unwrap());
+        let gateway = Gateway::new(registry.await.unwrap());
+
+        let err = MarketData::get_ticker(
+            &gateway,
+            Request::new(proto::SymbolRequest {
+                exchange: "okx".into(),
+                symbol: "BTCUSDT".into(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert!(err.message().contains("unknown exchange"));
+
+        let err = Trading::create_order(
+            &gateway,
+            Request::new(proto::CreateOrderRequest {
+                exchange: "binance".into(),
+                symbol: "BTCUSDT".into(),
+                price: 1.0,
+                amount: 1.0,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let spot = serde_json::json!({ "asset": "BTC", "free": 1.5, "locked": 0.5 });
+        let balance = to_balance("BTC", &spot);
+        assert_eq!((balance.free, balance.locked), (1.5, 0.5));
+        let future = serde_json::json!({ "available_balance": 80.0, "initial_margin": 20.0 });
+        let balance = to_balance("USDT", &future);
+        assert_eq!((balance.free, balance.locked), (80.0, 20.0));
+
+        let order = proto::Order::from(models::Order {
+            symbol: "BTCUSDT".into(),
+            order_id: "1".into(),
+            amount: 1.0,
+            price: 100.0,
+            side: "BUY".into(),
+            filled: 0.5,
+            avg_price: 100.0,
+            status: models::OrderStatus::PartiallyFilled,
+            create_time: 1,
+            update_time: 2,
+        });
+        assert_eq!(order.status(), proto::OrderStatus::PartiallyFilled);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::errors::*;
+use crate::http::http_error;
+use crate::utils::Pending;
+
+use log::warn;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// how long a host that failed is skipped by failover
+const COOLDOWN: Duration = Duration::from_secs(30);
+// weight of the latest sample in the moving average latency
+const LATENCY_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    // the first healthy host in the order given
+    Preference,
+    // the healthy host with the lowest average ping
+    Latency,
+}
+
+// probe results of one host
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HostLatency {
+    pub host: String,
+    pub healthy: bool,
+    // moving average of the successful pings, None until one succeeded
+    pub avg_ms: Option<f64>,
+    pub last_ms: Option<f64>,
+    pub samples: u64,
+    pub failures: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    hosts: Vec<String>,
+    active: AtomicUsize,
+    // skipped by failover until then
+    down_until: Mutex<Vec<Option<Instant>>>,
+    cooldown: Mutex<Duration>,
+    selection: Mutex<Selection>,
+    latencies: Mutex<Vec<HostLatency>>,
+}
+
+// interchangeable hosts of one api, in order of preference. requests go to
+// the active host, a host that times out hands over to the next one that
+// isn't cooling down and check() moves back to the best healthy host
+//
+//     let hosts = HostPool::new(BINANCE_SPOT_HOSTS);
+//     hosts.set_selection(Selection::Latency);
+//     api.set_hosts(hosts.clone());
+//     api.check_hosts_every(Duration::from_secs(60));
+//     // ping averages per host
+//     hosts.latencies();
+#[derive(Debug, Clone)]
+pub struct HostPool {
+    inner: Arc<Inner>,
+}
+
+impl HostPool {
+    pub fn new(hosts: &[&str]) -> Self {
+        let hosts: Vec<String> = hosts
+            .iter()
+            .map(|h| h.trim_end_matches('/').into())
+            .collect();
+        assert!(!hosts.is_empty(), "a host pool needs at least one host");
+        HostPool {
+            inner: Arc::new(Inner {
+                down_until: Mutex::new(vec![None; hosts.len()]),
+                latencies: Mutex::new(
+                    hosts
+                        .iter()
+                        .map(|host| HostLatency {
+                            host: host.clone(),
+                            healthy: true,
+                            avg_ms: None,
+                            last_ms: None,
+                            samples: 0,
+                            failures: 0,
+                        })
+                        .collect(),
+                ),
+                selection: Mutex::new(Selection::Preference),
+                hosts,
+                active: AtomicUsize::new(0),
+                cooldown: Mutex::new(COOLDOWN),
+            }),
+        }
+    }
+
+    pub fn single(host: &str) -> Self {
+        HostPool::new(&[host])
+    }
+
+    pub fn set_cooldown(&self, cooldown: Duration) {
+        *self.inner.cooldown.lock().unwrap() = cooldown;
+    }
+
+    pub fn set_selection(&self, selection: Selection) {
+        *self.inner.selection.lock().unwrap() = selection;
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.inner.hosts
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.hosts.is_empty()
+    }
+
+    // the host requests go to
+    pub fn active(&self) -> &str {
+        &self.inner.hosts[self.inner.active.load(Ordering::Relaxed)]
+    }
+
+    pub fn is_down(&self, host: &str) -> bool {
+        match self.index(host) {
+            Some(i) => self.down(i, Instant::now()),
+            None => false,
+        }
+    }
+
+    // marks the host down and fails over when it is the active one. when
+    // every host is down the next one in order is used anyway
+    pub fn report_failure(&self, host: &str) {
+        let i = match self.index(host) {
+            Some(i) => i,
+            None => return,
+        };
+        let now = Instant::now();
+        let cooldown = *self.inner.cooldown.lock().unwrap();
+        self.inner.down_until.lock().unwrap()[i] = Some(now + cooldown);
+        if self.inner.active.load(Ordering::Relaxed) != i {
+            return;
+        }
+        let len = self.len();
+        let next = (1..len)
+            .map(|step| (i + step) % len)
+            .find(|&j| !self.down(j, now))
+            .unwrap_or((i + 1) % len);
+        if next != i {
+            warn!(
+                "host {} failed, switching to {}",
+                host, self.inner.hosts[next]
+            );
+            self.inner.active.store(next, Ordering::Relaxed);
+        }
+    }
+
+    pub fn report_success(&self, host: &str) {
+        if let Some(i) = self.index(host) {
+            self.inner.down_until.lock().unwrap()[i] = None;
+        }
+    }
+
+    // pings every host, marks the failing ones down and makes the best
+    // healthy host active, see Selection
+    //
+    //     hosts.check(|host| api.ping(host));
+    pub fn check<F>(&self, ping: F) -> Vec<(String, bool)>
+    where
+        F: Fn(&str) -> APIResult<()>,
+    {
+        let mut results = vec![];
+        for (i, host) in self.inner.hosts.iter().enumerate() {
+            let start = Instant::now();
+            let healthy = ping(host).is_ok();
+            let ms = start.elapsed().as_secs_f64() * 1000.0;
+            self.record(i, healthy, ms);
+            if healthy {
+                self.report_success(host);
+            } else {
+                self.report_failure(host);
+            }
+            results.push((host.clone(), healthy));
+        }
+        if let Some(i) = self.best() {
+            self.inner.active.store(i, Ordering::Relaxed);
+        }
+        results
+    }
+
+    // latest probe results, in the order of the hosts
+    pub fn latencies(&self) -> Vec<HostLatency> {
+        self.inner.latencies.lock().unwrap().clone()
+    }
+
+    // runs check() every interval on a background thread, which stops once
+    // every clone of the pool is dropped
+    pub fn check_every<F>(&self, interval: Duration, ping: F) -> JoinHandle<()>
+    where
+        F: Fn(&str) -> APIResult<()> + Send + 'static,
+    {
+        let weak: Weak<Inner> = Arc::downgrade(&self.inner);
+        thread::spawn(move || loop {
+            match weak.upgrade() {
+                Some(inner) => {
+                    HostPool { inner }.check(&ping);
+                }
+                None => return,
+            }
+            thread::sleep(interval);
+        })
+    }
+
+    fn record(&self, i: usize, healthy: bool, ms: f64) {
+        let mut latencies = self.inner.latencies.lock().unwrap();
+        let latency = &mut latencies[i];
+        latency.healthy = healthy;
+        if !healthy {
+            latency.failures += 1;
+            return;
+        }
+        latency.samples += 1;
+        latency.last_ms = Some(ms);
+        latency.avg_ms = Some(match latency.avg_ms {
+            Some(avg) => avg + LATENCY_WEIGHT * (ms - avg),
+            None => ms,
+        });
+    }
+
+    fn best(&self) -> Option<usize> {
+        let latencies = self.inner.latencies.lock().unwrap();
+        let healthy = latencies.iter().enumerate().filter(|(_, l)| l.healthy);
+        match *self.inner.selection.lock().unwrap() {
+            Selection::Preference => healthy.map(|(i, _)| i).next(),
+            Selection::Latency => healthy
+                .min_by(|(_, a), (_, b)| {
+                    let a = a.avg_ms.unwrap_or(f64::MAX);
+                    let b = b.avg_ms.unwrap_or(f64::MAX);
+                    a.total_cmp(&b)
+                })
+                .map(|(i, _)| i),
+        }
+    }
+
+    fn index(&self, host: &str) -> Option<usize> {
+        self.inner.hosts.iter().position(|h| h == host)
+    }
+
+    fn down(&self, i: usize, now: Instant) -> bool {
+        matches!(self.inner.down_until.lock().unwrap()[i], Some(until) if until > now)
+    }
+}
+
+impl From<String> for HostPool {
+    fn from(host: String) -> Self {
+        HostPool::single(&host)
+    }
+}
+
+// a request that never reached the host, safe to send again elsewhere
+pub fn is_connect_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(http_error(err), Some(err) if err.is_connect())
+}
+
+// connect errors and timeouts, a timed out request may still have reached the host
+pub fn is_host_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(http_error(err), Some(err) if err.is_connect() || err.is_timeout())
+}
+
+// sends to the active host. a host error fails over, the request is then
+// retried on the next host if it is idempotent or never left the client
+#[maybe_async::maybe_async]
+pub async fn send_with_failover<'a, T, F>(
+    hosts: &HostPool,
+    idempotent: bool,
+    send: F,
+) -> APIResult<T>
+where
+    F: Fn(String) -> Pending<'a, APIResult<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        let host = hosts.active().to_string();
+        attempts += 1;
+        match send(host.clone()).await {
+            Err(err) if is_host_error(err.as_ref()) => {
+                hosts.report_failure(&host);
+                let retry = idempotent || is_connect_error(err.as_ref());
+                if !retry || attempts >= hosts.len() {
+                    return Err(err);
+                }
+            }
+            ret => {
+                hosts.report_success(&host);
+                return ret;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_failover() {
+        let hosts = HostPool::new(&["https://a", "https://b/", "https://c"]);
+        assert_eq!(hosts.active(), "https://a");
+        hosts.report_failure("https://a");
+        assert_eq!(hosts.active(), "https://b");
+        assert!(hosts.is_down("https://a"));
+        // not the active host, only marked down
+        hosts.report_failure("https://c");
+        assert_eq!(hosts.active(), "https://b");
+        // everything down, rotates anyway
+        hosts.report_failure("https://b");
+        assert_eq!(hosts.active(), "https://c");
+
+        let results = hosts.check(|host| {
+            if host == "https://a" {
+                Err(Box::new(ExError::ApiError("down".into())))
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(results[0], ("https://a".to_string(), false));
+        assert_eq!(hosts.active(), "https://b");
+        assert!(!hosts.is_down("https://c"));
+    }
+
+    #[test]
+    fn test_latency_selection() {
+        let hosts = HostPool::new(&["https://a", "https://b", "https://c"]);
+        hosts.set_selection(Selection::Latency);
+        let delays = [30, 5, 1];
+        let ping = |host: &str| {
+            let i = hosts.hosts().iter().position(|h| h == host).unwrap();
+            if i == 2 {
+                return Err(Box::new(ExError::ApiError("down".into())) as Box<_>);
+            }
+            thread::sleep(Duration::from_millis(delays[i]));
+            Ok(())
+        };
+        hosts.check(ping);
+        // c is the fastest but failed
+        assert_eq!(hosts.active(), "https://b");
+        let latencies = hosts.latencies();
+        assert!(latencies[0].avg_ms.unwrap() > latencies[1].avg_ms.unwrap());
+        assert_eq!(latencies[2].failures, 1);
+        assert_eq!(latencies[2].avg_ms, None);
+
+        hosts.set_selection(Selection::Preference);
+        hosts.check(ping);
+        assert_eq!(hosts.active(), "https://a");
+        assert_eq!(hosts.latencies()[0].samples, 2);
+    }
+
+    #[test]
+    fn test_check_every() {
+        let hosts = HostPool::new(&["https://a"]);
+        let handle = hosts.check_every(Duration::from_millis(5), |_| Ok(()));
+        thread::sleep(Duration::from_millis(30));
+        assert!(hosts.latencies()[0].samples > 1);
+        drop(hosts);
+        handle.join().unwrap();
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_send_with_failover() {
+        // a bound port with nothing accepting refuses once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let hosts = HostPool::new(&[&dead, "http://up"]);
+        let tried = Mutex::new(vec![]);
+        let client = crate::http::Client::new();
+        let (tried, client, dead) = (&tried, &client, &dead);
+        let ret = send_with_failover(&hosts, false, |host| {
+            Box::pin(async move {
+                tried.lock().unwrap().push(host.clone());
+                if host == *dead {
+                    client.get(&host).send().await?;
+                }
+                Ok("ok".to_string())
+            })
+        })
+        .await;
+        assert_eq!(ret.unwrap(), "ok");
+        assert_eq!(
+            *tried.lock().unwrap(),
+            vec![dead.clone(), "http://up".to_string()]
+        );
+        assert_eq!(hosts.active(), "http://up");
+    }
+}
@@ -0,0 +1,664 @@
+use crate::errors::*;
+
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+// the http client under the rest clients. reqwest by default, with the ureq
+// feature a smaller, rustls only client without tokio or hyper:
+//
+//     rsex = { version = "0.1", default-features = false, features = ["blocking", "ureq"] }
+//
+// both enabled picks ureq. the websocket streams use the ws crate either way.
+// without the blocking feature send is async, on reqwest's async client
+// running in the caller's tokio runtime. on wasm32 requests go through the
+// global fetch instead, build with default-features = false there, see
+// src/public.rs
+//
+//     let client = http::Client::new();
+//     let resp = client.get("https://api.binance.com/api/v3/time").send()?;
+//     assert_eq!(resp.status(), http::OK);
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(any(feature = "reqwest", feature = "ureq"))
+))]
+compile_error!("rsex needs an http client, enable the reqwest or the ureq feature");
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(feature = "blocking"),
+    not(feature = "reqwest")
+))]
+compile_error!("the async rest clients send with reqwest, enable the reqwest feature");
+
+#[cfg(all(target_arch = "wasm32", feature = "blocking"))]
+compile_error!("fetch can't block, build for wasm32 without the blocking feature");
+
+pub const OK: u16 = 200;
+pub const NOT_MODIFIED: u16 = 304;
+pub const IM_A_TEAPOT: u16 = 418;
+pub const TOO_MANY_REQUESTS: u16 = 429;
+pub const SERVICE_UNAVAILABLE: u16 = 503;
+
+// matches reqwest's default
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "brotli"))]
+const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "brotli")))]
+const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+// header names are kept lowercase
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    headers: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    // replaces a header of the same name
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let name = name.to_lowercase();
+        self.headers.retain(|(n, _)| *n != name);
+        self.headers.push((name, value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    // the request never reached the host
+    Connect,
+    // no response in time, the request may have reached the host
+    Timeout,
+    Other,
+}
+
+// a request that got no response, error statuses are responses
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, message: String) -> Self {
+        Error { kind, message }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn is_connect(&self) -> bool {
+        self.kind == ErrorKind::Connect
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.kind == ErrorKind::Timeout
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http error: {}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "blocking", feature = "ureq"))]
+type Backend = ureq::Agent;
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "blocking",
+    feature = "reqwest",
+    not(feature = "ureq")
+))]
+type Backend = reqwest::blocking::Client;
+// ureq can't do async, the async flavor takes reqwest even with both enabled
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    not(feature = "blocking"),
+    feature = "reqwest"
+))]
+type Backend = reqwest::Client;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "blocking", feature = "ureq"))]
+fn backend(proxy: Option<&str>, timeout: Duration) -> APIResult<Backend> {
+    let mut builder = ureq::AgentBuilder::new().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(ureq::Proxy::new(proxy)?);
+    }
+    Ok(builder.build())
+}
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "reqwest",
+    not(all(feature = "blocking", feature = "ureq"))
+))]
+fn backend(proxy: Option<&str>, timeout: Duration) -> APIResult<Backend> {
+    let mut builder = Backend::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(target_arch = "wasm32")]
+type Backend = ();
+
+#[cfg(target_arch = "wasm32")]
+fn backend(proxy: Option<&str>, _timeout: Duration) -> APIResult<Backend> {
+    match proxy {
+        // fetch goes through the browser's proxy settings
+        Some(proxy) => Err(Box::new(ExError::ApiError(format!(
+            "proxy {} not supported on wasm32",
+            proxy
+        )))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod fetch {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        // the global fetch of browsers, workers and node
+        pub fn fetch(input: &web_sys::Request) -> js_sys::Promise;
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    backend: Backend,
+    timeout: Duration,
+    // fetch negotiates the encoding itself on wasm32
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    compression: bool,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Client")
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client::with_timeout(TIMEOUT).expect("http client")
+    }
+
+    // total time of a request, 30s by default
+    pub fn with_timeout(timeout: Duration) -> APIResult<Self> {
+        Ok(Client {
+            backend: backend(None, timeout)?,
+            timeout,
+            compression: true,
+        })
+    }
+
+    // every request goes through the proxy, e.g. "http://127.0.0.1:8080"
+    pub fn set_proxy(&mut self, proxy: &str) -> APIResult<()> {
+        self.backend = backend(Some(proxy), self.timeout)?;
+        Ok(())
+    }
+
+    // asks for gzip responses, and brotli with the brotli feature, unless a
+    // request sets its own Accept-Encoding. on by default, exchange info and
+    // deep books shrink to a tenth
+    pub fn set_compression(&mut self, compression: bool) {
+        self.compression = compression;
+    }
+
+    pub fn request(&self, method: &str, url: &str) -> RequestBuilder {
+        RequestBuilder {
+            client: self.clone(),
+            method: method.to_uppercase(),
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request("GET", url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.request("POST", url)
+    }
+
+    pub fn put(&self, url: &str) -> RequestBuilder {
+        self.request("PUT", url)
+    }
+
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.request("DELETE", url)
+    }
+}
+
+pub struct RequestBuilder {
+    client: Client,
+    method: String,
+    url: String,
+    headers: HeaderMap,
+    body: Option<Vec<u8>>,
+}
+
+impl RequestBuilder {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        for (name, value) in headers.iter() {
+            self.headers.insert(name, value);
+        }
+        self
+    }
+
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.header("authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn json<T: Serialize>(self, json: &T) -> APIResult<Self> {
+        let body = serde_json::to_vec(json)?;
+        Ok(self.header("content-type", "application/json").body(body))
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), feature = "blocking", feature = "ureq"))]
+    pub fn send(mut self) -> APIResult<Response> {
+        use std::io::Read;
+
+        self.accept_encoding();
+        let mut req = self.client.backend.request(&self.method, &self.url);
+        for (name, value) in self.headers.iter() {
+            req = req.set(name, value);
+        }
+        let ret = match self.body.as_ref() {
+            Some(body) => req.send_bytes(body),
+            None => req.call(),
+        };
+        let resp = match ret {
+            Ok(resp) => resp,
+            // error statuses are handled by the caller like any response
+            Err(ureq::Error::Status(_, resp)) => resp,
+            Err(ureq::Error::Transport(err)) => return Err(Box::new(transport_error(err))),
+        };
+        let status = resp.status();
+        let mut headers = HeaderMap::new();
+        for name in resp.headers_names() {
+            if let Some(value) = resp.header(&name) {
+                headers.insert(&name, value);
+            }
+        }
+        let mut body = vec![];
+        resp.into_reader()
+            .read_to_end(&mut body)
+            .map_err(|err| io_error(&err))?;
+        Response::decoded(status, headers, body)
+    }
+
+    // blocking::Client mirrors the async one, so this is both
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        feature = "reqwest",
+        not(all(feature = "blocking", feature = "ureq"))
+    ))]
+    #[maybe_async::maybe_async]
+    pub async fn send(mut self) -> APIResult<Response> {
+        self.accept_encoding();
+        let method = reqwest::Method::from_bytes(self.method.as_bytes())?;
+        let mut req = self.client.backend.request(method, self.url.as_str());
+        for (name, value) in self.headers.iter() {
+            req = req.header(name, value);
+        }
+        if let Some(body) = self.body {
+            req = req.body(body);
+        }
+        let resp = req.send().await.map_err(reqwest_error)?;
+        let status = resp.status().as_u16();
+        let mut headers = HeaderMap::new();
+        for (name, value) in resp.headers().iter() {
+            if let Ok(value) = value.to_str() {
+                headers.insert(name.as_str(), value);
+            }
+        }
+        let body = resp.bytes().await.map_err(reqwest_error)?.to_vec();
+        Response::decoded(status, headers, body)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub async fn send(self) -> APIResult<Response> {
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let init = web_sys::RequestInit::new();
+        init.set_method(&self.method);
+        if let Some(body) = self.body.as_ref() {
+            init.set_body(&js_sys::Uint8Array::from(body.as_slice()));
+        }
+        let timeout = self.client.timeout.as_millis().min(u32::MAX as u128) as u32;
+        init.set_signal(Some(&web_sys::AbortSignal::timeout_with_u32(timeout)));
+        let req = web_sys::Request::new_with_str_and_init(&self.url, &init).map_err(js_error)?;
+        for (name, value) in self.headers.iter() {
+            req.headers().set(name, value).map_err(js_error)?;
+        }
+        let resp: web_sys::Response = JsFuture::from(fetch::fetch(&req))
+            .await
+            .map_err(js_error)?
+            .unchecked_into();
+        let mut headers = HeaderMap::new();
+        let entries = js_sys::try_iter(&resp.headers()).map_err(js_error)?;
+        for entry in entries.into_iter().flatten() {
+            let pair: js_sys::Array = entry.map_err(js_error)?.unchecked_into();
+            if let (Some(name), Some(value)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+                headers.insert(&name, &value);
+            }
+        }
+        let buffer = JsFuture::from(resp.array_buffer().map_err(js_error)?)
+            .await
+            .map_err(js_error)?;
+        // fetch hands out the body already decompressed
+        headers.remove("content-encoding");
+        headers.remove("content-length");
+        Ok(Response {
+            status: resp.status(),
+            headers,
+            body: js_sys::Uint8Array::new(&buffer).to_vec(),
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn accept_encoding(&mut self) {
+        if self.client.compression && self.headers.get("accept-encoding").is_none() {
+            self.headers.insert("accept-encoding", ACCEPT_ENCODING);
+        }
+    }
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "blocking", feature = "ureq"))]
+fn transport_error(err: ureq::Transport) -> Error {
+    let kind = match err.kind() {
+        ureq::ErrorKind::Dns
+        | ureq::ErrorKind::ConnectionFailed
+        | ureq::ErrorKind::ProxyConnect => ErrorKind::Connect,
+        _ => {
+            let timed_out = std::error::Error::source(&err)
+                .and_then(|source| source.downcast_ref::<std::io::Error>())
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::TimedOut);
+            if timed_out {
+                ErrorKind::Timeout
+            } else {
+                ErrorKind::Other
+            }
+        }
+    };
+    Error::new(kind, err.to_string())
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "blocking", feature = "ureq"))]
+fn io_error(err: &std::io::Error) -> Error {
+    let kind = match err.kind() {
+        std::io::ErrorKind::TimedOut => ErrorKind::Timeout,
+        _ => ErrorKind::Other,
+    };
+    Error::new(kind, err.to_string())
+}
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "reqwest",
+    not(all(feature = "blocking", feature = "ureq"))
+))]
+fn reqwest_error(err: reqwest::Error) -> Error {
+    let kind = if err.is_connect() {
+        ErrorKind::Connect
+    } else if err.is_timeout() {
+        ErrorKind::Timeout
+    } else {
+        ErrorKind::Other
+    };
+    Error::new(kind, err.to_string())
+}
+
+// fetch rejects with a TypeError when the request can't be made and with a
+// TimeoutError once the signal fires
+#[cfg(target_arch = "wasm32")]
+fn js_error(err: wasm_bindgen::JsValue) -> Error {
+    use wasm_bindgen::JsCast;
+
+    let kind = match err.dyn_ref::<web_sys::DomException>() {
+        Some(exception) if exception.name() == "TimeoutError" => ErrorKind::Timeout,
+        _ if err.is_instance_of::<js_sys::TypeError>() => ErrorKind::Connect,
+        _ => ErrorKind::Other,
+    };
+    let message = match err.dyn_ref::<js_sys::Error>() {
+        Some(err) => String::from(err.message()),
+        None => format!("{:?}", err),
+    };
+    Error::new(kind, message)
+}
+
+// the whole body is read by send
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Response {
+    // the body decompressed, the backends are built without decoding so it
+    // works the same on both
+    #[cfg(not(target_arch = "wasm32"))]
+    fn decoded(status: u16, mut headers: HeaderMap, body: Vec<u8>) -> APIResult<Self> {
+        let encoding = headers
+            .get("content-encoding")
+            .unwrap_or("identity")
+            .trim()
+            .to_lowercase();
+        let body = decode(&encoding, body)?;
+        if encoding != "identity" {
+            headers.remove("content-encoding");
+            headers.remove("content-length");
+        }
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn text(self) -> APIResult<String> {
+        Ok(String::from_utf8(self.body)?)
+    }
+
+    // an error for 4xx and 5xx statuses
+    pub fn error_for_status(self) -> APIResult<Self> {
+        if self.status >= 400 {
+            return Err(Box::new(ExError::ApiError(format!(
+                "http status {}",
+                self.status
+            ))));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn decode(encoding: &str, body: Vec<u8>) -> APIResult<Vec<u8>> {
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    let mut decoded = vec![];
+    match encoding {
+        "identity" | "" => return Ok(body),
+        "gzip" | "x-gzip" => GzDecoder::new(body.as_slice()).read_to_end(&mut decoded)?,
+        "deflate" => ZlibDecoder::new(body.as_slice()).read_to_end(&mut decoded)?,
+        #[cfg(feature = "brotli")]
+        "br" => brotli_decompressor::Decompressor::new(body.as_slice(), 4096)
+            .read_to_end(&mut decoded)?,
+        _ => {
+            return Err(Box::new(ExError::ApiError(format!(
+                "unsupported content encoding: {}",
+                encoding
+            ))))
+        }
+    };
+    Ok(decoded)
+}
+
+// an error a request failed with before any response
+pub fn http_error<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a Error> {
+    err.downcast_ref::<Error>()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_header_map() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-MBX-APIKEY", "a");
+        headers.insert("x-mbx-apikey", "b");
+        headers.insert("User-Agent", "rsquant");
+        assert_eq!(headers.get("X-Mbx-ApiKey"), Some("b"));
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![("x-mbx-apikey", "b"), ("user-agent", "rsquant")]
+        );
+    }
+
+    // answers one request with head and body, returns the request lines
+    fn serve(head: &str, body: Vec<u8>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let head = format!(
+            "{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            head,
+            body.len()
+        );
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = vec![];
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                request.push(line.trim_end().to_lowercase());
+                line.clear();
+            }
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+            request
+        });
+        (host, server)
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_client() {
+        let (host, server) = serve(
+            "HTTP/1.1 429 Too Many Requests\r\nX-MBX-USED-WEIGHT-1M: 1200",
+            br#"{"code": -1003}"#.to_vec(),
+        );
+        let resp = Client::new()
+            .get(&format!("{}/api/v3/time", host))
+            .header("X-MBX-APIKEY", "key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), TOO_MANY_REQUESTS);
+        assert!(!resp.is_success());
+        assert_eq!(resp.headers().get("x-mbx-used-weight-1m"), Some("1200"));
+        assert_eq!(resp.text().unwrap(), r#"{"code": -1003}"#);
+        let request = server.join().unwrap();
+        assert_eq!(request[0], "get /api/v3/time http/1.1");
+        assert!(request.contains(&"x-mbx-apikey: key".to_string()));
+
+        // nothing listens there anymore
+        let err = Client::new().get(&host).send().await.unwrap_err();
+        assert!(http_error(err.as_ref()).unwrap().is_connect());
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_compression() {
+        use flate2::write::GzEncoder;
+
+        let info = r#"{"symbols": []}"#;
+        let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(info.as_bytes()).unwrap();
+        let (host, server) = serve(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip",
+            gz.finish().unwrap(),
+        );
+        let resp = Client::new().get(&host).send().await.unwrap();
+        assert_eq!(resp.headers().get("content-encoding"), None);
+        assert_eq!(resp.text().unwrap(), info);
+        let request = server.join().unwrap();
+        assert!(request.contains(&format!("accept-encoding: {}", ACCEPT_ENCODING)));
+
+        let (host, server) = serve("HTTP/1.1 200 OK", info.as_bytes().to_vec());
+        let mut client = Client::new();
+        client.set_compression(false);
+        let resp = client.get(&host).send().await.unwrap();
+        assert_eq!(resp.text().unwrap(), info);
+        let request = server.join().unwrap();
+        assert!(!request
+            .iter()
+            .any(|line| line.starts_with("accept-encoding")));
+    }
+}
@@ -1,3 +1,6 @@
-pub mod spot_rest;
-pub mod spot_ws;
 pub mod types;
+
+native! {
+    pub mod spot_rest;
+    pub mod spot_ws;
+}
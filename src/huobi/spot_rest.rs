@@ -1,41 +1,95 @@
+use crate::audit::{audited, AuditSink};
+use crate::cache::{Fetched, ResponseCache};
+use crate::coalesce::Coalescer;
 use crate::errors::*;
+use crate::expiry::OrderExpiry;
+use crate::http::{self, RequestBuilder};
 use crate::huobi::types::*;
+use crate::limits::RateLimiter;
 use crate::models::*;
+use crate::signer::{HmacSigner, Signer};
+#[cfg(feature = "blocking")]
+use crate::traits::WalletRest;
 use crate::utils::*;
 
-use ring::{digest, hmac};
-use serde_json::Value;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Huobi {
     api_key: String,
-    secret_key: String,
+    signer: Arc<dyn Signer>,
     host: String,
     account_id: String,
     account_type: String,
+    client: http::Client,
+    limiter: Option<RateLimiter>,
+    scope: Scope,
+    audit: Option<Arc<dyn AuditSink>>,
+    cache: Option<ResponseCache>,
+    coalescer: Option<Coalescer>,
+    expiry: Option<OrderExpiry>,
 }
 
+#[maybe_async::maybe_async]
 impl Huobi {
     pub fn new(api_key: Option<String>, secret_key: Option<String>, host: String) -> Self {
         Huobi {
             api_key: api_key.unwrap_or_else(|| "".into()),
-            secret_key: secret_key.unwrap_or_else(|| "".into()),
+            signer: Arc::new(HmacSigner::base64(&secret_key.unwrap_or_default())),
             host,
             account_id: "".into(),
             account_type: "spot".into(),
+            client: http::Client::new(),
+            limiter: None,
+            scope: Scope::all(),
+            audit: None,
+            cache: None,
+            coalescer: None,
+            expiry: None,
         }
     }
 
+    // signs with an external signer instead of the secret key, it must
+    // return the base64 HmacSHA256 huobi expects
+    pub fn set_signer(&mut self, signer: Arc<dyn Signer>) {
+        self.signer = signer;
+    }
+
+    // calls outside the scope fail with PermissionDenied before any request
+    pub fn set_scope(&mut self, scope: Scope) {
+        self.scope = scope;
+    }
+
+    // signed writes (orders, cancels, transfers) are recorded there
+    pub fn set_audit(&mut self, audit: Arc<dyn AuditSink>) {
+        self.audit = Some(audit);
+    }
+
+    // public GETs of endpoints with a ttl in the cache are answered from it
+    pub fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    // identical public GETs in flight at once share one request
+    pub fn set_coalescer(&mut self, coalescer: Coalescer) {
+        self.coalescer = Some(coalescer);
+    }
+
+    // cancels the good till orders the exchange doesn't expire itself
+    pub fn set_expiry(&mut self, expiry: OrderExpiry) {
+        self.expiry = Some(expiry);
+    }
+
     pub fn set_account(&mut self, account_type: &str, account_id: &str) {
         self.account_id = account_id.into();
         self.account_type = account_type.into();
     }
 
-    pub fn get_account_id(&self, account_type: &str) -> APIResult<String> {
+    pub async fn get_account_id(&self, account_type: &str) -> APIResult<String> {
         let uri = "/v1/account/accounts";
         let params: BTreeMap<String, String> = BTreeMap::new();
-        let ret = self.get_signed(&uri, params)?;
+        let ret = self.get_signed(uri, params).await?;
         let resp: Response<Vec<AccountInfo>> = serde_json::from_str(&ret)?;
         let account_id = resp.data.iter().find(|account| account.ty == account_type);
         match account_id {
@@ -44,9 +98,9 @@ impl Huobi {
         }
     }
 
-    pub fn get_symbols(&self) -> APIResult<Vec<SymbolInfo>> {
+    pub async fn get_symbols(&self) -> APIResult<Vec<SymbolInfo>> {
         let uri = "/v1/common/symbols";
-        let ret = self.get(&uri, "")?;
+        let ret = self.get(uri, "").await?;
         let resp: Response<Vec<RawSymbolInfo>> = serde_json::from_str(&ret)?;
         let symbols: Vec<SymbolInfo> = resp
             .data
@@ -56,42 +110,85 @@ impl Huobi {
         Ok(symbols)
     }
 
-    pub fn get(&self, endpoint: &str, request: &str) -> APIResult<String> {
-        let mut url: String = format!("{}{}", self.host, endpoint);
+    // from the precisions, huobi has no tick or step sizes
+    pub async fn get_symbol_rules(&self) -> APIResult<Vec<SymbolRules>> {
+        Ok(self
+            .get_symbols()
+            .await?
+            .iter()
+            .map(|symbol| symbol.into())
+            .collect())
+    }
+
+    // huobi has no ping endpoint, the timestamp call is the cheapest
+    pub async fn ping(&self) -> APIResult<()> {
+        self.get_server_time().await?;
+        Ok(())
+    }
+
+    pub async fn get_server_time(&self) -> APIResult<u64> {
+        let ret = self.get("/v1/common/timestamp", "").await?;
+        let resp: Response<u64> = serde_json::from_str(&ret)?;
+        Ok(resp.data)
+    }
+
+    pub async fn get_system_status(&self) -> APIResult<SystemStatus> {
+        let uri = "/v2/market-status";
+        let ret = self.get(uri, "").await?;
+        let resp: Response<RawMarketStatus> = serde_json::from_str(&ret)?;
+
+        Ok(resp.data.into())
+    }
+
+    pub async fn get_fee_rates(&self, symbol: &str) -> APIResult<FeeRate> {
+        let uri = "/v2/reference/transact-fee-rate";
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("symbols".into(), symbol.to_lowercase());
+        let ret = self.get_signed(uri, params).await?;
+        let resp: Response<Vec<RawFeeRate>> = serde_json::from_str(&ret)?;
+        match resp.data.into_iter().next() {
+            Some(fee) => Ok(fee.into()),
+            None => Err(Box::new(ExError::ApiError(format!(
+                "no fee rate for {}",
+                symbol
+            )))),
+        }
+    }
+
+    pub async fn get(&self, endpoint: &str, request: &str) -> APIResult<String> {
+        let mut path = endpoint.to_string();
         if !request.is_empty() {
-            url.push_str(format!("?{}", request).as_str());
+            path.push_str(format!("?{}", request).as_str());
         }
-        let resp = reqwest::blocking::get(url.as_str())?;
-        let body = resp.text()?;
-        let val: Value = serde_json::from_str(body.as_str())?;
-        if val["status"].as_str() == Some("error") {
-            if let Some(err_msg) = val["err_msg"].as_str() {
-                return Err(Box::new(ExError::ApiError(err_msg.into())));
-            } else {
-                return Err(Box::new(ExError::ApiError(format!("response: {:?}", val))));
-            }
+        let url = format!("{}{}", self.host, path);
+        let (path, url) = (path.as_str(), url.as_str());
+        let get = || -> Pending<APIResult<String>> {
+            Box::pin(async move {
+                match &self.cache {
+                    Some(cache) => {
+                        cache
+                            .get_or_fetch("huobi", endpoint, path, |etag| {
+                                Box::pin(async move { self.fetch(url, etag).await })
+                            })
+                            .await
+                    }
+                    None => self.send(self.client.get(url)).await,
+                }
+            })
+        };
+        match &self.coalescer {
+            Some(coalescer) => coalescer.run(&format!("huobi {}", path), get).await,
+            None => get().await,
         }
-        Ok(body)
     }
 
-    pub fn post(&self, endpoint: &str) -> APIResult<String> {
+    pub async fn post(&self, endpoint: &str) -> APIResult<String> {
         let url: String = format!("{}{}", self.host, endpoint);
-        let client = reqwest::blocking::Client::new();
-        let resp = client.post(url.as_str()).send()?;
-
-        let body = resp.text()?;
-        let val: Value = serde_json::from_str(body.as_str())?;
-        if val["status"].as_str() == Some("error") {
-            if let Some(err_msg) = val["err_msg"].as_str() {
-                return Err(Box::new(ExError::ApiError(err_msg.into())));
-            } else {
-                return Err(Box::new(ExError::ApiError(format!("response: {:?}", val))));
-            }
-        }
-        Ok(body)
+        let req = self.client.post(url.as_str());
+        self.send(req).await
     }
 
-    pub fn get_signed(
+    pub(crate) async fn get_signed(
         &self,
         endpoint: &str,
         mut params: BTreeMap<String, String>,
@@ -102,13 +199,13 @@ impl Huobi {
         params.insert("SignatureMethod".into(), "HmacSHA256".into());
         params.insert("SignatureVersion".into(), "2".into());
 
-        let params_str = self.build_query_string(params);
+        let params_str = build_query_string(&params);
         let split = self.host.split("//").collect::<Vec<&str>>();
         let hostname = split[1];
         let signature = self.sign(&format!(
             "{}\n{}\n{}\n{}",
             "GET", hostname, endpoint, params_str
-        ));
+        ))?;
 
         let req = format!(
             "{}{}?{}&Signature={}",
@@ -118,21 +215,11 @@ impl Huobi {
             percent_encode(&signature)
         );
 
-        let client = reqwest::blocking::Client::new();
-        let resp = client.get(req.as_str()).send()?;
-        let body = resp.text()?;
-        let val: Value = serde_json::from_str(body.as_str())?;
-        if val["status"].as_str() == Some("error") {
-            if let Some(err_msg) = val["err_msg"].as_str() {
-                return Err(Box::new(ExError::ApiError(err_msg.into())));
-            } else {
-                return Err(Box::new(ExError::ApiError(format!("response: {:?}", val))));
-            }
-        }
-        Ok(body)
+        let req = self.client.get(req.as_str());
+        self.send(req).await
     }
 
-    pub fn post_signed(
+    pub(crate) async fn post_signed(
         &self,
         endpoint: &str,
         mut params: BTreeMap<String, String>,
@@ -144,13 +231,13 @@ impl Huobi {
         params.insert("SignatureMethod".into(), "HmacSHA256".into());
         params.insert("SignatureVersion".into(), "2".into());
 
-        let params_str = self.build_query_string(params);
+        let params_str = build_query_string(&params);
         let split = self.host.split("//").collect::<Vec<&str>>();
         let hostname = split[1];
         let signature = self.sign(&format!(
             "{}\n{}\n{}\n{}",
             "POST", hostname, endpoint, params_str
-        ));
+        ))?;
 
         let req = format!(
             "{}{}?{}&Signature={}",
@@ -160,40 +247,87 @@ impl Huobi {
             percent_encode(&signature)
         );
 
-        let client = reqwest::blocking::Client::new();
-        let resp = client.post(req.as_str()).json(body).send()?;
-        let body = resp.text()?;
-        let val: Value = serde_json::from_str(body.as_str())?;
-        if val["status"].as_str() == Some("error") {
-            if let Some(err_msg) = val["err_msg"].as_str() {
-                return Err(Box::new(ExError::ApiError(err_msg.into())));
-            } else {
-                return Err(Box::new(ExError::ApiError(format!("response: {:?}", val))));
-            }
+        let path = format!("{}?{}", endpoint, params_str);
+        let body_str = serde_json::to_string(body)?;
+        audited(&self.audit, "huobi", "POST", &path, Some(body_str), || {
+            Box::pin(async {
+                let req = self.client.post(req.as_str()).json(body)?;
+                self.send(req).await
+            })
+        })
+        .await
+    }
+
+    pub fn set_proxy(&mut self, proxy: &str) -> APIResult<()> {
+        self.client.set_proxy(proxy)
+    }
+
+    // gzip responses, on by default, see http::Client::set_compression
+    pub fn set_compression(&mut self, compression: bool) {
+        self.client.set_compression(compression);
+    }
+
+    pub fn set_rate_limit(&mut self, per_minute: u32) {
+        self.limiter = Some(RateLimiter::per_minute(per_minute));
+    }
+
+    // shares the budget of `limiter` with other clients, see limits::shared_limiter
+    pub fn set_limiter(&mut self, limiter: RateLimiter) {
+        self.limiter = Some(limiter);
+    }
+
+    async fn send(&self, req: RequestBuilder) -> APIResult<String> {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait().await;
         }
-        Ok(body)
+        let body = req.send().await?.text()?;
+        check_response(body)
     }
 
-    fn sign(&self, digest: &str) -> String {
-        use data_encoding::BASE64;
-        let key = hmac::SigningKey::new(&digest::SHA256, self.secret_key.as_bytes());
-        let sig = hmac::sign(&key, digest.as_bytes());
-        BASE64.encode(sig.as_ref())
+    // a public GET for the cache, NotModified while etag still matches
+    async fn fetch(&self, url: &str, etag: Option<String>) -> APIResult<Fetched> {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait().await;
+        }
+        let mut req = self.client.get(url);
+        if let Some(etag) = etag {
+            req = req.header("if-none-match", &etag);
+        }
+        let resp = req.send().await?;
+        if resp.status() == http::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+        let etag = resp.headers().get("etag").map(String::from);
+        Ok(Fetched::Body {
+            body: check_response(resp.text()?)?,
+            etag,
+        })
     }
 
-    fn build_query_string(&self, params: BTreeMap<String, String>) -> String {
-        params
-            .into_iter()
-            .map(|(k, v)| format!("{}={}", k, percent_encode(&v)))
-            .collect::<Vec<String>>()
-            .join("&")
+    fn sign(&self, digest: &str) -> APIResult<String> {
+        self.signer.sign(digest.as_bytes())
     }
 
-    pub fn get_orderbook(&self, symbol: &str, depth: u8) -> APIResult<Orderbook> {
+    // depth 150 returns the full step0 book
+    pub async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
         let uri = "/market/depth";
         let symbol = symbol.to_lowercase();
-        let params = format!("symbol={}&depth={}&type=step0", symbol, depth);
-        let ret = self.get(uri, &params)?;
+        let depth = match depth {
+            5 | 10 | 20 => Some(depth),
+            150 => None,
+            _ => {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid depth: {}, must be one of 5, 10, 20, 150",
+                    depth
+                ))))
+            }
+        };
+        let params = to_query(&DepthParams {
+            symbol: &symbol,
+            depth,
+            step: "step0",
+        })?;
+        let ret = self.get(uri, &params).await?;
         let resp: Response<RawOrderbook> = serde_json::from_str(&ret)?;
         let mut orderbook: Orderbook = resp.tick.into();
         if orderbook.timestamp == 0 {
@@ -202,27 +336,29 @@ impl Huobi {
         Ok(orderbook)
     }
 
-    pub fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+    pub async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
         let uri = "/market/detail/merged";
-        let params = format!("symbol={}", symbol.to_lowercase());
-        let ret = self.get(uri, &params)?;
+        let params = to_query(&SymbolParams {
+            symbol: &symbol.to_lowercase(),
+        })?;
+        let ret = self.get(uri, &params).await?;
         let resp: Response<RawTicker> = serde_json::from_str(&ret)?;
         let mut ticker: Ticker = resp.tick.into();
+        ticker.symbol = symbol.to_lowercase();
         if ticker.timestamp == 0 {
             ticker.timestamp = resp.ts;
         }
         Ok(ticker)
     }
 
-    pub fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+    pub async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
         let uri = "/market/history/kline";
-        let params = format!(
-            "symbol={}&period={}&size={}",
-            symbol.to_lowercase(),
+        let params = to_query(&KlineParams {
+            symbol: &symbol.to_lowercase(),
             period,
-            limit
-        );
-        let ret = self.get(uri, &params)?;
+            size: limit,
+        })?;
+        let ret = self.get(uri, &params).await?;
         let resp: Response<Vec<RawKline>> = serde_json::from_str(&ret)?;
         let klines = resp
             .data
@@ -232,10 +368,10 @@ impl Huobi {
         Ok(klines)
     }
 
-    pub fn get_balance(&self, asset: &str) -> APIResult<Balance> {
+    pub async fn get_balance(&self, asset: &str) -> APIResult<Balance> {
         let uri = format!("/v1/account/accounts/{}/balance", self.account_id);
         let params: BTreeMap<String, String> = BTreeMap::new();
-        let ret = self.get_signed(&uri, params)?;
+        let ret = self.get_signed(&uri, params).await?;
         let resp: Response<BalanceInfo> = serde_json::from_str(&ret)?;
 
         let mut balance = Balance {
@@ -258,36 +394,93 @@ impl Huobi {
         Ok(balance)
     }
 
-    pub fn create_order(
-        &self,
-        symbol: &str,
-        price: f64,
-        amount: f64,
-        action: &str,
-        order_type: &str,
-    ) -> APIResult<String> {
-        let uri = "/v1/order/orders/place";
-        let params: BTreeMap<String, String> = BTreeMap::new();
-        let mut body: BTreeMap<String, String> = BTreeMap::new();
-        body.insert("account-id".into(), self.account_id.clone());
-        body.insert("symbol".into(), symbol.to_string().to_lowercase());
-        let body_type = action.to_string() + "-" + order_type;
-        let body_type = body_type.to_lowercase();
-        body.insert("type".into(), body_type);
-        body.insert("amount".into(), amount.to_string());
-        body.insert("price".into(), price.to_string());
-        body.insert("source".into(), self.account_type.clone() + "-api");
-        let ret = self.post_signed(uri, params, &body)?;
-        let resp: Response<String> = serde_json::from_str(&ret)?;
+    // limit-maker orders are post only
+    pub fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            post_only: true,
+            quote_amount: true,
+            ..ExchangeCapabilities::default()
+        }
+    }
 
-        Ok(resp.data)
+    pub async fn create_order(&self, order: &OrderRequest) -> APIResult<String> {
+        self.scope.check(Capability::Trade, "create_order")?;
+        if order.reduce_only || order.position_side.is_some() {
+            return Err(Box::new(ExError::ApiError(
+                "reduce only and position side are futures only".into(),
+            )));
+        }
+        if order.self_trade_prevention.is_some() {
+            return Err(Box::new(ExError::ApiError(
+                "self trade prevention is not supported on huobi".into(),
+            )));
+        }
+        // buy-limit, sell-market, buy-limit-maker, sell-ioc ...
+        let order_type = match (order.order_type.as_str(), order.post_only) {
+            ("MARKET", true) => {
+                return Err(Box::new(ExError::ApiError("post only market order".into())))
+            }
+            ("LIMIT", true) => "limit-maker".to_string(),
+            ("LIMIT", false) if order.time_in_force == "IOC" => "ioc".to_string(),
+            ("LIMIT", false) if order.time_in_force == "FOK" => "limit-fok".to_string(),
+            (order_type, _) => order_type.to_lowercase(),
+        };
+        // buy-market takes the quote amount to spend, the others the base
+        let amount = match order.quote_amount {
+            Some(quote_amount) if order.is_market() && order.side == "BUY" => quote_amount,
+            Some(_) => {
+                return Err(Box::new(ExError::ApiError(
+                    "quote amount is for market buys only".into(),
+                )))
+            }
+            None => order.amount,
+        };
+        let uri = "/v1/order/orders/place";
+        let order_type = order_type.as_str();
+        let place = |client_id: String| -> Pending<APIResult<String>> {
+            Box::pin(async move {
+                let params: BTreeMap<String, String> = BTreeMap::new();
+                let mut body: BTreeMap<String, String> = BTreeMap::new();
+                body.insert("account-id".into(), self.account_id.clone());
+                body.insert("symbol".into(), order.symbol.to_lowercase());
+                body.insert(
+                    "type".into(),
+                    format!("{}-{}", order.side.to_lowercase(), order_type),
+                );
+                body.insert("amount".into(), amount.to_string());
+                if !order.is_market() {
+                    body.insert("price".into(), order.price.to_string());
+                }
+                body.insert("source".into(), self.account_type.clone() + "-api");
+                body.insert("client-order-id".into(), client_id);
+                let ret = self.post_signed(uri, params, &body).await?;
+                let resp: Response<String> = serde_json::from_str(&ret)?;
+                Ok(resp.data)
+            })
+        };
+        let lookup = |client_id: String| -> Pending<APIResult<String>> {
+            Box::pin(async move {
+                let mut params: BTreeMap<String, String> = BTreeMap::new();
+                params.insert("clientOrderId".into(), client_id);
+                let ret = self
+                    .get_signed("/v1/order/orders/getClientOrder", params)
+                    .await?;
+                let resp: Response<RawOrderInfo> = serde_json::from_str(&ret)?;
+                Ok(resp.data.id.to_string())
+            })
+        };
+        OrderExpiry::place(self.expiry.as_ref(), order, false, || {
+            Box::pin(async { place_order_once(order.client_id.as_deref(), place, lookup).await })
+        })
+        .await
     }
 
-    pub fn cancel(&self, id: &str) -> APIResult<bool> {
+    pub async fn cancel(&self, _symbol: &str, id: &str) -> APIResult<bool> {
+        self.scope.check(Capability::Trade, "cancel")?;
         let uri = format!("/v1/order/orders/{}/submitcancel", id);
         let params: BTreeMap<String, String> = BTreeMap::new();
         let body: BTreeMap<String, String> = BTreeMap::new();
-        let ret = self.post_signed(&uri, params, &body)?;
+        let ret = self.post_signed(&uri, params, &body).await?;
         let resp: Response<String> = serde_json::from_str(&ret)?;
         if resp.status == "ok" {
             Ok(true)
@@ -296,31 +489,32 @@ impl Huobi {
         }
     }
 
-    pub fn cancel_all(&self, symbol: &str) -> APIResult<bool> {
+    pub async fn cancel_all(&self, symbol: &str) -> APIResult<bool> {
+        self.scope.check(Capability::Trade, "cancel_all")?;
         let uri = "/v1/order/orders/batchCancelOpenOrders";
         let params: BTreeMap<String, String> = BTreeMap::new();
         let mut body: BTreeMap<String, String> = BTreeMap::new();
         body.insert("account-id".into(), self.account_id.clone());
         body.insert("symbol".into(), symbol.to_string().to_lowercase());
-        let _ret = self.post_signed(uri, params, &body)?;
+        let _ret = self.post_signed(uri, params, &body).await?;
         Ok(true)
     }
 
-    pub fn get_order(&self, id: &str) -> APIResult<Order> {
+    pub async fn get_order(&self, _symbol: &str, id: &str) -> APIResult<Order> {
         let uri = format!("/v1/order/orders/{}", id);
         let params: BTreeMap<String, String> = BTreeMap::new();
-        let ret = self.get_signed(&uri, params)?;
+        let ret = self.get_signed(&uri, params).await?;
         let resp: Response<RawOrderInfo> = serde_json::from_str(&ret)?;
 
         Ok(resp.data.into())
     }
 
-    pub fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+    pub async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
         let uri = "/v1/order/openOrders";
         let mut params: BTreeMap<String, String> = BTreeMap::new();
         params.insert("account-id".into(), self.account_id.clone());
         params.insert("symbol".into(), symbol.to_string().to_lowercase());
-        let ret = self.get_signed(uri, params)?;
+        let ret = self.get_signed(uri, params).await?;
         let resp: Response<Vec<RawOrderInfo>> = serde_json::from_str(&ret)?;
 
         let orders = resp
@@ -332,19 +526,121 @@ impl Huobi {
         Ok(orders)
     }
 
-    pub fn get_history_orders(&self, _symbol: &str) -> APIResult<Vec<Order>> {
-        unimplemented!()
+    pub async fn get_history_orders(&self, _symbol: &str) -> APIResult<Vec<Order>> {
+        Err(Box::new(ExError::NotSupported(
+            "history orders on huobi".into(),
+        )))
+    }
+
+    // latest 500 deposits or withdrawals of the currency, for the wallet
+    #[cfg(feature = "blocking")]
+    async fn get_transfers(&self, coin: &str, ty: &str) -> APIResult<Vec<RawTransfer>> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("currency".into(), coin.to_lowercase());
+        params.insert("type".into(), ty.into());
+        params.insert("size".into(), "500".into());
+        let ret = self
+            .get_signed("/v1/query/deposit-withdraw", params)
+            .await?;
+        let resp: Response<Vec<RawTransfer>> = serde_json::from_str(&ret)?;
+        Ok(resp.data)
+    }
+}
+
+// huobi answers errors with status 200 and "status": "error" in the body
+#[cfg(feature = "blocking")]
+impl WalletRest for Huobi {
+    fn get_coin_networks(&self, coin: &str) -> APIResult<Vec<CoinNetwork>> {
+        let params = to_query(&CurrencyParams {
+            currency: &coin.to_lowercase(),
+        })?;
+        let ret = self.get("/v2/reference/currencies", &params)?;
+        let resp: Response<Vec<RawCurrencyChains>> = serde_json::from_str(&ret)?;
+        Ok(resp
+            .data
+            .into_iter()
+            .flat_map(|c| {
+                let currency = c.currency;
+                c.chains
+                    .into_iter()
+                    .map(move |chain| chain.into_network(&currency))
+            })
+            .collect())
+    }
+
+    // one address per chain, the first one when no network is given
+    fn get_deposit_address(&self, coin: &str, network: Option<&str>) -> APIResult<DepositAddress> {
+        let mut params: BTreeMap<String, String> = BTreeMap::new();
+        params.insert("currency".into(), coin.to_lowercase());
+        let ret = self.get_signed("/v2/account/deposit/address", params)?;
+        let resp: Response<Vec<RawDepositAddress>> = serde_json::from_str(&ret)?;
+        match resp
+            .data
+            .into_iter()
+            .find(|a| network.is_none_or(|n| a.chain.eq_ignore_ascii_case(n)))
+        {
+            Some(address) => Ok(address.into()),
+            None => Err(Box::new(ExError::ApiError(format!(
+                "no deposit address for {} on {:?}",
+                coin, network
+            )))),
+        }
+    }
+
+    fn get_deposits(&self, coin: &str, since: u64) -> APIResult<Vec<Deposit>> {
+        let mut deposits: Vec<Deposit> = self
+            .get_transfers(coin, "deposit")?
+            .into_iter()
+            .filter(|t| t.created_at >= since)
+            .map(|t| t.into())
+            .collect();
+        deposits.sort_by_key(|deposit| deposit.timestamp);
+        Ok(deposits)
+    }
+
+    fn withdraw(&self, req: &WithdrawRequest) -> APIResult<String> {
+        self.scope.check(Capability::Withdraw, "withdraw")?;
+        let mut body: BTreeMap<String, String> = BTreeMap::new();
+        body.insert("currency".into(), req.coin.to_lowercase());
+        if let Some(network) = &req.network {
+            body.insert("chain".into(), network.to_lowercase());
+        }
+        body.insert("address".into(), req.address.clone());
+        body.insert("amount".into(), req.amount.to_string());
+        if let Some(memo) = &req.memo {
+            body.insert("addr-tag".into(), memo.clone());
+        }
+        if let Some(client_id) = &req.client_id {
+            body.insert("client-order-id".into(), client_id.clone());
+        }
+        let ret = self.post_signed("/v1/dw/withdraw/api/create", BTreeMap::new(), &body)?;
+        let resp: Response<u64> = serde_json::from_str(&ret)?;
+        Ok(resp.data.to_string())
+    }
+
+    fn get_withdrawal(&self, coin: &str, id: &str) -> APIResult<Withdrawal> {
+        match self
+            .get_transfers(coin, "withdraw")?
+            .into_iter()
+            .find(|t| t.id.to_string() == id)
+        {
+            Some(withdrawal) => Ok(withdrawal.into()),
+            None => Err(Box::new(ExError::ApiError(format!(
+                "withdrawal {} not found",
+                id
+            )))),
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "blocking"))]
 mod test {
     #![allow(dead_code)]
     use super::*;
 
-    const HOST: &'static str = "https://api.huobi.pro";
-    const API_KEY: &'static str = "2ed1ae8e-7015f4e4-85c65e29-edrfhh5h53";
-    const SECRET_KEY: &'static str = "259f957f-e568adb8-5b4e5a15-be8d6";
+    const HOST: &str = "https://api.huobi.pro";
+    const API_KEY: &str = "2ed1ae8e-7015f4e4-85c65e29-edrfhh5h53";
+    const SECRET_KEY: &str = "259f957f-e568adb8-5b4e5a15-be8d6";
 
     //#[test]
     fn test_get_symbols() {
@@ -398,7 +694,7 @@ mod test {
         api.set_account("spot", &acc_id);
 
         // create_order
-        let order_id = api.create_order("NEXOBTC", 0.00002500, 100.0, "SELL", "LIMIT");
+        let order_id = api.create_order(&OrderRequest::limit_sell("NEXOBTC", 0.00002500, 100.0));
         println!("order_id: {:?}", order_id);
 
         // get_open_orders
@@ -409,7 +705,7 @@ mod test {
         let _ = api.cancel_all("NEXOBTC");
 
         // get_order
-        let order = api.get_order(&order_id.unwrap());
+        let order = api.get_order("NEXOBTC", &order_id.unwrap());
         println!("order: {:?}", order);
     }
 }
@@ -1,14 +1,25 @@
 use crate::errors::*;
 use crate::huobi::types::*;
 use crate::models::*;
-use flate2::read::GzDecoder;
-use std::io::prelude::*;
-use ws::{Handler, Handshake, Message, Result, Sender};
+use crate::shutdown::{Shutdown, WsGuard};
+use crate::stream::EventStream;
+use crate::utils::{decode_ws_message, from_ws_str};
+use log::warn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use ws::util::Token;
+use ws::{CloseCode, Frame, Handler, Handshake, Message, Result, Sender};
 
-#[derive(Debug)]
+const HEARTBEAT: Token = Token(1);
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize)]
 pub enum WsEvent {
     OrderbookEvent(Orderbook),
-    KlineEvent(Kline),
+    KlineEvent(KlineUpdate),
     TickerEvent(Ticker),
     TradeEvent(Vec<Trade>),
     ResponseEvent(ResponseEvent),
@@ -32,6 +43,16 @@ pub struct HuobiWs<'a> {
     host: String,
     subs: Vec<String>,
     out: Option<Sender>,
+    reconnect: bool,
+    // huobi pings every 5s, the connection is dropped after stale_after without any frame
+    stale_after: Duration,
+    last_activity: Instant,
+    // latest kline per channel, huobi has no close flag so a candle is
+    // closed once an update with a newer id arrives
+    last_kline: Rc<RefCell<HashMap<String, KlineUpdate>>>,
+    // closes the connection and stops reconnecting once shut down
+    shutdown: Option<Shutdown>,
+    registered: Option<WsGuard>,
 
     handler: Box<dyn FnMut(WsEvent) -> Result<()> + 'a>,
 }
@@ -42,6 +63,12 @@ impl<'a> HuobiWs<'a> {
             host: host.into(),
             subs: vec![],
             out: None,
+            reconnect: true,
+            stale_after: Duration::from_secs(30),
+            last_activity: Instant::now(),
+            last_kline: Rc::new(RefCell::new(HashMap::new())),
+            shutdown: None,
+            registered: None,
             handler: Box::new(|event| {
                 println!("event: {:?}", event);
                 Ok(())
@@ -49,17 +76,77 @@ impl<'a> HuobiWs<'a> {
         }
     }
 
-    pub fn connect<Callback: Clone>(&mut self, handler: Callback)
+    pub fn set_reconnect(&mut self, reconnect: bool) {
+        self.reconnect = reconnect;
+    }
+
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = Some(shutdown);
+    }
+
+    pub fn set_stale_after(&mut self, stale_after: Duration) {
+        self.stale_after = stale_after;
+    }
+
+    // blocks; subscriptions are sent again after every reconnect
+    pub fn connect<Callback>(&mut self, handler: Callback)
     where
-        Callback: FnMut(WsEvent) -> Result<()> + 'a,
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
     {
-        ws::connect(self.host.clone(), |out| HuobiWs {
-            host: self.host.clone(),
-            subs: self.subs.clone(),
-            out: Some(out),
-            handler: Box::new(handler.clone()),
+        self.connect_until(handler, &|| false);
+    }
+
+    // runs the client on its own thread and yields its events
+    pub fn into_stream(self) -> EventStream<WsEvent> {
+        let host = self.host;
+        let subs = self.subs;
+        let reconnect = self.reconnect;
+        let shutdown = self.shutdown;
+        let stale_after = self.stale_after;
+        EventStream::spawn(move |tx| {
+            let mut client = HuobiWs::new(&host);
+            client.subs = subs;
+            client.reconnect = reconnect;
+            client.shutdown = shutdown;
+            client.stale_after = stale_after;
+            let done = tx.clone();
+            client.connect_until(
+                move |event| {
+                    tx.unbounded_send(event)
+                        .map_err(|err| ws::Error::new(ws::ErrorKind::Internal, err.to_string()))
+                },
+                &|| done.is_closed(),
+            );
         })
-        .unwrap();
+    }
+
+    fn connect_until<Callback>(&mut self, handler: Callback, done: &dyn Fn() -> bool)
+    where
+        Callback: FnMut(WsEvent) -> Result<()> + Clone + 'a,
+    {
+        loop {
+            let ret = ws::connect(self.host.clone(), |out| HuobiWs {
+                host: self.host.clone(),
+                subs: self.subs.clone(),
+                out: Some(out),
+                reconnect: self.reconnect,
+                stale_after: self.stale_after,
+                last_activity: Instant::now(),
+                last_kline: self.last_kline.clone(),
+                shutdown: self.shutdown.clone(),
+                registered: None,
+                handler: Box::new(handler.clone()),
+            });
+            if let Err(err) = ret {
+                warn!("ws error: {:?}", err);
+            }
+            let stopped = matches!(&self.shutdown, Some(s) if s.is_shutdown());
+            if !self.reconnect || stopped || done() {
+                break;
+            }
+            warn!("ws disconnected, reconnecting");
+            thread::sleep(Duration::from_secs(1));
+        }
     }
 
     pub fn sub_kline(&mut self, symbol: &str, period: &str) {
@@ -92,9 +179,45 @@ impl<'a> HuobiWs<'a> {
         ));
     }
 
+    // emits the previous candle as closed when a new one starts
+    fn kline_events(&self, update: KlineUpdate) -> Vec<WsEvent> {
+        let key = format!("{}@{}", update.symbol, update.period);
+        let mut last_kline = self.last_kline.borrow_mut();
+        let mut events = vec![];
+        match last_kline.get(&key) {
+            Some(last) if last.kline.timestamp > update.kline.timestamp => return events,
+            Some(last) if last.kline.timestamp < update.kline.timestamp => {
+                let mut closed = last.clone();
+                closed.is_closed = true;
+                events.push(WsEvent::KlineEvent(closed));
+            }
+            _ => {}
+        }
+        last_kline.insert(key, update.clone());
+        events.push(WsEvent::KlineEvent(update));
+        events
+    }
+
+    pub fn sub_tickers(&mut self, symbols: &[&str]) {
+        for symbol in symbols {
+            self.sub_ticker(symbol);
+        }
+    }
+
+    // a handler error closes the connection
+    fn dispatch(&mut self, event: WsEvent) -> Result<()> {
+        if let Err(err) = (self.handler)(event) {
+            warn!("handler error: {:?}", err);
+            if let Some(out) = &self.out {
+                out.close(CloseCode::Normal)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn deseralize(&self, s: &str) -> APIResult<WsEvent> {
-        if s.find("ping") != None {
-            let ping: Ping = serde_json::from_str(s)?;
+        if s.contains("ping") {
+            let ping: Ping = from_ws_str(s)?;
             match &self.out {
                 Some(out) => {
                     let msg = format!("{{\"pong\":{}}}", ping.ping);
@@ -106,27 +229,45 @@ impl<'a> HuobiWs<'a> {
             }
             return Ok(WsEvent::PingEvent(ping));
         }
-        if s.find("tick") == None {
-            let resp: ResponseEvent = serde_json::from_str(s)?;
+        if !s.contains("tick") {
+            let resp: ResponseEvent = from_ws_str(s)?;
             return Ok(WsEvent::ResponseEvent(resp));
         }
-        //let val: Value = serde_json::from_str(s)?;
-        if s.find("kline") != None {
-            let resp: Response<RawKline> = serde_json::from_str(&s)?;
-            Ok(WsEvent::KlineEvent(resp.tick.into()))
-        } else if s.find("depth") != None {
-            let resp: Response<RawOrderbook> = serde_json::from_str(&s)?;
+        //let val: Value = from_ws_str(s)?;
+        if s.contains("kline") {
+            let resp: Response<RawKline> = from_ws_str(s)?;
+            // ch: market.$symbol.kline.$period
+            let parts: Vec<&str> = resp.ch.split('.').collect();
+            if parts.len() != 4 {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "invalid kline channel: {}",
+                    resp.ch
+                ))));
+            }
+            Ok(WsEvent::KlineEvent(KlineUpdate {
+                symbol: parts[1].into(),
+                period: parts[3].into(),
+                kline: resp.tick.into(),
+                is_closed: false,
+            }))
+        } else if s.contains("depth") {
+            let resp: Response<RawOrderbook> = from_ws_str(s)?;
             Ok(WsEvent::OrderbookEvent(resp.tick.into()))
-        } else if s.find("bbo") != None {
-            let resp: Response<RawTicker> = serde_json::from_str(&s)?;
+        } else if s.contains("bbo") {
+            let resp: Response<RawBbo> = from_ws_str(s)?;
             Ok(WsEvent::TickerEvent(resp.tick.into()))
-        } else if s.find("trade.detail") != None {
-            let resp: Response<Response<Vec<RawTrade>>> = serde_json::from_str(&s)?;
+        } else if s.contains("trade.detail") {
+            let resp: Response<Response<Vec<RawTrade>>> = from_ws_str(s)?;
+            // ch: market.$symbol.trade.detail
+            let symbol = resp.ch.split('.').nth(1).unwrap_or_default().to_string();
             let trades = resp
                 .tick
                 .data
                 .into_iter()
-                .map(|raw_trade| raw_trade.into())
+                .map(|raw_trade| Trade {
+                    symbol: symbol.clone(),
+                    ..raw_trade.into()
+                })
                 .collect::<Vec<Trade>>();
             Ok(WsEvent::TradeEvent(trades))
         } else {
@@ -138,9 +279,15 @@ impl<'a> HuobiWs<'a> {
 impl<'a> Handler for HuobiWs<'a> {
     fn on_open(&mut self, _shake: Handshake) -> Result<()> {
         match &self.out {
-            Some(out) => self.subs.iter().for_each(|s| {
-                let _ = out.send(s.as_str());
-            }),
+            Some(out) => {
+                if let Some(shutdown) = &self.shutdown {
+                    self.registered = Some(shutdown.register_ws(out.clone()));
+                }
+                self.subs.iter().for_each(|s| {
+                    let _ = out.send(s.as_str());
+                });
+                out.timeout(self.stale_after.as_millis() as u64, HEARTBEAT)?;
+            }
             None => {
                 println!("self.out is None");
             }
@@ -148,17 +295,45 @@ impl<'a> Handler for HuobiWs<'a> {
         Ok(())
     }
 
+    fn on_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        self.last_activity = Instant::now();
+        Ok(Some(frame))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> Result<()> {
+        if event != HEARTBEAT {
+            return Ok(());
+        }
+        if let Some(out) = &self.out {
+            let elapsed = self.last_activity.elapsed();
+            if elapsed >= self.stale_after {
+                warn!("ws stale for {:?}, dropping connection", elapsed);
+                return out.shutdown();
+            }
+            out.timeout((self.stale_after - elapsed).as_millis() as u64, HEARTBEAT)?;
+        }
+        Ok(())
+    }
+
     fn on_message(&mut self, msg: Message) -> Result<()> {
-        let slice = &msg.into_data()[..];
-        let mut d = GzDecoder::new(slice);
-        let mut s = String::new();
-        d.read_to_string(&mut s).unwrap();
+        let s = match decode_ws_message(msg) {
+            Ok(s) => s,
+            Err(err) => {
+                warn!("decode msg error: {:?}", err);
+                return Ok(());
+            }
+        };
         match self.deseralize(&s) {
+            Ok(WsEvent::KlineEvent(update)) => {
+                for event in self.kline_events(update) {
+                    self.dispatch(event)?;
+                }
+            }
             Ok(event) => {
-                let _ = (self.handler)(event);
+                self.dispatch(event)?;
             }
             Err(err) => {
-                println!("deseralize msg error: {:?}", err);
+                warn!("deseralize msg error: {:?}", err);
             }
         }
         Ok(())
@@ -167,9 +342,38 @@ impl<'a> Handler for HuobiWs<'a> {
 
 #[cfg(test)]
 mod test {
+    #![allow(dead_code)]
     use super::*;
     use crate::utils::get_timestamp;
 
+    #[test]
+    fn test_kline_close() {
+        let ws = HuobiWs::new("wss://api.huobi.pro/ws");
+        let msg = |id: u64, close: f64| {
+            format!(
+                "{{\"ch\":\"market.btcusdt.kline.1min\",\"ts\":1,\"tick\":{{\"id\":{},\"amount\":1.0,\"count\":1,\"open\":1.0,\"high\":2.0,\"low\":0.5,\"close\":{},\"vol\":1.0}}}}",
+                id, close
+            )
+        };
+        let mut closed = vec![];
+        for (id, close) in [(60, 1.0), (60, 1.5), (120, 1.2), (60, 1.7)].iter() {
+            if let WsEvent::KlineEvent(update) = ws.deseralize(&msg(*id, *close)).unwrap() {
+                assert_eq!(update.symbol, "btcusdt");
+                assert_eq!(update.period, "1min");
+                for event in ws.kline_events(update) {
+                    if let WsEvent::KlineEvent(e) = event {
+                        if e.is_closed {
+                            closed.push(e);
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].kline.timestamp, 60);
+        assert_eq!(closed[0].kline.close, 1.5);
+    }
+
     //#[test]
     fn test_huobiws() {
         env_logger::init();
@@ -1,8 +1,40 @@
-use crate::constant::*;
+use crate::errors::*;
 use crate::models::*;
 
+use serde_json::Value;
+
+// query params of the market endpoints
+#[derive(Serialize)]
+pub struct SymbolParams<'a> {
+    pub symbol: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct DepthParams<'a> {
+    pub symbol: &'a str,
+    // the full book when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u16>,
+    #[serde(rename = "type")]
+    pub step: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct KlineParams<'a> {
+    pub symbol: &'a str,
+    pub period: &'a str,
+    pub size: u16,
+}
+
+// of the reference endpoints, currency is lowercase
+#[derive(Serialize)]
+pub struct CurrencyParams<'a> {
+    pub currency: &'a str,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Response<T> {
+    #[serde(default)]
     pub status: String,
     #[serde(default)]
     pub ch: String,
@@ -20,6 +52,32 @@ pub struct Response<T> {
     pub order_state: i8,
 }
 
+// the body, or the error of a `"status": "error"` response
+pub fn check_response(body: String) -> APIResult<String> {
+    let val: Value = serde_json::from_str(body.as_str())?;
+    if val["status"].as_str() == Some("error") {
+        if let Some(code) = val["err-code"]
+            .as_str()
+            .filter(|c| c.contains("maintenance"))
+        {
+            return Err(Box::new(ExError::ExchangeInMaintenance(code.into())));
+        }
+        // the order is filled or cancelled already, or there is no such order
+        if let Some("order-orderstate-error" | "base-record-invalid") = val["err-code"].as_str() {
+            return Err(Box::new(ExError::UnknownOrder(format!(
+                "response: {:?}",
+                val
+            ))));
+        }
+        if let Some(err_msg) = val["err_msg"].as_str() {
+            return Err(Box::new(ExError::ApiError(err_msg.into())));
+        } else {
+            return Err(Box::new(ExError::ApiError(format!("response: {:?}", val))));
+        }
+    }
+    Ok(body)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AccountInfo {
     pub id: u32,
@@ -57,10 +115,17 @@ pub struct RawSymbolInfo {
 
 impl From<RawSymbolInfo> for SymbolInfo {
     fn from(item: RawSymbolInfo) -> SymbolInfo {
+        let status = match item.state.as_str() {
+            "online" => SymbolStatus::Trading,
+            "pre-online" => SymbolStatus::PreTrading,
+            "offline" => SymbolStatus::Delisted,
+            _ => SymbolStatus::Suspended,
+        };
         SymbolInfo {
             base: item.base,
             quote: item.quote,
             symbol: item.symbol,
+            status,
             price_precision: item.price_precision,
             amount_precision: item.amount_precision,
             min_amount: item.min_amount,
@@ -99,6 +164,7 @@ impl From<RawOrderbook> for Orderbook {
             .collect::<Vec<Ask>>();
         Orderbook {
             timestamp: item.ts,
+            last_update_id: item.version,
             bids,
             asks,
         }
@@ -124,6 +190,7 @@ pub struct RawTicker {
 impl From<RawTicker> for Ticker {
     fn from(item: RawTicker) -> Ticker {
         Ticker {
+            symbol: String::new(),
             timestamp: item.ts,
             ask: Ask {
                 price: item.ask[0],
@@ -163,6 +230,35 @@ impl From<RawKline> for Kline {
     }
 }
 
+// best bid/offer pushed by the market.$symbol.bbo channel
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBbo {
+    pub symbol: String,
+    pub quote_time: u64,
+    pub ask: f64,
+    pub ask_size: f64,
+    pub bid: f64,
+    pub bid_size: f64,
+}
+
+impl From<RawBbo> for Ticker {
+    fn from(item: RawBbo) -> Ticker {
+        Ticker {
+            symbol: item.symbol,
+            timestamp: item.quote_time,
+            ask: Ask {
+                price: item.ask,
+                amount: item.ask_size,
+            },
+            bid: Bid {
+                price: item.bid,
+                amount: item.bid_size,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct BalanceInfoItem {
     pub currency: String,
@@ -211,13 +307,19 @@ pub struct RawOrderInfo {
 impl From<RawOrderInfo> for Order {
     fn from(item: RawOrderInfo) -> Order {
         let status = match item.state.as_str() {
-            "partial-filled" => ORDER_STATUS_PART_FILLED,
-            "partial-canceled" => ORDER_STATUS_CANCELLED,
-            "filled" => ORDER_STATUS_FILLED,
-            "canceled" => ORDER_STATUS_CANCELLED,
-            "created" => ORDER_STATUS_SUBMITTED,
-            "submitted" => ORDER_STATUS_SUBMITTED,
-            _ => ORDER_STATUS_FAILED,
+            "partial-filled" => OrderStatus::PartiallyFilled,
+            "partial-canceled" => OrderStatus::Canceled,
+            "filled" => OrderStatus::Filled,
+            "canceled" => OrderStatus::Canceled,
+            "created" | "submitted" | "canceling" => OrderStatus::New,
+            "rejected" => OrderStatus::Rejected,
+            _ => OrderStatus::Unknown,
+        };
+        let filled = item.filled_amount.parse::<f64>().unwrap_or(0.0);
+        let avg_price = if filled > 0.0 {
+            item.filled_cash_amount.parse::<f64>().unwrap_or(0.0) / filled
+        } else {
+            0.0
         };
         let side = if item.ty.starts_with("sell") {
             "SELL"
@@ -231,8 +333,11 @@ impl From<RawOrderInfo> for Order {
             amount: item.amount.parse::<f64>().unwrap_or(0.0),
             price: item.price.parse::<f64>().unwrap_or(0.0),
             side: side.into(),
-            filled: item.price.parse::<f64>().unwrap_or(0.0),
+            filled,
+            avg_price,
             status,
+            create_time: item.create_at,
+            update_time: item.create_at.max(item.finished_at).max(item.canceled_at),
         }
     }
 }
@@ -251,6 +356,8 @@ pub struct RawTrade {
 impl From<RawTrade> for Trade {
     fn from(item: RawTrade) -> Trade {
         Trade {
+            symbol: String::new(),
+            trade_id: item.trade_id,
             timestamp: item.ts,
             amount: item.amount,
             price: item.price,
@@ -258,3 +365,237 @@ impl From<RawTrade> for Trade {
         }
     }
 }
+
+// /v2/reference/transact-fee-rate
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawFeeRate {
+    pub symbol: String,
+    pub actual_maker_rate: String,
+    pub actual_taker_rate: String,
+}
+
+impl From<RawFeeRate> for FeeRate {
+    fn from(item: RawFeeRate) -> FeeRate {
+        FeeRate {
+            symbol: item.symbol,
+            maker: item.actual_maker_rate.parse::<f64>().unwrap_or(0.0),
+            taker: item.actual_taker_rate.parse::<f64>().unwrap_or(0.0),
+        }
+    }
+}
+
+// /v2/market-status, 1 normal, 2 halted and 3 cancel only
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawMarketStatus {
+    pub market_status: u8,
+    #[serde(default)]
+    pub halt_reason: Option<u8>,
+}
+
+impl From<RawMarketStatus> for SystemStatus {
+    fn from(item: RawMarketStatus) -> SystemStatus {
+        match item.market_status {
+            1 => SystemStatus::Normal,
+            2 => SystemStatus::Maintenance("halted".into()),
+            _ => SystemStatus::Maintenance("cancel only".into()),
+        }
+    }
+}
+
+// /v2/reference/currencies
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawCurrencyChains {
+    pub currency: String,
+    pub chains: Vec<RawChain>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawChain {
+    pub chain: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub num_of_confirmations: u32,
+    // allowed or prohibited
+    pub deposit_status: String,
+    pub withdraw_status: String,
+    #[serde(default)]
+    pub min_withdraw_amt: String,
+    #[serde(default)]
+    pub max_withdraw_amt: String,
+    // set for fixed fees, the min fee otherwise
+    #[serde(default)]
+    pub transact_fee_withdraw: String,
+    #[serde(default)]
+    pub min_transact_fee_withdraw: String,
+    #[serde(default)]
+    pub addr_with_tag: bool,
+}
+
+impl RawChain {
+    pub fn into_network(self, currency: &str) -> CoinNetwork {
+        let num = |v: &str| v.parse::<f64>().unwrap_or(0.0);
+        let fee = if self.transact_fee_withdraw.is_empty() {
+            num(&self.min_transact_fee_withdraw)
+        } else {
+            num(&self.transact_fee_withdraw)
+        };
+        CoinNetwork {
+            coin: currency.to_uppercase(),
+            network: self.chain,
+            name: self.display_name,
+            is_default: false,
+            deposit_enabled: self.deposit_status == "allowed",
+            withdraw_enabled: self.withdraw_status == "allowed",
+            withdraw_fee: fee,
+            withdraw_min: num(&self.min_withdraw_amt),
+            withdraw_max: num(&self.max_withdraw_amt),
+            memo_required: self.addr_with_tag,
+            min_confirmations: self.num_of_confirmations,
+        }
+    }
+}
+
+// /v2/account/deposit/address
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawDepositAddress {
+    pub currency: String,
+    pub address: String,
+    #[serde(default)]
+    pub address_tag: String,
+    #[serde(default)]
+    pub chain: String,
+}
+
+impl From<RawDepositAddress> for DepositAddress {
+    fn from(item: RawDepositAddress) -> DepositAddress {
+        DepositAddress {
+            coin: item.currency.to_uppercase(),
+            network: item.chain,
+            address: item.address,
+            memo: Some(item.address_tag).filter(|tag| !tag.is_empty()),
+        }
+    }
+}
+
+// /v1/query/deposit-withdraw, type deposit or withdraw
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct RawTransfer {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub currency: String,
+    #[serde(default)]
+    pub tx_hash: String,
+    #[serde(default)]
+    pub chain: String,
+    pub amount: f64,
+    pub address: String,
+    #[serde(default)]
+    pub fee: f64,
+    pub state: String,
+    pub created_at: u64,
+}
+
+impl From<RawTransfer> for Deposit {
+    fn from(item: RawTransfer) -> Deposit {
+        // confirmed is tradable, safe withdrawable as well
+        let status = match item.state.as_str() {
+            "safe" => DepositStatus::Success,
+            "confirmed" => DepositStatus::Credited,
+            "orphan" => DepositStatus::Failed,
+            _ => DepositStatus::Pending,
+        };
+        Deposit {
+            id: item.id.to_string(),
+            coin: item.currency.to_uppercase(),
+            network: item.chain,
+            amount: item.amount,
+            address: item.address,
+            tx_id: item.tx_hash,
+            status,
+            confirmations: 0,
+            required_confirmations: 0,
+            timestamp: item.created_at,
+        }
+    }
+}
+
+impl From<RawTransfer> for Withdrawal {
+    fn from(item: RawTransfer) -> Withdrawal {
+        let status = match item.state.as_str() {
+            "confirmed" => WithdrawStatus::Completed,
+            "canceled" | "repealed" => WithdrawStatus::Canceled,
+            "reject" | "wallet-reject" | "confirm-error" => WithdrawStatus::Failed,
+            "pass" | "pre-transfer" | "wallet-transfer" => WithdrawStatus::Processing,
+            _ => WithdrawStatus::Pending,
+        };
+        Withdrawal {
+            id: item.id.to_string(),
+            coin: item.currency.to_uppercase(),
+            network: item.chain,
+            amount: item.amount,
+            fee: item.fee,
+            address: item.address,
+            tx_id: item.tx_hash,
+            status,
+            timestamp: item.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_response() {
+        let body = r#"{"status": "ok", "data": 1}"#;
+        assert_eq!(check_response(body.into()).unwrap(), body);
+        let body = r#"{"status": "error", "err-code": "order-orderstate-error", "err-msg": "Incorrect order state"}"#;
+        let err = check_response(body.into()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ExError::UnknownOrder(_))));
+    }
+
+    #[test]
+    fn test_transfers() {
+        let raw = r#"{"status": "ok", "data": [{"id": 1171, "type": "deposit",
+            "currency": "usdt", "tx-hash": "ed03094b84", "chain": "trc20usdt",
+            "amount": 100.0, "address": "TXyz", "address-tag": "", "fee": 0,
+            "state": "confirmed", "created-at": 1510912472199,
+            "updated-at": 1511145876575}]}"#;
+        let resp: Response<Vec<RawTransfer>> = serde_json::from_str(raw).unwrap();
+        let deposit: Deposit = resp.data[0].clone().into();
+        assert_eq!(deposit.coin, "USDT");
+        assert_eq!(deposit.status, DepositStatus::Credited);
+
+        let mut raw = resp.data[0].clone();
+        raw.state = "wallet-transfer".into();
+        let withdrawal: Withdrawal = raw.into();
+        assert_eq!(withdrawal.status, WithdrawStatus::Processing);
+
+        let raw = r#"{"code": 200, "data": [{"currency": "usdt", "assetType": 1,
+            "instStatus": "normal", "chains": [{"chain": "trc20usdt",
+            "displayName": "TRC20", "baseChain": "TRX", "baseChainProtocol": "TRC20",
+            "isDynamic": false, "numOfConfirmations": 20, "numOfFastConfirmations": 20,
+            "depositStatus": "allowed", "minDepositAmt": "1", "withdrawStatus": "allowed",
+            "minWithdrawAmt": "2", "withdrawPrecision": 6, "maxWithdrawAmt": "1000000",
+            "withdrawFeeType": "fixed", "transactFeeWithdraw": "1", "addrWithTag": false,
+            "addrDepositTag": false}]}]}"#;
+        let resp: Response<Vec<RawCurrencyChains>> = serde_json::from_str(raw).unwrap();
+        let chain = resp.data[0].chains[0].clone();
+        let network = chain.into_network(&resp.data[0].currency);
+        assert_eq!(network.coin, "USDT");
+        assert_eq!(network.network, "trc20usdt");
+        assert_eq!(network.withdraw_fee, 1.0);
+        assert_eq!(network.min_confirmations, 20);
+        assert!(network.withdraw_enabled);
+        assert_eq!(withdrawal.tx_id, "ed03094b84");
+    }
+}
@@ -0,0 +1,174 @@
+use crate::errors::*;
+
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{aead, pbkdf2};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+const PBKDF2_ITERATIONS: NonZeroU32 = NonZeroU32::new(100_000).unwrap();
+const SALT_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Credential {
+    pub api_key: String,
+    pub secret_key: String,
+}
+
+// on-disk layout, all fields hex encoded
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// passphrase encrypted (PBKDF2-SHA256 + AES-256-GCM) store of api keys
+#[derive(Default, Clone)]
+pub struct Keystore {
+    entries: BTreeMap<String, Credential>,
+}
+
+impl Keystore {
+    pub fn new() -> Self {
+        Keystore::default()
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> APIResult<Keystore> {
+        let content = fs::read_to_string(path)?;
+        Keystore::decrypt(&content, passphrase)
+    }
+
+    // readable by the owner only on unix, also when the file exists
+    pub fn save<P: AsRef<Path>>(&self, path: P, passphrase: &str) -> APIResult<()> {
+        let content = self.encrypt(passphrase)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            let file = options.open(path)?;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            (&file).write_all(content.as_bytes())?;
+        }
+        #[cfg(not(unix))]
+        options.open(path)?.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Credential> {
+        self.entries.get(name)
+    }
+
+    pub fn insert(&mut self, name: &str, api_key: &str, secret_key: &str) {
+        self.entries.insert(
+            name.into(),
+            Credential {
+                api_key: api_key.into(),
+                secret_key: secret_key.into(),
+            },
+        );
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Credential> {
+        self.entries.remove(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    pub fn encrypt(&self, passphrase: &str) -> APIResult<String> {
+        let rng = SystemRandom::new();
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill(&mut salt)?;
+        let mut nonce = vec![0u8; aead::AES_256_GCM.nonce_len()];
+        rng.fill(&mut nonce)?;
+
+        let key = aead_key(passphrase, &salt)?;
+        let mut in_out = serde_json::to_vec(&self.entries)?;
+        key.seal_in_place_append_tag(
+            aead::Nonce::try_assume_unique_for_key(&nonce)?,
+            aead::Aad::empty(),
+            &mut in_out,
+        )?;
+
+        let file = EncryptedFile {
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(in_out),
+        };
+        Ok(serde_json::to_string(&file)?)
+    }
+
+    pub fn decrypt(content: &str, passphrase: &str) -> APIResult<Keystore> {
+        let file: EncryptedFile = serde_json::from_str(content)?;
+        let salt = hex::decode(file.salt)?;
+        let nonce = hex::decode(file.nonce)?;
+        let mut in_out = hex::decode(file.ciphertext)?;
+
+        let key = aead_key(passphrase, &salt)?;
+        let plain = key
+            .open_in_place(
+                aead::Nonce::try_assume_unique_for_key(&nonce)?,
+                aead::Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| Box::new(ExError::ApiError("wrong passphrase".into())))?;
+        let entries: BTreeMap<String, Credential> = serde_json::from_slice(plain)?;
+        Ok(Keystore { entries })
+    }
+}
+
+fn aead_key(passphrase: &str, salt: &[u8]) -> APIResult<aead::LessSafeKey> {
+    let mut key = vec![0u8; aead::AES_256_GCM.key_len()];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = aead::UnboundKey::new(&aead::AES_256_GCM, &key)?;
+    Ok(aead::LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let mut keystore = Keystore::new();
+        keystore.insert("binance", "key", "secret");
+        let content = keystore.encrypt("passphrase").unwrap();
+        assert!(!content.contains("secret"));
+
+        let keystore = Keystore::decrypt(&content, "passphrase").unwrap();
+        let cred = keystore.get("binance").unwrap();
+        assert_eq!(cred.api_key, "key");
+        assert_eq!(cred.secret_key, "secret");
+        assert!(Keystore::decrypt(&content, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_save() {
+        let path = std::env::temp_dir().join(format!("rsex-keystore-{}.json", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        let mut keystore = Keystore::new();
+        keystore.insert("binance", "key", "secret");
+        keystore.save(&path, "passphrase").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let keystore = Keystore::open(&path, "passphrase").unwrap();
+        assert_eq!(keystore.get("binance").unwrap().api_key, "key");
+        fs::remove_file(&path).unwrap();
+    }
+}
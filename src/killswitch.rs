@@ -0,0 +1,145 @@
+use crate::config::{Client, Registry};
+
+use log::{error, info};
+use std::thread;
+use std::time::Instant;
+
+// what the kill switch did on one venue
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VenueKill {
+    pub exchange: String,
+    // symbols whose open orders were canceled
+    pub canceled: Vec<String>,
+    // market orders that closed positions, (symbol, order id)
+    pub flattened: Vec<(String, String)>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KillReport {
+    pub venues: Vec<VenueKill>,
+    pub elapsed_ms: u64,
+}
+
+impl KillReport {
+    // every cancel and flatten went through
+    pub fn is_complete(&self) -> bool {
+        self.venues.iter().all(|v| v.errors.is_empty())
+    }
+}
+
+// emergency stop: cancels the open orders of the symbols on every given
+// venue, all venues of the registry when exchanges is empty, and with
+// flatten closes the futures positions of those symbols, see
+// BinanceSwap::close_position. venues run concurrently, a failing venue or
+// symbol doesn't stop the others
+//
+//     let report = kill_switch(&registry, &[], &["BTCUSDT", "ETHUSDT"], true);
+//     if !report.is_complete() {
+//         error!("kill switch incomplete: {:?}", report);
+//     }
+pub fn kill_switch(
+    registry: &Registry,
+    exchanges: &[&str],
+    symbols: &[&str],
+    flatten: bool,
+) -> KillReport {
+    let started = Instant::now();
+    let mut names: Vec<&str> = if exchanges.is_empty() {
+        registry.keys().map(|k| k.as_str()).collect()
+    } else {
+        exchanges.to_vec()
+    };
+    names.sort_unstable();
+    names.dedup();
+
+    let venues = thread::scope(|s| {
+        let handles: Vec<_> = names
+            .iter()
+            .map(|&name| {
+                s.spawn(move || match registry.get(name) {
+                    Some(client) => kill_venue(name, client, symbols, flatten),
+                    None => VenueKill {
+                        exchange: name.into(),
+                        errors: vec![format!("unknown exchange {}", name)],
+                        ..Default::default()
+                    },
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .zip(names.iter())
+            .map(|(handle, &name)| {
+                handle.join().unwrap_or_else(|_| VenueKill {
+                    exchange: name.into(),
+                    errors: vec!["kill switch thread panicked".into()],
+                    ..Default::default()
+                })
+            })
+            .collect()
+    });
+    let report = KillReport {
+        venues,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    if report.is_complete() {
+        info!("kill switch done in {}ms", report.elapsed_ms);
+    } else {
+        error!("kill switch incomplete: {:?}", report);
+    }
+    report
+}
+
+fn kill_venue(name: &str, client: &Client, symbols: &[&str], flatten: bool) -> VenueKill {
+    let mut kill = VenueKill {
+        exchange: name.into(),
+        ..Default::default()
+    };
+    for &symbol in symbols {
+        match client.cancel_all(symbol) {
+            Ok(_) => kill.canceled.push(symbol.into()),
+            Err(err) => kill.errors.push(format!("cancel_all {}: {}", symbol, err)),
+        }
+    }
+    if let (true, Client::BinanceSwap(api)) = (flatten, client) {
+        for &symbol in symbols {
+            match api.close_position(symbol) {
+                Ok(ids) => kill
+                    .flattened
+                    .extend(ids.into_iter().map(|id| (symbol.to_string(), id))),
+                Err(err) => kill.errors.push(format!("flatten {}: {}", symbol, err)),
+            }
+        }
+    }
+    kill
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binance::spot_rest::Binance;
+    use crate::models::Scope;
+
+    #[test]
+    fn test_kill_switch() {
+        let mut registry = Registry::new();
+        registry.insert(
+            "spot".into(),
+            Client::Binance(Binance::new(None, None, "http://127.0.0.1:1".into())),
+        );
+        let mut readonly = Binance::new(None, None, "http://127.0.0.1:1".into());
+        readonly.set_scope(Scope::read_only());
+        registry.insert("readonly".into(), Client::Binance(readonly));
+
+        let report = kill_switch(&registry, &[], &["BTCUSDT", "ETHUSDT"], true);
+        assert!(!report.is_complete());
+        assert_eq!(report.venues.len(), 2);
+        assert_eq!(report.venues[0].exchange, "readonly");
+        assert!(report.venues[0].errors[0].contains("cancel_all BTCUSDT"));
+        assert_eq!(report.venues[1].errors.len(), 2);
+
+        let report = kill_switch(&registry, &["missing"], &["BTCUSDT"], false);
+        assert!(report.venues[0].errors[0].contains("unknown exchange"));
+    }
+}
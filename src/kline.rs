@@ -0,0 +1,499 @@
+use crate::errors::*;
+use crate::models::*;
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+
+const DAY_MS: i64 = 24 * 3600 * 1000;
+// the unix epoch was a thursday, weeks start on the monday after it like
+// the exchanges' weekly candles
+const MONDAY_MS: i64 = 4 * DAY_MS;
+
+fn split_period(period: &str) -> Option<(u64, &str)> {
+    let split = period.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = period.split_at(split);
+    Some((count.parse().ok()?, unit))
+}
+
+// length of a kline period in ms, binance (1m, 4h, 1d) and huobi (1min,
+// 60min, 1day) names. months have no fixed length
+pub fn period_ms(period: &str) -> Option<u64> {
+    let (count, unit) = split_period(period)?;
+    let unit_ms = match unit {
+        "s" => 1000,
+        "m" | "min" => 60 * 1000,
+        "h" | "hour" => 3600 * 1000,
+        "d" | "day" => 24 * 3600 * 1000,
+        "w" | "week" => 7 * 24 * 3600 * 1000,
+        _ => return None,
+    };
+    Some(count * unit_ms)
+}
+
+// calendar months of binance 1M and huobi 1mon
+fn period_months(period: &str) -> Option<i64> {
+    match split_period(period)? {
+        (count, "M") | (count, "mon") if count > 0 => Some(count as i64),
+        _ => None,
+    }
+}
+
+enum Buckets {
+    // fixed length, aligned to origin
+    Fixed { len: i64, origin: i64 },
+    Months(i64),
+}
+
+impl Buckets {
+    fn new(period: &str) -> APIResult<Buckets> {
+        if let Some(months) = period_months(period) {
+            return Ok(Buckets::Months(months));
+        }
+        let len = period_ms(period)
+            .filter(|len| *len > 0)
+            .ok_or_else(|| Box::new(ExError::ApiError(format!("unsupported period: {}", period))))?
+            as i64;
+        let origin = if len % (7 * DAY_MS) == 0 {
+            MONDAY_MS
+        } else {
+            0
+        };
+        Ok(Buckets::Fixed { len, origin })
+    }
+
+    // open of the bucket a local time falls in, also local
+    fn start(&self, local: i64) -> APIResult<i64> {
+        match *self {
+            Buckets::Fixed { len, origin } => Ok(local - (local - origin).rem_euclid(len)),
+            Buckets::Months(count) => {
+                let out_of_range = || {
+                    Box::new(ExError::ApiError(format!(
+                        "timestamp out of range: {}",
+                        local
+                    )))
+                };
+                let time = Utc
+                    .timestamp_millis_opt(local)
+                    .single()
+                    .ok_or_else(out_of_range)?;
+                let month = time.year() as i64 * 12 + time.month0() as i64;
+                let month = month - month.rem_euclid(count);
+                let first =
+                    NaiveDate::from_ymd_opt((month / 12) as i32, (month % 12) as u32 + 1, 1)
+                        .ok_or_else(out_of_range)?;
+                Ok(first.and_time(NaiveTime::MIN).and_utc().timestamp_millis())
+            }
+        }
+    }
+}
+
+// aggregates candles, e.g. 1m, into a coarser period. buckets start at
+// multiples of the period in the timezone `utc_offset_ms` east of utc, so
+// daily candles of utc+8 (starting 16:00 utc) use 8 hours. weeks start on
+// monday and months (1M, 1mon) on the first, like the exchanges' candles. a
+// bucket is built from whatever candles are given, the latest one may still
+// be open
+pub fn resample(klines: &[Kline], period: &str, utc_offset_ms: i64) -> APIResult<Vec<Kline>> {
+    let buckets = Buckets::new(period)?;
+    let mut resampled: BTreeMap<i64, Kline> = BTreeMap::new();
+    let mut sorted: Vec<&Kline> = klines.iter().collect();
+    sorted.sort_by_key(|kline| kline.timestamp);
+    for kline in sorted {
+        let local = kline.timestamp as i64 + utc_offset_ms;
+        let start = buckets.start(local)? - utc_offset_ms;
+        resampled
+            .entry(start)
+            .and_modify(|bucket| {
+                bucket.high = bucket.high.max(kline.high);
+                bucket.low = bucket.low.min(kline.low);
+                bucket.close = kline.close;
+                bucket.volume += kline.volume;
+            })
+            .or_insert_with(|| Kline {
+                timestamp: start.max(0) as u64,
+                ..kline.clone()
+            });
+    }
+    Ok(resampled.into_values().collect())
+}
+
+// candles missing from a series, open times of the first and last one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct KlineGap {
+    pub start: u64,
+    pub end: u64,
+}
+
+// candles opened between two open times in ms, e.g. Binance::get_kline_range
+type Backfill = Box<dyn FnMut(&str, &str, u64, u64) -> APIResult<Vec<Kline>> + Send>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    exchange: String,
+    symbol: String,
+    period: String,
+}
+
+struct Series {
+    period_ms: u64,
+    klines: BTreeMap<u64, Kline>,
+}
+
+impl Series {
+    fn gaps(&self) -> Vec<KlineGap> {
+        let mut gaps = vec![];
+        let mut open_times = self.klines.keys();
+        let mut last = match open_times.next() {
+            Some(first) => *first,
+            None => return gaps,
+        };
+        for &open_time in open_times {
+            if open_time > last + self.period_ms {
+                gaps.push(KlineGap {
+                    start: last + self.period_ms,
+                    end: open_time - self.period_ms,
+                });
+            }
+            last = open_time;
+        }
+        gaps
+    }
+}
+
+// candles per (exchange, symbol, period) fed from kline streams. a candle
+// arriving more than one period after the previous one (e.g. after a
+// reconnect) leaves a gap that is filled from the exchange's backfill, or
+// later with backfill() if that fails
+//
+//     let mut cache = KlineCache::new(1000);
+//     cache.set_backfill("binance", move |symbol, period, start, end| {
+//         api.get_kline_range(symbol, period, start, end)
+//     });
+//     // for every KlineEvent
+//     cache.on_update("binance", &update);
+pub struct KlineCache {
+    capacity: usize,
+    series: HashMap<SeriesKey, Series>,
+    backfills: HashMap<String, Backfill>,
+}
+
+impl KlineCache {
+    // keeps the latest `capacity` candles of each series
+    pub fn new(capacity: usize) -> Self {
+        KlineCache {
+            capacity: capacity.max(1),
+            series: HashMap::new(),
+            backfills: HashMap::new(),
+        }
+    }
+
+    pub fn set_backfill<F>(&mut self, exchange: &str, backfill: F)
+    where
+        F: FnMut(&str, &str, u64, u64) -> APIResult<Vec<Kline>> + Send + 'static,
+    {
+        self.backfills.insert(exchange.into(), Box::new(backfill));
+    }
+
+    // stores the open or closed candle of a stream update, returns the gap
+    // it left if the backfill couldn't fill it
+    pub fn on_update(
+        &mut self,
+        exchange: &str,
+        update: &KlineUpdate,
+    ) -> APIResult<Option<KlineGap>> {
+        let key = SeriesKey {
+            exchange: exchange.into(),
+            symbol: update.symbol.clone(),
+            period: update.period.clone(),
+        };
+        let last = self.insert(&key, vec![update.kline.clone()])?;
+        let period_ms = self.series[&key].period_ms;
+        let gap = match last {
+            Some(last) if update.kline.timestamp > last + period_ms => KlineGap {
+                start: last + period_ms,
+                end: update.kline.timestamp - period_ms,
+            },
+            _ => return Ok(None),
+        };
+        match self.fill(&key, gap) {
+            Ok(()) => Ok(None),
+            Err(err) => {
+                warn!("backfill {:?} {:?} failed: {:?}", key, gap, err);
+                Ok(Some(gap))
+            }
+        }
+    }
+
+    // adds candles, e.g. the history loaded at start, and returns the open
+    // time of the latest candle before them
+    pub fn extend(
+        &mut self,
+        exchange: &str,
+        symbol: &str,
+        period: &str,
+        klines: Vec<Kline>,
+    ) -> APIResult<Option<u64>> {
+        let key = SeriesKey {
+            exchange: exchange.into(),
+            symbol: symbol.into(),
+            period: period.into(),
+        };
+        self.insert(&key, klines)
+    }
+
+    fn insert(&mut self, key: &SeriesKey, klines: Vec<Kline>) -> APIResult<Option<u64>> {
+        if !self.series.contains_key(key) {
+            let period_ms = period_ms(&key.period).ok_or_else(|| {
+                Box::new(ExError::ApiError(format!(
+                    "unsupported period: {}",
+                    key.period
+                )))
+            })?;
+            self.series.insert(
+                key.clone(),
+                Series {
+                    period_ms,
+                    klines: BTreeMap::new(),
+                },
+            );
+        }
+        let series = self.series.get_mut(key).unwrap();
+        let last = series.klines.keys().next_back().copied();
+        for kline in klines {
+            series.klines.insert(kline.timestamp, kline);
+        }
+        while series.klines.len() > self.capacity {
+            let first = *series.klines.keys().next().unwrap();
+            series.klines.remove(&first);
+        }
+        Ok(last)
+    }
+
+    fn fill(&mut self, key: &SeriesKey, gap: KlineGap) -> APIResult<()> {
+        let backfill = self.backfills.get_mut(&key.exchange).ok_or_else(|| {
+            Box::new(ExError::ApiError(format!(
+                "no backfill for {}",
+                key.exchange
+            )))
+        })?;
+        let klines = backfill(&key.symbol, &key.period, gap.start, gap.end)?;
+        self.insert(key, klines)?;
+        Ok(())
+    }
+
+    // missing candles between the first and the latest one
+    pub fn gaps(&self, exchange: &str, symbol: &str, period: &str) -> Vec<KlineGap> {
+        let key = SeriesKey {
+            exchange: exchange.into(),
+            symbol: symbol.into(),
+            period: period.into(),
+        };
+        self.series.get(&key).map(Series::gaps).unwrap_or_default()
+    }
+
+    // retries every gap of every series, returns the ones still open
+    pub fn backfill(&mut self) -> Vec<KlineGap> {
+        let mut open = vec![];
+        let keys: Vec<SeriesKey> = self.series.keys().cloned().collect();
+        for key in keys {
+            for gap in self.series[&key].gaps() {
+                if let Err(err) = self.fill(&key, gap) {
+                    warn!("backfill {:?} {:?} failed: {:?}", key, gap, err);
+                }
+            }
+            open.extend(self.series[&key].gaps());
+        }
+        open
+    }
+
+    // oldest first
+    pub fn klines(&self, exchange: &str, symbol: &str, period: &str) -> Vec<Kline> {
+        let key = SeriesKey {
+            exchange: exchange.into(),
+            symbol: symbol.into(),
+            period: period.into(),
+        };
+        self.series
+            .get(&key)
+            .map(|series| series.klines.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const MINUTE: u64 = 60 * 1000;
+
+    fn kline(timestamp: u64) -> Kline {
+        Kline {
+            timestamp,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 1.0,
+        }
+    }
+
+    fn update(timestamp: u64) -> KlineUpdate {
+        KlineUpdate {
+            symbol: "BTCUSDT".into(),
+            period: "1m".into(),
+            kline: kline(timestamp),
+            is_closed: true,
+        }
+    }
+
+    #[test]
+    fn test_period_ms() {
+        assert_eq!(period_ms("1m"), Some(MINUTE));
+        assert_eq!(period_ms("60min"), Some(60 * MINUTE));
+        assert_eq!(period_ms("4h"), Some(240 * MINUTE));
+        assert_eq!(period_ms("1week"), Some(7 * 24 * 60 * MINUTE));
+        assert_eq!(period_ms("1M"), None);
+        assert_eq!(period_ms("m"), None);
+    }
+
+    #[test]
+    fn test_resample() {
+        // BTCUSDT 1m candles from 2023-01-01 00:00 utc and the 5m candle
+        // binance reports for the same time
+        let rows = [
+            (16541.77, 16545.7, 16508.39, 16529.67, 4364.8357),
+            (16529.59, 16556.8, 16521.9, 16551.47, 3590.06669),
+            (16551.47, 16558.43, 16531.44, 16548.19, 3546.09876),
+            (16548.19, 16549.33, 16535.0, 16533.04, 2454.51095),
+            (16533.04, 16535.45, 16510.0, 16521.85, 3125.26253),
+        ];
+        let start = 1672531200000;
+        let klines: Vec<Kline> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, &(open, high, low, close, volume))| Kline {
+                timestamp: start + i as u64 * MINUTE,
+                open,
+                high,
+                low,
+                close,
+                volume,
+            })
+            .collect();
+        let five = resample(&klines, "5m", 0).unwrap();
+        assert_eq!(five.len(), 1);
+        assert_eq!(five[0].timestamp, start);
+        assert_eq!(five[0].open, 16541.77);
+        assert_eq!(five[0].high, 16558.43);
+        assert_eq!(five[0].low, 16508.39);
+        assert_eq!(five[0].close, 16521.85);
+        assert!((five[0].volume - 17080.77463).abs() < 1e-6);
+
+        // days of utc+8, starting 16:00 utc
+        let day = 24 * 60 * MINUTE;
+        let hours: Vec<Kline> = (0..48).map(|i| kline(start + i * 60 * MINUTE)).collect();
+        let days = resample(&hours, "1d", 8 * 3600 * 1000).unwrap();
+        let opens: Vec<u64> = days.iter().map(|k| k.timestamp).collect();
+        assert_eq!(
+            opens,
+            vec![
+                start - 8 * 60 * MINUTE,
+                start + 16 * 60 * MINUTE,
+                start + day + 16 * 60 * MINUTE
+            ]
+        );
+        assert_eq!(days[0].volume, 16.0);
+        assert!(resample(&hours, "1y", 0).is_err());
+        assert!(resample(&hours, "0d", 0).is_err());
+    }
+
+    #[test]
+    fn test_resample_calendar() {
+        // daily candles from sunday 2023-01-01. binance's 1w candles open on
+        // monday 00:00 utc: 2022-12-26, 2023-01-02 and 2023-01-09, and its
+        // 1M candles on the first of the month
+        let start = 1672531200000;
+        let day = 24 * 60 * MINUTE;
+        let days: Vec<Kline> = (0..63).map(|i| kline(start + i * day)).collect();
+
+        let weeks = resample(&days, "1w", 0).unwrap();
+        let opens: Vec<u64> = weeks.iter().take(3).map(|k| k.timestamp).collect();
+        assert_eq!(opens, vec![1672012800000, 1672617600000, 1673222400000]);
+        let volumes: Vec<f64> = weeks.iter().take(3).map(|k| k.volume).collect();
+        assert_eq!(volumes, vec![1.0, 7.0, 7.0]);
+        assert_eq!(weeks.len(), 10);
+        // huobi's names
+        assert_eq!(
+            resample(&days, "1week", 0).unwrap()[1].timestamp,
+            1672617600000
+        );
+
+        let months = resample(&days, "1M", 0).unwrap();
+        let opens: Vec<u64> = months.iter().map(|k| k.timestamp).collect();
+        assert_eq!(opens, vec![start, 1675209600000, 1677628800000]);
+        let volumes: Vec<f64> = months.iter().map(|k| k.volume).collect();
+        assert_eq!(volumes, vec![31.0, 28.0, 4.0]);
+        assert_eq!(resample(&days, "1mon", 0).unwrap().len(), 3);
+
+        // quarters, and months of utc+8 opening at 16:00 utc the day before
+        let quarters = resample(&days, "3M", 0).unwrap();
+        assert_eq!(quarters.len(), 1);
+        assert_eq!(quarters[0].volume, 63.0);
+        let months = resample(&days, "1M", 8 * 3600 * 1000).unwrap();
+        assert_eq!(months[1].timestamp, 1675209600000 - 8 * 60 * MINUTE);
+    }
+
+    #[test]
+    fn test_kline_cache() {
+        let mut cache = KlineCache::new(100);
+        cache.on_update("binance", &update(0)).unwrap();
+        cache.on_update("binance", &update(MINUTE)).unwrap();
+        // no backfill yet, the gap stays open
+        let gap = cache.on_update("binance", &update(4 * MINUTE)).unwrap();
+        assert_eq!(
+            gap,
+            Some(KlineGap {
+                start: 2 * MINUTE,
+                end: 3 * MINUTE
+            })
+        );
+        assert_eq!(cache.gaps("binance", "BTCUSDT", "1m"), vec![gap.unwrap()]);
+
+        let requested = Arc::new(Mutex::new(vec![]));
+        let log = requested.clone();
+        cache.set_backfill("binance", move |_, _, start, end| {
+            log.lock().unwrap().push((start, end));
+            Ok((start..=end).step_by(MINUTE as usize).map(kline).collect())
+        });
+        assert!(cache.backfill().is_empty());
+        assert_eq!(cache.klines("binance", "BTCUSDT", "1m").len(), 5);
+
+        // filled right away once a backfill is set
+        assert_eq!(
+            cache.on_update("binance", &update(7 * MINUTE)).unwrap(),
+            None
+        );
+        assert_eq!(
+            *requested.lock().unwrap(),
+            vec![(2 * MINUTE, 3 * MINUTE), (5 * MINUTE, 6 * MINUTE)]
+        );
+        assert!(cache.gaps("binance", "BTCUSDT", "1m").is_empty());
+
+        let mut cache = KlineCache::new(2);
+        cache
+            .extend(
+                "huobi",
+                "btcusdt",
+                "1min",
+                (0..3).map(|i| kline(i * MINUTE)).collect(),
+            )
+            .unwrap();
+        assert_eq!(
+            cache.klines("huobi", "btcusdt", "1min")[0].timestamp,
+            MINUTE
+        );
+        assert!(cache.extend("huobi", "btcusdt", "1mon", vec![]).is_err());
+    }
+}
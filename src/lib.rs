@@ -1,20 +1,90 @@
+#![allow(clippy::result_large_err)]
+
 extern crate base64;
-extern crate env_logger;
 extern crate log;
 extern crate serde;
 extern crate serde_json;
-extern crate ws;
 
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
 
+// items left out of the wasm32 build, they need threads, sockets, the ws
+// crate or ring
+macro_rules! native {
+    ($($item:item)*) => {
+        $(
+            #[cfg(not(target_arch = "wasm32"))]
+            $item
+        )*
+    };
+}
+
+pub mod book;
+pub mod checksum;
+pub mod clock;
 pub mod constant;
 pub mod errors;
+pub mod http;
+pub mod kline;
 pub mod models;
-pub mod traits;
+pub mod public;
 mod utils;
 
 pub mod binance;
 pub mod huobi;
+
+native! {
+    extern crate env_logger;
+    extern crate ws;
+
+    pub mod alerts;
+    pub mod audit;
+    pub mod balances;
+    pub mod cache;
+    pub mod coalesce;
+    pub mod config;
+    #[cfg(feature = "blocking")]
+    pub mod deposits;
+    pub mod expiry;
+    #[cfg(all(test, feature = "blocking"))]
+    mod fakes;
+    #[cfg(feature = "blocking")]
+    pub mod ffi;
+    #[cfg(feature = "grpc")]
+    pub mod grpc;
+    pub mod hosts;
+    pub mod keystore;
+    #[cfg(feature = "blocking")]
+    pub mod killswitch;
+    pub mod limits;
+    pub mod liquidation;
+    pub mod listings;
+    pub mod notify;
+    pub mod paginate;
+    pub mod pnl;
+    pub mod polling;
+    #[cfg(feature = "python")]
+    pub mod python;
+    pub mod queue;
+    pub mod readonly;
+    pub mod reconcile;
+    pub mod relay;
+    pub mod rules;
+    pub mod scheduler;
+    #[cfg(feature = "server")]
+    pub mod server;
+    pub mod shutdown;
+    pub mod signer;
+    pub mod sink;
+    pub mod sizing;
+    pub mod stream;
+    pub mod supervisor;
+    pub mod tape;
+    pub mod traits;
+    #[cfg(feature = "blocking")]
+    pub mod transfer;
+    #[cfg(feature = "vcr")]
+    pub mod vcr;
+}
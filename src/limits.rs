@@ -0,0 +1,296 @@
+use crate::errors::*;
+use crate::http::HeaderMap;
+use crate::models::RateLimitStatus;
+use crate::utils::{get_timestamp, sleep};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// orders wait for a window to reset at most this long, e.g. the 10s
+// window, hitting a daily cap fails right away
+const MAX_ORDER_WAIT_MS: u64 = 10_000;
+
+// spaces requests evenly so that at most `per_minute` are sent in any minute
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        RateLimiter {
+            interval: Duration::from_secs(60) / per_minute.max(1),
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // takes the next slot, then sleeps until it comes
+    #[maybe_async::maybe_async]
+    pub async fn wait(&self) {
+        let delay = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let delay = next.saturating_duration_since(now);
+            *next = (*next).max(now) + self.interval;
+            delay
+        };
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+
+    pub fn ready_at(&self, now: Instant) -> Instant {
+        (*self.next.lock().unwrap()).max(now)
+    }
+}
+
+lazy_static! {
+    static ref SHARED: Mutex<HashMap<String, RateLimiter>> = Mutex::new(HashMap::new());
+}
+
+// process wide limiter registered under `name`, clients given the same name
+// share one budget, e.g. every client behind one egress ip or using one api
+// key. the budget of the first call wins
+pub fn shared_limiter(name: &str, per_minute: u32) -> RateLimiter {
+    SHARED
+        .lock()
+        .unwrap()
+        .entry(name.into())
+        .or_insert_with(|| RateLimiter::per_minute(per_minute))
+        .clone()
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    kind: String,
+    interval_ms: u64,
+    limit: Option<u64>,
+    used: u64,
+    started_at: u64,
+}
+
+impl Window {
+    // windows are aligned to the interval, a finished one is empty
+    fn used(&self, now: u64) -> u64 {
+        if now >= self.started_at + self.interval_ms {
+            0
+        } else {
+            self.used
+        }
+    }
+
+    fn status(&self, now: u64) -> RateLimitStatus {
+        let used = self.used(now);
+        RateLimitStatus {
+            kind: self.kind.clone(),
+            interval_ms: self.interval_ms,
+            limit: self.limit,
+            used,
+            remaining: self.limit.map(|limit| limit.saturating_sub(used)),
+            reset_at: (now / self.interval_ms + 1) * self.interval_ms,
+        }
+    }
+}
+
+// rate limit usage of a client, limits come from exchange info and usage
+// from the X-MBX-USED-WEIGHT-* and X-MBX-ORDER-COUNT-* response headers.
+// clones share the same counters
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    windows: Arc<Mutex<Vec<Window>>>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        RateLimits::default()
+    }
+
+    // interval is MINUTE, SECOND, HOUR or DAY as in exchange info
+    pub fn seed(&self, kind: &str, interval: &str, interval_num: u64, limit: u64) {
+        let unit = match interval {
+            "SECOND" => 1000,
+            "MINUTE" => 60_000,
+            "HOUR" => 3_600_000,
+            "DAY" => 86_400_000,
+            _ => return,
+        };
+        let mut windows = self.windows.lock().unwrap();
+        let window = find_or_insert(&mut windows, kind, unit * interval_num);
+        window.limit = Some(limit);
+    }
+
+    pub fn update(&self, headers: &HeaderMap, now: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        for (name, value) in headers.iter() {
+            let (kind, interval) = if let Some(i) = name.strip_prefix("x-mbx-used-weight-") {
+                ("REQUEST_WEIGHT", i)
+            } else if let Some(i) = name.strip_prefix("x-mbx-order-count-") {
+                ("ORDERS", i)
+            } else {
+                continue;
+            };
+            let used = value.parse().ok();
+            if let (Some(interval_ms), Some(used)) = (parse_interval(interval), used) {
+                let window = find_or_insert(&mut windows, kind, interval_ms);
+                window.used = used;
+                window.started_at = now - now % interval_ms;
+            }
+        }
+    }
+
+    // counts an order against every ORDERS window, when one of them is full
+    // nothing is counted and the time that window resets is returned
+    pub fn try_acquire_order(&self, now: u64) -> Result<(), u64> {
+        let mut windows = self.windows.lock().unwrap();
+        let orders = windows.iter_mut().filter(|w| w.kind == "ORDERS");
+        let mut full = None;
+        for window in orders {
+            if let Some(limit) = window.limit {
+                if window.used(now) >= limit {
+                    full = full.max(Some(now - now % window.interval_ms + window.interval_ms));
+                }
+            }
+        }
+        if let Some(reset_at) = full {
+            return Err(reset_at);
+        }
+        for window in windows.iter_mut().filter(|w| w.kind == "ORDERS") {
+            window.used = window.used(now) + 1;
+            window.started_at = now - now % window.interval_ms;
+        }
+        Ok(())
+    }
+
+    // blocks until an order fits the order count limits, fails with
+    // RateLimitExceeded when that takes too long
+    #[maybe_async::maybe_async]
+    pub async fn acquire_order(&self) -> APIResult<()> {
+        loop {
+            let now = get_timestamp()?;
+            match self.try_acquire_order(now) {
+                Ok(()) => return Ok(()),
+                Err(reset_at) if reset_at - now <= MAX_ORDER_WAIT_MS => {
+                    sleep(Duration::from_millis(reset_at - now)).await;
+                }
+                Err(reset_at) => {
+                    return Err(Box::new(ExError::RateLimitExceeded(format!(
+                        "order count limit reached until {}",
+                        reset_at
+                    ))))
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, now: u64) -> Vec<RateLimitStatus> {
+        let windows = self.windows.lock().unwrap();
+        windows.iter().map(|window| window.status(now)).collect()
+    }
+}
+
+fn find_or_insert<'a>(
+    windows: &'a mut Vec<Window>,
+    kind: &str,
+    interval_ms: u64,
+) -> &'a mut Window {
+    let pos = windows
+        .iter()
+        .position(|w| w.kind == kind && w.interval_ms == interval_ms);
+    let i = match pos {
+        Some(i) => i,
+        None => {
+            windows.push(Window {
+                kind: kind.into(),
+                interval_ms,
+                limit: None,
+                used: 0,
+                started_at: 0,
+            });
+            windows.len() - 1
+        }
+    };
+    &mut windows[i]
+}
+
+// header suffixes like 10s, 1m or 1d
+fn parse_interval(s: &str) -> Option<u64> {
+    let (num, unit) = s.split_at(s.len().checked_sub(1)?);
+    let unit = match unit {
+        "s" => 1000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    num.parse::<u64>().ok().filter(|n| *n > 0).map(|n| n * unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits() {
+        let limits = RateLimits::new();
+        limits.seed("REQUEST_WEIGHT", "MINUTE", 1, 1200);
+        limits.seed("ORDERS", "SECOND", 10, 50);
+        limits.seed("RAW_REQUESTS", "WEEK", 1, 1);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", "20");
+        headers.insert("x-mbx-used-weight", "20");
+        headers.insert("x-mbx-order-count-10s", "3");
+        headers.insert("x-mbx-order-count-1d", "7");
+        limits.update(&headers, 125_000);
+
+        let status = limits.status(130_000);
+        assert_eq!(status.len(), 3);
+        assert_eq!(status[0].kind, "REQUEST_WEIGHT");
+        assert_eq!(status[0].used, 20);
+        assert_eq!(status[0].remaining, Some(1180));
+        assert_eq!(status[0].reset_at, 180_000);
+        // the 10s window of the update ended at 130s
+        assert_eq!(status[1].used, 0);
+        assert_eq!(status[1].remaining, Some(50));
+        // daily order count is not in exchange info
+        assert_eq!(status[2].interval_ms, 86_400_000);
+        assert_eq!(status[2].used, 7);
+        assert_eq!(status[2].limit, None);
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_shared_limiter() {
+        let a = shared_limiter("test:shared", 60);
+        let b = shared_limiter("test:shared", 6000);
+        let other = shared_limiter("test:other", 60);
+        let now = Instant::now();
+        a.wait().await;
+        // b spends the same one request per second budget
+        assert!(b.ready_at(now) > now + Duration::from_millis(900));
+        assert!(other.ready_at(now) < now + Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_order_limits() {
+        let limits = RateLimits::new();
+        // header only windows have no limit to enforce
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-order-count-1m", "100");
+        limits.update(&headers, 0);
+        assert!(limits.try_acquire_order(1000).is_ok());
+
+        limits.seed("ORDERS", "SECOND", 10, 2);
+        limits.seed("ORDERS", "DAY", 1, 3);
+        assert!(limits.try_acquire_order(1000).is_ok());
+        assert!(limits.try_acquire_order(2000).is_ok());
+        assert_eq!(limits.try_acquire_order(3000), Err(10_000));
+        // new 10s window, one order left for the day
+        assert!(limits.try_acquire_order(11_000).is_ok());
+        assert_eq!(limits.try_acquire_order(21_000), Err(86_400_000));
+        let status = limits.status(21_000);
+        assert_eq!(status[2].used, 3);
+        assert_eq!(status[2].remaining, Some(0));
+    }
+}
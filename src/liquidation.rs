@@ -0,0 +1,262 @@
+use crate::binance::future_rest::BinanceSwap;
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::notify::Notifier;
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::Receiver;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Threshold {
+    // maintenance margin over margin balance, liquidation at 1.0
+    MarginRatioAbove(f64),
+    // |mark - liquidation price| / mark, e.g. 0.05 for 5% away
+    DistanceBelow(f64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskEvent {
+    pub id: u64,
+    pub symbol: String,
+    pub position_side: String,
+    pub threshold: Threshold,
+    // the margin ratio or distance that crossed the threshold
+    pub value: f64,
+    pub amount: f64,
+    pub mark_price: f64,
+    pub liquidation_price: f64,
+    pub timestamp: u64,
+}
+
+struct Position {
+    risk: PositionRisk,
+    // ids of the thresholds currently breached, they fire on the edge only
+    active: Vec<u64>,
+}
+
+impl Position {
+    // None when the position can't be liquidated
+    fn distance(&self) -> Option<f64> {
+        let risk = &self.risk;
+        if risk.liquidation_price <= 0.0 || risk.mark_price <= 0.0 {
+            return None;
+        }
+        Some((risk.mark_price - risk.liquidation_price).abs() / risk.mark_price)
+    }
+
+    fn value(&self, threshold: &Threshold) -> Option<(bool, f64)> {
+        match threshold {
+            Threshold::MarginRatioAbove(level) => {
+                Some((self.risk.margin_ratio > *level, self.risk.margin_ratio))
+            }
+            Threshold::DistanceBelow(level) => self.distance().map(|d| (d < *level, d)),
+        }
+    }
+}
+
+// warns before positions get liquidated. position risk snapshots set the
+// margin ratio and liquidation price, mark price updates move the distance
+// to liquidation in between. a threshold fires when a position breaches it
+// and again only after it recovered in between
+//
+//     let mut monitor = LiquidationMonitor::new();
+//     monitor.add(Threshold::MarginRatioAbove(0.8));
+//     monitor.add(Threshold::DistanceBelow(0.05));
+//     monitor.on_trigger(|event| warn!("liquidation risk: {:?}", event));
+//     // every minute
+//     monitor.poll(&api, &["BTCUSDT"])?;
+//     // for every MarkPriceEvent
+//     monitor.on_mark_price(&mark);
+pub struct LiquidationMonitor {
+    thresholds: Vec<(u64, Threshold)>,
+    next_id: u64,
+    // by (symbol, position side)
+    positions: BTreeMap<(String, String), Position>,
+    notifier: Notifier<RiskEvent>,
+}
+
+impl Default for LiquidationMonitor {
+    fn default() -> Self {
+        LiquidationMonitor::new()
+    }
+}
+
+impl LiquidationMonitor {
+    pub fn new() -> Self {
+        LiquidationMonitor {
+            thresholds: vec![],
+            next_id: 1,
+            positions: BTreeMap::new(),
+            notifier: Notifier::new(),
+        }
+    }
+
+    // returns the id to remove it with
+    pub fn add(&mut self, threshold: Threshold) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.thresholds.push((id, threshold));
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> bool {
+        let len = self.thresholds.len();
+        self.thresholds.retain(|(i, _)| *i != id);
+        self.thresholds.len() != len
+    }
+
+    pub fn on_trigger<F>(&mut self, callback: F)
+    where
+        F: FnMut(&RiskEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<RiskEvent> {
+        self.notifier.subscribe()
+    }
+
+    // the tracked positions, closed ones are dropped
+    pub fn positions(&self) -> Vec<PositionRisk> {
+        self.positions.values().map(|p| p.risk.clone()).collect()
+    }
+
+    pub fn on_position_risk(&mut self, risk: &PositionRisk) {
+        let key = (risk.symbol.to_uppercase(), risk.position_side.clone());
+        if risk.amount == 0.0 {
+            self.positions.remove(&key);
+            return;
+        }
+        let position = self.positions.entry(key.clone()).or_insert(Position {
+            risk: risk.clone(),
+            active: vec![],
+        });
+        position.risk = risk.clone();
+        self.check(&key, now_ms());
+    }
+
+    pub fn on_mark_price(&mut self, mark: &MarkPrice) {
+        let symbol = mark.symbol.to_uppercase();
+        let keys: Vec<(String, String)> = self
+            .positions
+            .keys()
+            .filter(|(s, _)| *s == symbol)
+            .cloned()
+            .collect();
+        for key in keys {
+            if let Some(position) = self.positions.get_mut(&key) {
+                position.risk.mark_price = mark.mark_price;
+            }
+            self.check(&key, mark.timestamp);
+        }
+    }
+
+    // fetches the position risk of every symbol
+    #[maybe_async::maybe_async]
+    pub async fn poll(&mut self, api: &BinanceSwap, symbols: &[&str]) -> APIResult<()> {
+        for symbol in symbols {
+            let risks = api.get_position_risk(symbol).await?;
+            for risk in risks.iter() {
+                self.on_position_risk(risk);
+            }
+        }
+        Ok(())
+    }
+
+    fn check(&mut self, key: &(String, String), timestamp: u64) {
+        let position = match self.positions.get_mut(key) {
+            Some(position) => position,
+            None => return,
+        };
+        let mut events = vec![];
+        for (id, threshold) in self.thresholds.iter() {
+            let (breached, value) = position.value(threshold).unwrap_or((false, 0.0));
+            let was_active = position.active.contains(id);
+            if breached && !was_active {
+                position.active.push(*id);
+                events.push(RiskEvent {
+                    id: *id,
+                    symbol: position.risk.symbol.clone(),
+                    position_side: position.risk.position_side.clone(),
+                    threshold: threshold.clone(),
+                    value,
+                    amount: position.risk.amount,
+                    mark_price: position.risk.mark_price,
+                    liquidation_price: position.risk.liquidation_price,
+                    timestamp,
+                });
+            } else if !breached && was_active {
+                position.active.retain(|i| i != id);
+            }
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn risk(amount: f64, margin_ratio: f64) -> PositionRisk {
+        PositionRisk {
+            symbol: "BTCUSDT".into(),
+            position_side: "BOTH".into(),
+            amount,
+            entry_price: 100.0,
+            mark_price: 100.0,
+            liquidation_price: 80.0,
+            unrealized_pnl: 0.0,
+            notional: amount * 100.0,
+            leverage: 5,
+            isolated: false,
+            margin_ratio,
+            adl_quantile: 1,
+        }
+    }
+
+    fn mark(price: f64) -> MarkPrice {
+        MarkPrice {
+            symbol: "btcusdt".into(),
+            timestamp: 1,
+            mark_price: price,
+            index_price: price,
+            funding_rate: 0.0,
+            next_funding_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_liquidation_monitor() {
+        let mut monitor = LiquidationMonitor::new();
+        let ratio = monitor.add(Threshold::MarginRatioAbove(0.8));
+        let distance = monitor.add(Threshold::DistanceBelow(0.1));
+        let rx = monitor.subscribe();
+
+        monitor.on_position_risk(&risk(1.0, 0.5));
+        assert!(rx.try_recv().is_err());
+
+        // 20% away, then 6.9% away, still breached, then recovered and breached again
+        for price in [100.0, 86.0, 85.0, 95.0, 88.0] {
+            monitor.on_mark_price(&mark(price));
+        }
+        let events: Vec<RiskEvent> = rx.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, distance);
+        assert_eq!(events[0].mark_price, 86.0);
+        assert!((events[0].value - 6.0 / 86.0).abs() < 1e-9);
+        assert_eq!(events[1].mark_price, 88.0);
+
+        monitor.on_position_risk(&risk(1.0, 0.9));
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.id, ratio);
+        assert_eq!(event.value, 0.9);
+
+        monitor.on_position_risk(&risk(0.0, 0.0));
+        assert!(monitor.positions().is_empty());
+        monitor.on_mark_price(&mark(81.0));
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+
+use log::warn;
+use std::collections::BTreeMap;
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingKind {
+    // new symbol, trading may not have started yet
+    Listed,
+    // started or resumed trading
+    Trading,
+    Suspended,
+    // gone from the list or marked delisted
+    Delisted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListingEvent {
+    pub exchange: String,
+    pub symbol: String,
+    pub kind: ListingKind,
+    // None once gone from the list
+    pub status: Option<SymbolStatus>,
+    pub timestamp: u64,
+}
+
+type FetchSymbols = Box<dyn Fn() -> APIResult<Vec<SymbolInfo>> + Send>;
+
+struct Watch {
+    exchange: String,
+    fetch: FetchSymbols,
+    // None until the first successful fetch, which only sets the baseline
+    symbols: Option<BTreeMap<String, SymbolStatus>>,
+}
+
+// diffs the symbols of exchanges between checks and reports new listings,
+// suspensions and delistings. the first check of an exchange only records
+// what is listed
+//
+//     let mut watcher = ListingWatcher::new();
+//     let api = Arc::new(binance);
+//     watcher.watch("binance", move || api.get_symbols());
+//     watcher.on_event(|event| match event.kind {
+//         ListingKind::Delisted | ListingKind::Suspended => pause(&event.symbol),
+//         ListingKind::Trading => info!("{} is trading", event.symbol),
+//         _ => {}
+//     });
+//     watcher.run_every(Duration::from_secs(60));
+pub struct ListingWatcher {
+    watches: Vec<Watch>,
+    notifier: Notifier<ListingEvent>,
+    shutdown: Shutdown,
+}
+
+impl Default for ListingWatcher {
+    fn default() -> Self {
+        ListingWatcher::new()
+    }
+}
+
+impl ListingWatcher {
+    pub fn new() -> Self {
+        ListingWatcher {
+            watches: vec![],
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // fetch returns every symbol of the exchange, e.g. get_symbols
+    pub fn watch<F>(&mut self, exchange: &str, fetch: F)
+    where
+        F: Fn() -> APIResult<Vec<SymbolInfo>> + Send + 'static,
+    {
+        self.watches.push(Watch {
+            exchange: exchange.into(),
+            fetch: Box::new(fetch),
+            symbols: None,
+        });
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(&ListingEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<ListingEvent> {
+        self.notifier.subscribe()
+    }
+
+    // run_every stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // the last known status of the symbol
+    pub fn status(&self, exchange: &str, symbol: &str) -> Option<SymbolStatus> {
+        self.watches
+            .iter()
+            .find(|w| w.exchange == exchange)?
+            .symbols
+            .as_ref()?
+            .get(&symbol.to_uppercase())
+            .copied()
+    }
+
+    // fetches every exchange, returns the events fired. failed fetches are
+    // logged and compared on the next check
+    pub fn check(&mut self) -> Vec<ListingEvent> {
+        let mut events = vec![];
+        let now = now_ms();
+        for watch in self.watches.iter_mut() {
+            let symbols = match (watch.fetch)() {
+                Ok(symbols) => symbols,
+                Err(err) => {
+                    warn!("symbols of {} failed: {:?}", watch.exchange, err);
+                    continue;
+                }
+            };
+            let current: BTreeMap<String, SymbolStatus> = symbols
+                .into_iter()
+                .map(|s| (s.symbol.to_uppercase(), s.status))
+                .collect();
+            let previous = match watch.symbols.replace(current.clone()) {
+                Some(previous) => previous,
+                None => continue,
+            };
+            let event = |symbol: &str, kind, status| ListingEvent {
+                exchange: watch.exchange.clone(),
+                symbol: symbol.into(),
+                kind,
+                status,
+                timestamp: now,
+            };
+            for (symbol, status) in current.iter() {
+                let kind = match (previous.get(symbol), status) {
+                    (None, _) => Some(ListingKind::Listed),
+                    (Some(was), is) if was == is => None,
+                    (_, SymbolStatus::Trading) => Some(ListingKind::Trading),
+                    (_, SymbolStatus::Suspended) => Some(ListingKind::Suspended),
+                    (_, SymbolStatus::Delisted) => Some(ListingKind::Delisted),
+                    (_, SymbolStatus::PreTrading) => None,
+                };
+                if let Some(kind) = kind {
+                    events.push(event(symbol, kind, Some(*status)));
+                }
+                // listed straight into trading
+                if kind == Some(ListingKind::Listed) && *status == SymbolStatus::Trading {
+                    events.push(event(symbol, ListingKind::Trading, Some(*status)));
+                }
+            }
+            for (symbol, status) in previous.iter() {
+                if !current.contains_key(symbol) && *status != SymbolStatus::Delisted {
+                    events.push(event(symbol, ListingKind::Delisted, None));
+                }
+            }
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+        events
+    }
+
+    // checks on a background thread, the first time right away
+    pub fn run_every(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.check();
+            if !self.shutdown.sleep(interval) {
+                return;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn symbol(name: &str, status: SymbolStatus) -> SymbolInfo {
+        SymbolInfo {
+            base: name.into(),
+            quote: "USDT".into(),
+            symbol: format!("{}USDT", name),
+            status,
+            price_precision: 2,
+            amount_precision: 3,
+            min_amount: 0.001,
+            min_value: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_listing_watcher() {
+        let symbols = Arc::new(Mutex::new(vec![
+            symbol("BTC", SymbolStatus::Trading),
+            symbol("LUNA", SymbolStatus::Trading),
+        ]));
+        let mut watcher = ListingWatcher::new();
+        let current = symbols.clone();
+        watcher.watch("binance", move || Ok(current.lock().unwrap().clone()));
+        let rx = watcher.subscribe();
+
+        assert!(watcher.check().is_empty());
+        assert_eq!(
+            watcher.status("binance", "btcusdt"),
+            Some(SymbolStatus::Trading)
+        );
+
+        {
+            let mut symbols = symbols.lock().unwrap();
+            symbols.push(symbol("NEW", SymbolStatus::PreTrading));
+            symbols.push(symbol("HOT", SymbolStatus::Trading));
+            symbols[1].status = SymbolStatus::Suspended;
+        }
+        let kinds: Vec<(String, ListingKind)> = watcher
+            .check()
+            .into_iter()
+            .map(|e| (e.symbol, e.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("HOTUSDT".to_string(), ListingKind::Listed),
+                ("HOTUSDT".to_string(), ListingKind::Trading),
+                ("LUNAUSDT".to_string(), ListingKind::Suspended),
+                ("NEWUSDT".to_string(), ListingKind::Listed),
+            ]
+        );
+
+        {
+            let mut symbols = symbols.lock().unwrap();
+            symbols.remove(1);
+            symbols[1].status = SymbolStatus::Trading;
+        }
+        let events = watcher.check();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].symbol, "NEWUSDT");
+        assert_eq!(events[0].kind, ListingKind::Trading);
+        assert_eq!(events[1].symbol, "LUNAUSDT");
+        assert_eq!(events[1].kind, ListingKind::Delisted);
+        assert_eq!(events[1].status, None);
+        assert!(watcher.check().is_empty());
+        assert_eq!(rx.try_iter().count(), 6);
+    }
+}
@@ -1,3 +1,19 @@
+use crate::errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum SystemStatus {
+    Normal,
+    Maintenance(String),
+}
+
 // for futures
 #[derive(Debug)]
 pub enum PositionType {
@@ -6,64 +22,445 @@ pub enum PositionType {
     All,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SymbolStatus {
+    Trading,
+    // listed, trading not started yet
+    PreTrading,
+    // halted for now, e.g. in a break or settling
+    Suspended,
+    Delisted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SymbolInfo {
     pub base: String,
     pub quote: String,
     pub symbol: String,
+    pub status: SymbolStatus,
     pub price_precision: u8,
     pub amount_precision: u8,
     pub min_amount: f64,
     pub min_value: f64,
 }
 
-#[derive(Debug)]
+// the order filters quoting depends on, 0 when the exchange has none
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolRules {
+    pub symbol: String,
+    pub tick_size: f64,
+    pub step_size: f64,
+    pub min_qty: f64,
+    pub min_notional: f64,
+}
+
+// for exchanges that give precisions instead of tick and step sizes
+impl From<&SymbolInfo> for SymbolRules {
+    fn from(item: &SymbolInfo) -> SymbolRules {
+        SymbolRules {
+            symbol: item.symbol.clone(),
+            tick_size: 10f64.powi(-(item.price_precision as i32)),
+            step_size: 10f64.powi(-(item.amount_precision as i32)),
+            min_qty: item.min_amount,
+            min_notional: item.min_value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Balance {
     pub asset: String,
     pub free: f64,
     pub locked: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+    // a status the crate doesn't know, the order may still be working
+    Unknown,
+}
+
+impl OrderStatus {
+    // no further fills can happen
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled
+                | OrderStatus::Canceled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        )
+    }
+}
+
+// for futures
+#[derive(Debug, Serialize)]
+pub struct FutureBalance {
+    pub asset: String,
+    pub wallet_balance: f64,
+    pub margin_balance: f64,
+    pub unrealized_pnl: f64,
+    pub available_balance: f64,
+    pub max_withdraw_amount: f64,
+    pub initial_margin: f64,
+    pub maint_margin: f64,
+    // in multi-assets margin mode the asset counts towards the shared usd
+    // collateral, the per asset available balance is then not what limits
+    // new positions
+    pub margin_available: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Order {
     pub symbol: String,
     pub order_id: String,
     pub amount: f64,
     pub price: f64,
     pub side: String,
+    // executed quantity
     pub filled: f64,
-    pub status: u8,
+    pub avg_price: f64,
+    pub status: OrderStatus,
+    pub create_time: u64,
+    pub update_time: u64,
 }
 
-#[derive(Debug)]
+// what the matching engine does when an order would trade against another
+// order of the same account
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SelfTradePrevention {
+    // cancels the incoming order
+    ExpireTaker,
+    // cancels the resting order
+    ExpireMaker,
+    ExpireBoth,
+}
+
+impl SelfTradePrevention {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SelfTradePrevention::ExpireTaker => "EXPIRE_TAKER",
+            SelfTradePrevention::ExpireMaker => "EXPIRE_MAKER",
+            SelfTradePrevention::ExpireBoth => "EXPIRE_BOTH",
+        }
+    }
+}
+
+// exchange agnostic order, built with the constructors and chained options
+//
+//     let order = OrderRequest::limit_buy("BTCUSDT", 9000.0, 0.01)
+//         .post_only()
+//         .client_id("grid-1");
+//     api.create_order(&order)?;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    // BUY or SELL
+    pub side: String,
+    // LIMIT or MARKET
+    pub order_type: String,
+    // ignored by market orders
+    pub price: f64,
+    pub amount: f64,
+    // GTC, IOC or FOK, limit orders only
+    pub time_in_force: String,
+    // rejected instead of taking liquidity
+    pub post_only: bool,
+    // futures only, never increases the position
+    pub reduce_only: bool,
+    // futures only, LONG or SHORT in hedge mode
+    #[serde(default)]
+    pub position_side: Option<String>,
+    // generated when placing the order if not set
+    pub client_id: Option<String>,
+    // market orders only, how much of the quote asset to spend or get,
+    // amount is ignored when set
+    #[serde(default)]
+    pub quote_amount: Option<f64>,
+    // limit orders only, ms timestamp the order is cancelled at
+    #[serde(default)]
+    pub expire_at: Option<u64>,
+    // cancelled by an expiry::OrderExpiry even where the exchange has GTD
+    #[serde(default)]
+    pub emulate_expiry: bool,
+    // the exchange or account default when None
+    #[serde(default)]
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+}
+
+impl OrderRequest {
+    pub fn new(symbol: &str, side: &str, order_type: &str, price: f64, amount: f64) -> Self {
+        OrderRequest {
+            symbol: symbol.into(),
+            side: side.to_uppercase(),
+            order_type: order_type.to_uppercase(),
+            price,
+            amount,
+            time_in_force: "GTC".into(),
+            post_only: false,
+            reduce_only: false,
+            position_side: None,
+            client_id: None,
+            quote_amount: None,
+            expire_at: None,
+            emulate_expiry: false,
+            self_trade_prevention: None,
+        }
+    }
+
+    // the reduce-only market order closing a futures position, amount is
+    // signed as the exchange reports it. None for an empty position
+    //
+    //     OrderRequest::close("BTCUSDT", "BOTH", -0.5) // market buy 0.5, reduce only
+    //     OrderRequest::close("BTCUSDT", "LONG", 0.5)  // market sell 0.5 of the long side
+    pub fn close(symbol: &str, position_side: &str, amount: f64) -> Option<Self> {
+        if amount == 0.0 {
+            return None;
+        }
+        let order = match position_side.to_uppercase().as_str() {
+            "LONG" => OrderRequest::market_sell(symbol, amount.abs()).position_side("LONG"),
+            "SHORT" => OrderRequest::market_buy(symbol, amount.abs()).position_side("SHORT"),
+            _ if amount > 0.0 => OrderRequest::market_sell(symbol, amount),
+            _ => OrderRequest::market_buy(symbol, -amount),
+        };
+        Some(order.reduce_only())
+    }
+
+    pub fn limit_buy(symbol: &str, price: f64, amount: f64) -> Self {
+        OrderRequest::new(symbol, "BUY", "LIMIT", price, amount)
+    }
+
+    pub fn limit_sell(symbol: &str, price: f64, amount: f64) -> Self {
+        OrderRequest::new(symbol, "SELL", "LIMIT", price, amount)
+    }
+
+    pub fn market_buy(symbol: &str, amount: f64) -> Self {
+        OrderRequest::new(symbol, "BUY", "MARKET", 0.0, amount)
+    }
+
+    pub fn market_sell(symbol: &str, amount: f64) -> Self {
+        OrderRequest::new(symbol, "SELL", "MARKET", 0.0, amount)
+    }
+
+    // buys for exactly quote_amount, e.g. 500 USDT of BTC, filled at
+    // whatever the book offers instead of a base amount from a stale price
+    pub fn market_buy_quote(symbol: &str, quote_amount: f64) -> Self {
+        OrderRequest::market_buy(symbol, 0.0).quote_amount(quote_amount)
+    }
+
+    pub fn market_sell_quote(symbol: &str, quote_amount: f64) -> Self {
+        OrderRequest::market_sell(symbol, 0.0).quote_amount(quote_amount)
+    }
+
+    pub fn quote_amount(mut self, quote_amount: f64) -> Self {
+        self.quote_amount = Some(quote_amount);
+        self
+    }
+
+    // GTD where the exchange has it, cancelled from the client otherwise.
+    // binance futures want expire_at at least 10 minutes ahead
+    pub fn good_till(mut self, expire_at: u64) -> Self {
+        self.expire_at = Some(expire_at);
+        self.emulate_expiry = false;
+        self
+    }
+
+    pub fn good_till_emulated(mut self, expire_at: u64) -> Self {
+        self.expire_at = Some(expire_at);
+        self.emulate_expiry = true;
+        self
+    }
+
+    pub fn self_trade_prevention(mut self, mode: SelfTradePrevention) -> Self {
+        self.self_trade_prevention = Some(mode);
+        self
+    }
+
+    pub fn time_in_force(mut self, time_in_force: &str) -> Self {
+        self.time_in_force = time_in_force.to_uppercase();
+        self
+    }
+
+    pub fn post_only(mut self) -> Self {
+        self.post_only = true;
+        self
+    }
+
+    pub fn reduce_only(mut self) -> Self {
+        self.reduce_only = true;
+        self
+    }
+
+    pub fn position_side(mut self, position_side: &str) -> Self {
+        self.position_side = Some(position_side.to_uppercase());
+        self
+    }
+
+    pub fn client_id(mut self, client_id: &str) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    pub fn is_market(&self) -> bool {
+        self.order_type == "MARKET"
+    }
+}
+
+// order features a client supports beyond plain limit and market orders,
+// for code generic over clients to branch on instead of failing at runtime.
+// false when the crate doesn't do it for the client, even if the exchange
+// has it
+//
+//     let order = if api.capabilities().post_only {
+//         order.post_only()
+//     } else {
+//         order
+//     };
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ExchangeCapabilities {
+    // one cancels the other order pairs
+    pub oco: bool,
+    pub post_only: bool,
+    // several orders in one request
+    pub batch_orders: bool,
+    // futures only, long and short positions of a symbol held at once
+    pub hedge_mode: bool,
+    // orders placed over the websocket connection
+    pub ws_trading: bool,
+    // market buys sized in the quote asset, OrderRequest::market_buy_quote
+    pub quote_amount: bool,
+    // good till orders expired by the exchange, OrderRequest::good_till
+    // falls back to expiry::OrderExpiry without
+    pub good_till_date: bool,
+    // OrderRequest::self_trade_prevention
+    pub self_trade_prevention: bool,
+}
+
+#[derive(Debug, Serialize)]
 pub struct Orderbook {
     pub timestamp: u64,
+    pub last_update_id: u64,
     pub bids: Vec<Bid>,
     pub asks: Vec<Ask>,
 }
 
-#[derive(Debug)]
+// expected execution of a market order against a book snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FillEstimate {
+    // less than asked when the book is too thin, see complete
+    pub filled: f64,
+    pub complete: bool,
+    pub avg_price: f64,
+    // price of the last level touched
+    pub worst_price: f64,
+    // avg_price against the best price, positive is a cost
+    pub slippage_bps: f64,
+    // avg_price against the mid, includes half the spread
+    pub impact_bps: f64,
+    // price levels consumed, the last one maybe partially
+    pub levels: usize,
+    pub notional: f64,
+}
+
+impl Orderbook {
+    // walks the asks for a BUY, the bids for a SELL. None when that side
+    // is empty or qty isn't positive
+    //
+    //     let est = book.estimate_fill("BUY", 2.5).unwrap();
+    //     if est.slippage_bps > 5.0 { /* split the order */ }
+    pub fn estimate_fill(&self, side: &str, qty: f64) -> Option<FillEstimate> {
+        let buy = side.eq_ignore_ascii_case("buy");
+        let mut levels: Vec<(f64, f64)> = if buy {
+            self.asks.iter().map(|a| (a.price, a.amount)).collect()
+        } else {
+            self.bids.iter().map(|b| (b.price, b.amount)).collect()
+        };
+        if buy {
+            levels.sort_by(|a, b| a.0.total_cmp(&b.0));
+        } else {
+            levels.sort_by(|a, b| b.0.total_cmp(&a.0));
+        }
+        let best = levels.first()?.0;
+        if qty <= 0.0 {
+            return None;
+        }
+        let (mut filled, mut notional, mut worst, mut used) = (0.0, 0.0, best, 0);
+        for (price, amount) in levels.iter().filter(|(_, amount)| *amount > 0.0) {
+            if filled >= qty {
+                break;
+            }
+            let take = amount.min(qty - filled);
+            filled += take;
+            notional += take * price;
+            worst = *price;
+            used += 1;
+        }
+        if filled == 0.0 {
+            return None;
+        }
+        let avg_price = notional / filled;
+        // positive when the fill is worse than the reference
+        let cost_bps = |reference: f64| {
+            let diff = if buy {
+                avg_price - reference
+            } else {
+                reference - avg_price
+            };
+            diff / reference * 10000.0
+        };
+        let mid = match (
+            self.bids.iter().map(|b| b.price).reduce(f64::max),
+            self.asks.iter().map(|a| a.price).reduce(f64::min),
+        ) {
+            (Some(bid), Some(ask)) => (bid + ask) / 2.0,
+            _ => best,
+        };
+        Some(FillEstimate {
+            filled,
+            complete: filled >= qty,
+            avg_price,
+            worst_price: worst,
+            slippage_bps: cost_bps(best),
+            impact_bps: cost_bps(mid),
+            levels: used,
+            notional,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Trade {
+    pub symbol: String,
+    pub trade_id: u64,
     pub timestamp: u64,
     pub amount: f64,
     pub price: f64,
+    // taker side, "buy" or "sell"
     pub side: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Bid {
     pub price: f64,
     pub amount: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Ask {
     pub price: f64,
     pub amount: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Ticker {
+    pub symbol: String,
     pub timestamp: u64,
     pub bid: Bid,
     pub ask: Ask,
@@ -72,6 +469,7 @@ pub struct Ticker {
 impl Ticker {
     pub fn new() -> Self {
         Ticker {
+            symbol: String::new(),
             timestamp: 0u64,
             bid: Bid {
                 price: 0f64,
@@ -85,7 +483,13 @@ impl Ticker {
     }
 }
 
-#[derive(Debug)]
+impl Default for Ticker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Kline {
     pub timestamp: u64,
     pub open: f64,
@@ -95,7 +499,115 @@ pub struct Kline {
     pub volume: f64,
 }
 
+// kline pushed by a websocket stream, is_closed is set on the final update of a candle
+#[derive(Debug, Clone, Serialize)]
+pub struct KlineUpdate {
+    pub symbol: String,
+    pub period: String,
+    pub kline: Kline,
+    pub is_closed: bool,
+}
+
 // for futures
+// futures mark price with the current funding rate
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkPrice {
+    pub symbol: String,
+    pub timestamp: u64,
+    pub mark_price: f64,
+    pub index_price: f64,
+    pub funding_rate: f64,
+    pub next_funding_time: u64,
+}
+
+// open contracts of a futures symbol, in the base asset
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenInterest {
+    pub symbol: String,
+    pub timestamp: u64,
+    pub open_interest: f64,
+}
+
+// order change pushed by the user data stream
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderUpdate {
+    pub symbol: String,
+    pub order_id: String,
+    pub client_order_id: String,
+    pub side: String,
+    pub order_type: String,
+    // NEW, TRADE, CANCELED, EXPIRED ...
+    pub execution_type: String,
+    pub status: OrderStatus,
+    pub price: f64,
+    pub amount: f64,
+    pub filled: f64,
+    pub avg_price: f64,
+    pub last_filled_amount: f64,
+    pub last_filled_price: f64,
+    pub commission: f64,
+    pub commission_asset: String,
+    pub trade_id: i64,
+    pub timestamp: u64,
+}
+
+// balances that changed in a spot account
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountUpdate {
+    pub timestamp: u64,
+    pub balances: Vec<Balance>,
+}
+
+// deposit, withdrawal or transfer on a spot account
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceDelta {
+    pub asset: String,
+    pub change: f64,
+    pub timestamp: u64,
+}
+
+// for futures
+#[derive(Debug, Clone, Serialize)]
+pub struct FutureAccountUpdate {
+    pub timestamp: u64,
+    // ORDER, FUNDING_FEE, DEPOSIT ...
+    pub reason: String,
+    pub balances: Vec<FutureBalanceUpdate>,
+    pub positions: Vec<PositionUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FutureBalanceUpdate {
+    pub asset: String,
+    pub wallet_balance: f64,
+    pub cross_wallet_balance: f64,
+    // balance change except pnl and commission
+    pub change: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionUpdate {
+    pub symbol: String,
+    pub amount: f64,
+    pub entry_price: f64,
+    pub unrealized_pnl: f64,
+    // BOTH, LONG or SHORT
+    pub position_side: String,
+}
+
+// forced liquidation order, side is the side of the liquidation order
+#[derive(Debug, Clone, Serialize)]
+pub struct Liquidation {
+    pub symbol: String,
+    pub side: String,
+    pub price: f64,
+    pub avg_price: f64,
+    pub amount: f64,
+    pub filled: f64,
+    pub status: OrderStatus,
+    pub timestamp: u64,
+}
+
 #[derive(Debug)]
 pub struct Position {
     pub symbol: String,
@@ -103,3 +615,532 @@ pub struct Position {
     pub price: f64,
     pub pos_type: PositionType,
 }
+
+// usage of one exchange rate limit window, e.g. REQUEST_WEIGHT per minute
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitStatus {
+    // REQUEST_WEIGHT, ORDERS or RAW_REQUESTS
+    pub kind: String,
+    pub interval_ms: u64,
+    // unknown until seeded from exchange info
+    pub limit: Option<u64>,
+    pub used: u64,
+    pub remaining: Option<u64>,
+    // timestamp the current window ends
+    pub reset_at: u64,
+}
+
+// commission of the account's fee tier, as a fraction of the traded value
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeRate {
+    pub symbol: String,
+    pub maker: f64,
+    pub taker: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRisk {
+    pub symbol: String,
+    // BOTH, LONG or SHORT
+    pub position_side: String,
+    pub amount: f64,
+    pub entry_price: f64,
+    pub mark_price: f64,
+    // 0 when the position can't be liquidated
+    pub liquidation_price: f64,
+    pub unrealized_pnl: f64,
+    pub notional: f64,
+    pub leverage: u32,
+    pub isolated: bool,
+    // maintenance margin over margin balance, liquidation happens at 1.0.
+    // cross positions share the account's ratio
+    pub margin_ratio: f64,
+    // 0 to 4, the higher the sooner the position is auto deleveraged
+    pub adl_quantile: u8,
+}
+
+// notional tier of a futures symbol
+#[derive(Debug, Clone, Serialize)]
+pub struct LeverageBracket {
+    pub bracket: u32,
+    pub initial_leverage: u32,
+    pub notional_floor: f64,
+    pub notional_cap: f64,
+    pub maint_margin_ratio: f64,
+    // maintenance amount, makes the margin continuous across tiers
+    pub cum: f64,
+}
+
+impl LeverageBracket {
+    // maintenance margin of a position of `notional` given the symbol's brackets
+    pub fn maint_margin(brackets: &[LeverageBracket], notional: f64) -> Option<f64> {
+        let notional = notional.abs();
+        brackets
+            .iter()
+            .find(|b| notional >= b.notional_floor && notional < b.notional_cap)
+            .map(|b| notional * b.maint_margin_ratio - b.cum)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertQuote {
+    pub quote_id: String,
+    // to asset per from asset
+    pub ratio: f64,
+    pub inverse_ratio: f64,
+    pub from_amount: f64,
+    pub to_amount: f64,
+    // the quote can be accepted until then
+    pub valid_until: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertOrder {
+    pub order_id: String,
+    // PROCESS, ACCEPT_SUCCESS, SUCCESS or FAIL
+    pub status: String,
+    pub create_time: u64,
+}
+
+// balance small enough to be converted to BNB
+#[derive(Debug, Clone, Serialize)]
+pub struct DustAsset {
+    pub asset: String,
+    pub amount: f64,
+    // BNB received after the service charge
+    pub to_bnb: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DustTransfer {
+    pub tran_id: u64,
+    pub from_asset: String,
+    pub amount: f64,
+    // BNB received and charged
+    pub transferred_amount: f64,
+    pub service_charge: f64,
+    pub timestamp: u64,
+}
+
+// simple earn flexible product
+#[derive(Debug, Clone, Serialize)]
+pub struct EarnProduct {
+    pub product_id: String,
+    pub asset: String,
+    // latest annual percentage rate, 0.05 is 5%
+    pub apr: f64,
+    pub min_purchase_amount: f64,
+    pub can_purchase: bool,
+    pub can_redeem: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EarnPosition {
+    pub product_id: String,
+    pub asset: String,
+    pub amount: f64,
+    pub apr: f64,
+    pub can_redeem: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyPermissions {
+    pub ip_restrict: bool,
+    pub reading: bool,
+    pub spot_and_margin_trading: bool,
+    pub margin: bool,
+    pub futures: bool,
+    pub withdrawals: bool,
+    pub internal_transfer: bool,
+    pub universal_transfer: bool,
+    pub vanilla_options: bool,
+    // spot and margin trading is disabled after this without ip restriction
+    pub trading_expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    // orders, cancels, conversions and account settings
+    Trade,
+    // moving assets between wallets and products, e.g. dust, earn
+    Transfer,
+    Withdraw,
+}
+
+// what a client may do on top of reading, checked locally before a request
+// is sent as a second line of defense behind the key's own permissions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Scope {
+    pub trade: bool,
+    pub transfer: bool,
+    pub withdraw: bool,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope::all()
+    }
+}
+
+impl Scope {
+    pub fn all() -> Self {
+        Scope {
+            trade: true,
+            transfer: true,
+            withdraw: true,
+        }
+    }
+
+    pub fn read_only() -> Self {
+        Scope {
+            trade: false,
+            transfer: false,
+            withdraw: false,
+        }
+    }
+
+    // read only plus the given capabilities
+    pub fn only(capabilities: &[Capability]) -> Self {
+        Scope {
+            trade: capabilities.contains(&Capability::Trade),
+            transfer: capabilities.contains(&Capability::Transfer),
+            withdraw: capabilities.contains(&Capability::Withdraw),
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Trade => self.trade,
+            Capability::Transfer => self.transfer,
+            Capability::Withdraw => self.withdraw,
+        }
+    }
+
+    // PermissionDenied naming the call when the capability is out of scope
+    pub fn check(&self, capability: Capability, call: &str) -> APIResult<()> {
+        if self.allows(capability) {
+            Ok(())
+        } else {
+            Err(Box::new(ExError::PermissionDenied(format!(
+                "{} needs the {:?} capability",
+                call, capability
+            ))))
+        }
+    }
+}
+
+// portfolio margin account, margin is shared across spot margin, um and cm
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioAccount {
+    // unified maintenance margin ratio, liquidation starts below 1.05
+    pub uni_mmr: f64,
+    pub account_equity: f64,
+    pub actual_equity: f64,
+    pub initial_margin: f64,
+    pub maint_margin: f64,
+    // NORMAL, MARGIN_CALL, SUPPLY_MARGIN, REDUCE_ONLY, ACTIVE_LIQUIDATION or FORCE_LIQUIDATION
+    pub status: String,
+    pub max_withdraw_amount: f64,
+}
+
+// own trade, one fill of an order
+#[derive(Debug, Clone, Serialize)]
+pub struct MyTrade {
+    pub symbol: String,
+    pub trade_id: u64,
+    pub order_id: String,
+    pub price: f64,
+    pub amount: f64,
+    pub fee: f64,
+    pub fee_asset: String,
+    // "buy" or "sell"
+    pub side: String,
+    pub is_maker: bool,
+    pub timestamp: u64,
+}
+
+// futures account income, e.g. REALIZED_PNL, FUNDING_FEE, COMMISSION
+#[derive(Debug, Clone, Serialize)]
+pub struct Income {
+    pub symbol: String,
+    pub kind: String,
+    pub amount: f64,
+    pub asset: String,
+    pub tran_id: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DepositStatus {
+    Pending,
+    // tradable but not yet withdrawable
+    Credited,
+    Success,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Deposit {
+    pub id: String,
+    pub coin: String,
+    pub network: String,
+    pub amount: f64,
+    pub address: String,
+    pub tx_id: String,
+    pub status: DepositStatus,
+    // block confirmations so far and needed, 0 when the exchange doesn't say
+    pub confirmations: u32,
+    pub required_confirmations: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DepositAddress {
+    pub coin: String,
+    // the network the address is on, empty when the exchange doesn't say
+    pub network: String,
+    pub address: String,
+    // tag or memo some coins need besides the address
+    pub memo: Option<String>,
+}
+
+// a chain a coin can be deposited and withdrawn on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoinNetwork {
+    pub coin: String,
+    // the exchange's own id, e.g. TRX on binance and trc20usdt on huobi
+    pub network: String,
+    // e.g. "Tron (TRC20)"
+    pub name: String,
+    // used when a withdrawal names no network
+    pub is_default: bool,
+    pub deposit_enabled: bool,
+    pub withdraw_enabled: bool,
+    pub withdraw_fee: f64,
+    pub withdraw_min: f64,
+    // 0 when unlimited
+    pub withdraw_max: f64,
+    // deposits need the memo besides the address
+    pub memo_required: bool,
+    pub min_confirmations: u32,
+}
+
+// withdrawal to an external address, built like OrderRequest
+//
+//     let req = WithdrawRequest::new("USDT", &address.address, 1000.0)
+//         .network("TRX")
+//         .client_id("rebalance-1");
+//     api.withdraw_checked(&req)?;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WithdrawRequest {
+    pub coin: String,
+    // the exchange's network id, its default network when None
+    #[serde(default)]
+    pub network: Option<String>,
+    pub address: String,
+    pub amount: f64,
+    pub memo: Option<String>,
+    // the exchange's withdraw order id, where supported
+    pub client_id: Option<String>,
+}
+
+impl WithdrawRequest {
+    pub fn new(coin: &str, address: &str, amount: f64) -> Self {
+        WithdrawRequest {
+            coin: coin.to_uppercase(),
+            network: None,
+            address: address.into(),
+            amount,
+            memo: None,
+            client_id: None,
+        }
+    }
+
+    // to a deposit address of another account. the network ids of the two
+    // exchanges may differ, set the one of the withdrawing exchange
+    pub fn to(address: &DepositAddress, amount: f64) -> Self {
+        WithdrawRequest {
+            memo: address.memo.clone(),
+            ..WithdrawRequest::new(&address.coin, &address.address, amount)
+        }
+    }
+
+    pub fn network(mut self, network: &str) -> Self {
+        self.network = Some(network.into());
+        self
+    }
+
+    pub fn memo(mut self, memo: &str) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn client_id(mut self, client_id: &str) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    // the network the request goes out on, an error unless it is safe to
+    // send: the network must be named when the coin has several, open for
+    // withdrawals, the amount within its limits and the memo set when the
+    // network needs one
+    pub fn check<'a>(&self, networks: &'a [CoinNetwork]) -> APIResult<&'a CoinNetwork> {
+        let invalid = |why: String| -> APIResult<&'a CoinNetwork> {
+            Err(Box::new(ExError::ApiError(format!(
+                "withdrawal of {} {}: {}",
+                self.amount, self.coin, why
+            ))))
+        };
+        let network = match self.network.as_ref() {
+            Some(name) => networks
+                .iter()
+                .find(|n| n.network.eq_ignore_ascii_case(name)),
+            None if networks.len() == 1 => networks.first(),
+            None => {
+                let names: Vec<&str> = networks.iter().map(|n| n.network.as_str()).collect();
+                return invalid(format!("no network given, one of {:?}", names));
+            }
+        };
+        let network = match network {
+            Some(network) => network,
+            None => return invalid(format!("unknown network {:?}", self.network)),
+        };
+        if !network.withdraw_enabled {
+            return invalid(format!("withdrawals on {} are suspended", network.network));
+        }
+        if self.amount < network.withdraw_min {
+            return invalid(format!("below the min {}", network.withdraw_min));
+        }
+        if network.withdraw_max > 0.0 && self.amount > network.withdraw_max {
+            return invalid(format!("above the max {}", network.withdraw_max));
+        }
+        if network.memo_required && self.memo.is_none() {
+            return invalid(format!("{} needs a memo", network.network));
+        }
+        Ok(network)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum WithdrawStatus {
+    // waiting for review or confirmation
+    Pending,
+    // approved, being broadcast
+    Processing,
+    Completed,
+    Canceled,
+    Failed,
+}
+
+impl WithdrawStatus {
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            WithdrawStatus::Completed | WithdrawStatus::Canceled | WithdrawStatus::Failed
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Withdrawal {
+    pub id: String,
+    pub coin: String,
+    pub network: String,
+    pub amount: f64,
+    pub fee: f64,
+    pub address: String,
+    // empty until broadcast
+    pub tx_id: String,
+    pub status: WithdrawStatus,
+    pub timestamp: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimate_fill() {
+        let book = Orderbook {
+            timestamp: 0,
+            last_update_id: 0,
+            bids: vec![
+                Bid {
+                    price: 99.0,
+                    amount: 1.0,
+                },
+                Bid {
+                    price: 98.0,
+                    amount: 2.0,
+                },
+            ],
+            asks: vec![
+                Ask {
+                    price: 102.0,
+                    amount: 2.0,
+                },
+                Ask {
+                    price: 101.0,
+                    amount: 1.0,
+                },
+            ],
+        };
+        let est = book.estimate_fill("BUY", 2.0).unwrap();
+        assert!(est.complete);
+        assert_eq!(est.avg_price, 101.5);
+        assert_eq!(est.worst_price, 102.0);
+        assert_eq!(est.levels, 2);
+        assert!((est.slippage_bps - 0.5 / 101.0 * 10000.0).abs() < 1e-9);
+        assert!((est.impact_bps - 1.5 / 100.0 * 10000.0).abs() < 1e-9);
+
+        let est = book.estimate_fill("sell", 5.0).unwrap();
+        assert!(!est.complete);
+        assert_eq!(est.filled, 3.0);
+        assert_eq!(est.notional, 99.0 + 196.0);
+        assert!(est.slippage_bps > 0.0);
+
+        assert!(book.estimate_fill("BUY", 0.0).is_none());
+    }
+
+    #[test]
+    fn test_withdraw_check() {
+        let network = |name: &str, memo_required: bool| CoinNetwork {
+            coin: "USDT".into(),
+            network: name.into(),
+            name: name.into(),
+            is_default: false,
+            deposit_enabled: true,
+            withdraw_enabled: true,
+            withdraw_fee: 1.0,
+            withdraw_min: 10.0,
+            withdraw_max: 1000.0,
+            memo_required,
+            min_confirmations: 1,
+        };
+        let mut networks = vec![network("TRX", false), network("EOS", true)];
+        let req = WithdrawRequest::new("usdt", "addr", 100.0);
+        assert!(req.check(&networks).is_err());
+        assert!(req.check(&networks[..1]).is_ok());
+        assert_eq!(
+            req.clone().network("trx").check(&networks).unwrap().network,
+            "TRX"
+        );
+        assert!(req.clone().network("BSC").check(&networks).is_err());
+        assert!(req.clone().network("EOS").check(&networks).is_err());
+        assert!(req
+            .clone()
+            .network("EOS")
+            .memo("1")
+            .check(&networks)
+            .is_ok());
+        let small = WithdrawRequest::new("USDT", "addr", 5.0).network("TRX");
+        assert!(small
+            .check(&networks)
+            .unwrap_err()
+            .to_string()
+            .contains("min"));
+        networks[0].withdraw_enabled = false;
+        assert!(req.network("TRX").check(&networks).is_err());
+    }
+}
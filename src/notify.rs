@@ -0,0 +1,76 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+type Callback<T> = Box<dyn FnMut(&T) + Send>;
+
+// fans events out to callbacks and channels, what the watchers share
+//
+//     let mut notifier = Notifier::new();
+//     notifier.on_event(|event: &BalanceEvent| warn!("{:?}", event));
+//     let rx = notifier.subscribe();
+//     notifier.notify(&event);
+pub struct Notifier<T> {
+    callbacks: Vec<Callback<T>>,
+    senders: Vec<Sender<T>>,
+}
+
+impl<T> Default for Notifier<T> {
+    fn default() -> Self {
+        Notifier::new()
+    }
+}
+
+impl<T> Notifier<T> {
+    pub fn new() -> Self {
+        Notifier {
+            callbacks: vec![],
+            senders: vec![],
+        }
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(&T) + Send + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.push(tx);
+        rx
+    }
+}
+
+impl<T: Clone> Notifier<T> {
+    // callbacks first, then the channels
+    pub fn notify(&mut self, event: &T) {
+        for callback in self.callbacks.iter_mut() {
+            callback(event);
+        }
+        // drop channels whose receiver is gone
+        self.senders.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_notify() {
+        let mut notifier = Notifier::new();
+        let seen = Arc::new(Mutex::new(vec![]));
+        let pushed = seen.clone();
+        notifier.on_event(move |n: &u32| pushed.lock().unwrap().push(*n));
+        let rx = notifier.subscribe();
+        let dropped = notifier.subscribe();
+        drop(dropped);
+
+        notifier.notify(&1);
+        notifier.notify(&2);
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2]);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(notifier.senders.len(), 1);
+    }
+}
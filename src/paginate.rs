@@ -0,0 +1,243 @@
+use crate::errors::*;
+
+use std::collections::VecDeque;
+
+// where the next page starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cursor {
+    // first id of the page, e.g. binance fromId / orderId
+    FromId(u64),
+    // 1-based page number
+    Page(u32),
+    // opaque token returned with the previous page, None for the first one
+    Token(Option<String>),
+    // [start, end] in milliseconds
+    Window(u64, u64),
+}
+
+// one page of results and the cursor of the next one, None on the last page
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<Cursor>,
+}
+
+type Fetch<'a, T> = Box<dyn FnMut(&Cursor) -> APIResult<Page<T>> + 'a>;
+
+// lazily walks the pages of an endpoint, a page is only requested once the
+// previous one is consumed. iteration stops after the first error
+//
+//     for trade in api.get_my_trades("BTCUSDT", 0) {
+//         let trade = trade?;
+//     }
+pub struct Paginated<'a, T> {
+    fetch: Fetch<'a, T>,
+    cursor: Option<Cursor>,
+    buffer: VecDeque<T>,
+}
+
+impl<'a, T: 'a> Paginated<'a, T> {
+    pub fn new<F>(first: Cursor, fetch: F) -> Self
+    where
+        F: FnMut(&Cursor) -> APIResult<Page<T>> + 'a,
+    {
+        Paginated {
+            fetch: Box::new(fetch),
+            cursor: Some(first),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    // ids ascending from `from_id`, a short page is the last one
+    pub fn by_id<F, I>(from_id: u64, limit: usize, mut fetch: F, id: I) -> Self
+    where
+        F: FnMut(u64) -> APIResult<Vec<T>> + 'a,
+        I: Fn(&T) -> u64 + 'a,
+    {
+        Paginated::new(Cursor::FromId(from_id), move |cursor| {
+            let from_id = match cursor {
+                Cursor::FromId(from_id) => *from_id,
+                _ => unreachable!(),
+            };
+            let items = fetch(from_id)?;
+            let next = match items.iter().map(&id).max() {
+                Some(last) if items.len() >= limit => Some(Cursor::FromId(last + 1)),
+                _ => None,
+            };
+            Ok(Page { items, next })
+        })
+    }
+
+    // numbered pages of `limit` items, a short page is the last one
+    pub fn by_page<F>(limit: usize, mut fetch: F) -> Self
+    where
+        F: FnMut(u32) -> APIResult<Vec<T>> + 'a,
+    {
+        Paginated::new(Cursor::Page(1), move |cursor| {
+            let page = match cursor {
+                Cursor::Page(page) => *page,
+                _ => unreachable!(),
+            };
+            let items = fetch(page)?;
+            let next = if items.len() >= limit {
+                Some(Cursor::Page(page + 1))
+            } else {
+                None
+            };
+            Ok(Page { items, next })
+        })
+    }
+
+    // pages linked by a token, the last page has none
+    pub fn by_token<F>(mut fetch: F) -> Self
+    where
+        F: FnMut(Option<&str>) -> APIResult<(Vec<T>, Option<String>)> + 'a,
+    {
+        Paginated::new(Cursor::Token(None), move |cursor| {
+            let token = match cursor {
+                Cursor::Token(token) => token.as_deref(),
+                _ => unreachable!(),
+            };
+            let (items, next) = fetch(token)?;
+            Ok(Page {
+                items,
+                next: next.map(|token| Cursor::Token(Some(token))),
+            })
+        })
+    }
+
+    // walks [start, end] in windows of at most `width` ms, for endpoints that
+    // cap the queried range. a full window is continued after its latest item
+    pub fn by_window<F, S>(
+        start: u64,
+        end: u64,
+        width: u64,
+        limit: usize,
+        mut fetch: F,
+        time: S,
+    ) -> Self
+    where
+        F: FnMut(u64, u64) -> APIResult<Vec<T>> + 'a,
+        S: Fn(&T) -> u64 + 'a,
+    {
+        let width = width.max(1);
+        Paginated::new(Cursor::Window(start, end), move |cursor| {
+            let (from, until) = match cursor {
+                Cursor::Window(from, until) => (*from, *until),
+                _ => unreachable!(),
+            };
+            let to = until.min(from.saturating_add(width - 1));
+            let items = fetch(from, to)?;
+            let next_from = match items.iter().map(&time).max() {
+                Some(latest) if items.len() >= limit => latest + 1,
+                _ => to + 1,
+            };
+            let next = if next_from <= until {
+                Some(Cursor::Window(next_from, until))
+            } else {
+                None
+            };
+            Ok(Page { items, next })
+        })
+    }
+}
+
+impl<'a, T> Iterator for Paginated<'a, T> {
+    type Item = APIResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                return Some(Ok(item));
+            }
+            let cursor = self.cursor.take()?;
+            match (self.fetch)(&cursor) {
+                Ok(page) => {
+                    self.buffer.extend(page.items);
+                    self.cursor = page.next;
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_paginated() {
+        let data: Vec<u64> = (1..=7).collect();
+        let pages = Paginated::by_id(
+            1,
+            3,
+            |from_id| {
+                Ok(data
+                    .iter()
+                    .copied()
+                    .filter(|id| *id >= from_id)
+                    .take(3)
+                    .collect())
+            },
+            |id| *id,
+        );
+        let ids: Vec<u64> = pages.map(Result::unwrap).collect();
+        assert_eq!(ids, data);
+
+        let requested = RefCell::new(vec![]);
+        let mut pages = Paginated::by_page(2, |page| {
+            requested.borrow_mut().push(page);
+            Ok(match page {
+                1 => vec!["a", "b"],
+                2 => vec!["c"],
+                _ => unreachable!(),
+            })
+        });
+        // pages are only fetched on demand
+        assert_eq!(pages.next().unwrap().unwrap(), "a");
+        assert_eq!(*requested.borrow(), vec![1]);
+        assert_eq!(pages.count(), 2);
+        assert_eq!(*requested.borrow(), vec![1, 2]);
+
+        let pages = Paginated::by_token(|token| {
+            Ok(match token {
+                None => (vec![1, 2], Some("next".to_string())),
+                Some("next") => (vec![3], None),
+                _ => unreachable!(),
+            })
+        });
+        assert_eq!(pages.map(Result::unwrap).sum::<i32>(), 6);
+
+        // a full window is continued after its latest item
+        let windows = RefCell::new(vec![]);
+        let times = [5, 10, 12, 25];
+        let pages = Paginated::by_window(
+            0,
+            29,
+            10,
+            2,
+            |from, to| {
+                windows.borrow_mut().push((from, to));
+                Ok(times
+                    .iter()
+                    .copied()
+                    .filter(|t| *t >= from && *t <= to)
+                    .take(2)
+                    .collect())
+            },
+            |t| *t,
+        );
+        assert_eq!(pages.map(Result::unwrap).collect::<Vec<u64>>(), times);
+        assert_eq!(
+            *windows.borrow(),
+            vec![(0, 9), (10, 19), (13, 22), (23, 29)]
+        );
+
+        let mut pages: Paginated<u64> = Paginated::by_page(1, |_| {
+            Err(Box::new(ExError::ApiError("response: 500".into())))
+        });
+        assert!(pages.next().unwrap().is_err());
+        assert!(pages.next().is_none());
+    }
+}
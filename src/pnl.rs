@@ -0,0 +1,327 @@
+use crate::models::*;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// strategy of fills whose client order id matches no prefix
+pub const UNTAGGED: &str = "untagged";
+
+// pnl of one strategy in one symbol. realized is in the quote asset, fees
+// and funding are kept per asset as the exchange charges them
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StrategyPnl {
+    pub strategy: String,
+    pub symbol: String,
+    // signed, negative when short
+    pub position: f64,
+    pub avg_price: f64,
+    // closed trading pnl before fees
+    pub realized: f64,
+    pub fees: BTreeMap<String, f64>,
+    // received is positive, paid negative
+    pub funding: BTreeMap<String, f64>,
+    pub trades: u64,
+}
+
+impl StrategyPnl {
+    // realized pnl with the fees and funding paid in the quote asset
+    pub fn net(&self, quote: &str) -> f64 {
+        self.realized + self.funding.get(quote).copied().unwrap_or(0.0)
+            - self.fees.get(quote).copied().unwrap_or(0.0)
+    }
+
+    fn fill(&mut self, side: &str, price: f64, amount: f64) {
+        let qty = if side.eq_ignore_ascii_case("buy") {
+            amount
+        } else {
+            -amount
+        };
+        self.trades += 1;
+        if self.position == 0.0 || self.position.signum() == qty.signum() {
+            let size = self.position.abs() + qty.abs();
+            self.avg_price = (self.avg_price * self.position.abs() + price * qty.abs()) / size;
+            self.position += qty;
+            return;
+        }
+        let closed = qty.abs().min(self.position.abs());
+        self.realized += closed * (price - self.avg_price) * self.position.signum();
+        self.position += qty;
+        if self.position.abs() < 1e-12 {
+            self.position = 0.0;
+            self.avg_price = 0.0;
+        } else if self.position.signum() == qty.signum() {
+            // flipped, the rest opened at the fill price
+            self.avg_price = price;
+        }
+    }
+}
+
+// average cost positions and realized pnl per strategy, with commissions
+// from fills and funding from the income history. strategies are told
+// apart by the prefix of the client order id, e.g. "grid-" for "grid-1".
+// a fill fed twice, from the user data stream and the trade history, is
+// counted once
+//
+//     let mut pnl = PnlTracker::new();
+//     pnl.add_strategy("grid-");
+//     pnl.add_strategy("hedge-");
+//     // for every OrderUpdate of the user data stream
+//     pnl.on_order_update(&update);
+//     for income in api.get_income(Some("BTCUSDT"), Some("FUNDING_FEE"), start, end) {
+//         pnl.on_income(&income?);
+//     }
+//     pnl.strategy("grid-");
+#[derive(Debug, Default)]
+pub struct PnlTracker {
+    prefixes: Vec<String>,
+    // by (strategy, symbol)
+    pnl: BTreeMap<(String, String), StrategyPnl>,
+    // order id to strategy, learned from order updates
+    orders: HashMap<String, String>,
+    seen_trades: HashSet<(String, String)>,
+    seen_income: HashSet<u64>,
+}
+
+impl PnlTracker {
+    pub fn new() -> Self {
+        PnlTracker::default()
+    }
+
+    // the longest matching prefix wins
+    pub fn add_strategy(&mut self, prefix: &str) {
+        if !self.prefixes.iter().any(|p| p == prefix) {
+            self.prefixes.push(prefix.into());
+            self.prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+        }
+    }
+
+    pub fn strategy_of(&self, client_order_id: &str) -> String {
+        self.prefixes
+            .iter()
+            .find(|p| client_order_id.starts_with(p.as_str()))
+            .cloned()
+            .unwrap_or_else(|| UNTAGGED.into())
+    }
+
+    // attributes the fills of an order fetched from the trade history,
+    // on_order_update does it for streamed orders
+    pub fn tag_order(&mut self, order_id: &str, client_order_id: &str) {
+        let strategy = self.strategy_of(client_order_id);
+        self.orders.insert(order_id.into(), strategy);
+    }
+
+    pub fn on_order_update(&mut self, update: &OrderUpdate) {
+        self.tag_order(&update.order_id, &update.client_order_id);
+        if update.execution_type != "TRADE" || update.last_filled_amount <= 0.0 {
+            return;
+        }
+        let strategy = self.strategy_of(&update.client_order_id);
+        self.fill(
+            &strategy,
+            &update.symbol,
+            &update.trade_id.to_string(),
+            &update.side,
+            update.last_filled_price,
+            update.last_filled_amount,
+            update.commission,
+            &update.commission_asset,
+        );
+    }
+
+    pub fn on_trade(&mut self, trade: &MyTrade) {
+        let strategy = self
+            .orders
+            .get(&trade.order_id)
+            .cloned()
+            .unwrap_or_else(|| UNTAGGED.into());
+        self.fill(
+            &strategy,
+            &trade.symbol,
+            &trade.trade_id.to_string(),
+            &trade.side,
+            trade.price,
+            trade.amount,
+            trade.fee,
+            &trade.fee_asset,
+        );
+    }
+
+    // funding fees are split over the strategies holding the symbol by
+    // signed position, the exchange charges the net one: long 3 and short 1
+    // pay 3/2 and get back 1/2 of it. with no net position the fee goes to
+    // UNTAGGED. commission income is skipped, fills already carry it
+    pub fn on_income(&mut self, income: &Income) {
+        if income.kind != "FUNDING_FEE" || !self.seen_income.insert(income.tran_id) {
+            return;
+        }
+        let holders: Vec<(String, f64)> = self
+            .pnl
+            .values()
+            .filter(|p| p.symbol == income.symbol && p.position != 0.0)
+            .map(|p| (p.strategy.clone(), p.position))
+            .collect();
+        let net: f64 = holders.iter().map(|(_, position)| position).sum();
+        let shares = if net.abs() < 1e-12 {
+            vec![(UNTAGGED.to_string(), 1.0)]
+        } else {
+            holders
+                .into_iter()
+                .map(|(strategy, position)| (strategy, position / net))
+                .collect()
+        };
+        for (strategy, share) in shares {
+            let pnl = self.entry(&strategy, &income.symbol);
+            *pnl.funding.entry(income.asset.clone()).or_insert(0.0) += income.amount * share;
+        }
+    }
+
+    pub fn pnl(&self) -> Vec<StrategyPnl> {
+        self.pnl.values().cloned().collect()
+    }
+
+    // every symbol of the strategy
+    pub fn strategy(&self, strategy: &str) -> Vec<StrategyPnl> {
+        self.pnl
+            .values()
+            .filter(|p| p.strategy == strategy)
+            .cloned()
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fill(
+        &mut self,
+        strategy: &str,
+        symbol: &str,
+        trade_id: &str,
+        side: &str,
+        price: f64,
+        amount: f64,
+        fee: f64,
+        fee_asset: &str,
+    ) {
+        if !self.seen_trades.insert((symbol.into(), trade_id.into())) {
+            return;
+        }
+        let pnl = self.entry(strategy, symbol);
+        pnl.fill(side, price, amount);
+        if fee != 0.0 {
+            *pnl.fees.entry(fee_asset.into()).or_insert(0.0) += fee;
+        }
+    }
+
+    fn entry(&mut self, strategy: &str, symbol: &str) -> &mut StrategyPnl {
+        self.pnl
+            .entry((strategy.into(), symbol.into()))
+            .or_insert_with(|| StrategyPnl {
+                strategy: strategy.into(),
+                symbol: symbol.into(),
+                ..Default::default()
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn update(client_id: &str, trade_id: i64, side: &str, price: f64, amount: f64) -> OrderUpdate {
+        OrderUpdate {
+            symbol: "BTCUSDT".into(),
+            order_id: format!("o{}", trade_id),
+            client_order_id: client_id.into(),
+            side: side.into(),
+            order_type: "LIMIT".into(),
+            execution_type: "TRADE".into(),
+            status: OrderStatus::Filled,
+            price,
+            amount,
+            filled: amount,
+            avg_price: price,
+            last_filled_amount: amount,
+            last_filled_price: price,
+            commission: 0.1,
+            commission_asset: "USDT".into(),
+            trade_id,
+            timestamp: 1,
+        }
+    }
+
+    #[test]
+    fn test_pnl_tracker() {
+        let mut tracker = PnlTracker::new();
+        tracker.add_strategy("grid-");
+        tracker.add_strategy("grid-fast-");
+        assert_eq!(tracker.strategy_of("grid-fast-1"), "grid-fast-");
+        assert_eq!(tracker.strategy_of("manual"), UNTAGGED);
+
+        tracker.on_order_update(&update("grid-1", 1, "BUY", 100.0, 1.0));
+        tracker.on_order_update(&update("grid-2", 2, "BUY", 110.0, 1.0));
+        // streamed and fetched again from the history
+        tracker.on_order_update(&update("grid-2", 2, "BUY", 110.0, 1.0));
+        tracker.on_order_update(&update("grid-fast-1", 3, "SELL", 100.0, 1.0));
+
+        // net long 1, the long 2 pays twice the fee and the short 1 gets it
+        tracker.on_income(&Income {
+            symbol: "BTCUSDT".into(),
+            kind: "FUNDING_FEE".into(),
+            amount: -0.6,
+            asset: "USDT".into(),
+            tran_id: 9,
+            timestamp: 2,
+        });
+
+        // sells 1.5 of 2 at 120 against an average of 105, flips short at 90
+        tracker.on_trade(&MyTrade {
+            symbol: "BTCUSDT".into(),
+            trade_id: 4,
+            order_id: "o1".into(),
+            price: 120.0,
+            amount: 1.5,
+            fee: 0.2,
+            fee_asset: "USDT".into(),
+            side: "sell".into(),
+            is_maker: true,
+            timestamp: 3,
+        });
+        tracker.on_trade(&MyTrade {
+            symbol: "BTCUSDT".into(),
+            trade_id: 5,
+            order_id: "o1".into(),
+            price: 90.0,
+            amount: 1.0,
+            fee: 0.0,
+            fee_asset: "BNB".into(),
+            side: "sell".into(),
+            is_maker: true,
+            timestamp: 4,
+        });
+
+        let grid = &tracker.strategy("grid-")[0];
+        assert_eq!(grid.trades, 4);
+        assert!((grid.realized - (15.0 * 1.5 - 15.0 * 0.5)).abs() < 1e-9);
+        assert!((grid.position + 0.5).abs() < 1e-9);
+        assert_eq!(grid.avg_price, 90.0);
+        assert!((grid.fees["USDT"] - 0.4).abs() < 1e-9);
+        assert!((grid.funding["USDT"] + 1.2).abs() < 1e-9);
+        assert!((grid.net("USDT") - (15.0 - 0.4 - 1.2)).abs() < 1e-9);
+
+        let fast = &tracker.strategy("grid-fast-")[0];
+        assert_eq!(fast.position, -1.0);
+        assert!((fast.funding["USDT"] - 0.6).abs() < 1e-9);
+        assert_eq!(tracker.pnl().len(), 2);
+
+        // short 0.5 and short 1, both get their part of the fee
+        tracker.on_income(&Income {
+            symbol: "BTCUSDT".into(),
+            kind: "FUNDING_FEE".into(),
+            amount: 0.3,
+            asset: "USDT".into(),
+            tran_id: 10,
+            timestamp: 5,
+        });
+        let grid = &tracker.strategy("grid-")[0];
+        assert!((grid.funding["USDT"] + 1.1).abs() < 1e-9);
+        let fast = &tracker.strategy("grid-fast-")[0];
+        assert!((fast.funding["USDT"] - 0.8).abs() < 1e-9);
+    }
+}
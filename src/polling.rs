@@ -0,0 +1,277 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+
+use log::warn;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketData {
+    Ticker(Ticker),
+    // mark price and funding rate
+    MarkPrice(MarkPrice),
+    OpenInterest(OpenInterest),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PollEvent {
+    pub exchange: String,
+    pub job: String,
+    pub data: MarketData,
+}
+
+type Fetch = Box<dyn Fn() -> APIResult<Vec<MarketData>> + Send>;
+type Budget = Box<dyn Fn() -> Vec<RateLimitStatus> + Send>;
+
+struct Job {
+    exchange: String,
+    name: String,
+    interval: Duration,
+    fetch: Fetch,
+    next_at: u64,
+}
+
+// polls rest endpoints at their own intervals and emits the results as
+// events, for venues or setups without websocket streams. intervals are
+// jittered so jobs don't line up, and the jobs of an exchange wait for the
+// next window while its request weight is close to the limit
+//
+//     let mut poller = PollScheduler::new();
+//     let api = Arc::new(swap);
+//     let a = api.clone();
+//     poller.add("binance_swap", "funding", Duration::from_secs(10), move || {
+//         Ok(vec![MarketData::MarkPrice(a.get_mark_price("BTCUSDT")?)])
+//     });
+//     let a = api.clone();
+//     poller.add("binance_swap", "oi", Duration::from_secs(60), move || {
+//         Ok(vec![MarketData::OpenInterest(a.get_open_interest("BTCUSDT")?)])
+//     });
+//     // keep a fifth of the weight for orders
+//     poller.set_budget("binance_swap", move || api.rate_limits(), 0.2);
+//     poller.on_event(|event| info!("{:?}", event.data));
+//     poller.run();
+pub struct PollScheduler {
+    jobs: Vec<Job>,
+    // by exchange, with the fraction of the weight kept free
+    budgets: HashMap<String, (Budget, f64)>,
+    jitter: f64,
+    notifier: Notifier<PollEvent>,
+    shutdown: Shutdown,
+}
+
+impl Default for PollScheduler {
+    fn default() -> Self {
+        PollScheduler::new()
+    }
+}
+
+impl PollScheduler {
+    pub fn new() -> Self {
+        PollScheduler {
+            jobs: vec![],
+            budgets: HashMap::new(),
+            jitter: 0.1,
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // the first poll is due right away
+    pub fn add<F>(&mut self, exchange: &str, name: &str, interval: Duration, fetch: F)
+    where
+        F: Fn() -> APIResult<Vec<MarketData>> + Send + 'static,
+    {
+        self.jobs.push(Job {
+            exchange: exchange.into(),
+            name: name.into(),
+            interval,
+            fetch: Box::new(fetch),
+            next_at: 0,
+        });
+    }
+
+    // intervals vary by up to this fraction either way, 0.1 by default
+    pub fn set_jitter(&mut self, jitter: f64) {
+        self.jitter = jitter.clamp(0.0, 1.0);
+    }
+
+    // status reports the request weight windows of the exchange, e.g. the
+    // client's rate_limits. polls wait for the window to reset while less
+    // than reserve of its limit is left
+    pub fn set_budget<F>(&mut self, exchange: &str, status: F, reserve: f64)
+    where
+        F: Fn() -> Vec<RateLimitStatus> + Send + 'static,
+    {
+        self.budgets
+            .insert(exchange.into(), (Box::new(status), reserve));
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(&PollEvent) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<PollEvent> {
+        self.notifier.subscribe()
+    }
+
+    // run stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // when the next job is due
+    pub fn next_at(&self) -> Option<u64> {
+        self.jobs.iter().map(|job| job.next_at).min()
+    }
+
+    // runs the jobs due at now, returns the events fired. failed jobs are
+    // logged and run again after their interval
+    pub fn poll_due(&mut self, now: u64) -> Vec<PollEvent> {
+        let mut events = vec![];
+        // asked once per exchange and poll
+        let mut deferred: HashMap<String, Option<u64>> = HashMap::new();
+        for i in 0..self.jobs.len() {
+            if self.jobs[i].next_at > now {
+                continue;
+            }
+            let exchange = self.jobs[i].exchange.clone();
+            let until = *deferred
+                .entry(exchange.clone())
+                .or_insert_with(|| self.deferred_until(&exchange, now));
+            if let Some(until) = until {
+                self.jobs[i].next_at = until;
+                continue;
+            }
+            let next_at = now + self.jittered(self.jobs[i].interval);
+            let job = &mut self.jobs[i];
+            job.next_at = next_at;
+            match (job.fetch)() {
+                Ok(data) => events.extend(data.into_iter().map(|data| PollEvent {
+                    exchange: job.exchange.clone(),
+                    job: job.name.clone(),
+                    data,
+                })),
+                Err(err) => warn!("poll {} on {} failed: {:?}", job.name, job.exchange, err),
+            }
+        }
+        for event in events.iter() {
+            self.notifier.notify(event);
+        }
+        events
+    }
+
+    // polls on a background thread until the shutdown
+    pub fn run(mut self) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.poll_due(now_ms());
+            let next_at = self.next_at().unwrap_or(u64::MAX);
+            let wait = Duration::from_millis(next_at.saturating_sub(now_ms()));
+            if !self.shutdown.sleep(wait) {
+                return;
+            }
+        })
+    }
+
+    // end of the weight window that is used up, if any
+    fn deferred_until(&self, exchange: &str, now: u64) -> Option<u64> {
+        let (status, reserve) = self.budgets.get(exchange)?;
+        status()
+            .into_iter()
+            .filter(|s| s.kind == "REQUEST_WEIGHT" && s.reset_at > now)
+            .filter(|s| match (s.limit, s.remaining) {
+                (Some(limit), Some(remaining)) => (remaining as f64) < limit as f64 * reserve,
+                _ => false,
+            })
+            .map(|s| s.reset_at)
+            .max()
+    }
+
+    fn jittered(&self, interval: Duration) -> u64 {
+        let ms = interval.as_millis() as f64;
+        let mut bytes = [0u8; 4];
+        if self.jitter == 0.0 || SystemRandom::new().fill(&mut bytes).is_err() {
+            return ms as u64;
+        }
+        // -1 to 1
+        let r = u32::from_le_bytes(bytes) as f64 / u32::MAX as f64 * 2.0 - 1.0;
+        (ms * (1.0 + self.jitter * r)).max(1.0) as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn ticker(symbol: &str) -> MarketData {
+        MarketData::Ticker(Ticker {
+            symbol: symbol.into(),
+            ..Ticker::new()
+        })
+    }
+
+    fn weight(remaining: u64, reset_at: u64) -> Vec<RateLimitStatus> {
+        vec![RateLimitStatus {
+            kind: "REQUEST_WEIGHT".into(),
+            interval_ms: 60_000,
+            limit: Some(1200),
+            used: 1200 - remaining,
+            remaining: Some(remaining),
+            reset_at,
+        }]
+    }
+
+    #[test]
+    fn test_poll_scheduler() {
+        let mut poller = PollScheduler::new();
+        poller.set_jitter(0.0);
+        poller.add("binance", "btc", Duration::from_secs(1), || {
+            Ok(vec![ticker("BTCUSDT")])
+        });
+        poller.add("binance", "eth", Duration::from_secs(5), || {
+            Ok(vec![ticker("ETHUSDT")])
+        });
+        poller.add("huobi", "broken", Duration::from_secs(1), || {
+            Err(Box::new(ExError::ApiError("down".into())))
+        });
+        let rx = poller.subscribe();
+
+        assert_eq!(poller.poll_due(1000).len(), 2);
+        assert_eq!(poller.next_at(), Some(2000));
+        assert!(poller.poll_due(1500).is_empty());
+        let events = poller.poll_due(2000);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].job, "btc");
+        assert_eq!(poller.poll_due(6000).len(), 2);
+        assert_eq!(rx.try_iter().count(), 5);
+
+        // weight close to the limit until 60000
+        let status = Arc::new(Mutex::new(weight(100, 60_000)));
+        let current = status.clone();
+        poller.set_budget("binance", move || current.lock().unwrap().clone(), 0.1);
+        assert!(poller.poll_due(7000).is_empty());
+        assert_eq!(poller.next_at(), Some(8000));
+        *status.lock().unwrap() = weight(1000, 60_000);
+        assert_eq!(poller.poll_due(60_000).len(), 2);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut poller = PollScheduler::new();
+        poller.set_jitter(0.5);
+        for _ in 0..20 {
+            let ms = poller.jittered(Duration::from_secs(10));
+            assert!((5000..=15000).contains(&ms));
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::binance::types as bn_types;
+use crate::errors::*;
+use crate::http;
+use crate::huobi::types as hb_types;
+use crate::models::*;
+use crate::utils::*;
+
+// market data of the public endpoints, nothing is signed so no keys are
+// needed. these are the clients of the wasm32 build, where requests go
+// through fetch and every call is async:
+//
+//     let binance = PublicClient::binance(BINANCE_SPOT_HOST);
+//     let ticker = binance.get_ticker("BTCUSDT").await?;
+//
+// elsewhere they are async or blocking with the rest clients, see the
+// blocking feature
+#[derive(Clone, Debug)]
+pub struct PublicClient {
+    api: Api,
+    host: String,
+    client: http::Client,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Api {
+    Binance,
+    Huobi,
+}
+
+impl PublicClient {
+    pub fn binance(host: &str) -> Self {
+        PublicClient::new(Api::Binance, host)
+    }
+
+    pub fn huobi(host: &str) -> Self {
+        PublicClient::new(Api::Huobi, host)
+    }
+
+    fn new(api: Api, host: &str) -> Self {
+        PublicClient {
+            api,
+            host: host.into(),
+            client: http::Client::new(),
+        }
+    }
+
+    pub fn set_client(&mut self, client: http::Client) {
+        self.client = client;
+    }
+}
+
+#[maybe_async::maybe_async]
+impl PublicClient {
+    pub async fn get(&self, endpoint: &str, request: &str) -> APIResult<String> {
+        let mut url = format!("{}{}", self.host, endpoint);
+        if !request.is_empty() {
+            url.push_str(format!("?{}", request).as_str());
+        }
+        let resp = self.client.get(&url).send().await?;
+        match self.api {
+            // same statuses as binance::spot_rest, without the weight tracking
+            Api::Binance => match resp.status() {
+                http::OK => resp.text(),
+                http::TOO_MANY_REQUESTS => Err(Box::new(ExError::RateLimitExceeded(
+                    "rate limit exceeded: 429".into(),
+                ))),
+                http::IM_A_TEAPOT => Err(Box::new(ExError::IpBanned("ip banned: 418".into()))),
+                http::SERVICE_UNAVAILABLE => Err(unavailable_error(&resp.text()?)),
+                s => Err(bn_types::response_error(s, &resp.text()?)),
+            },
+            Api::Huobi => hb_types::check_response(resp.text()?),
+        }
+    }
+
+    pub async fn get_server_time(&self) -> APIResult<u64> {
+        match self.api {
+            Api::Binance => {
+                let ret = self.get("/api/v3/time", "").await?;
+                let resp: bn_types::ServerTime = serde_json::from_str(&ret)?;
+                Ok(resp.server_time)
+            }
+            Api::Huobi => {
+                let ret = self.get("/v1/common/timestamp", "").await?;
+                let resp: hb_types::Response<u64> = serde_json::from_str(&ret)?;
+                Ok(resp.data)
+            }
+        }
+    }
+
+    pub async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
+        match self.api {
+            Api::Binance => {
+                let params = to_query(&bn_types::DepthParams {
+                    symbol,
+                    limit: depth,
+                })?;
+                let ret = self.get("/api/v3/depth", &params).await?;
+                let resp: bn_types::RawOrderbook = serde_json::from_str(&ret)?;
+                let mut orderbook: Orderbook = resp.into();
+                if orderbook.timestamp == 0 {
+                    orderbook.timestamp = get_timestamp()?;
+                }
+                Ok(orderbook)
+            }
+            Api::Huobi => {
+                // 150 is the full step0 book
+                let params = to_query(&hb_types::DepthParams {
+                    symbol: &symbol.to_lowercase(),
+                    depth: Some(depth).filter(|depth| *depth != 150),
+                    step: "step0",
+                })?;
+                let ret = self.get("/market/depth", &params).await?;
+                let resp: hb_types::Response<hb_types::RawOrderbook> = serde_json::from_str(&ret)?;
+                let mut orderbook: Orderbook = resp.tick.into();
+                if orderbook.timestamp == 0 {
+                    orderbook.timestamp = resp.ts;
+                }
+                Ok(orderbook)
+            }
+        }
+    }
+
+    pub async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+        match self.api {
+            Api::Binance => {
+                let params = to_query(&bn_types::SymbolParams { symbol })?;
+                let ret = self.get("/api/v3/ticker/bookTicker", &params).await?;
+                let resp: bn_types::RawTicker = serde_json::from_str(&ret)?;
+                Ok(resp.into())
+            }
+            Api::Huobi => {
+                let symbol = symbol.to_lowercase();
+                let params = to_query(&hb_types::SymbolParams { symbol: &symbol })?;
+                let ret = self.get("/market/detail/merged", &params).await?;
+                let resp: hb_types::Response<hb_types::RawTicker> = serde_json::from_str(&ret)?;
+                let mut ticker: Ticker = resp.tick.into();
+                ticker.symbol = symbol;
+                if ticker.timestamp == 0 {
+                    ticker.timestamp = resp.ts;
+                }
+                Ok(ticker)
+            }
+        }
+    }
+
+    pub async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+        let klines = match self.api {
+            Api::Binance => {
+                let params = to_query(&bn_types::KlineParams {
+                    symbol,
+                    interval: period,
+                    limit,
+                    start_time: None,
+                    end_time: None,
+                })?;
+                let ret = self.get("/api/v3/klines", &params).await?;
+                let resp: Vec<bn_types::RawKlineRow> = serde_json::from_str(&ret)?;
+                resp.into_iter().map(|kline| kline.into()).collect()
+            }
+            Api::Huobi => {
+                let params = to_query(&hb_types::KlineParams {
+                    symbol: &symbol.to_lowercase(),
+                    period,
+                    size: limit,
+                })?;
+                let ret = self.get("/market/history/kline", &params).await?;
+                let resp: hb_types::Response<Vec<hb_types::RawKline>> = serde_json::from_str(&ret)?;
+                resp.data.into_iter().map(|kline| kline.into()).collect()
+            }
+        };
+        Ok(klines)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // answers one request with body, returns the request line
+    fn serve(body: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body.as_bytes()).unwrap();
+            request.trim_end().into()
+        });
+        (host, server)
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_binance() {
+        let (host, server) = serve(
+            r#"{"symbol":"BTCUSDT","bidPrice":"100.5","bidQty":"1","askPrice":"101","askQty":"2"}"#,
+        );
+        let ticker = PublicClient::binance(&host)
+            .get_ticker("BTCUSDT")
+            .await
+            .unwrap();
+        assert_eq!(ticker.bid.price, 100.5);
+        assert_eq!(ticker.ask.amount, 2.0);
+        assert_eq!(
+            server.join().unwrap(),
+            "GET /api/v3/ticker/bookTicker?symbol=BTCUSDT HTTP/1.1"
+        );
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_huobi() {
+        let (host, server) = serve(
+            r#"{"status":"ok","ch":"market.btcusdt.kline.1min","ts":1,"data":[{"id":1600000000,"open":1.0,"close":2.0,"low":0.5,"high":3.0,"amount":10.0,"vol":20.0,"count":5}]}"#,
+        );
+        let klines = PublicClient::huobi(&host)
+            .get_kline("BTCUSDT", "1min", 1)
+            .await
+            .unwrap();
+        assert_eq!(klines.len(), 1);
+        assert_eq!(klines[0].close, 2.0);
+        assert_eq!(
+            server.join().unwrap(),
+            "GET /market/history/kline?symbol=btcusdt&period=1min&size=1 HTTP/1.1"
+        );
+
+        let (host, _server) =
+            serve(r#"{"status":"error","err-code":"bad-request","err-msg":"invalid symbol"}"#);
+        let ret = PublicClient::huobi(&host).get_server_time().await;
+        assert!(ret.is_err());
+    }
+}
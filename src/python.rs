@@ -0,0 +1,224 @@
+// python module over config::Client, built with maturin:
+//
+//     maturin develop --features python
+//
+//     import rsex
+//     client = rsex.Client("binance")
+//     client.get_ticker("BTCUSDT")["bid"]["price"]
+//
+// models come back as dicts and lists shaped like their json, errors are
+// raised as rsex.RsexError. calls release the GIL while the request is sent
+use crate::config::{Client, Config, ExchangeConfig};
+use crate::errors::*;
+use crate::models::{Network, OrderRequest};
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use pyo3::IntoPyObjectExt;
+use serde::Serialize;
+use serde_json::Value;
+
+create_exception!(rsex, RsexError, PyException);
+
+#[pyclass(name = "Client", module = "rsex", frozen)]
+pub struct PyClient {
+    client: Client,
+}
+
+#[pymethods]
+impl PyClient {
+    // exchange is binance, binance_swap or huobi
+    #[new]
+    #[pyo3(signature = (exchange, api_key=None, secret_key=None, testnet=false))]
+    fn new(
+        exchange: &str,
+        api_key: Option<&str>,
+        secret_key: Option<&str>,
+        testnet: bool,
+    ) -> PyResult<Self> {
+        let mut config = ExchangeConfig::new(exchange);
+        if let (Some(api_key), Some(secret_key)) = (api_key, secret_key) {
+            config = config.credentials(api_key, secret_key);
+        }
+        if testnet {
+            config = config.network(Network::Testnet);
+        }
+        let client = config.build().map_err(to_py_err)?;
+        Ok(PyClient { client })
+    }
+
+    // the client named `name` in a config file, see config::Config
+    #[staticmethod]
+    fn from_config(path: &str, name: &str) -> PyResult<Self> {
+        let mut registry = Config::from_file(path)
+            .and_then(|config| config.build())
+            .map_err(to_py_err)?;
+        match registry.remove(name) {
+            Some(client) => Ok(PyClient { client }),
+            None => Err(RsexError::new_err(format!("unknown exchange: {}", name))),
+        }
+    }
+
+    fn get_server_time(&self, py: Python<'_>) -> PyResult<u64> {
+        call(py, || self.client.get_server_time())
+    }
+
+    #[pyo3(signature = (symbol, depth=20))]
+    fn get_orderbook(&self, py: Python<'_>, symbol: &str, depth: u16) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_orderbook(symbol, depth))
+    }
+
+    fn get_ticker(&self, py: Python<'_>, symbol: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_ticker(symbol))
+    }
+
+    #[pyo3(signature = (symbol, period="1m", limit=100))]
+    fn get_kline(
+        &self,
+        py: Python<'_>,
+        symbol: &str,
+        period: &str,
+        limit: u16,
+    ) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_kline(symbol, period, limit))
+    }
+
+    fn get_balance(&self, py: Python<'_>, asset: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_balance(asset))
+    }
+
+    fn get_fee_rates(&self, py: Python<'_>, symbol: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_fee_rates(symbol))
+    }
+
+    // action is BUY or SELL, returns the order id
+    #[pyo3(signature = (symbol, action, price, amount, order_type="LIMIT"))]
+    fn create_order(
+        &self,
+        py: Python<'_>,
+        symbol: &str,
+        action: &str,
+        price: f64,
+        amount: f64,
+        order_type: &str,
+    ) -> PyResult<String> {
+        let order = OrderRequest::new(symbol, action, order_type, price, amount);
+        call(py, || self.client.create_order(&order))
+    }
+
+    fn cancel(&self, py: Python<'_>, symbol: &str, id: &str) -> PyResult<bool> {
+        call(py, || self.client.cancel(symbol, id))
+    }
+
+    fn cancel_all(&self, py: Python<'_>, symbol: &str) -> PyResult<bool> {
+        call(py, || self.client.cancel_all(symbol))
+    }
+
+    fn get_order(&self, py: Python<'_>, symbol: &str, id: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_order(symbol, id))
+    }
+
+    fn get_open_orders(&self, py: Python<'_>, symbol: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_open_orders(symbol))
+    }
+
+    fn get_history_orders(&self, py: Python<'_>, symbol: &str) -> PyResult<Py<PyAny>> {
+        call_value(py, || self.client.get_history_orders(symbol))
+    }
+}
+
+#[pymodule]
+fn rsex(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyClient>()?;
+    m.add("RsexError", m.py().get_type::<RsexError>())?;
+    Ok(())
+}
+
+fn to_py_err(err: Box<dyn std::error::Error>) -> PyErr {
+    RsexError::new_err(err.to_string())
+}
+
+// runs f without the GIL, errors are turned into strings first since they
+// are not Send
+fn call<T, F>(py: Python<'_>, f: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce() -> APIResult<T> + Send,
+{
+    py.detach(|| f().map_err(|err| err.to_string()))
+        .map_err(RsexError::new_err)
+}
+
+fn call_value<T, F>(py: Python<'_>, f: F) -> PyResult<Py<PyAny>>
+where
+    T: Serialize,
+    F: FnOnce() -> APIResult<T> + Send,
+{
+    let value = call(py, || Ok(serde_json::to_value(f()?)?))?;
+    to_py(py, &value)
+}
+
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<Py<PyAny>> {
+    match value {
+        Value::Null => Ok(py.None()),
+        Value::Bool(b) => b.into_py_any(py),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => u.into_py_any(py),
+            (None, Some(i)) => i.into_py_any(py),
+            _ => n.as_f64().unwrap_or_default().into_py_any(py),
+        },
+        Value::String(s) => s.into_py_any(py),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_py_any(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                dict.set_item(key, to_py(py, value)?)?;
+            }
+            dict.into_py_any(py)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_py() {
+        Python::initialize();
+        Python::attach(|py| {
+            let value = serde_json::json!({"symbol": "BTCUSDT", "bids": [[1.5, 2]], "id": null});
+            let obj = to_py(py, &value).unwrap();
+            let dict = obj.bind(py).cast::<PyDict>().unwrap();
+            let symbol: String = dict.get_item("symbol").unwrap().unwrap().extract().unwrap();
+            assert_eq!(symbol, "BTCUSDT");
+            let bids = dict.get_item("bids").unwrap().unwrap();
+            let level: Vec<f64> = bids.get_item(0).unwrap().extract().unwrap();
+            assert_eq!(level, vec![1.5, 2.0]);
+            assert!(dict.get_item("id").unwrap().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_client() {
+        Python::initialize();
+        Python::attach(|py| {
+            assert!(PyClient::new("binance", None, None, true).is_ok());
+            let err = PyClient::new("okx", None, None, false).err().unwrap();
+            assert!(err.is_instance_of::<RsexError>(py));
+
+            let module = PyModule::new(py, "rsex").unwrap();
+            rsex(&module).unwrap();
+            assert!(module.getattr("Client").is_ok());
+            assert!(module.getattr("RsexError").is_ok());
+        });
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// what a full queue does with a new event
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backpressure {
+    // wait for the consumer, the event is never dropped
+    Block,
+    // drop the oldest droppable event
+    DropOldest,
+    // replace the queued event with the same key, e.g. depth or ticker of a symbol
+    Coalesce(String),
+}
+
+struct Entry<T> {
+    item: T,
+    policy: Backpressure,
+}
+
+struct Inner<T> {
+    entries: VecDeque<Entry<T>>,
+    dropped: u64,
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+// bounded queue between a websocket handler and a slow consumer,
+// the policy is chosen per event so order updates can block while
+// market data is conflated
+pub struct EventQueue<T> {
+    capacity: usize,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for EventQueue<T> {
+    fn clone(&self) -> Self {
+        EventQueue {
+            capacity: self.capacity,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> EventQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        EventQueue {
+            capacity: capacity.max(1),
+            shared: Arc::new(Shared {
+                inner: Mutex::new(Inner {
+                    entries: VecDeque::with_capacity(capacity),
+                    dropped: 0,
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn push(&self, item: T, policy: Backpressure) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        if let Backpressure::Coalesce(key) = &policy {
+            let queued = inner
+                .entries
+                .iter_mut()
+                .find(|e| matches!(&e.policy, Backpressure::Coalesce(k) if k == key));
+            if let Some(entry) = queued {
+                entry.item = item;
+                inner.dropped += 1;
+                return;
+            }
+        }
+        while inner.entries.len() >= self.capacity {
+            let droppable = inner
+                .entries
+                .iter()
+                .position(|e| e.policy != Backpressure::Block);
+            match droppable {
+                Some(i) => {
+                    inner.entries.remove(i);
+                    inner.dropped += 1;
+                }
+                // only blocking events queued, the new event is the one to drop
+                None if policy != Backpressure::Block => {
+                    inner.dropped += 1;
+                    return;
+                }
+                None => inner = self.shared.not_full.wait(inner).unwrap(),
+            }
+        }
+        inner.entries.push_back(Entry { item, policy });
+        self.shared.not_empty.notify_one();
+    }
+
+    // blocks until an event is available
+    pub fn pop(&self) -> T {
+        let mut inner = self.shared.inner.lock().unwrap();
+        loop {
+            if let Some(entry) = inner.entries.pop_front() {
+                self.shared.not_full.notify_one();
+                return entry.item;
+            }
+            inner = self.shared.not_empty.wait(inner).unwrap();
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let mut inner = self.shared.inner.lock().unwrap();
+        let entry = inner.entries.pop_front()?;
+        self.shared.not_full.notify_one();
+        Some(entry.item)
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.shared.inner.lock().unwrap();
+        loop {
+            if let Some(entry) = inner.entries.pop_front() {
+                self.shared.not_full.notify_one();
+                return Some(entry.item);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            inner = self
+                .shared
+                .not_empty
+                .wait_timeout(inner, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // events dropped or replaced since the queue was created
+    pub fn dropped(&self) -> u64 {
+        self.shared.inner.lock().unwrap().dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_event_queue() {
+        let queue = EventQueue::new(3);
+        queue.push("order1", Backpressure::Block);
+        queue.push("depth1", Backpressure::Coalesce("depth@btcusdt".into()));
+        queue.push("trade1", Backpressure::DropOldest);
+        // replaces depth1 in place
+        queue.push("depth2", Backpressure::Coalesce("depth@btcusdt".into()));
+        // full, the oldest droppable event is depth2
+        queue.push("trade2", Backpressure::DropOldest);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(queue.pop(), "order1");
+        assert_eq!(queue.pop(), "trade1");
+        assert_eq!(queue.pop(), "trade2");
+        assert_eq!(queue.try_pop(), None);
+        assert_eq!(queue.pop_timeout(Duration::from_millis(10)), None);
+
+        // blocking events wait for the consumer instead of dropping
+        let queue = EventQueue::new(1);
+        queue.push(1, Backpressure::Block);
+        let producer = queue.clone();
+        let handle = thread::spawn(move || producer.push(2, Backpressure::Block));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(queue.pop(), 1);
+        handle.join().unwrap();
+        assert_eq!(queue.pop(), 2);
+        assert_eq!(queue.dropped(), 0);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::errors::*;
+use crate::models::*;
+use crate::traits::*;
+
+// a client that can't trade, for analytics deployments. only the read half
+// of SpotRest / FutureRest goes through, orders and cancels fail before any
+// request is sent. the wrapped client isn't reachable, so neither are its
+// other signed endpoints (transfers, dust conversion, raw post_signed)
+//
+//     let api = ReadOnly::new(Binance::new(key, secret, BINANCE_SPOT_HOST.into()));
+//     api.get_balance("BTC")?;
+//     assert!(api.create_order(&order).is_err());
+#[derive(Debug, Clone)]
+pub struct ReadOnly<T> {
+    inner: T,
+}
+
+impl<T> ReadOnly<T> {
+    pub fn new(inner: T) -> Self {
+        ReadOnly { inner }
+    }
+}
+
+fn denied<R>(action: &str) -> APIResult<R> {
+    Err(Box::new(ExError::PermissionDenied(format!(
+        "{} is not allowed on a read-only client",
+        action
+    ))))
+}
+
+#[maybe_async::maybe_async]
+impl<T: SpotRest + Sync> SpotRest for ReadOnly<T> {
+    async fn ping(&self) -> APIResult<()> {
+        self.inner.ping().await
+    }
+
+    async fn get_server_time(&self) -> APIResult<u64> {
+        self.inner.get_server_time().await
+    }
+
+    async fn get_balance(&self, asset: &str) -> APIResult<Balance> {
+        self.inner.get_balance(asset).await
+    }
+
+    async fn create_order(&self, _order: &OrderRequest) -> APIResult<String> {
+        denied("create_order")
+    }
+
+    async fn cancel(&self, _symbol: &str, _id: &str) -> APIResult<bool> {
+        denied("cancel")
+    }
+
+    async fn cancel_all(&self, _symbol: &str) -> APIResult<bool> {
+        denied("cancel_all")
+    }
+
+    async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order> {
+        self.inner.get_order(symbol, id).await
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        self.inner.get_open_orders(symbol).await
+    }
+
+    async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        self.inner.get_history_orders(symbol).await
+    }
+
+    async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
+        self.inner.get_orderbook(symbol, depth).await
+    }
+
+    async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+        self.inner.get_ticker(symbol).await
+    }
+
+    async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+        self.inner.get_kline(symbol, period, limit).await
+    }
+}
+
+#[maybe_async::maybe_async]
+impl<T: FutureRest + Sync> FutureRest for ReadOnly<T> {
+    async fn ping(&self) -> APIResult<()> {
+        self.inner.ping().await
+    }
+
+    async fn get_server_time(&self) -> APIResult<u64> {
+        self.inner.get_server_time().await
+    }
+
+    async fn get_balance(&self, asset: &str) -> APIResult<FutureBalance> {
+        self.inner.get_balance(asset).await
+    }
+
+    async fn create_order(&self, _order: &OrderRequest) -> APIResult<String> {
+        denied("create_order")
+    }
+
+    async fn cancel(&self, _symbol: &str, _id: &str) -> APIResult<bool> {
+        denied("cancel")
+    }
+
+    async fn cancel_all(&self, _symbol: &str) -> APIResult<bool> {
+        denied("cancel_all")
+    }
+
+    async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order> {
+        self.inner.get_order(symbol, id).await
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        self.inner.get_open_orders(symbol).await
+    }
+
+    async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>> {
+        self.inner.get_history_orders(symbol).await
+    }
+
+    async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook> {
+        self.inner.get_orderbook(symbol, depth).await
+    }
+
+    async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+        self.inner.get_ticker(symbol).await
+    }
+
+    async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>> {
+        self.inner.get_kline(symbol, period, limit).await
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod test {
+    use super::*;
+    use crate::binance::future_rest::BinanceSwap;
+    use crate::binance::spot_rest::Binance;
+
+    #[test]
+    fn test_read_only() {
+        // nothing listens there, reads fail on the network
+        let api = ReadOnly::new(Binance::new(None, None, "http://127.0.0.1:1".into()));
+        let order = OrderRequest::limit_buy("BTCUSDT", 10000.0, 1.0);
+        let err = api.create_order(&order).unwrap_err().to_string();
+        assert!(err.contains("read-only"));
+        assert!(SpotRest::cancel_all(&api, "BTCUSDT").is_err());
+        assert!(!api
+            .get_ticker("BTCUSDT")
+            .unwrap_err()
+            .to_string()
+            .contains("read-only"));
+
+        // can't trade, so nothing to report
+        assert_eq!(api.capabilities(), ExchangeCapabilities::default());
+        let swap = BinanceSwap::new(None, None, "http://127.0.0.1:1".into());
+        assert!(swap.capabilities().hedge_mode);
+
+        let api = ReadOnly::new(BinanceSwap::new(None, None, "http://127.0.0.1:1".into()));
+        assert!(FutureRest::cancel(&api, "BTCUSDT", "1")
+            .unwrap_err()
+            .to_string()
+            .contains("read-only"));
+    }
+}
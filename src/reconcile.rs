@@ -0,0 +1,276 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::shutdown::Shutdown;
+#[cfg(feature = "blocking")]
+use crate::traits::*;
+
+use log::warn;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// ids of the orders the local order management considers open
+pub trait LocalOrders: Send + Sync {
+    fn open_orders(&self, exchange: &str, symbol: &str) -> Vec<String>;
+}
+
+impl<F> LocalOrders for F
+where
+    F: Fn(&str, &str) -> Vec<String> + Send + Sync,
+{
+    fn open_orders(&self, exchange: &str, symbol: &str) -> Vec<String> {
+        self(exchange, symbol)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Discrepancy {
+    // open on the exchange, not known locally
+    Unknown {
+        exchange: String,
+        order: Order,
+    },
+    // open locally, no longer open on the exchange
+    Missing {
+        exchange: String,
+        symbol: String,
+        order_id: String,
+    },
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    pub discrepancies: Vec<Discrepancy>,
+    // (exchange, symbol, error) of the fetches that failed, those symbols
+    // are not compared
+    pub errors: Vec<(String, String, String)>,
+    pub timestamp: u64,
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty() && self.errors.is_empty()
+    }
+}
+
+type FetchOpen = Box<dyn Fn(&str) -> APIResult<Vec<Order>> + Send + Sync>;
+
+struct Venue {
+    name: String,
+    symbols: Vec<String>,
+    fetch: FetchOpen,
+}
+
+// compares the open orders of every exchange with the local order
+// management, so orders placed elsewhere or filled / canceled without the
+// update reaching us are noticed
+//
+//     let mut reconciler = Reconciler::new(oms.clone());
+//     reconciler.add_spot("binance", binance.clone(), &["BTCUSDT", "ETHUSDT"]);
+//     reconciler.add_future("binance-futures", swap.clone(), &["BTCUSDT"]);
+//     reconciler.run_every(Duration::from_secs(30), |report| {
+//         for d in report.discrepancies.iter() {
+//             warn!("order drift: {:?}", d);
+//         }
+//     });
+pub struct Reconciler {
+    local: Arc<dyn LocalOrders>,
+    venues: Vec<Venue>,
+    grace_ms: u64,
+    shutdown: Shutdown,
+}
+
+impl Reconciler {
+    pub fn new(local: Arc<dyn LocalOrders>) -> Self {
+        Reconciler {
+            local,
+            venues: vec![],
+            grace_ms: 5000,
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // exchange orders younger than that are not reported unknown, the local
+    // side may not have recorded them yet
+    pub fn set_grace(&mut self, grace: Duration) {
+        self.grace_ms = grace.as_millis() as u64;
+    }
+
+    // run_every stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // fetch returns the open orders of one symbol
+    pub fn add<F>(&mut self, exchange: &str, symbols: &[&str], fetch: F)
+    where
+        F: Fn(&str) -> APIResult<Vec<Order>> + Send + Sync + 'static,
+    {
+        self.venues.push(Venue {
+            name: exchange.into(),
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+            fetch: Box::new(fetch),
+        });
+    }
+
+    #[cfg(feature = "blocking")]
+    pub fn add_spot<T>(&mut self, exchange: &str, api: Arc<T>, symbols: &[&str])
+    where
+        T: SpotRest + Send + Sync + 'static,
+    {
+        self.add(exchange, symbols, move |symbol| api.get_open_orders(symbol));
+    }
+
+    #[cfg(feature = "blocking")]
+    pub fn add_future<T>(&mut self, exchange: &str, api: Arc<T>, symbols: &[&str])
+    where
+        T: FutureRest + Send + Sync + 'static,
+    {
+        self.add(exchange, symbols, move |symbol| api.get_open_orders(symbol));
+    }
+
+    pub fn reconcile(&self) -> ReconcileReport {
+        let now = now_ms();
+        let mut report = ReconcileReport {
+            timestamp: now,
+            ..Default::default()
+        };
+        for venue in self.venues.iter() {
+            for symbol in venue.symbols.iter() {
+                let remote = match (venue.fetch)(symbol) {
+                    Ok(orders) => orders,
+                    Err(err) => {
+                        report
+                            .errors
+                            .push((venue.name.clone(), symbol.clone(), err.to_string()));
+                        continue;
+                    }
+                };
+                let local: HashSet<String> = self
+                    .local
+                    .open_orders(&venue.name, symbol)
+                    .into_iter()
+                    .collect();
+                let remote_ids: HashSet<&str> =
+                    remote.iter().map(|o| o.order_id.as_str()).collect();
+                for order in remote.iter() {
+                    let young = order.create_time + self.grace_ms > now;
+                    if !local.contains(&order.order_id) && !young {
+                        report.discrepancies.push(Discrepancy::Unknown {
+                            exchange: venue.name.clone(),
+                            order: order.clone(),
+                        });
+                    }
+                }
+                let mut missing: Vec<&String> = local
+                    .iter()
+                    .filter(|id| !remote_ids.contains(id.as_str()))
+                    .collect();
+                missing.sort();
+                for order_id in missing {
+                    report.discrepancies.push(Discrepancy::Missing {
+                        exchange: venue.name.clone(),
+                        symbol: symbol.clone(),
+                        order_id: order_id.clone(),
+                    });
+                }
+            }
+        }
+        report
+    }
+
+    // reconciles on a background thread, the first run right away
+    pub fn run_every<F>(self, interval: Duration, mut on_report: F) -> JoinHandle<()>
+    where
+        F: FnMut(ReconcileReport) + Send + 'static,
+    {
+        thread::spawn(move || loop {
+            let report = self.reconcile();
+            if !report.errors.is_empty() {
+                warn!("reconcile fetches failed: {:?}", report.errors);
+            }
+            on_report(report);
+            if !self.shutdown.sleep(interval) {
+                return;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn order(id: &str, create_time: u64) -> Order {
+        Order {
+            symbol: "BTCUSDT".into(),
+            order_id: id.into(),
+            amount: 1.0,
+            price: 10000.0,
+            side: "BUY".into(),
+            filled: 0.0,
+            avg_price: 0.0,
+            status: OrderStatus::New,
+            create_time,
+            update_time: create_time,
+        }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let local = |exchange: &str, _symbol: &str| -> Vec<String> {
+            match exchange {
+                "binance" => vec!["1".into(), "2".into()],
+                _ => vec![],
+            }
+        };
+        let mut reconciler = Reconciler::new(Arc::new(local));
+        let now = now_ms();
+        reconciler.add("binance", &["BTCUSDT"], move |_| {
+            // 2 is gone, 3 is unknown, 4 was just placed
+            Ok(vec![order("1", 0), order("3", 0), order("4", now)])
+        });
+        reconciler.add("huobi", &["btcusdt"], |_| {
+            Err(Box::new(ExError::ApiError("timeout".into())))
+        });
+
+        let report = reconciler.reconcile();
+        assert!(!report.is_clean());
+        assert_eq!(report.discrepancies.len(), 2);
+        assert!(matches!(
+            &report.discrepancies[0],
+            Discrepancy::Unknown { order, .. } if order.order_id == "3"
+        ));
+        assert_eq!(
+            report.discrepancies[1],
+            Discrepancy::Missing {
+                exchange: "binance".into(),
+                symbol: "BTCUSDT".into(),
+                order_id: "2".into(),
+            }
+        );
+        assert_eq!(report.errors[0].0, "huobi");
+
+        reconciler.set_grace(Duration::from_millis(0));
+        assert_eq!(reconciler.reconcile().discrepancies.len(), 3);
+    }
+
+    #[test]
+    fn test_run_every() {
+        let mut reconciler = Reconciler::new(Arc::new(|_: &str, _: &str| vec![]));
+        reconciler.add("binance", &["BTCUSDT"], |_| Ok(vec![]));
+        let shutdown = Shutdown::new();
+        reconciler.set_shutdown(shutdown.clone());
+        let (tx, rx) = mpsc::channel();
+        let handle = reconciler.run_every(Duration::from_secs(60), move |report| {
+            tx.send(report.is_clean()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+        shutdown.shutdown(Duration::from_secs(1));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use crate::errors::*;
+
+use log::{info, warn};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::thread;
+use ws::{Sender, WebSocket};
+
+// local websocket server fanning out events of one upstream connection to
+// every connected client as json, messages from clients are ignored
+//
+//     let relay = Relay::listen("127.0.0.1:9000")?;
+//     binance.connect(|event| relay.publish(&event));
+#[derive(Clone)]
+pub struct Relay {
+    broadcaster: Sender,
+    local_addr: SocketAddr,
+}
+
+impl Relay {
+    pub fn listen(addr: &str) -> APIResult<Relay> {
+        let server = WebSocket::new(|_out| {
+            info!("relay client connected");
+            |_msg| Ok(())
+        })?;
+        let server = server.bind(addr)?;
+        let local_addr = server.local_addr()?;
+        let broadcaster = server.broadcaster();
+        thread::spawn(move || {
+            if let Err(err) = server.run() {
+                warn!("relay stopped: {:?}", err);
+            }
+        });
+        info!("relay listening on {}", local_addr);
+        Ok(Relay {
+            broadcaster,
+            local_addr,
+        })
+    }
+
+    // the bound address, e.g. the port picked for "127.0.0.1:0"
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn publish<T: Serialize>(&self, event: &T) -> ws::Result<()> {
+        match serde_json::to_string(event) {
+            Ok(msg) => self.broadcaster.send(msg),
+            Err(err) => {
+                warn!("serialize event error: {:?}", err);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn shutdown(&self) -> ws::Result<()> {
+        self.broadcaster.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Trade;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_publish() {
+        let relay = Relay::listen("127.0.0.1:0").unwrap();
+        let addr = relay.local_addr();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            ws::connect(format!("ws://{}", addr), |out| {
+                let tx = tx.clone();
+                tx.send(None).unwrap();
+                move |msg: ws::Message| {
+                    tx.send(Some(msg.to_string())).unwrap();
+                    out.close(ws::CloseCode::Normal)
+                }
+            })
+            .unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+        thread::sleep(Duration::from_millis(100));
+
+        let trade = Trade {
+            symbol: "BTCUSDT".into(),
+            trade_id: 1,
+            timestamp: 2,
+            amount: 0.5,
+            price: 100.0,
+            side: "buy".into(),
+        };
+        relay.publish(&trade).unwrap();
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert!(msg.contains("\"symbol\":\"BTCUSDT\""));
+        relay.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleField {
+    TickSize,
+    StepSize,
+    MinQty,
+    MinNotional,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleChange {
+    pub exchange: String,
+    pub symbol: String,
+    pub field: RuleField,
+    pub old: f64,
+    pub new: f64,
+    pub timestamp: u64,
+}
+
+type FetchRules = Box<dyn Fn() -> APIResult<Vec<SymbolRules>> + Send>;
+
+// by exchange, then symbol
+type Snapshot = BTreeMap<String, BTreeMap<String, SymbolRules>>;
+
+// alerts when the tick size, step size or min notional of a symbol change
+// between refreshes, a quoter keeps rounding to the old ones otherwise.
+// with a file the last rules survive restarts, so changes made while the
+// process was down are caught on the first check
+//
+//     let mut watcher = RulesWatcher::open("rules.json")?;
+//     let api = Arc::new(binance);
+//     watcher.watch("binance", move || api.get_symbol_rules());
+//     watcher.on_change(|change| warn!("rules changed: {:?}", change));
+//     watcher.run_every(Duration::from_secs(300));
+pub struct RulesWatcher {
+    fetches: Vec<(String, FetchRules)>,
+    rules: Snapshot,
+    path: Option<PathBuf>,
+    notifier: Notifier<RuleChange>,
+    shutdown: Shutdown,
+}
+
+impl Default for RulesWatcher {
+    fn default() -> Self {
+        RulesWatcher::new()
+    }
+}
+
+impl RulesWatcher {
+    // keeps the rules in memory only
+    pub fn new() -> Self {
+        RulesWatcher {
+            fetches: vec![],
+            rules: BTreeMap::new(),
+            path: None,
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // loads the rules saved at path, the file is created on the first check
+    pub fn open<P: AsRef<Path>>(path: P) -> APIResult<Self> {
+        let path = path.as_ref();
+        let rules = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(RulesWatcher {
+            rules,
+            path: Some(path.into()),
+            ..RulesWatcher::new()
+        })
+    }
+
+    // fetch returns the rules of every symbol, e.g. get_symbol_rules
+    pub fn watch<F>(&mut self, exchange: &str, fetch: F)
+    where
+        F: Fn() -> APIResult<Vec<SymbolRules>> + Send + 'static,
+    {
+        self.fetches.push((exchange.into(), Box::new(fetch)));
+    }
+
+    pub fn on_change<F>(&mut self, callback: F)
+    where
+        F: FnMut(&RuleChange) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<RuleChange> {
+        self.notifier.subscribe()
+    }
+
+    // run_every stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // the latest known rules of the symbol
+    pub fn rules(&self, exchange: &str, symbol: &str) -> Option<SymbolRules> {
+        self.rules
+            .get(exchange)?
+            .get(&symbol.to_uppercase())
+            .cloned()
+    }
+
+    // fetches every exchange and saves the rules, returns the changes.
+    // failed fetches are logged and compared on the next check, new and
+    // removed symbols are not changes
+    pub fn check(&mut self) -> Vec<RuleChange> {
+        let mut changes = vec![];
+        let now = now_ms();
+        let mut fetched = false;
+        for (exchange, fetch) in self.fetches.iter() {
+            let rules = match fetch() {
+                Ok(rules) => rules,
+                Err(err) => {
+                    warn!("rules of {} failed: {:?}", exchange, err);
+                    continue;
+                }
+            };
+            fetched = true;
+            let known = self.rules.entry(exchange.clone()).or_default();
+            for rule in rules {
+                let symbol = rule.symbol.to_uppercase();
+                if let Some(old) = known.get(&symbol) {
+                    let fields = [
+                        (RuleField::TickSize, old.tick_size, rule.tick_size),
+                        (RuleField::StepSize, old.step_size, rule.step_size),
+                        (RuleField::MinQty, old.min_qty, rule.min_qty),
+                        (RuleField::MinNotional, old.min_notional, rule.min_notional),
+                    ];
+                    for (field, old, new) in fields {
+                        if (old - new).abs() > old.abs().max(new.abs()) * 1e-9 {
+                            changes.push(RuleChange {
+                                exchange: exchange.clone(),
+                                symbol: symbol.clone(),
+                                field,
+                                old,
+                                new,
+                                timestamp: now,
+                            });
+                        }
+                    }
+                }
+                known.insert(symbol, rule);
+            }
+        }
+        if fetched {
+            if let Err(err) = self.save() {
+                warn!("saving rules failed: {:?}", err);
+            }
+        }
+        for change in changes.iter() {
+            self.notifier.notify(change);
+        }
+        changes
+    }
+
+    // checks on a background thread, the first time right away
+    pub fn run_every(mut self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.check();
+            if !self.shutdown.sleep(interval) {
+                return;
+            }
+        })
+    }
+
+    fn save(&self) -> APIResult<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // written aside and renamed, a crash never leaves half a file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.rules)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::sync::{Arc, Mutex};
+
+    fn rules(symbol: &str, tick_size: f64, min_notional: f64) -> SymbolRules {
+        SymbolRules {
+            symbol: symbol.into(),
+            tick_size,
+            step_size: 0.001,
+            min_qty: 0.001,
+            min_notional,
+        }
+    }
+
+    #[test]
+    fn test_rules_watcher() {
+        let path = env::temp_dir().join(format!("rsex-rules-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let current = Arc::new(Mutex::new(vec![
+            rules("BTCUSDT", 0.01, 10.0),
+            rules("ETHUSDT", 0.01, 10.0),
+        ]));
+
+        let mut watcher = RulesWatcher::open(&path).unwrap();
+        let fetch = current.clone();
+        watcher.watch("binance", move || Ok(fetch.lock().unwrap().clone()));
+        assert!(watcher.check().is_empty());
+        current.lock().unwrap()[0].tick_size = 0.1;
+        current.lock().unwrap().push(rules("SOLUSDT", 0.01, 5.0));
+        let changes = watcher.check();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, RuleField::TickSize);
+        assert_eq!((changes[0].old, changes[0].new), (0.01, 0.1));
+        drop(watcher);
+
+        // changed while not running
+        current.lock().unwrap()[1].min_notional = 5.0;
+        let mut watcher = RulesWatcher::open(&path).unwrap();
+        assert_eq!(watcher.rules("binance", "btcusdt").unwrap().tick_size, 0.1);
+        let fetch = current.clone();
+        watcher.watch("binance", move || Ok(fetch.lock().unwrap().clone()));
+        let rx = watcher.subscribe();
+        let changes = watcher.check();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].symbol, "ETHUSDT");
+        assert_eq!(changes[0].field, RuleField::MinNotional);
+        assert!(watcher.check().is_empty());
+        assert_eq!(rx.try_iter().count(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
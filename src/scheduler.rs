@@ -0,0 +1,433 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::notify::Notifier;
+use crate::shutdown::Shutdown;
+
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const MINUTE_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    // the first run is right away
+    Every(Duration),
+    // minute, hour, day of month, month and day of week in utc, as bit sets
+    Cron {
+        minutes: u64,
+        hours: u64,
+        days: u64,
+        months: u64,
+        weekdays: u64,
+    },
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Self {
+        Schedule::Every(interval)
+    }
+
+    // "minute hour day month weekday" in utc, each `*`, `*/step`, a number,
+    // a range `a-b` or a list of those, weekday 0 is sunday. unlike cron a
+    // restricted day and weekday must both match
+    //
+    //     Schedule::cron("*/30 * * * *")   // every half hour
+    //     Schedule::cron("5 0 * * 1-5")    // 00:05 on weekdays
+    pub fn cron(expr: &str) -> APIResult<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(cron_error(expr));
+        }
+        let field = |i: usize, min: u64, max: u64| parse_field(fields[i], min, max, expr);
+        Ok(Schedule::Cron {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays: field(4, 0, 6)?,
+        })
+    }
+
+    // first run after last, at or after now for the first one
+    pub fn next_after(&self, last: Option<u64>, now: u64) -> u64 {
+        match self {
+            Schedule::Every(interval) => match last {
+                Some(last) => last + interval.as_millis() as u64,
+                None => now,
+            },
+            Schedule::Cron { .. } => {
+                let from = last.map_or(now, |last| last.max(now) + 1);
+                // the next whole minute that matches, within 5 years
+                let mut minute = from.div_ceil(MINUTE_MS) * MINUTE_MS;
+                for _ in 0..(5 * 366 * 24 * 60) {
+                    if self.matches(minute) {
+                        return minute;
+                    }
+                    minute += MINUTE_MS;
+                }
+                u64::MAX
+            }
+        }
+    }
+
+    fn matches(&self, ts: u64) -> bool {
+        let (minutes, hours, days, months, weekdays) = match self {
+            Schedule::Cron {
+                minutes,
+                hours,
+                days,
+                months,
+                weekdays,
+            } => (minutes, hours, days, months, weekdays),
+            Schedule::Every(_) => return false,
+        };
+        let t = match Utc.timestamp_millis_opt(ts as i64).single() {
+            Some(t) => t,
+            None => return false,
+        };
+        let set = |bits: &u64, v: u32| bits & (1 << v) != 0;
+        set(minutes, t.minute())
+            && set(hours, t.hour())
+            && set(days, t.day())
+            && set(months, t.month())
+            && set(weekdays, t.weekday().num_days_from_sunday())
+    }
+}
+
+fn cron_error(expr: &str) -> Box<dyn std::error::Error> {
+    Box::new(ExError::ApiError(format!(
+        "invalid cron expression: {}",
+        expr
+    )))
+}
+
+fn parse_field(field: &str, min: u64, max: u64, expr: &str) -> APIResult<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().map_err(|_| cron_error(expr))?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (
+                    a.parse().map_err(|_| cron_error(expr))?,
+                    b.parse().map_err(|_| cron_error(expr))?,
+                ),
+                None => {
+                    let v = range.parse().map_err(|_| cron_error(expr))?;
+                    (v, v)
+                }
+            },
+        };
+        if step == 0 || from < min || to > max || from > to {
+            return Err(cron_error(expr));
+        }
+        for v in (from..=to).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub paused: bool,
+    pub runs: u64,
+    // in a row, reset by a success
+    pub failures: u64,
+    pub last_run: Option<u64>,
+    pub last_error: Option<String>,
+    pub next_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    pub job: String,
+    pub error: String,
+    pub failures: u64,
+    pub timestamp: u64,
+}
+
+type Job = Box<dyn FnMut() -> APIResult<()> + Send>;
+
+struct Task {
+    name: String,
+    schedule: Schedule,
+    job: Job,
+}
+
+// pauses, resumes and reads the status of the jobs of a Scheduler, also
+// while it runs
+#[derive(Clone, Default)]
+pub struct Jobs {
+    status: Arc<Mutex<HashMap<String, JobStatus>>>,
+}
+
+impl Jobs {
+    // a paused job is skipped until resumed, then runs at its next time
+    pub fn pause(&self, name: &str) {
+        if let Some(status) = self.status.lock().unwrap().get_mut(name) {
+            status.paused = true;
+        }
+    }
+
+    pub fn resume(&self, name: &str) {
+        if let Some(status) = self.status.lock().unwrap().get_mut(name) {
+            status.paused = false;
+        }
+    }
+
+    pub fn status(&self, name: &str) -> Option<JobStatus> {
+        self.status.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn all(&self) -> Vec<JobStatus> {
+        let mut all: Vec<JobStatus> = self.status.lock().unwrap().values().cloned().collect();
+        all.sort_by(|a, b| a.name.cmp(&b.name));
+        all
+    }
+}
+
+// runs recurring maintenance jobs: listen key keepalives, clock syncs,
+// exchange info refreshes, snapshot saves. a failed job is reported and
+// runs again at its next time
+//
+//     let mut scheduler = Scheduler::new();
+//     let api = Arc::new(binance);
+//     let a = api.clone();
+//     scheduler.add("keepalive", Schedule::every(Duration::from_secs(1800)), move || {
+//         a.put("/api/v3/userDataStream", &listen_key).map(|_| ())
+//     });
+//     let clock = clock.clone();
+//     scheduler.add("time sync", Schedule::cron("*/10 * * * *")?, move || {
+//         clock.sync(|| api.get_server_time()).map(|_| ())
+//     });
+//     scheduler.on_error(|err| warn!("{} failed {} times: {}", err.job, err.failures, err.error));
+//     let jobs = scheduler.jobs();
+//     scheduler.run();
+//     jobs.pause("keepalive");
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    jobs: Jobs,
+    notifier: Notifier<JobError>,
+    shutdown: Shutdown,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            tasks: vec![],
+            jobs: Jobs::default(),
+            notifier: Notifier::new(),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // names are unique, adding one again replaces the job
+    pub fn add<F>(&mut self, name: &str, schedule: Schedule, job: F)
+    where
+        F: FnMut() -> APIResult<()> + Send + 'static,
+    {
+        self.tasks.retain(|task| task.name != name);
+        let next_at = schedule.next_after(None, now_ms());
+        self.jobs.status.lock().unwrap().insert(
+            name.into(),
+            JobStatus {
+                name: name.into(),
+                next_at,
+                ..JobStatus::default()
+            },
+        );
+        self.tasks.push(Task {
+            name: name.into(),
+            schedule,
+            job: Box::new(job),
+        });
+    }
+
+    pub fn jobs(&self) -> Jobs {
+        self.jobs.clone()
+    }
+
+    pub fn on_error<F>(&mut self, callback: F)
+    where
+        F: FnMut(&JobError) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<JobError> {
+        self.notifier.subscribe()
+    }
+
+    // run stops once the shutdown starts
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) {
+        self.shutdown = shutdown;
+    }
+
+    // runs the jobs due at now that are not paused, returns their errors
+    pub fn run_due(&mut self, now: u64) -> Vec<JobError> {
+        let mut errors = vec![];
+        for task in self.tasks.iter_mut() {
+            let (due, paused) = match self.jobs.status.lock().unwrap().get(&task.name) {
+                Some(s) => (s.next_at <= now, s.paused),
+                None => continue,
+            };
+            if !due {
+                continue;
+            }
+            if paused {
+                // skipped, not made up for on resume
+                let next_at = task.schedule.next_after(Some(now), now);
+                if let Some(s) = self.jobs.status.lock().unwrap().get_mut(&task.name) {
+                    s.next_at = next_at;
+                }
+                continue;
+            }
+            let ret = (task.job)();
+            let next_at = task.schedule.next_after(Some(now), now);
+            let mut status = self.jobs.status.lock().unwrap();
+            let s = match status.get_mut(&task.name) {
+                Some(s) => s,
+                None => continue,
+            };
+            s.runs += 1;
+            s.last_run = Some(now);
+            s.next_at = next_at;
+            match ret {
+                Ok(()) => {
+                    s.failures = 0;
+                    s.last_error = None;
+                }
+                Err(err) => {
+                    s.failures += 1;
+                    s.last_error = Some(err.to_string());
+                    warn!("job {} failed: {:?}", task.name, err);
+                    errors.push(JobError {
+                        job: task.name.clone(),
+                        error: err.to_string(),
+                        failures: s.failures,
+                        timestamp: now,
+                    });
+                }
+            }
+        }
+        for error in errors.iter() {
+            self.notifier.notify(error);
+        }
+        errors
+    }
+
+    // runs the jobs on a background thread until the shutdown
+    pub fn run(mut self) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            self.run_due(now_ms());
+            let next_at = self
+                .jobs
+                .all()
+                .iter()
+                .map(|s| s.next_at)
+                .min()
+                .unwrap_or(u64::MAX);
+            let wait = Duration::from_millis(next_at.saturating_sub(now_ms()));
+            if !self.shutdown.sleep(wait) {
+                return;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 2021-01-04 00:00:00 utc, a monday
+    const MONDAY: u64 = 1_609_718_400_000;
+
+    #[test]
+    fn test_cron() {
+        let every_30 = Schedule::cron("*/30 * * * *").unwrap();
+        assert_eq!(every_30.next_after(None, MONDAY), MONDAY);
+        assert_eq!(
+            every_30.next_after(Some(MONDAY), MONDAY),
+            MONDAY + 30 * MINUTE_MS
+        );
+        assert_eq!(
+            every_30.next_after(None, MONDAY + 1),
+            MONDAY + 30 * MINUTE_MS
+        );
+
+        // 00:05 on weekdays, from saturday to monday
+        let weekdays = Schedule::cron("5 0 * * 1-5").unwrap();
+        let saturday = MONDAY - 2 * 24 * 60 * MINUTE_MS;
+        assert_eq!(weekdays.next_after(None, saturday), MONDAY + 5 * MINUTE_MS);
+        assert_eq!(
+            Schedule::cron("0 12 1,15 * *").unwrap(),
+            Schedule::cron("0 12 1,15 1-12 *").unwrap()
+        );
+        for bad in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(Schedule::cron(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::new();
+        let mut fail = true;
+        scheduler.add("sync", Schedule::every(Duration::from_secs(10)), || Ok(()));
+        scheduler.add(
+            "refresh",
+            Schedule::every(Duration::from_secs(10)),
+            move || {
+                fail = !fail;
+                if !fail {
+                    return Err(Box::new(ExError::ApiError("timeout".into())));
+                }
+                Ok(())
+            },
+        );
+        let jobs = scheduler.jobs();
+        let rx = scheduler.subscribe();
+        let now = now_ms();
+
+        let errors = scheduler.run_due(now);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].job, "refresh");
+        assert_eq!(jobs.status("refresh").unwrap().failures, 1);
+        assert!(scheduler.run_due(now + 5_000).is_empty());
+
+        jobs.pause("sync");
+        assert!(scheduler.run_due(now + 10_000).is_empty());
+        let sync = jobs.status("sync").unwrap();
+        assert_eq!((sync.runs, sync.next_at), (1, now + 20_000));
+        let refresh = jobs.status("refresh").unwrap();
+        assert_eq!((refresh.runs, refresh.failures), (2, 0));
+        assert_eq!(refresh.last_error, None);
+
+        jobs.resume("sync");
+        scheduler.run_due(now + 20_000);
+        assert_eq!(jobs.status("sync").unwrap().runs, 2);
+        assert_eq!(rx.try_iter().count(), 2);
+        assert_eq!(jobs.all().len(), 2);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::config::{Client, Registry};
+use crate::errors::*;
+use crate::models::OrderRequest;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::info;
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// http server exposing the clients of a Registry as json. every request needs
+// `Authorization: Bearer <token>`, the token goes over plain http so bind it
+// to localhost or put it behind a tls proxy. the blocking clients run their
+// own runtime, so build the registry before entering tokio's
+//
+//     let registry = Config::from_file("config.toml")?.build()?;
+//     server::serve("127.0.0.1:8080", registry, &env::var("RSEX_API_TOKEN")?).await?;
+//
+// GET    /{exchange}/time
+// GET    /{exchange}/orderbook/{symbol}?depth=20
+// GET    /{exchange}/ticker/{symbol}
+// GET    /{exchange}/kline/{symbol}?period=1m&limit=100
+// GET    /{exchange}/balance/{asset}
+// GET    /{exchange}/fees/{symbol}
+// GET    /{exchange}/orders/{symbol}
+// GET    /{exchange}/history/{symbol}
+// GET    /{exchange}/order/{symbol}/{id}
+// POST   /{exchange}/order/{symbol}?price=1&amount=1&action=BUY&order_type=LIMIT
+// DELETE /{exchange}/order/{symbol}/{id}
+// DELETE /{exchange}/orders/{symbol}
+pub async fn serve(addr: &str, registry: Registry, token: &str) -> APIResult<()> {
+    let app = router(registry, token)?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// the routes of serve, e.g. to mount them under another router
+pub fn router(registry: Registry, token: &str) -> APIResult<Router> {
+    if token.is_empty() {
+        return Err(Box::new(ExError::ApiError(
+            "an api token is required".into(),
+        )));
+    }
+    // the token is compared as an hmac tag under a random key, in constant time
+    let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())?;
+    let tag = hmac::sign(&key, token.as_bytes());
+    let state = AppState {
+        registry: Arc::new(registry),
+        key: Arc::new(key),
+        tag: Arc::new(tag.as_ref().to_vec()),
+    };
+    Ok(Router::new()
+        .route("/{exchange}/time", get(time))
+        .route("/{exchange}/orderbook/{symbol}", get(orderbook))
+        .route("/{exchange}/ticker/{symbol}", get(ticker))
+        .route("/{exchange}/kline/{symbol}", get(kline))
+        .route("/{exchange}/balance/{asset}", get(balance))
+        .route("/{exchange}/fees/{symbol}", get(fees))
+        .route(
+            "/{exchange}/orders/{symbol}",
+            get(open_orders).delete(cancel_all),
+        )
+        .route("/{exchange}/history/{symbol}", get(history))
+        .route("/{exchange}/order/{symbol}", post(create_order))
+        .route("/{exchange}/order/{symbol}/{id}", get(order).delete(cancel))
+        .fallback(|| async { RouteError(StatusCode::NOT_FOUND, "route not found".into()) })
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state))
+}
+
+#[derive(Clone)]
+struct AppState {
+    registry: Arc<Registry>,
+    key: Arc<hmac::Key>,
+    tag: Arc<Vec<u8>>,
+}
+
+// status code and message, sent as {"error": msg}
+struct RouteError(StatusCode, String);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type RouteResult = Result<Json<Value>, RouteError>;
+
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let response = match hmac::verify(&state.key, token.as_bytes(), &state.tag) {
+        Ok(()) => next.run(request).await,
+        Err(_) => RouteError(StatusCode::UNAUTHORIZED, "invalid api token".into()).into_response(),
+    };
+    info!("{} {} {}", method, uri, response.status().as_u16());
+    response
+}
+
+// runs a call of the blocking client on tokio's blocking pool
+async fn call<F>(state: AppState, exchange: String, f: F) -> RouteResult
+where
+    F: FnOnce(&Client) -> Result<Value, RouteError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || match state.registry.get(&exchange) {
+        Some(client) => f(client).map(Json),
+        None => Err(RouteError(
+            StatusCode::NOT_FOUND,
+            format!("unknown exchange: {}", exchange),
+        )),
+    })
+    .await
+    .map_err(|err| RouteError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+}
+
+async fn time(State(state): State<AppState>, Path(exchange): Path<String>) -> RouteResult {
+    call(state, exchange, |client| {
+        let time = client.get_server_time().map_err(bad_gateway)?;
+        Ok(json!({ "server_time": time }))
+    })
+    .await
+}
+
+async fn orderbook(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> RouteResult {
+    let depth = param(&params, "depth", Some(20))?;
+    call(state, exchange, move |client| {
+        to_json(client.get_orderbook(&symbol, depth))
+    })
+    .await
+}
+
+async fn ticker(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.get_ticker(&symbol))
+    })
+    .await
+}
+
+async fn kline(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> RouteResult {
+    let period: String = param(&params, "period", Some("1m".into()))?;
+    let limit = param(&params, "limit", Some(100))?;
+    call(state, exchange, move |client| {
+        to_json(client.get_kline(&symbol, &period, limit))
+    })
+    .await
+}
+
+async fn balance(
+    State(state): State<AppState>,
+    Path((exchange, asset)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        client.get_balance(&asset).map_err(bad_gateway)
+    })
+    .await
+}
+
+async fn fees(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.get_fee_rates(&symbol))
+    })
+    .await
+}
+
+async fn open_orders(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.get_open_orders(&symbol))
+    })
+    .await
+}
+
+async fn history(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.get_history_orders(&symbol))
+    })
+    .await
+}
+
+async fn order(
+    State(state): State<AppState>,
+    Path((exchange, symbol, id)): Path<(String, String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.get_order(&symbol, &id))
+    })
+    .await
+}
+
+async fn create_order(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> RouteResult {
+    let price = param(&params, "price", None)?;
+    let amount = param(&params, "amount", None)?;
+    let action: String = param(&params, "action", None)?;
+    let order_type: String = param(&params, "order_type", Some("LIMIT".into()))?;
+    let order = OrderRequest::new(&symbol, &action, &order_type, price, amount);
+    call(state, exchange, move |client| {
+        let id = client.create_order(&order).map_err(bad_gateway)?;
+        Ok(json!({ "order_id": id }))
+    })
+    .await
+}
+
+async fn cancel(
+    State(state): State<AppState>,
+    Path((exchange, symbol, id)): Path<(String, String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.cancel(&symbol, &id))
+    })
+    .await
+}
+
+async fn cancel_all(
+    State(state): State<AppState>,
+    Path((exchange, symbol)): Path<(String, String)>,
+) -> RouteResult {
+    call(state, exchange, move |client| {
+        to_json(client.cancel_all(&symbol))
+    })
+    .await
+}
+
+fn param<T: FromStr>(
+    params: &HashMap<String, String>,
+    key: &str,
+    default: Option<T>,
+) -> Result<T, RouteError> {
+    let bad_request = |msg| RouteError(StatusCode::BAD_REQUEST, msg);
+    match (params.get(key), default) {
+        (Some(v), _) => v
+            .parse()
+            .map_err(|_| bad_request(format!("invalid {}: {}", key, v))),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err(bad_request(format!("missing {}", key))),
+    }
+}
+
+fn to_json<T: Serialize>(ret: APIResult<T>) -> Result<Value, RouteError> {
+    let value = ret.map_err(bad_gateway)?;
+    serde_json::to_value(value).map_err(|err| RouteError(StatusCode::BAD_GATEWAY, err.to_string()))
+}
+
+fn bad_gateway(err: Box<dyn std::error::Error>) -> RouteError {
+    let status = match err.downcast_ref::<ExError>() {
+        Some(ExError::ExchangeInMaintenance(_)) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(ExError::NotSupported(_)) => StatusCode::NOT_IMPLEMENTED,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    RouteError(status, err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use axum::body::{self, Body};
+    use tower::ServiceExt;
+
+    async fn request(app: &Router, method: &str, uri: &str, token: &str) -> (u16, String) {
+        let request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let config = Config::from_toml(
+            r#"
+            [[exchanges]]
+            name = "binance"
+            exchange = "binance"
+
+            [[exchanges]]
+            name = "swap"
+            exchange = "binance_swap"
+            "#,
+        )
+        .unwrap();
+        let build = || {
+            let config = config.clone();
+            tokio::task::spawn_blocking(move || config.build().unwrap())
+        };
+        assert!(router(build().await.unwrap(), "").is_err());
+        let app = router(build().await.unwrap(), "secret").unwrap();
+
+        let (status, body) = request(&app, "GET", "/binance/ticker/BTCUSDT", "wrong").await;
+        assert_eq!(status, 401);
+        assert!(body.contains("invalid api token"));
+        let (status, _) = request(&app, "DELETE", "/binance/orders/BTCUSDT", "").await;
+        assert_eq!(status, 401);
+
+        let (status, body) = request(&app, "GET", "/okx/ticker/BTCUSDT", "secret").await;
+        assert_eq!(status, 404);
+        assert!(body.contains("unknown exchange"));
+        let (status, _) = request(&app, "GET", "/binance/ticker", "secret").await;
+        assert_eq!(status, 404);
+        let (status, body) = request(
+            &app,
+            "GET",
+            "/binance/orderbook/BTCUSDT?depth=abc",
+            "secret",
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(body.contains("invalid depth"));
+        let (status, body) =
+            request(&app, "POST", "/binance/order/BTCUSDT?price=1", "secret").await;
+        assert_eq!(status, 400);
+        assert!(body.contains("missing amount"));
+        let (status, body) = request(&app, "GET", "/swap/history/BTCUSDT", "secret").await;
+        assert_eq!(status, 501);
+        assert!(body.contains("NotSupported"));
+    }
+}
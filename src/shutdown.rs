@@ -0,0 +1,321 @@
+use crate::errors::*;
+
+use log::{info, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use ws::{CloseCode, Sender};
+
+type Hook = Box<dyn FnOnce() -> APIResult<()> + Send>;
+
+struct Inner {
+    stopping: AtomicBool,
+    next_id: AtomicU64,
+    sockets: Mutex<Vec<(u64, Sender)>>,
+    cancels: Mutex<Vec<Hook>>,
+    flushes: Mutex<Vec<Hook>>,
+    in_flight: Mutex<usize>,
+    idle: Condvar,
+    sleeping: Mutex<()>,
+    wake: Condvar,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownReport {
+    // websocket connections sent a close frame
+    pub closed: usize,
+    // requests still running when the timeout expired
+    pub in_flight: usize,
+    // failed cancel and flush hooks, shutdown carries on past them
+    pub errors: Vec<String>,
+}
+
+// stops a process without stranding state, e.g. from a SIGTERM handler in a
+// container. shutdown() refuses new requests, closes every registered
+// websocket with a close frame, waits for in-flight requests, cancels open
+// orders and flushes recorders, in that order
+//
+//     let shutdown = Shutdown::new();
+//     ws.set_shutdown(shutdown.clone());
+//     shutdown.cancel_open_orders(&["BTCUSDT"], move |symbol| client.cancel_all(symbol));
+//     shutdown.on_flush(move || sink.flush());
+//     // in the signal handler
+//     shutdown.shutdown(Duration::from_secs(10));
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown {
+            inner: Arc::new(Inner {
+                stopping: AtomicBool::new(false),
+                next_id: AtomicU64::new(1),
+                sockets: Mutex::new(vec![]),
+                cancels: Mutex::new(vec![]),
+                flushes: Mutex::new(vec![]),
+                in_flight: Mutex::new(0),
+                idle: Condvar::new(),
+                sleeping: Mutex::new(()),
+                wake: Condvar::new(),
+            }),
+        }
+    }
+
+    // reconnect loops stop once this is true
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.stopping.load(Ordering::SeqCst)
+    }
+
+    // sleeps for d or until the shutdown starts, false if it did
+    //
+    //     while shutdown.sleep(interval) {
+    //         poll();
+    //     }
+    pub fn sleep(&self, d: Duration) -> bool {
+        // too far out to be a deadline, e.g. nothing scheduled
+        let deadline = Instant::now().checked_add(d);
+        let mut guard = self.inner.sleeping.lock().unwrap();
+        loop {
+            if self.is_shutdown() {
+                return false;
+            }
+            guard = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return true;
+                    }
+                    self.inner.wake.wait_timeout(guard, deadline - now).unwrap().0
+                }
+                None => self.inner.wake.wait(guard).unwrap(),
+            };
+        }
+    }
+
+    // closed on shutdown until the guard is dropped with its connection, a
+    // connection opened after shutdown is closed right away
+    pub fn register_ws(&self, out: Sender) -> WsGuard {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        if self.is_shutdown() {
+            let _ = out.close(CloseCode::Away);
+        } else {
+            self.inner.sockets.lock().unwrap().push((id, out));
+        }
+        WsGuard {
+            shutdown: self.clone(),
+            id,
+        }
+    }
+
+    // run after in-flight requests finished, e.g. flushing a sink or recorder
+    pub fn on_flush<F>(&self, flush: F)
+    where
+        F: FnOnce() -> APIResult<()> + Send + 'static,
+    {
+        self.inner.flushes.lock().unwrap().push(Box::new(flush));
+    }
+
+    // cancels the open orders of every symbol before the flush hooks run
+    //
+    //     shutdown.cancel_open_orders(&["BTCUSDT", "ETHUSDT"], move |symbol| api.cancel_all(symbol));
+    pub fn cancel_open_orders<F>(&self, symbols: &[&str], cancel_all: F)
+    where
+        F: Fn(&str) -> APIResult<bool> + Send + 'static,
+    {
+        let symbols: Vec<String> = symbols.iter().map(|s| s.to_string()).collect();
+        self.inner.cancels.lock().unwrap().push(Box::new(move || {
+            let mut failed = vec![];
+            for symbol in symbols.iter() {
+                if let Err(err) = cancel_all(symbol) {
+                    failed.push(format!("{}: {}", symbol, err));
+                }
+            }
+            if failed.is_empty() {
+                Ok(())
+            } else {
+                Err(Box::new(ExError::ApiError(format!(
+                    "cancel all failed for {}",
+                    failed.join(", ")
+                ))))
+            }
+        }));
+    }
+
+    // counts a request until the guard is dropped, None once shutting down
+    //
+    //     let _request = shutdown.request().ok_or("shutting down")?;
+    //     api.create_order(&order)?;
+    pub fn request(&self) -> Option<InFlight> {
+        let mut count = self.inner.in_flight.lock().unwrap();
+        if self.is_shutdown() {
+            return None;
+        }
+        *count += 1;
+        Some(InFlight {
+            shutdown: self.clone(),
+        })
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.inner.in_flight.lock().unwrap()
+    }
+
+    // waits up to timeout for in-flight requests, the hooks run either way.
+    // only the first call does anything
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        if self.inner.stopping.swap(true, Ordering::SeqCst) {
+            return report;
+        }
+        info!("shutting down");
+        drop(self.inner.sleeping.lock().unwrap());
+        self.inner.wake.notify_all();
+
+        let sockets: Vec<(u64, Sender)> = self.inner.sockets.lock().unwrap().drain(..).collect();
+        for (_, out) in sockets.iter() {
+            match out.close(CloseCode::Away) {
+                Ok(()) => report.closed += 1,
+                Err(err) => warn!("ws close error: {:?}", err),
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut count = self.inner.in_flight.lock().unwrap();
+        while *count > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!("{} requests still in flight", *count);
+                break;
+            }
+            count = self
+                .inner
+                .idle
+                .wait_timeout(count, deadline - now)
+                .unwrap()
+                .0;
+        }
+        report.in_flight = *count;
+        drop(count);
+
+        let cancels: Vec<Hook> = self.inner.cancels.lock().unwrap().drain(..).collect();
+        let flushes: Vec<Hook> = self.inner.flushes.lock().unwrap().drain(..).collect();
+        for hook in cancels.into_iter().chain(flushes) {
+            if let Err(err) = hook() {
+                warn!("shutdown hook error: {:?}", err);
+                report.errors.push(err.to_string());
+            }
+        }
+        report
+    }
+}
+
+pub struct WsGuard {
+    shutdown: Shutdown,
+    id: u64,
+}
+
+impl Drop for WsGuard {
+    fn drop(&mut self) {
+        self.shutdown
+            .inner
+            .sockets
+            .lock()
+            .unwrap()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+pub struct InFlight {
+    shutdown: Shutdown,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut count = self.shutdown.inner.in_flight.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.shutdown.inner.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_shutdown() {
+        let shutdown = Shutdown::new();
+        let log = Arc::new(Mutex::new(vec![]));
+
+        let cancelled = log.clone();
+        shutdown.cancel_open_orders(&["BTCUSDT", "ETHUSDT"], move |symbol| {
+            cancelled.lock().unwrap().push(format!("cancel {}", symbol));
+            if symbol == "ETHUSDT" {
+                return Err(Box::new(ExError::ApiError("rejected".into())));
+            }
+            Ok(true)
+        });
+        let flushed = log.clone();
+        shutdown.on_flush(move || {
+            flushed.lock().unwrap().push("flush".into());
+            Ok(())
+        });
+
+        let request = shutdown.request().unwrap();
+        let finished = log.clone();
+        let worker = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            finished.lock().unwrap().push("request".into());
+            drop(request);
+        });
+        assert_eq!(shutdown.in_flight(), 1);
+
+        let report = shutdown.clone().shutdown(Duration::from_secs(5));
+        worker.join().unwrap();
+        assert!(shutdown.is_shutdown());
+        assert!(shutdown.request().is_none());
+        assert_eq!(report.in_flight, 0);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["request", "cancel BTCUSDT", "cancel ETHUSDT", "flush"]
+        );
+
+        // hooks only run once
+        assert!(shutdown.shutdown(Duration::from_secs(1)).errors.is_empty());
+        assert_eq!(log.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let shutdown = Shutdown::new();
+        assert!(shutdown.sleep(Duration::from_millis(10)));
+
+        let sleeper = shutdown.clone();
+        let started = Instant::now();
+        let handle = thread::spawn(move || sleeper.sleep(Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(20));
+        shutdown.shutdown(Duration::from_secs(1));
+        assert!(!handle.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!shutdown.sleep(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let shutdown = Shutdown::new();
+        let _request = shutdown.request().unwrap();
+        let report = shutdown.shutdown(Duration::from_millis(20));
+        assert_eq!(report.in_flight, 1);
+    }
+}
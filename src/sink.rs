@@ -0,0 +1,464 @@
+use crate::errors::*;
+
+use serde::Serialize;
+use serde_json::Value;
+#[cfg(feature = "kafka")]
+use {
+    futures::FutureExt,
+    rdkafka::producer::{FutureProducer, FutureRecord, Producer},
+    rdkafka::ClientConfig,
+    std::future::Future,
+    std::pin::Pin,
+    std::time::Duration,
+};
+#[cfg(feature = "zmq")]
+use {
+    log::info,
+    std::sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    // named fields, like the json
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+pub fn encode<T: Serialize>(event: &T, format: Format) -> APIResult<Vec<u8>> {
+    match format {
+        Format::Json => Ok(serde_json::to_vec(event)?),
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack => Ok(rmp_serde::to_vec_named(event)?),
+    }
+}
+
+// symbol of a serialized event, looked up on the event itself or on the
+// payload of a WsEvent variant
+pub fn symbol_of(value: &Value) -> Option<String> {
+    let symbol = |v: &Value| v.get("symbol").and_then(Value::as_str).map(String::from);
+    symbol(value).or_else(|| match value {
+        Value::Object(map) if map.len() == 1 => map.values().next().and_then(symbol),
+        _ => None,
+    })
+}
+
+// destination for normalized events, the key is the symbol so partitioned
+// transports keep the order of each symbol
+pub trait Sink {
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> APIResult<()>;
+    fn format(&self) -> Format;
+
+    // writes anything buffered, called before the process exits
+    fn flush(&mut self) -> APIResult<()> {
+        Ok(())
+    }
+
+    fn publish_event<T: Serialize>(&mut self, topic: &str, event: &T) -> APIResult<()>
+    where
+        Self: Sized,
+    {
+        let value = serde_json::to_value(event)?;
+        let key = symbol_of(&value).unwrap_or_default();
+        let payload = encode(&value, self.format())?;
+        self.send(topic, &key, &payload)
+    }
+}
+
+// publishes events to redis channels
+#[cfg(feature = "redis")]
+pub struct RedisSink {
+    conn: redis::Connection,
+    format: Format,
+}
+
+#[cfg(feature = "redis")]
+impl RedisSink {
+    // addr is host:port
+    pub fn connect(addr: &str, password: Option<&str>, format: Format) -> APIResult<RedisSink> {
+        let mut url = url::Url::parse(&format!("redis://{}/", addr))?;
+        url.set_password(password)
+            .map_err(|_| ExError::ApiError(format!("invalid redis addr: {}", addr)))?;
+        let client = redis::Client::open(url.as_str())?;
+        Ok(RedisSink {
+            conn: client.get_connection()?,
+            format,
+        })
+    }
+
+    // returns the number of subscribers that received the event
+    pub fn publish<T: Serialize>(&mut self, channel: &str, event: &T) -> APIResult<i64> {
+        let payload = encode(event, self.format)?;
+        Ok(redis::Commands::publish(&mut self.conn, channel, payload)?)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Sink for RedisSink {
+    // redis channels are not partitioned, the key is unused
+    fn send(&mut self, topic: &str, _key: &str, payload: &[u8]) -> APIResult<()> {
+        let _: i64 = redis::Commands::publish(&mut self.conn, topic, payload)?;
+        Ok(())
+    }
+
+    fn format(&self) -> Format {
+        self.format
+    }
+}
+
+// what KafkaSink needs from a producer, rdkafka's FutureProducer or a fake in
+// tests. a record is only queued by enqueue, the returned delivery resolves
+// once the broker acknowledged it
+#[cfg(feature = "kafka")]
+pub trait KafkaProducer {
+    fn enqueue(&self, topic: &str, key: &str, payload: &[u8]) -> APIResult<KafkaDelivery>;
+    fn flush(&self, timeout: Duration) -> APIResult<()>;
+}
+
+#[cfg(feature = "kafka")]
+pub type KafkaDelivery = Pin<Box<dyn Future<Output = APIResult<()>> + Send>>;
+
+#[cfg(feature = "kafka")]
+impl KafkaProducer for FutureProducer {
+    fn enqueue(&self, topic: &str, key: &str, payload: &[u8]) -> APIResult<KafkaDelivery> {
+        let record = FutureRecord::to(topic).key(key).payload(payload);
+        let delivery = self.send_result(record).map_err(|(err, _)| err)?;
+        Ok(Box::pin(async move {
+            match delivery.await {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err((err, _))) => Err(err.into()),
+                Err(_) => Err(Box::new(ExError::ApiError("kafka delivery canceled".into())) as _),
+            }
+        }))
+    }
+
+    fn flush(&self, timeout: Duration) -> APIResult<()> {
+        Ok(Producer::flush(self, timeout)?)
+    }
+}
+
+// produces events to kafka topics keyed by symbol, so every symbol stays in
+// one partition and keeps its order. sends don't wait for the broker, a
+// failed delivery is returned by a later send (which is still queued) or by
+// flush
+//
+//     let mut sink = KafkaSink::connect("localhost:9092", Format::Json)?;
+//     sink.publish_event("tickers", &ticker)?;
+//     sink.flush()?;
+#[cfg(feature = "kafka")]
+pub struct KafkaSink<P: KafkaProducer = FutureProducer> {
+    producer: P,
+    format: Format,
+    pending: Vec<KafkaDelivery>,
+    flush_timeout: Duration,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink<FutureProducer> {
+    // brokers is a comma separated host:port list
+    pub fn connect(brokers: &str, format: Format) -> APIResult<Self> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()?;
+        Ok(KafkaSink::with_producer(producer, format))
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl<P: KafkaProducer> KafkaSink<P> {
+    pub fn with_producer(producer: P, format: Format) -> Self {
+        KafkaSink {
+            producer,
+            format,
+            pending: vec![],
+            flush_timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn set_flush_timeout(&mut self, timeout: Duration) {
+        self.flush_timeout = timeout;
+    }
+
+    // drops the deliveries already acknowledged, returning the first failure
+    fn poll_pending(&mut self) -> APIResult<()> {
+        let mut ret = Ok(());
+        self.pending
+            .retain_mut(|delivery| match delivery.now_or_never() {
+                None => true,
+                Some(Ok(())) => false,
+                Some(Err(err)) => {
+                    if ret.is_ok() {
+                        ret = Err(err);
+                    }
+                    false
+                }
+            });
+        ret
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl<P: KafkaProducer> Sink for KafkaSink<P> {
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> APIResult<()> {
+        let delivery = self.producer.enqueue(topic, key, payload)?;
+        self.pending.push(delivery);
+        self.poll_pending()
+    }
+
+    fn format(&self) -> Format {
+        self.format
+    }
+
+    fn flush(&mut self) -> APIResult<()> {
+        self.producer.flush(self.flush_timeout)?;
+        let mut ret = Ok(());
+        for delivery in self.pending.drain(..) {
+            if let Err(err) = futures::executor::block_on(delivery) {
+                if ret.is_ok() {
+                    ret = Err(err);
+                }
+            }
+        }
+        ret
+    }
+}
+
+// zeromq PUB socket, SUB sockets connect to it and receive [topic, payload]
+// messages matching their subscriptions
+//
+//     let publisher = ZmqPublisher::bind("tcp://127.0.0.1:5556", Format::Json)?;
+#[cfg(feature = "zmq")]
+#[derive(Clone)]
+pub struct ZmqPublisher {
+    socket: Arc<Mutex<zmq::Socket>>,
+    format: Format,
+}
+
+#[cfg(feature = "zmq")]
+impl ZmqPublisher {
+    // endpoint is a zmq endpoint, tcp://127.0.0.1:* binds a free port, see
+    // endpoint for the one picked
+    pub fn bind(endpoint: &str, format: Format) -> APIResult<ZmqPublisher> {
+        let socket = zmq::Context::new().socket(zmq::PUB)?;
+        socket.bind(endpoint)?;
+        info!("zmq publisher bound to {}", endpoint);
+        Ok(ZmqPublisher {
+            socket: Arc::new(Mutex::new(socket)),
+            format,
+        })
+    }
+
+    // the endpoint the socket is bound to
+    pub fn endpoint(&self) -> APIResult<String> {
+        let endpoint = self.socket.lock().unwrap().get_last_endpoint()?;
+        endpoint.map_err(|_| Box::new(ExError::ApiError("invalid zmq endpoint".into())) as _)
+    }
+}
+
+#[cfg(feature = "zmq")]
+impl Sink for ZmqPublisher {
+    // the key is unused, SUB sockets filter on the topic prefix
+    fn send(&mut self, topic: &str, _key: &str, payload: &[u8]) -> APIResult<()> {
+        let socket = self.socket.lock().unwrap();
+        socket.send_multipart([topic.as_bytes(), payload], 0)?;
+        Ok(())
+    }
+
+    fn format(&self) -> Format {
+        self.format
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "kafka")]
+    use std::sync::Mutex;
+    #[cfg(any(feature = "redis", feature = "zmq"))]
+    use std::thread;
+    #[cfg(feature = "redis")]
+    use {
+        crate::models::{Ask, Bid, Ticker},
+        std::io::Write,
+        std::net::TcpListener,
+    };
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let value = serde_json::json!({"a": 1, "b": [true, null], "c": -1, "d": 1.5});
+        let buf = encode(&value, Format::MessagePack).unwrap();
+        assert_eq!(
+            buf,
+            vec![
+                0x84, 0xa1, b'a', 0x01, 0xa1, b'b', 0x92, 0xc3, 0xc0, 0xa1, b'c', 0xff, 0xa1, b'd',
+                0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbol_of() {
+        let event = serde_json::json!({"TickerEvent": {"symbol": "BTCUSDT", "timestamp": 1}});
+        assert_eq!(symbol_of(&event), Some("BTCUSDT".into()));
+        let event = serde_json::json!({"symbol": "ETHUSDT"});
+        assert_eq!(symbol_of(&event), Some("ETHUSDT".into()));
+        let event = serde_json::json!({"OrderbookEvent": {"timestamp": 1}});
+        assert_eq!(symbol_of(&event), None);
+    }
+
+    #[cfg(feature = "zmq")]
+    #[test]
+    fn test_zmq_publish() {
+        let mut publisher = ZmqPublisher::bind("tcp://127.0.0.1:*", Format::Json).unwrap();
+        let endpoint = publisher.endpoint().unwrap();
+        assert!(!endpoint.ends_with(":*"));
+
+        let subscriber = zmq::Context::new().socket(zmq::SUB).unwrap();
+        subscriber.set_rcvtimeo(5000).unwrap();
+        subscriber.connect(&endpoint).unwrap();
+        subscriber.set_subscribe(b"ticker").unwrap();
+        // a PUB socket drops messages until the subscription reached it
+        thread::sleep(std::time::Duration::from_millis(200));
+
+        let ticker = serde_json::json!({"symbol": "BTCUSDT"});
+        publisher.publish_event("trade.BTCUSDT", &ticker).unwrap();
+        publisher.publish_event("ticker.BTCUSDT", &ticker).unwrap();
+        let msg = subscriber.recv_multipart(0).unwrap();
+        assert_eq!(
+            msg,
+            vec![
+                b"ticker.BTCUSDT".to_vec(),
+                b"{\"symbol\":\"BTCUSDT\"}".to_vec()
+            ]
+        );
+    }
+
+    // RESP arguments of the next command
+    #[cfg(feature = "redis")]
+    fn read_command<R: std::io::BufRead>(reader: &mut R) -> Option<Vec<Vec<u8>>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut args = vec![];
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0u8; len + 2];
+            reader.read_exact(&mut arg).ok()?;
+            arg.truncate(len);
+            args.push(arg);
+        }
+        Some(args)
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // answers PUBLISH with 2 subscribers and anything the client sends
+        // on connect with OK
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut commands = vec![];
+            while let Some(args) = read_command(&mut reader) {
+                if args[0].eq_ignore_ascii_case(b"PUBLISH") {
+                    stream.write_all(b":2\r\n").unwrap();
+                    return (commands, args);
+                }
+                stream.write_all(b"+OK\r\n").unwrap();
+                commands.push(args);
+            }
+            panic!("no PUBLISH");
+        });
+
+        let mut sink = RedisSink::connect(&addr, Some("secret"), Format::Json).unwrap();
+        let ticker = Ticker {
+            symbol: "BTCUSDT".into(),
+            timestamp: 1,
+            bid: Bid {
+                price: 1.0,
+                amount: 2.0,
+            },
+            ask: Ask {
+                price: 3.0,
+                amount: 4.0,
+            },
+        };
+        assert_eq!(sink.publish("ticker:BTCUSDT", &ticker).unwrap(), 2);
+        let (commands, publish) = server.join().unwrap();
+        assert!(
+            commands
+                .iter()
+                .any(|args| args[0].eq_ignore_ascii_case(b"AUTH")
+                    && args.last().unwrap() == b"secret")
+        );
+        assert_eq!(publish[1], b"ticker:BTCUSDT");
+        assert!(String::from_utf8_lossy(&publish[2]).contains("\"symbol\":\"BTCUSDT\""));
+    }
+
+    #[cfg(feature = "kafka")]
+    #[derive(Default)]
+    struct FakeProducer {
+        records: Mutex<Vec<(String, String, Vec<u8>)>>,
+        fail: bool,
+        flushed: Mutex<bool>,
+    }
+
+    #[cfg(feature = "kafka")]
+    impl KafkaProducer for FakeProducer {
+        fn enqueue(&self, topic: &str, key: &str, payload: &[u8]) -> APIResult<KafkaDelivery> {
+            self.records
+                .lock()
+                .unwrap()
+                .push((topic.into(), key.into(), payload.to_vec()));
+            let fail = self.fail;
+            Ok(Box::pin(async move {
+                match fail {
+                    true => Err(Box::new(ExError::ApiError("broker down".into())) as _),
+                    false => Ok(()),
+                }
+            }))
+        }
+
+        fn flush(&self, _timeout: Duration) -> APIResult<()> {
+            *self.flushed.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "kafka")]
+    #[test]
+    fn test_kafka_sink() {
+        let mut sink = KafkaSink::with_producer(FakeProducer::default(), Format::Json);
+        let event = serde_json::json!({"TradeEvent": {"symbol": "BTCUSDT", "price": 1.0}});
+        sink.publish_event("trades", &event).unwrap();
+        sink.publish_event("trades", &serde_json::json!({"symbol": "ETHUSDT"}))
+            .unwrap();
+        sink.flush().unwrap();
+        assert!(*sink.producer.flushed.lock().unwrap());
+        assert!(sink.pending.is_empty());
+        let records = sink.producer.records.lock().unwrap();
+        let keys: Vec<(&str, &str)> = records
+            .iter()
+            .map(|(topic, key, _)| (topic.as_str(), key.as_str()))
+            .collect();
+        assert_eq!(keys, vec![("trades", "BTCUSDT"), ("trades", "ETHUSDT")]);
+        assert!(String::from_utf8_lossy(&records[0].2).contains("TradeEvent"));
+
+        // failed deliveries are returned by a send, the record it was given
+        // is still queued
+        let producer = FakeProducer {
+            fail: true,
+            ..FakeProducer::default()
+        };
+        let mut sink = KafkaSink::with_producer(producer, Format::Json);
+        assert!(sink.send("trades", "BTCUSDT", b"{}").is_err());
+        assert_eq!(sink.producer.records.lock().unwrap().len(), 1);
+        assert!(sink.pending.is_empty());
+        assert!(sink.flush().is_ok());
+    }
+}
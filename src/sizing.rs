@@ -0,0 +1,175 @@
+use crate::errors::*;
+use crate::models::*;
+
+// order quantities from a risk budget, rounded down to the symbol's amount
+// precision and checked against its minimum amount and notional
+//
+//     let info = symbols.iter().find(|s| s.symbol == "BTCUSDT").unwrap();
+//     // lose at most 1% of 10k if the stop at 29000 is hit
+//     let qty = fixed_risk(info, 10000.0, 0.01, 30000.0, 29000.0)?;
+//     // stop 2 ATR away
+//     let atr = atr(&klines, 14).unwrap();
+//     let qty = volatility_scaled(info, 10000.0, 0.01, price, atr, 2.0)?;
+
+fn sizing_error(why: String) -> Box<dyn std::error::Error> {
+    Box::new(ExError::ApiError(why))
+}
+
+// rounds down, never sizes above the budget
+pub fn round_amount(info: &SymbolInfo, amount: f64) -> f64 {
+    let scale = 10f64.powi(info.amount_precision as i32);
+    // the epsilon keeps 0.3 / 0.1 style results from losing a step
+    (amount * scale + 1e-9).floor() / scale
+}
+
+// the rounded amount, an error when it is under the symbol minimums
+pub fn fit(info: &SymbolInfo, amount: f64, price: f64) -> APIResult<f64> {
+    if !amount.is_finite() || amount <= 0.0 || price <= 0.0 {
+        return Err(sizing_error(format!(
+            "invalid size {} at price {} for {}",
+            amount, price, info.symbol
+        )));
+    }
+    let amount = round_amount(info, amount);
+    if amount < info.min_amount || amount == 0.0 {
+        return Err(sizing_error(format!(
+            "{} {} is below the min amount {}",
+            info.symbol, amount, info.min_amount
+        )));
+    }
+    if amount * price < info.min_value {
+        return Err(sizing_error(format!(
+            "{} notional {} is below the min notional {}",
+            info.symbol,
+            amount * price,
+            info.min_value
+        )));
+    }
+    Ok(amount)
+}
+
+// quantity worth notional in the quote asset
+pub fn target_notional(info: &SymbolInfo, notional: f64, price: f64) -> APIResult<f64> {
+    fit(info, notional / price, price)
+}
+
+// quantity that loses risk_pct of equity, e.g. 0.01, when the price goes
+// from entry to stop
+pub fn fixed_risk(
+    info: &SymbolInfo,
+    equity: f64,
+    risk_pct: f64,
+    entry: f64,
+    stop: f64,
+) -> APIResult<f64> {
+    let distance = (entry - stop).abs();
+    if distance == 0.0 {
+        return Err(sizing_error("stop equals entry".into()));
+    }
+    fit(info, equity * risk_pct / distance, entry)
+}
+
+// fixed_risk with the stop atr_mult ATRs away from price, positions get
+// smaller as volatility grows
+pub fn volatility_scaled(
+    info: &SymbolInfo,
+    equity: f64,
+    risk_pct: f64,
+    price: f64,
+    atr: f64,
+    atr_mult: f64,
+) -> APIResult<f64> {
+    fixed_risk(info, equity, risk_pct, price, price - atr * atr_mult)
+}
+
+// average true range with wilder smoothing over period klines, oldest
+// kline first. None with fewer than period + 1 klines
+pub fn atr(klines: &[Kline], period: usize) -> Option<f64> {
+    if period == 0 || klines.len() < period + 1 {
+        return None;
+    }
+    let ranges: Vec<f64> = klines
+        .windows(2)
+        .map(|w| {
+            let (prev, k) = (&w[0], &w[1]);
+            (k.high - k.low)
+                .max((k.high - prev.close).abs())
+                .max((k.low - prev.close).abs())
+        })
+        .collect();
+    let mut atr = ranges[..period].iter().sum::<f64>() / period as f64;
+    for range in ranges[period..].iter() {
+        atr = (atr * (period - 1) as f64 + range) / period as f64;
+    }
+    Some(atr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn info() -> SymbolInfo {
+        SymbolInfo {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            symbol: "BTCUSDT".into(),
+            status: SymbolStatus::Trading,
+            price_precision: 2,
+            amount_precision: 3,
+            min_amount: 0.001,
+            min_value: 10.0,
+        }
+    }
+
+    fn kline(high: f64, low: f64, close: f64) -> Kline {
+        Kline {
+            timestamp: 0,
+            open: close,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_sizing() {
+        let info = info();
+        assert_eq!(round_amount(&info, 0.12345), 0.123);
+        assert_eq!(round_amount(&info, 0.3 / 0.1 / 10.0), 0.3);
+        assert_eq!(target_notional(&info, 1000.0, 30000.0).unwrap(), 0.033);
+        // 100 of risk over a 1000 stop
+        assert_eq!(
+            fixed_risk(&info, 10000.0, 0.01, 30000.0, 29000.0).unwrap(),
+            0.1
+        );
+        assert_eq!(
+            fixed_risk(&info, 10000.0, 0.01, 30000.0, 31000.0).unwrap(),
+            0.1
+        );
+        assert!(fixed_risk(&info, 10000.0, 0.01, 30000.0, 30000.0).is_err());
+        assert!(target_notional(&info, 5.0, 30000.0).is_err());
+        assert!(target_notional(&info, 20.0, 30000.0)
+            .unwrap_err()
+            .to_string()
+            .contains("min amount"));
+        assert_eq!(
+            volatility_scaled(&info, 10000.0, 0.01, 30000.0, 250.0, 2.0).unwrap(),
+            0.2
+        );
+    }
+
+    #[test]
+    fn test_atr() {
+        let klines = vec![
+            kline(11.0, 9.0, 10.0),
+            kline(12.0, 10.0, 11.0),
+            kline(11.5, 8.0, 9.0),
+            kline(10.0, 9.0, 9.5),
+        ];
+        // true ranges 2, 3.5, 1
+        assert_eq!(atr(&klines, 3), Some(6.5 / 3.0));
+        assert_eq!(atr(&klines, 2), Some((2.75 + 1.0) / 2.0));
+        assert_eq!(atr(&klines, 4), None);
+    }
+}
@@ -0,0 +1,54 @@
+use futures_channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+// events of a websocket client running on its own thread, dropping the
+// stream closes the connection on the next event
+pub struct EventStream<T> {
+    rx: UnboundedReceiver<T>,
+}
+
+impl<T: Send + 'static> EventStream<T> {
+    pub(crate) fn spawn<F>(run: F) -> Self
+    where
+        F: FnOnce(UnboundedSender<T>) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        thread::spawn(move || run(tx));
+        EventStream { rx }
+    }
+}
+
+impl<T> Stream for EventStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::task::Waker;
+    use std::time::Duration;
+
+    #[test]
+    fn test_event_stream() {
+        let mut stream = EventStream::spawn(|tx| {
+            for i in 0..3 {
+                tx.unbounded_send(i).unwrap();
+            }
+        });
+        thread::sleep(Duration::from_millis(20));
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        let mut events = vec![];
+        while let Poll::Ready(Some(i)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            events.push(i);
+        }
+        assert_eq!(events, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::shutdown::Shutdown;
+
+use log::{info, warn};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// exponential backoff between restarts of a crashed task
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // gives up after that many restarts in a row, None restarts forever
+    pub max_restarts: Option<u32>,
+    // a task that ran this long before crashing starts over from initial_backoff
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_restarts: None,
+            reset_after: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RestartPolicy {
+    // wait before the nth restart in a row, starting at 1
+    pub fn backoff(&self, restarts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(restarts.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    // crashed, waiting for its backoff
+    Restarting,
+    // returned Ok or stopped by shutdown
+    Finished,
+    // crashed more than max_restarts times in a row
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub state: TaskState,
+    // since the supervisor started it
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    // ms timestamp of the current run
+    pub started_at: u64,
+}
+
+type Tasks = Arc<Mutex<BTreeMap<String, TaskHealth>>>;
+
+// owns long running tasks such as websocket collectors, each on its own
+// thread. a task that returns an error or panics is started again after the
+// backoff of its policy, one that returns Ok is done
+//
+//     let mut supervisor = Supervisor::new();
+//     supervisor.spawn("binance-trades", RestartPolicy::default(), move || {
+//         let mut ws = BinanceWs::new(BINANCE_SPOT_WS_HOST);
+//         ws.sub_trade("BTCUSDT");
+//         ws.connect(handler.clone());
+//         Err("ws client stopped".into())
+//     });
+//     // e.g. from a health check endpoint
+//     supervisor.health();
+pub struct Supervisor {
+    tasks: Tasks,
+    handles: Vec<JoinHandle<()>>,
+    shutdown: Shutdown,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::with_shutdown(Shutdown::new())
+    }
+
+    // crashed tasks are not restarted once the shutdown starts
+    pub fn with_shutdown(shutdown: Shutdown) -> Self {
+        Supervisor {
+            tasks: Arc::new(Mutex::new(BTreeMap::new())),
+            handles: vec![],
+            shutdown,
+        }
+    }
+
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    // names are unique, a task spawned under a taken name replaces its health entry
+    pub fn spawn<F>(&mut self, name: &str, policy: RestartPolicy, task: F)
+    where
+        F: Fn() -> APIResult<()> + Send + 'static,
+    {
+        let name = name.to_string();
+        let tasks = self.tasks.clone();
+        let shutdown = self.shutdown.clone();
+        set_health(&tasks, &name, |h| h.state = TaskState::Running);
+        let handle = thread::spawn(move || supervise(&name, &policy, &tasks, &shutdown, task));
+        self.handles.push(handle);
+    }
+
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.tasks.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn task(&self, name: &str) -> Option<TaskHealth> {
+        self.tasks.lock().unwrap().get(name).cloned()
+    }
+
+    // every task is running or finished
+    pub fn is_healthy(&self) -> bool {
+        self.tasks
+            .lock()
+            .unwrap()
+            .values()
+            .all(|h| matches!(h.state, TaskState::Running | TaskState::Finished))
+    }
+
+    // blocks until every task finished or failed
+    pub fn join(self) {
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn set_health<F: FnOnce(&mut TaskHealth)>(tasks: &Tasks, name: &str, update: F) {
+    let mut tasks = tasks.lock().unwrap();
+    let health = tasks.entry(name.to_string()).or_insert_with(|| TaskHealth {
+        name: name.to_string(),
+        state: TaskState::Running,
+        restarts: 0,
+        last_error: None,
+        started_at: now_ms(),
+    });
+    update(health);
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        format!("panic: {}", s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        format!("panic: {}", s)
+    } else {
+        "panic".to_string()
+    }
+}
+
+fn supervise<F>(name: &str, policy: &RestartPolicy, tasks: &Tasks, shutdown: &Shutdown, task: F)
+where
+    F: Fn() -> APIResult<()>,
+{
+    // restarts in a row, reset by a long enough run
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        set_health(tasks, name, |h| {
+            h.state = TaskState::Running;
+            h.started_at = now_ms();
+        });
+        let error = match panic::catch_unwind(AssertUnwindSafe(&task)) {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(err.to_string()),
+            Err(payload) => Some(panic_message(payload)),
+        };
+        let error = match error {
+            Some(error) if !shutdown.is_shutdown() => error,
+            _ => {
+                info!("task {} finished", name);
+                set_health(tasks, name, |h| h.state = TaskState::Finished);
+                return;
+            }
+        };
+        if started.elapsed() >= policy.reset_after {
+            failures = 0;
+        }
+        failures += 1;
+        if matches!(policy.max_restarts, Some(max) if failures > max) {
+            warn!("task {} failed: {}, giving up", name, error);
+            set_health(tasks, name, |h| {
+                h.state = TaskState::Failed;
+                h.last_error = Some(error);
+            });
+            return;
+        }
+        let backoff = policy.backoff(failures);
+        warn!(
+            "task {} failed: {}, restarting in {:?}",
+            name, error, backoff
+        );
+        set_health(tasks, name, |h| {
+            h.state = TaskState::Restarting;
+            h.restarts += 1;
+            h.last_error = Some(error);
+        });
+        if !shutdown.sleep(backoff) {
+            set_health(tasks, name, |h| h.state = TaskState::Finished);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(max_restarts: Option<u32>) -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            max_restarts,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), Duration::from_secs(60));
+        assert_eq!(policy.backoff(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_supervisor() {
+        let mut supervisor = Supervisor::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        let count = runs.clone();
+        supervisor.spawn("flaky", policy(None), move || {
+            match count.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(Box::new(ExError::ApiError("disconnected".into()))),
+                1 => panic!("bad message"),
+                _ => Ok(()),
+            }
+        });
+        supervisor.spawn("broken", policy(Some(2)), || panic!("always"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while supervisor
+            .health()
+            .iter()
+            .any(|h| matches!(h.state, TaskState::Running | TaskState::Restarting))
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let flaky = supervisor.task("flaky").unwrap();
+        assert_eq!(flaky.state, TaskState::Finished);
+        assert_eq!(flaky.restarts, 2);
+        assert_eq!(flaky.last_error.unwrap(), "panic: bad message");
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        let broken = supervisor.task("broken").unwrap();
+        assert_eq!(broken.state, TaskState::Failed);
+        assert_eq!(broken.restarts, 2);
+        assert!(!supervisor.is_healthy());
+        supervisor.join();
+    }
+
+    #[test]
+    fn test_supervisor_shutdown() {
+        let mut supervisor = Supervisor::new();
+        let shutdown = supervisor.shutdown_handle();
+        let mut slow = policy(None);
+        slow.initial_backoff = Duration::from_secs(60);
+        supervisor.spawn("collector", slow, || {
+            Err(Box::new(ExError::ApiError("disconnected".into())))
+        });
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            supervisor.task("collector").unwrap().state,
+            TaskState::Restarting
+        );
+        shutdown.shutdown(Duration::from_secs(1));
+        let started = Instant::now();
+        let tasks = supervisor.tasks.clone();
+        supervisor.join();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            tasks.lock().unwrap()["collector"].state,
+            TaskState::Finished
+        );
+    }
+}
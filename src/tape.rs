@@ -0,0 +1,139 @@
+use crate::models::*;
+
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TapeStats {
+    pub count: usize,
+    // base amounts
+    pub volume: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    // taker buy volume over total volume
+    pub buy_ratio: f64,
+    pub vwap: f64,
+    pub max_trade: f64,
+}
+
+// recent trades per symbol, bounded by count and by age relative to the
+// newest trade of the symbol
+//
+//     let mut tape = TradeTape::new(10000, 60 * 1000);
+//     // for every TradeEvent
+//     tape.push(trade);
+//     let last_10s = tape.stats("BTCUSDT", 10 * 1000);
+pub struct TradeTape {
+    max_trades: usize,
+    max_age_ms: u64,
+    tapes: HashMap<String, VecDeque<Trade>>,
+}
+
+impl TradeTape {
+    pub fn new(max_trades: usize, max_age_ms: u64) -> Self {
+        TradeTape {
+            max_trades: max_trades.max(1),
+            max_age_ms,
+            tapes: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, trade: Trade) {
+        let tape = self.tapes.entry(trade.symbol.clone()).or_default();
+        // streams can deliver slightly out of order, keep the tape sorted
+        let at = tape
+            .iter()
+            .rposition(|t| t.timestamp <= trade.timestamp)
+            .map_or(0, |i| i + 1);
+        tape.insert(at, trade);
+        let newest = tape.back().map_or(0, |t| t.timestamp);
+        while tape.len() > self.max_trades
+            || matches!(tape.front(), Some(t) if t.timestamp + self.max_age_ms < newest)
+        {
+            tape.pop_front();
+        }
+    }
+
+    // oldest first
+    pub fn trades(&self, symbol: &str) -> impl Iterator<Item = &Trade> {
+        self.tapes.get(symbol).into_iter().flatten()
+    }
+
+    pub fn last(&self, symbol: &str) -> Option<&Trade> {
+        self.tapes.get(symbol)?.back()
+    }
+
+    // trades of the last `window_ms` before the newest one, None without trades
+    pub fn stats(&self, symbol: &str, window_ms: u64) -> Option<TapeStats> {
+        let newest = self.last(symbol)?.timestamp;
+        let since = newest.saturating_sub(window_ms);
+        let mut stats = TapeStats::default();
+        let mut notional = 0.0;
+        for trade in self.trades(symbol).filter(|t| t.timestamp >= since) {
+            stats.count += 1;
+            stats.volume += trade.amount;
+            if trade.side == "buy" {
+                stats.buy_volume += trade.amount;
+            } else {
+                stats.sell_volume += trade.amount;
+            }
+            stats.max_trade = stats.max_trade.max(trade.amount);
+            notional += trade.price * trade.amount;
+        }
+        if stats.volume > 0.0 {
+            stats.buy_ratio = stats.buy_volume / stats.volume;
+            stats.vwap = notional / stats.volume;
+        }
+        Some(stats)
+    }
+
+    pub fn clear(&mut self, symbol: &str) {
+        self.tapes.remove(symbol);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trade(timestamp: u64, price: f64, amount: f64, side: &str) -> Trade {
+        Trade {
+            symbol: "BTCUSDT".into(),
+            trade_id: timestamp,
+            timestamp,
+            amount,
+            price,
+            side: side.into(),
+        }
+    }
+
+    #[test]
+    fn test_trade_tape() {
+        let mut tape = TradeTape::new(4, 1000);
+        tape.push(trade(100, 10.0, 1.0, "buy"));
+        tape.push(trade(300, 12.0, 3.0, "sell"));
+        // out of order
+        tape.push(trade(200, 11.0, 2.0, "buy"));
+        let ids: Vec<u64> = tape.trades("BTCUSDT").map(|t| t.trade_id).collect();
+        assert_eq!(ids, vec![100, 200, 300]);
+
+        let stats = tape.stats("BTCUSDT", 1000).unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.volume, 6.0);
+        assert_eq!(stats.buy_ratio, 0.5);
+        assert_eq!(stats.vwap, (10.0 + 22.0 + 36.0) / 6.0);
+        assert_eq!(stats.max_trade, 3.0);
+        let stats = tape.stats("BTCUSDT", 100).unwrap();
+        assert_eq!(stats.count, 2);
+        assert!(tape.stats("ETHUSDT", 1000).is_none());
+
+        // older than a second before the newest trade
+        tape.push(trade(1250, 12.0, 1.0, "buy"));
+        assert_eq!(tape.trades("BTCUSDT").next().unwrap().trade_id, 300);
+        // over the count limit
+        for ts in 1300..1304 {
+            tape.push(trade(ts, 12.0, 1.0, "buy"));
+        }
+        assert_eq!(tape.trades("BTCUSDT").count(), 4);
+        assert_eq!(tape.last("BTCUSDT").unwrap().trade_id, 1303);
+    }
+}
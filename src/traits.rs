@@ -1,46 +1,151 @@
 use crate::errors::*;
 use crate::models::*;
 
+// SpotRest and FutureRest are written once with maybe_async and come out
+// blocking with the default blocking feature, or as async traits without
+// it. impls take the same attribute and write .await after calls through
+// the traits, it is stripped from the blocking flavor
+//
+//     #[maybe_async::maybe_async]
+//     impl<T: SpotRest + Sync> SpotRest for Logged<T> {
+//         async fn get_balance(&self, asset: &str) -> APIResult<Balance> {
+//             info!("get_balance {}", asset);
+//             self.inner.get_balance(asset).await
+//         }
+//         ...
+//     }
+//
+// the async flavor sends through reqwest's async client, see src/http.rs
+#[maybe_async::maybe_async]
 pub trait SpotRest {
-    fn get_balance(&self, asset: &str) -> APIResult<Balance>;
-    fn create_order(
-        &self,
-        symbol: &str,
-        price: f64,
-        amount: f64,
-        action: &str,
-        order_type: &str,
-    ) -> APIResult<String>;
-    fn cancel(&self, id: &str) -> APIResult<bool>;
-    fn cancel_all(&self, symbol: &str) -> APIResult<bool>;
-    fn get_order(&self, id: &str) -> APIResult<Order>;
-    fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
-    fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
-
-    fn get_orderbook(&self, symbol: &str, depth: u8) -> APIResult<Orderbook>;
-    fn get_ticker(&self, symbol: &str) -> APIResult<Ticker>;
-    fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>>;
+    // none by default, clients list what they support
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities::default()
+    }
+
+    async fn ping(&self) -> APIResult<()>;
+    // milliseconds
+    async fn get_server_time(&self) -> APIResult<u64>;
+
+    async fn get_balance(&self, asset: &str) -> APIResult<Balance>;
+    async fn create_order(&self, order: &OrderRequest) -> APIResult<String>;
+    async fn cancel(&self, symbol: &str, id: &str) -> APIResult<bool>;
+    async fn cancel_all(&self, symbol: &str) -> APIResult<bool>;
+    async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order>;
+    async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
+    async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
+
+    async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook>;
+    async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker>;
+    async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>>;
 }
 
+// shorthands over SpotRest for simple bots, every SpotRest client has them
+//
+//     use rsex::traits::SpotRestExt;
+//     let id = api.limit_buy("BTCUSDT", 9000.0, 0.01)?;
+//     let mid = api.get_mid_price("BTCUSDT")?;
+//     // spend 100 USDT
+//     api.market_buy_quote("BTCUSDT", 100.0)?;
+#[maybe_async::maybe_async]
+pub trait SpotRestExt: SpotRest + Sync {
+    async fn limit_buy(&self, symbol: &str, price: f64, amount: f64) -> APIResult<String> {
+        self.create_order(&OrderRequest::limit_buy(symbol, price, amount))
+            .await
+    }
+
+    async fn limit_sell(&self, symbol: &str, price: f64, amount: f64) -> APIResult<String> {
+        self.create_order(&OrderRequest::limit_sell(symbol, price, amount))
+            .await
+    }
+
+    async fn market_buy(&self, symbol: &str, amount: f64) -> APIResult<String> {
+        self.create_order(&OrderRequest::market_buy(symbol, amount))
+            .await
+    }
+
+    async fn market_sell(&self, symbol: &str, amount: f64) -> APIResult<String> {
+        self.create_order(&OrderRequest::market_sell(symbol, amount))
+            .await
+    }
+
+    // buys for quote_amount of the quote asset. clients without quote
+    // amount orders get the amount from the best ask, not rounded to the
+    // step size, see sizing::round_amount
+    async fn market_buy_quote(&self, symbol: &str, quote_amount: f64) -> APIResult<String> {
+        if self.capabilities().quote_amount {
+            return self
+                .create_order(&OrderRequest::market_buy_quote(symbol, quote_amount))
+                .await;
+        }
+        let ticker = self.get_ticker(symbol).await?;
+        if ticker.ask.price <= 0.0 {
+            return Err(Box::new(ExError::ApiError(format!(
+                "no ask to price {} at",
+                symbol
+            ))));
+        }
+        self.market_buy(symbol, quote_amount / ticker.ask.price)
+            .await
+    }
+
+    // halfway between the best bid and ask
+    async fn get_mid_price(&self, symbol: &str) -> APIResult<f64> {
+        let ticker = self.get_ticker(symbol).await?;
+        Ok((ticker.bid.price + ticker.ask.price) / 2.0)
+    }
+}
+
+#[maybe_async::maybe_async]
+impl<T: SpotRest + Sync> SpotRestExt for T {}
+
+#[maybe_async::maybe_async]
 pub trait FutureRest {
-    fn get_balance(&self, asset: &str) -> APIResult<Balance>;
-    fn create_order(
-        &self,
-        symbol: &str,
-        price: f64,
-        amount: f64,
-        action: &str,
-        order_type: &str,
-    ) -> APIResult<String>;
-    fn cancel(&self, id: &str) -> APIResult<bool>;
-    fn cancel_all(&self, symbol: &str) -> APIResult<bool>;
-    fn get_order(&self, id: &str) -> APIResult<Order>;
-    fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
-    fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
-
-    fn get_orderbook(&self, symbol: &str, depth: u8) -> APIResult<Orderbook>;
-    fn get_ticker(&self, symbol: &str) -> APIResult<Ticker>;
-    fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>>;
+    // none by default, clients list what they support
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities::default()
+    }
+
+    async fn ping(&self) -> APIResult<()>;
+    // milliseconds
+    async fn get_server_time(&self) -> APIResult<u64>;
+
+    async fn get_balance(&self, asset: &str) -> APIResult<FutureBalance>;
+    async fn create_order(&self, order: &OrderRequest) -> APIResult<String>;
+    async fn cancel(&self, symbol: &str, id: &str) -> APIResult<bool>;
+    async fn cancel_all(&self, symbol: &str) -> APIResult<bool>;
+    async fn get_order(&self, symbol: &str, id: &str) -> APIResult<Order>;
+    async fn get_open_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
+    async fn get_history_orders(&self, symbol: &str) -> APIResult<Vec<Order>>;
+
+    async fn get_orderbook(&self, symbol: &str, depth: u16) -> APIResult<Orderbook>;
+    async fn get_ticker(&self, symbol: &str) -> APIResult<Ticker>;
+    async fn get_kline(&self, symbol: &str, period: &str, limit: u16) -> APIResult<Vec<Kline>>;
+}
+
+// deposits and withdrawals of a spot account. the deposit and transfer
+// watchers poll it from threads, so it stays sync and comes with the
+// blocking client only
+#[cfg(feature = "blocking")]
+pub trait WalletRest {
+    // the chains the coin moves on, with their fees and limits
+    fn get_coin_networks(&self, coin: &str) -> APIResult<Vec<CoinNetwork>>;
+    // on the default network when None
+    fn get_deposit_address(&self, coin: &str, network: Option<&str>) -> APIResult<DepositAddress>;
+    // deposits of the coin since the ms timestamp, oldest first
+    fn get_deposits(&self, coin: &str, since: u64) -> APIResult<Vec<Deposit>>;
+    // returns the withdrawal id
+    fn withdraw(&self, req: &WithdrawRequest) -> APIResult<String>;
+    fn get_withdrawal(&self, coin: &str, id: &str) -> APIResult<Withdrawal>;
+
+    // withdraw after WithdrawRequest::check against the coin's networks
+    fn withdraw_checked(&self, req: &WithdrawRequest) -> APIResult<String> {
+        let networks = self.get_coin_networks(&req.coin)?;
+        let network = req.check(&networks)?;
+        let mut req = req.clone();
+        req.network = Some(network.network.clone());
+        self.withdraw(&req)
+    }
 }
 
 pub trait SpotWs {
@@ -60,3 +165,123 @@ pub trait FutureWs {
 
     fn sub_order_update(&self, symbol: &str);
 }
+
+#[cfg(all(test, feature = "blocking"))]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    // quotes 100 / 102, holds nothing and records the orders as new
+    #[derive(Default)]
+    struct Mock {
+        orders: Mutex<Vec<OrderRequest>>,
+    }
+
+    impl SpotRest for Mock {
+        fn ping(&self) -> APIResult<()> {
+            Ok(())
+        }
+
+        fn get_server_time(&self) -> APIResult<u64> {
+            Ok(0)
+        }
+
+        fn get_balance(&self, asset: &str) -> APIResult<Balance> {
+            Ok(Balance {
+                asset: asset.into(),
+                free: 0.0,
+                locked: 0.0,
+            })
+        }
+
+        fn create_order(&self, order: &OrderRequest) -> APIResult<String> {
+            let mut orders = self.orders.lock().unwrap();
+            orders.push(order.clone());
+            Ok(orders.len().to_string())
+        }
+
+        fn cancel(&self, _symbol: &str, _id: &str) -> APIResult<bool> {
+            Ok(true)
+        }
+
+        fn cancel_all(&self, _symbol: &str) -> APIResult<bool> {
+            Ok(true)
+        }
+
+        fn get_order(&self, _symbol: &str, id: &str) -> APIResult<Order> {
+            let orders = self.orders.lock().unwrap();
+            // ids are 1 based, see create_order
+            let index = id.parse::<usize>().ok().and_then(|i| i.checked_sub(1));
+            match index.and_then(|i| orders.get(i)) {
+                Some(order) => Ok(Order {
+                    symbol: order.symbol.clone(),
+                    order_id: id.into(),
+                    amount: order.amount,
+                    price: order.price,
+                    side: order.side.clone(),
+                    filled: 0.0,
+                    avg_price: 0.0,
+                    status: OrderStatus::New,
+                    create_time: 0,
+                    update_time: 0,
+                }),
+                None => Err(Box::new(ExError::ApiError(format!(
+                    "unknown order: {}",
+                    id
+                )))),
+            }
+        }
+
+        fn get_open_orders(&self, _symbol: &str) -> APIResult<Vec<Order>> {
+            Ok(vec![])
+        }
+
+        fn get_history_orders(&self, _symbol: &str) -> APIResult<Vec<Order>> {
+            Ok(vec![])
+        }
+
+        fn get_orderbook(&self, _symbol: &str, _depth: u16) -> APIResult<Orderbook> {
+            Ok(Orderbook {
+                timestamp: 0,
+                last_update_id: 0,
+                bids: vec![Bid {
+                    price: 100.0,
+                    amount: 1.0,
+                }],
+                asks: vec![Ask {
+                    price: 102.0,
+                    amount: 1.0,
+                }],
+            })
+        }
+
+        fn get_ticker(&self, symbol: &str) -> APIResult<Ticker> {
+            let mut ticker = Ticker::new();
+            ticker.symbol = symbol.into();
+            ticker.bid.price = 100.0;
+            ticker.ask.price = 102.0;
+            Ok(ticker)
+        }
+
+        fn get_kline(&self, _symbol: &str, _period: &str, _limit: u16) -> APIResult<Vec<Kline>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_spot_rest_ext() {
+        let api = Mock::default();
+        assert_eq!(api.get_mid_price("BTCUSDT").unwrap(), 101.0);
+        assert_eq!(api.limit_buy("BTCUSDT", 99.0, 2.0).unwrap(), "1");
+        api.limit_sell("BTCUSDT", 105.0, 1.0).unwrap();
+        api.market_buy_quote("BTCUSDT", 51.0).unwrap();
+
+        let orders = api.orders.lock().unwrap();
+        assert_eq!(orders[0], OrderRequest::limit_buy("BTCUSDT", 99.0, 2.0));
+        assert_eq!(orders[1].side, "SELL");
+        assert_eq!(orders[2], OrderRequest::market_buy("BTCUSDT", 0.5));
+        drop(orders);
+        assert_eq!(api.get_order("BTCUSDT", "2").unwrap().side, "SELL");
+        assert!(api.get_order("BTCUSDT", "4").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::clock::now_ms;
+use crate::errors::*;
+use crate::models::*;
+use crate::notify::Notifier;
+use crate::traits::WalletRest;
+
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// deposits older than the withdrawal by more than that are not matched
+const DEPOSIT_SLACK_MS: u64 = 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+    // withdrawal accepted by the source exchange
+    Submitted,
+    // withdrawal broadcast or completed
+    Sent,
+    // deposit seen on the destination, not yet credited
+    Arriving,
+    // tradable on the destination
+    Completed,
+    Failed,
+}
+
+impl TransferState {
+    pub fn is_final(&self) -> bool {
+        matches!(self, TransferState::Completed | TransferState::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transfer {
+    pub id: u64,
+    pub from: String,
+    pub to: String,
+    pub coin: String,
+    pub amount: f64,
+    // network id on the destination, empty for its default
+    pub network: String,
+    pub address: String,
+    pub withdraw_id: String,
+    // empty until the source exchange reports it
+    pub tx_id: String,
+    pub fee: f64,
+    // what the destination credited, once seen
+    pub received: Option<f64>,
+    pub deposit_id: Option<String>,
+    pub state: TransferState,
+    pub error: Option<String>,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+pub type Wallet = Arc<dyn WalletRest + Send + Sync>;
+
+// moves funds between exchanges: withdraws on one to the deposit address
+// of the other, then follows the withdrawal and the matching deposit until
+// the funds are tradable. deposits are matched by tx id, or by address and
+// amount net of the fee while the tx id is unknown
+//
+//     let mut transfers = TransferOrchestrator::new();
+//     transfers.add_wallet("binance", Arc::new(binance));
+//     transfers.add_wallet("huobi", Arc::new(huobi));
+//     let id = transfers.start_on("binance", Some("TRX"), "huobi", Some("trc20usdt"), "USDT", 1000.0)?;
+//     let transfer = transfers.wait(id, Duration::from_secs(3600), Duration::from_secs(30))?;
+pub struct TransferOrchestrator {
+    wallets: HashMap<String, Wallet>,
+    transfers: Vec<Transfer>,
+    next_id: u64,
+    // (exchange, deposit id) already matched to a transfer
+    claimed: HashSet<(String, String)>,
+    notifier: Notifier<Transfer>,
+}
+
+impl Default for TransferOrchestrator {
+    fn default() -> Self {
+        TransferOrchestrator::new()
+    }
+}
+
+impl TransferOrchestrator {
+    pub fn new() -> Self {
+        TransferOrchestrator {
+            wallets: HashMap::new(),
+            transfers: vec![],
+            next_id: 1,
+            claimed: HashSet::new(),
+            notifier: Notifier::new(),
+        }
+    }
+
+    pub fn add_wallet(&mut self, name: &str, wallet: Wallet) {
+        self.wallets.insert(name.into(), wallet);
+    }
+
+    // called on every state change
+    pub fn on_update<F>(&mut self, callback: F)
+    where
+        F: FnMut(&Transfer) + Send + 'static,
+    {
+        self.notifier.on_event(callback);
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<Transfer> {
+        self.notifier.subscribe()
+    }
+
+    pub fn transfer(&self, id: u64) -> Option<Transfer> {
+        self.transfers.iter().find(|t| t.id == id).cloned()
+    }
+
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.transfers.clone()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.transfers
+            .iter()
+            .filter(|t| !t.state.is_final())
+            .count()
+    }
+
+    // start_on without networks, refused for coins on several networks
+    pub fn start(&mut self, from: &str, to: &str, coin: &str, amount: f64) -> APIResult<u64> {
+        self.start_on(from, None, to, None, coin, amount)
+    }
+
+    // withdraws amount of coin on from to the deposit address of to,
+    // returns the id of the transfer. the networks are the two exchanges'
+    // ids of the same chain, e.g. TRX on binance and trc20usdt on huobi.
+    // the withdrawal is checked against the networks of from first
+    pub fn start_on(
+        &mut self,
+        from: &str,
+        from_network: Option<&str>,
+        to: &str,
+        to_network: Option<&str>,
+        coin: &str,
+        amount: f64,
+    ) -> APIResult<u64> {
+        let source = self.wallet(from)?;
+        let address = self.wallet(to)?.get_deposit_address(coin, to_network)?;
+        let id = self.next_id;
+        let now = now_ms();
+        let mut req =
+            WithdrawRequest::to(&address, amount).client_id(&format!("rsex{}{}", now, id));
+        req.network = from_network.map(|n| n.to_string());
+        let withdraw_id = source.withdraw_checked(&req)?;
+        self.next_id += 1;
+        info!(
+            "transfer {}: {} {} from {} to {}, withdrawal {}",
+            id, amount, coin, from, to, withdraw_id
+        );
+        let transfer = Transfer {
+            id,
+            from: from.into(),
+            to: to.into(),
+            coin: req.coin.clone(),
+            amount,
+            network: address.network,
+            address: address.address,
+            withdraw_id,
+            tx_id: String::new(),
+            fee: 0.0,
+            received: None,
+            deposit_id: None,
+            state: TransferState::Submitted,
+            error: None,
+            started_at: now,
+            updated_at: now,
+        };
+        self.notify(&transfer);
+        self.transfers.push(transfer);
+        Ok(id)
+    }
+
+    // advances every pending transfer, returns the ones that changed.
+    // query errors are logged and retried on the next poll
+    pub fn poll(&mut self) -> Vec<Transfer> {
+        let mut changed = vec![];
+        for i in 0..self.transfers.len() {
+            if self.transfers[i].state.is_final() {
+                continue;
+            }
+            let before = self.transfers[i].state;
+            if let Err(err) = self.advance(i) {
+                warn!("transfer {} poll failed: {:?}", self.transfers[i].id, err);
+                continue;
+            }
+            if self.transfers[i].state != before {
+                self.transfers[i].updated_at = now_ms();
+                changed.push(self.transfers[i].clone());
+            }
+        }
+        for transfer in changed.iter() {
+            self.notify(transfer);
+        }
+        changed
+    }
+
+    // polls every interval until the transfer is final or timeout passes
+    pub fn wait(&mut self, id: u64, timeout: Duration, interval: Duration) -> APIResult<Transfer> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.poll();
+            let transfer = self
+                .transfer(id)
+                .ok_or_else(|| ExError::ApiError(format!("unknown transfer {}", id)))?;
+            if transfer.state.is_final() {
+                return Ok(transfer);
+            }
+            if Instant::now() >= deadline {
+                return Err(Box::new(ExError::ApiError(format!(
+                    "transfer {} still {:?} after {:?}",
+                    id, transfer.state, timeout
+                ))));
+            }
+            thread::sleep(interval.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+
+    fn wallet(&self, name: &str) -> APIResult<Wallet> {
+        match self.wallets.get(name) {
+            Some(wallet) => Ok(wallet.clone()),
+            None => Err(Box::new(ExError::ApiError(format!(
+                "no wallet for {}",
+                name
+            )))),
+        }
+    }
+
+    fn advance(&mut self, i: usize) -> APIResult<()> {
+        let (source, dest) = {
+            let t = &self.transfers[i];
+            (self.wallet(&t.from)?, self.wallet(&t.to)?)
+        };
+        if matches!(
+            self.transfers[i].state,
+            TransferState::Submitted | TransferState::Sent
+        ) {
+            let t = &mut self.transfers[i];
+            let withdrawal = source.get_withdrawal(&t.coin, &t.withdraw_id)?;
+            t.fee = withdrawal.fee;
+            if !withdrawal.tx_id.is_empty() {
+                t.tx_id = withdrawal.tx_id.clone();
+            }
+            match withdrawal.status {
+                WithdrawStatus::Canceled | WithdrawStatus::Failed => {
+                    t.state = TransferState::Failed;
+                    t.error = Some(format!("withdrawal {:?}", withdrawal.status));
+                    return Ok(());
+                }
+                WithdrawStatus::Completed => t.state = TransferState::Sent,
+                _ if !t.tx_id.is_empty() => t.state = TransferState::Sent,
+                _ => {}
+            }
+        }
+
+        let t = &self.transfers[i];
+        let since = t.started_at.saturating_sub(DEPOSIT_SLACK_MS);
+        let deposits = dest.get_deposits(&t.coin, since)?;
+        let deposit = match t.deposit_id.as_ref() {
+            Some(id) => deposits.into_iter().find(|d| &d.id == id),
+            None => deposits.into_iter().find(|d| {
+                let claimed = self.claimed.contains(&(t.to.clone(), d.id.clone()));
+                !claimed && matches_transfer(t, d)
+            }),
+        };
+        let deposit = match deposit {
+            Some(deposit) => deposit,
+            None => return Ok(()),
+        };
+        self.claimed.insert((t.to.clone(), deposit.id.clone()));
+        let t = &mut self.transfers[i];
+        t.deposit_id = Some(deposit.id.clone());
+        t.received = Some(deposit.amount);
+        if t.tx_id.is_empty() {
+            t.tx_id = deposit.tx_id.clone();
+        }
+        t.state = match deposit.status {
+            DepositStatus::Pending => TransferState::Arriving,
+            DepositStatus::Credited | DepositStatus::Success => TransferState::Completed,
+            DepositStatus::Failed => {
+                t.error = Some("deposit failed".into());
+                TransferState::Failed
+            }
+        };
+        Ok(())
+    }
+
+    fn notify(&mut self, transfer: &Transfer) {
+        self.notifier.notify(transfer);
+    }
+}
+
+fn matches_transfer(transfer: &Transfer, deposit: &Deposit) -> bool {
+    if !transfer.tx_id.is_empty() && !deposit.tx_id.is_empty() {
+        return transfer.tx_id.eq_ignore_ascii_case(&deposit.tx_id);
+    }
+    // the fee may not be known yet, anything between net and gross matches
+    let net = transfer.amount - transfer.fee;
+    deposit.address == transfer.address
+        && deposit.amount <= transfer.amount + 1e-9
+        && deposit.amount >= net.min(transfer.amount * 0.9) - 1e-9
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fakes::FakeWallet;
+
+    fn deposit(id: &str, amount: f64, tx_id: &str, status: DepositStatus) -> Deposit {
+        Deposit {
+            id: id.into(),
+            coin: "USDT".into(),
+            network: "TRX".into(),
+            amount,
+            address: "addr-b".into(),
+            tx_id: tx_id.into(),
+            status,
+            confirmations: 0,
+            required_confirmations: 0,
+            timestamp: now_ms(),
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        let a = Arc::new(FakeWallet::default());
+        let b = Arc::new(FakeWallet::default());
+        let mut transfers = TransferOrchestrator::new();
+        transfers.add_wallet("a", a.clone());
+        transfers.add_wallet("b", b.clone());
+        let rx = transfers.subscribe();
+        assert!(transfers.start("a", "missing", "USDT", 100.0).is_err());
+        // two networks and none given
+        assert!(transfers.start("a", "b", "USDT", 100.0).is_err());
+        assert!(transfers
+            .start_on("a", Some("SOL"), "b", None, "USDT", 100.0)
+            .is_err());
+        assert!(a.withdrawals.lock().unwrap().is_empty());
+
+        let id = transfers
+            .start_on("a", Some("TRX"), "b", Some("tron"), "usdt", 100.0)
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(transfers.transfer(id).unwrap().network, "tron");
+        assert_eq!(a.withdrawals.lock().unwrap()[0].address, "addr-b");
+        assert_eq!(a.withdrawals.lock().unwrap()[0].network, "TRX");
+        // an unrelated deposit of another amount
+        b.deposits
+            .lock()
+            .unwrap()
+            .push(deposit("d0", 5.0, "0xother", DepositStatus::Success));
+        assert!(transfers.poll().is_empty());
+
+        {
+            let mut withdrawals = a.withdrawals.lock().unwrap();
+            withdrawals[0].tx_id = "0xABC".into();
+            withdrawals[0].status = WithdrawStatus::Processing;
+        }
+        assert_eq!(transfers.poll()[0].state, TransferState::Sent);
+
+        b.deposits
+            .lock()
+            .unwrap()
+            .push(deposit("d1", 99.0, "0xabc", DepositStatus::Pending));
+        assert_eq!(transfers.poll()[0].state, TransferState::Arriving);
+        b.deposits.lock().unwrap()[1].status = DepositStatus::Credited;
+        let done = transfers
+            .wait(id, Duration::from_secs(1), Duration::from_millis(1))
+            .unwrap();
+        assert_eq!(done.state, TransferState::Completed);
+        assert_eq!(done.received, Some(99.0));
+        assert_eq!(done.deposit_id.as_deref(), Some("d1"));
+        assert_eq!(transfers.pending(), 0);
+        assert_eq!(rx.try_iter().count(), 4);
+
+        // a rejected withdrawal fails the transfer
+        let id = transfers
+            .start_on("a", Some("eth"), "b", None, "USDT", 50.0)
+            .unwrap();
+        a.withdrawals.lock().unwrap()[1].status = WithdrawStatus::Failed;
+        transfers.poll();
+        assert_eq!(transfers.transfer(id).unwrap().state, TransferState::Failed);
+    }
+}
@@ -1,44 +1,528 @@
+// the wasm32 build only uses the parsing helpers
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use crate::clock;
 use crate::errors::*;
 use chrono::prelude::*;
 use percent_encoding::{define_encode_set, utf8_percent_encode, USERINFO_ENCODE_SET};
-use serde_json::Value;
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http::http_error;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::limits::{shared_limiter, RateLimiter};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::signer::{self, Signer};
+#[cfg(not(target_arch = "wasm32"))]
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+#[cfg(not(target_arch = "wasm32"))]
+use log::warn;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Read;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+#[cfg(not(target_arch = "wasm32"))]
+use uuid::Uuid;
+
+// what the request closures of the client helpers (failover, cache,
+// coalescer, audit) return: the result itself, in the async flavor a future
+// of it. written as Box::pin(async move { .. }) inside a maybe_async fn, the
+// blocking build unwraps it to the block
+#[cfg(all(not(target_arch = "wasm32"), feature = "blocking"))]
+pub type Pending<'a, T> = T;
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "blocking")))]
+pub type Pending<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+// waits without holding the runtime's thread in the async flavor
+#[cfg(not(target_arch = "wasm32"))]
+#[maybe_async::sync_impl]
+pub fn sleep(d: Duration) {
+    std::thread::sleep(d);
+}
 
+#[cfg(not(target_arch = "wasm32"))]
+#[maybe_async::async_impl]
+pub async fn sleep(d: Duration) {
+    tokio::time::sleep(d).await;
+}
+
+// milliseconds from the global time source, see clock::set_time_source
 pub fn get_timestamp() -> APIResult<u64> {
-    let start = SystemTime::now();
-    let since_epoch = start.duration_since(UNIX_EPOCH)?;
-    Ok(since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_nanos()) / 1_000_000)
+    Ok(clock::now_ms())
 }
 
 pub fn get_utc_ts() -> String {
-    let dt = Utc::now();
+    let dt = Utc
+        .timestamp_millis_opt(clock::now_ms() as i64)
+        .single()
+        .unwrap_or_else(Utc::now);
     dt.format("%Y-%m-%dT%H:%M:%S").to_string()
 }
 
 pub fn percent_encode(source: &str) -> String {
     define_encode_set! {
-        pub CUSTOM_ENCODE_SET = [USERINFO_ENCODE_SET] | { '+', ',' }
+        pub CUSTOM_ENCODE_SET = [USERINFO_ENCODE_SET] | {
+            '+', ',', '&', '%', '!', '$', '\'', '(', ')', '*'
+        }
     }
-    utf8_percent_encode(&source, CUSTOM_ENCODE_SET).to_string()
+    utf8_percent_encode(source, CUSTOM_ENCODE_SET).to_string()
+}
+
+// sorted, percent encoded `k1=v1&k2=v2`, this exact string is what gets signed
+pub fn build_query_string(params: &BTreeMap<String, String>) -> String {
+    params
+        .iter()
+        .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+        .collect::<Vec<String>>()
+        .join("&")
 }
 
-pub fn to_i64(v: &Value) -> i64 {
-    v.as_i64().unwrap()
+// url encoded query of a param struct, fields in declaration order
+pub fn to_query<T: Serialize>(params: &T) -> APIResult<String> {
+    Ok(serde_urlencoded::to_string(params)?)
 }
 
-pub fn to_f64(v: &Value) -> f64 {
-    v.as_str().unwrap().parse().unwrap()
+// websocket payload as text, binary frames are gzip (huobi), zlib or raw deflate (okx)
+#[cfg(not(target_arch = "wasm32"))]
+pub fn decode_ws_message(msg: ws::Message) -> APIResult<String> {
+    let data = match msg {
+        ws::Message::Text(s) => return Ok(s),
+        ws::Message::Binary(data) => data,
+    };
+    let mut s = String::new();
+    match data.as_slice() {
+        [0x1f, 0x8b, ..] => GzDecoder::new(data.as_slice()).read_to_string(&mut s)?,
+        [0x78, b, ..] if (0x78u16 * 256 + u16::from(*b)).is_multiple_of(31) => {
+            ZlibDecoder::new(data.as_slice()).read_to_string(&mut s)?
+        }
+        _ => match String::from_utf8(data.clone()) {
+            Ok(text) => return Ok(text),
+            Err(_) => DeflateDecoder::new(data.as_slice()).read_to_string(&mut s)?,
+        },
+    };
+    Ok(s)
+}
+
+// a websocket message parsed into T, by simd-json with the simd-json feature.
+// it parses in place, so the text is copied into a scratch buffer first
+pub fn from_ws_str<T: DeserializeOwned>(s: &str) -> APIResult<T> {
+    #[cfg(feature = "simd-json")]
+    {
+        let mut buf = s.as_bytes().to_vec();
+        Ok(simd_json::serde::from_slice(&mut buf)?)
+    }
+    #[cfg(not(feature = "simd-json"))]
+    {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+// binance answers 503 both during maintenance and when the gateway timed out
+// waiting for the matching engine, only the body tells them apart
+pub fn unavailable_error(body: &str) -> Box<ExError> {
+    if body.to_lowercase().contains("maintenance") {
+        Box::new(ExError::ExchangeInMaintenance(body.into()))
+    } else {
+        Box::new(ExError::ApiError(format!("response: 503 {}", body)))
+    }
 }
 
 pub fn str_to_f64(v: &str) -> f64 {
     v.parse().unwrap()
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub api_key: String,
+    pub signer: Arc<dyn Signer>,
+    limiter: Option<RateLimiter>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ApiKey {
+    pub fn new(api_key: &str, secret_key: &str, rate_limit: Option<u32>) -> APIResult<Self> {
+        Ok(ApiKey {
+            api_key: api_key.into(),
+            signer: signer::from_secret(secret_key)?,
+            // instances using the same key share its budget
+            limiter: rate_limit.map(|rate| shared_limiter(&format!("key:{}", api_key), rate)),
+        })
+    }
+
+    // signs with anything implementing Signer, the secret isn't needed
+    pub fn with_signer(api_key: &str, signer: Arc<dyn Signer>, rate_limit: Option<u32>) -> Self {
+        ApiKey {
+            api_key: api_key.into(),
+            signer,
+            limiter: rate_limit.map(|rate| shared_limiter(&format!("key:{}", api_key), rate)),
+        }
+    }
+
+    // like new, but a malformed secret only fails once a request is signed
+    pub fn new_unchecked(api_key: &str, secret_key: &str) -> Self {
+        let signer = signer::from_secret(secret_key)
+            .unwrap_or_else(|err| Arc::new(signer::InvalidKey(err.to_string())));
+        ApiKey {
+            api_key: api_key.into(),
+            signer,
+            limiter: None,
+        }
+    }
+
+    #[maybe_async::maybe_async]
+    pub async fn wait(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait().await;
+        }
+    }
+
+    fn ready_at(&self, now: Instant) -> Instant {
+        match &self.limiter {
+            Some(limiter) => limiter.ready_at(now),
+            None => now,
+        }
+    }
+}
+
+// round robin over the keys, skipping ahead to the one with budget left soonest
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_key<'a>(keys: &'a [ApiKey], counter: &AtomicUsize) -> &'a ApiKey {
+    let start = counter.fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+    (0..keys.len())
+        .map(|i| &keys[(start + i) % keys.len()])
+        .min_by_key(|key| key.ready_at(now))
+        .unwrap()
+}
+
+// random (v4) uuid, used as client order id
+#[cfg(not(target_arch = "wasm32"))]
+pub fn new_client_order_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const ORDER_RETRIES: usize = 1;
+
+// places an order under the given or a fresh client order id. when the request
+// times out the order is looked up by that id, and sent again only when the
+// exchange doesn't know it, so a retry can't place it twice
+#[cfg(not(target_arch = "wasm32"))]
+#[maybe_async::maybe_async]
+pub async fn place_order_once<'a, P, L>(
+    client_id: Option<&str>,
+    place: P,
+    lookup: L,
+) -> APIResult<String>
+where
+    P: Fn(String) -> Pending<'a, APIResult<String>>,
+    L: Fn(String) -> Pending<'a, APIResult<String>>,
+{
+    let client_id = client_id.map_or_else(new_client_order_id, String::from);
+    let mut retries = 0;
+    loop {
+        // only the http error is kept across the lookup, boxed errors aren't Send
+        let timeout = match place(client_id.clone()).await {
+            Err(err) if retries < ORDER_RETRIES => match http_error(err.as_ref()) {
+                Some(http_err) if http_err.is_timeout() => http_err.clone(),
+                _ => return Err(err),
+            },
+            ret => return ret,
+        };
+        warn!("create order {} timed out, looking it up", client_id);
+        match lookup(client_id.clone()).await {
+            Ok(id) => return Ok(id),
+            // not placed, send it again
+            Err(err) if matches!(err.downcast_ref(), Some(ExError::UnknownOrder(_))) => {
+                retries += 1
+            }
+            // still unknown whether the order was placed
+            Err(_) => return Err(Box::new(timeout)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     #![allow(dead_code)]
     use super::*;
 
+    #[test]
+    fn test_decode_ws_message() {
+        use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let text = "{\"ping\":1492420473027}";
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(text.as_bytes()).unwrap();
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(text.as_bytes()).unwrap();
+        let mut deflate = DeflateEncoder::new(vec![], Compression::default());
+        deflate.write_all(text.as_bytes()).unwrap();
+
+        let payloads = [
+            gz.finish().unwrap(),
+            zlib.finish().unwrap(),
+            deflate.finish().unwrap(),
+            text.as_bytes().to_vec(),
+        ];
+        for data in payloads.iter() {
+            let msg = ws::Message::Binary(data.clone());
+            assert_eq!(decode_ws_message(msg).unwrap(), text);
+        }
+        assert_eq!(
+            decode_ws_message(ws::Message::Text(text.into())).unwrap(),
+            text
+        );
+    }
+
+    // runs under serde_json by default and simd-json with the feature, both
+    // have to read the same events
+    #[test]
+    fn test_from_ws_str() {
+        use crate::binance::types::DepthOrderbookEvent;
+        use crate::models::Orderbook;
+        use serde_json::Value;
+
+        let raw = r#"{"e": "depthUpdate", "E": 123456789, "s": "BNBBTC", "U": 157, "u": 160,
+            "b": [["0.0024", "10"]], "a": [["0.0026", 100], [0.0027, "1.5"]]}"#;
+        let orderbook: Orderbook = from_ws_str::<DepthOrderbookEvent>(raw).unwrap().into();
+        assert_eq!(orderbook.last_update_id, 160);
+        assert_eq!(orderbook.bids[0].price, 0.0024);
+        assert_eq!(orderbook.asks[0].amount, 100.0);
+        assert_eq!(orderbook.asks[1].price, 0.0027);
+        assert_eq!(
+            from_ws_str::<Value>(raw).unwrap(),
+            serde_json::from_str::<Value>(raw).unwrap()
+        );
+        let escaped = r#"{"msg": "a \"quoted\" é"}"#;
+        assert_eq!(
+            from_ws_str::<Value>(escaped).unwrap()["msg"],
+            "a \"quoted\" é"
+        );
+        assert!(from_ws_str::<DepthOrderbookEvent>(r#"{"e": "depthUpdate""#).is_err());
+    }
+
+    #[test]
+    fn test_build_query_string() {
+        let mut params = BTreeMap::new();
+        params.insert("symbol".to_string(), "BTCUSDT".to_string());
+        params.insert("newClientOrderId".to_string(), "a&b=c d+e/%".to_string());
+        assert_eq!(
+            build_query_string(&params),
+            "newClientOrderId=a%26b%3Dc%20d%2Be%2F%25&symbol=BTCUSDT"
+        );
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_pick_key() {
+        let keys = vec![
+            ApiKey::new("a", "", Some(1)).unwrap(),
+            ApiKey::new("b", "", Some(1)).unwrap(),
+        ];
+        let counter = AtomicUsize::new(0);
+        let first = pick_key(&keys, &counter);
+        assert_eq!(first.api_key, "a");
+        first.wait().await;
+        // "a" spent its budget, "b" is picked even when "a" is next in turn
+        counter.store(0, Ordering::Relaxed);
+        assert_eq!(pick_key(&keys, &counter).api_key, "b");
+    }
+
+    #[test]
+    fn test_unavailable_error() {
+        let err = unavailable_error(r#"{"code": -1016, "msg": "System is under maintenance."}"#);
+        assert!(matches!(*err, ExError::ExchangeInMaintenance(_)));
+        let err = unavailable_error("Service Unavailable");
+        assert!(matches!(*err, ExError::ApiError(_)));
+    }
+
+    #[test]
+    fn test_to_query() {
+        #[derive(Serialize)]
+        struct Params<'a> {
+            symbol: &'a str,
+            limit: u16,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            from: Option<u64>,
+        }
+        let params = Params {
+            symbol: "BTC USDT&x=1",
+            limit: 5,
+            from: None,
+        };
+        assert_eq!(
+            to_query(&params).unwrap(),
+            "symbol=BTC+USDT%26x%3D1&limit=5"
+        );
+        let assets = [("asset", "BTC"), ("asset", "ETH")];
+        assert_eq!(to_query(&assets).unwrap(), "asset=BTC&asset=ETH");
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_place_order_once() {
+        use std::sync::Mutex;
+
+        let id = new_client_order_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(id, new_client_order_id());
+
+        let sent = &Mutex::new(vec![]);
+        let ret = place_order_once(
+            None,
+            |client_id| {
+                Box::pin(async move {
+                    sent.lock().unwrap().push(client_id);
+                    Ok("1".into())
+                })
+            },
+            |_| unreachable!(),
+        )
+        .await;
+        assert_eq!(ret.unwrap(), "1");
+        assert_eq!(sent.lock().unwrap().len(), 1);
+        let ret = place_order_once(
+            Some("my-order"),
+            |client_id| {
+                Box::pin(async move {
+                    sent.lock().unwrap().push(client_id);
+                    Ok("2".into())
+                })
+            },
+            |_| unreachable!(),
+        )
+        .await;
+        assert_eq!(ret.unwrap(), "2");
+        assert_eq!(sent.lock().unwrap()[1], "my-order");
+
+        // only timeouts are retried
+        let ret = place_order_once(
+            None,
+            |_| Box::pin(async { Err(ExError::ApiError("response: 400".into()).into()) }),
+            |_| unreachable!(),
+        )
+        .await;
+        assert!(ret.is_err());
+    }
+
+    fn timeout() -> Box<dyn std::error::Error> {
+        use crate::http::{Error, ErrorKind};
+        Box::new(Error::new(ErrorKind::Timeout, "timed out".into()))
+    }
+
+    fn is_timeout(err: &(dyn std::error::Error + 'static)) -> bool {
+        matches!(http_error(err), Some(err) if err.is_timeout())
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_place_order_once_found() {
+        use std::sync::Mutex;
+
+        // the timed out request did place the order, it must not be sent again
+        let sent = &Mutex::new(vec![]);
+        let looked_up = &Mutex::new(vec![]);
+        let ret = place_order_once(
+            None,
+            |client_id| {
+                Box::pin(async move {
+                    sent.lock().unwrap().push(client_id);
+                    Err(timeout())
+                })
+            },
+            |client_id| {
+                Box::pin(async move {
+                    looked_up.lock().unwrap().push(client_id);
+                    Ok("7".into())
+                })
+            },
+        )
+        .await;
+        assert_eq!(ret.unwrap(), "7");
+        assert_eq!(sent.lock().unwrap().len(), 1);
+        assert_eq!(*looked_up.lock().unwrap(), *sent.lock().unwrap());
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_place_order_once_not_found() {
+        use std::sync::Mutex;
+
+        // not placed, retried once under the same client id
+        let sent = &Mutex::new(vec![]);
+        let ret = place_order_once(
+            Some("my-order"),
+            |client_id| {
+                Box::pin(async move {
+                    let mut sent = sent.lock().unwrap();
+                    sent.push(client_id);
+                    match sent.len() {
+                        1 => Err(timeout()),
+                        _ => Ok("8".into()),
+                    }
+                })
+            },
+            |_| {
+                Box::pin(async { Err(ExError::UnknownOrder("order does not exist".into()).into()) })
+            },
+        )
+        .await;
+        assert_eq!(ret.unwrap(), "8");
+        assert_eq!(*sent.lock().unwrap(), vec!["my-order", "my-order"]);
+
+        // a second timeout is returned, whether it was placed stays unknown
+        let sent = &Mutex::new(vec![]);
+        let ret = place_order_once(
+            None,
+            |client_id| {
+                Box::pin(async move {
+                    sent.lock().unwrap().push(client_id);
+                    Err(timeout())
+                })
+            },
+            |_| {
+                Box::pin(async { Err(ExError::UnknownOrder("order does not exist".into()).into()) })
+            },
+        )
+        .await;
+        assert!(is_timeout(ret.unwrap_err().as_ref()));
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0], sent[1]);
+    }
+
+    #[maybe_async::test(feature = "blocking", async(not(feature = "blocking"), tokio::test))]
+    async fn test_place_order_once_lookup_failed() {
+        use std::sync::Mutex;
+
+        // the lookup was throttled, the order may be live so it isn't sent again
+        let sent = &Mutex::new(vec![]);
+        let ret = place_order_once(
+            None,
+            |client_id| {
+                Box::pin(async move {
+                    sent.lock().unwrap().push(client_id);
+                    Err(timeout())
+                })
+            },
+            |_| {
+                Box::pin(async {
+                    Err(ExError::RateLimitExceeded("rate limit exceeded: 429".into()).into())
+                })
+            },
+        )
+        .await;
+        assert!(is_timeout(ret.unwrap_err().as_ref()));
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
+
     //#[test]
     fn test_get_utc_ts() {
         let ret = get_utc_ts();
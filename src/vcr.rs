@@ -0,0 +1,330 @@
+use crate::errors::*;
+use crate::http;
+
+use log::{info, warn};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// params that change on every run or would leak credentials, never written
+// to a cassette nor used to match a request
+const VOLATILE_PARAMS: &[&str] = &[
+    "signature",
+    "timestamp",
+    "recvWindow",
+    "newClientOrderId",
+    "AccessKeyId",
+    "SignatureMethod",
+    "SignatureVersion",
+    "Timestamp",
+    "Signature",
+    "client-order-id",
+];
+
+// request headers passed upstream when recording
+const FORWARDED_HEADERS: &[&str] = &["content-type", "x-mbx-apikey", "user-agent"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    // serve recorded responses, unknown requests get a 404
+    Replay,
+    // forward to the upstream host, e.g. https://api.binance.com, and append
+    // every exchange to the cassette
+    Record(String),
+}
+
+impl Mode {
+    // Record when RSEX_VCR=record, so CI replays and a developer with keys
+    // refreshes the fixtures by running the same tests
+    pub fn from_env(upstream: &str) -> Mode {
+        match env::var("RSEX_VCR") {
+            Ok(mode) if mode == "record" => Mode::Record(upstream.into()),
+            _ => Mode::Replay,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    // normalized, see VOLATILE_PARAMS
+    pub query: String,
+    #[serde(default)]
+    pub body: String,
+    pub status: u16,
+    pub response: String,
+}
+
+struct Cassette {
+    path: PathBuf,
+    interactions: Vec<Interaction>,
+    // replayed interactions, repeated requests are answered in recorded order
+    used: Vec<bool>,
+}
+
+impl Cassette {
+    fn load(path: &Path) -> APIResult<Cassette> {
+        let interactions: Vec<Interaction> = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        } else {
+            vec![]
+        };
+        Ok(Cassette {
+            path: path.into(),
+            used: vec![false; interactions.len()],
+            interactions,
+        })
+    }
+
+    fn replay(&mut self, key: &Interaction) -> Option<&Interaction> {
+        let matches = |i: &Interaction| {
+            i.method == key.method
+                && i.path == key.path
+                && i.query == key.query
+                && i.body == key.body
+        };
+        let unused =
+            (0..self.interactions.len()).find(|&i| !self.used[i] && matches(&self.interactions[i]));
+        match unused {
+            Some(i) => {
+                self.used[i] = true;
+                Some(&self.interactions[i])
+            }
+            // all replayed already, keep answering with the latest one
+            None => self.interactions.iter().rev().find(|i| matches(i)),
+        }
+    }
+
+    fn record(&mut self, interaction: Interaction) -> APIResult<()> {
+        self.interactions.push(interaction);
+        self.used.push(true);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.interactions)?,
+        )?;
+        Ok(())
+    }
+}
+
+// fixture http server standing in for an exchange host. point a client at
+// host() to record its responses to a json cassette or replay them offline,
+// no client code changes and no keys on disk
+//
+//     let vcr = Vcr::start("tests/fixtures/binance_spot.json", Mode::from_env(BINANCE_SPOT_HOST))?;
+//     let api = Binance::new(key, secret, vcr.host());
+pub struct Vcr {
+    host: String,
+}
+
+impl Vcr {
+    pub fn start<P: AsRef<Path>>(cassette: P, mode: Mode) -> APIResult<Vcr> {
+        let cassette = Arc::new(Mutex::new(Cassette::load(cassette.as_ref())?));
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let host = format!("http://{}", listener.local_addr()?);
+        info!("vcr {:?} on {}", mode, host);
+        let client = http::Client::new();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!("vcr accept error: {:?}", err);
+                        continue;
+                    }
+                };
+                if let Err(err) = handle(stream, &cassette, &mode, &client) {
+                    warn!("vcr error: {:?}", err);
+                }
+            }
+        });
+        Ok(Vcr { host })
+    }
+
+    pub fn host(&self) -> String {
+        self.host.clone()
+    }
+}
+
+struct Request {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+fn read_request(stream: &TcpStream) -> APIResult<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? <= 2 {
+            break;
+        }
+        if let Some((name, value)) = line.trim_end().split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        target,
+        headers,
+        body: String::from_utf8(body)?,
+    })
+}
+
+fn handle(
+    mut stream: TcpStream,
+    cassette: &Mutex<Cassette>,
+    mode: &Mode,
+    client: &http::Client,
+) -> APIResult<()> {
+    let request = read_request(&stream)?;
+    let (path, query) = match request.target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (request.target.as_str(), ""),
+    };
+    let mut key = Interaction {
+        method: request.method.clone(),
+        path: path.into(),
+        query: normalize_query(query),
+        body: normalize_body(&request.body),
+        status: 0,
+        response: String::new(),
+    };
+    let (status, response) = match mode {
+        Mode::Replay => match cassette.lock().unwrap().replay(&key) {
+            Some(i) => (i.status, i.response.clone()),
+            None => {
+                warn!("vcr: no recording of {} {}", request.method, request.target);
+                (404, r#"{"error": "not recorded"}"#.to_string())
+            }
+        },
+        Mode::Record(upstream) => {
+            let mut req = client
+                .request(&request.method, &format!("{}{}", upstream, request.target))
+                .body(request.body.clone());
+            for (name, value) in request.headers.iter() {
+                if FORWARDED_HEADERS.contains(&name.as_str()) {
+                    req = req.header(name.as_str(), value.as_str());
+                }
+            }
+            let resp = req.send()?;
+            key.status = resp.status();
+            key.response = resp.text()?;
+            cassette.lock().unwrap().record(key.clone())?;
+            (key.status, key.response)
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} VCR\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    )?;
+    Ok(())
+}
+
+// sorted pairs without the volatile params
+fn normalize_query(query: &str) -> String {
+    let mut pairs: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .filter(|(k, _)| !VOLATILE_PARAMS.contains(&k.as_str()))
+        .collect();
+    pairs.sort();
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish()
+}
+
+// json bodies (huobi) lose their volatile keys, anything else is form encoded
+fn normalize_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(mut map)) => {
+            for key in VOLATILE_PARAMS {
+                map.remove(*key);
+            }
+            Value::Object(map).to_string()
+        }
+        _ => normalize_query(body),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binance::spot_rest::Binance;
+    use crate::traits::SpotRest;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize_query("symbol=BTCUSDT&timestamp=1&limit=5&signature=abc"),
+            "limit=5&symbol=BTCUSDT"
+        );
+        assert_eq!(
+            normalize_body(r#"{"symbol": "btcusdt", "client-order-id": "x"}"#),
+            r#"{"symbol":"btcusdt"}"#
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let vcr = Vcr::start(fixture("binance_spot.json"), Mode::Replay).unwrap();
+        let api = Binance::new(None, None, vcr.host());
+        let ticker = api.get_ticker("BTCUSDT").unwrap();
+        assert_eq!(ticker.bid.price, 16901.5);
+        let orderbook = api.get_orderbook("BTCUSDT", 5).unwrap();
+        assert_eq!(orderbook.last_update_id, 27871236402);
+        assert_eq!(orderbook.asks.len(), 2);
+        // not in the cassette
+        assert!(api.get_ticker("ETHUSDT").is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        // records from a replaying vcr instead of the exchange
+        let upstream = Vcr::start(fixture("binance_spot.json"), Mode::Replay).unwrap();
+        let path = env::temp_dir().join(format!("rsex-vcr-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let vcr = Vcr::start(&path, Mode::Record(upstream.host())).unwrap();
+        let api = Binance::new(None, None, vcr.host());
+        api.get_ticker("BTCUSDT").unwrap();
+        assert!(api.get_ticker("ETHUSDT").is_err());
+
+        let recorded: Vec<Interaction> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].path, "/api/v3/ticker/bookTicker");
+        assert_eq!(recorded[0].query, "symbol=BTCUSDT");
+        assert_eq!(recorded[1].status, 404);
+        fs::remove_file(&path).unwrap();
+    }
+}
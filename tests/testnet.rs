@@ -0,0 +1,99 @@
+// end to end order lifecycle against the exchange testnets, run with
+//
+//     BINANCE_TESTNET_API_KEY=.. BINANCE_TESTNET_SECRET_KEY=.. \
+//     BINANCE_FUTURES_TESTNET_API_KEY=.. BINANCE_FUTURES_TESTNET_SECRET_KEY=.. \
+//     cargo test --features integration-tests --test testnet
+//
+// a test without its keys in the environment is skipped. orders are placed
+// far from the market so they rest until cancelled
+#![cfg(feature = "integration-tests")]
+
+use rsex::binance::future_rest::BinanceSwap;
+use rsex::binance::spot_rest::Binance;
+use rsex::errors::APIResult;
+use rsex::models::*;
+use rsex::traits::{FutureRest, SpotRest};
+use std::env;
+
+const SYMBOL: &str = "BTCUSDT";
+
+fn keys(prefix: &str) -> Option<(String, String)> {
+    let api_key = env::var(format!("{}_API_KEY", prefix)).ok()?;
+    let secret_key = env::var(format!("{}_SECRET_KEY", prefix)).ok()?;
+    Some((api_key, secret_key))
+}
+
+fn round_to(value: f64, precision: u8) -> f64 {
+    let scale = 10f64.powi(precision as i32);
+    (value * scale).floor() / scale
+}
+
+// resting bid at 80% of the best bid, sized over the minimum notional
+fn resting_bid(info: &SymbolInfo, bid: f64) -> OrderRequest {
+    let price = round_to(bid * 0.8, info.price_precision);
+    let min_amount = (info.min_value * 1.5 / price).max(info.min_amount);
+    let scale = 10f64.powi(info.amount_precision as i32);
+    let amount = (min_amount * scale).ceil() / scale;
+    OrderRequest::limit_buy(SYMBOL, price, amount)
+}
+
+fn symbol_info(symbols: APIResult<Vec<SymbolInfo>>) -> SymbolInfo {
+    symbols
+        .unwrap()
+        .into_iter()
+        .find(|s| s.symbol == SYMBOL)
+        .expect("symbol not listed")
+}
+
+#[test]
+fn test_spot_order_lifecycle() {
+    let (api_key, secret_key) = match keys("BINANCE_TESTNET") {
+        Some(keys) => keys,
+        None => return eprintln!("BINANCE_TESTNET_API_KEY not set, skipped"),
+    };
+    let api = Binance::testnet(Some(api_key), Some(secret_key));
+    api.ping().unwrap();
+    api.get_balance("USDT").unwrap();
+
+    let info = symbol_info(api.get_symbols());
+    let ticker = api.get_ticker(SYMBOL).unwrap();
+    let request = resting_bid(&info, ticker.bid.price)
+        .client_id(&format!("rsex-it-{}", api.get_server_time().unwrap()));
+    let id = api.create_order(&request).unwrap();
+
+    let order = api.get_order(SYMBOL, &id).unwrap();
+    assert_eq!(order.status, OrderStatus::New);
+    assert_eq!(order.price, request.price);
+    assert_eq!(order.amount, request.amount);
+    let open = api.get_open_orders(SYMBOL).unwrap();
+    assert!(open.iter().any(|o| o.order_id == id));
+
+    assert!(api.cancel(SYMBOL, &id).unwrap());
+    let order = api.get_order(SYMBOL, &id).unwrap();
+    assert_eq!(order.status, OrderStatus::Canceled);
+}
+
+#[test]
+fn test_futures_order_lifecycle() {
+    let (api_key, secret_key) = match keys("BINANCE_FUTURES_TESTNET") {
+        Some(keys) => keys,
+        None => return eprintln!("BINANCE_FUTURES_TESTNET_API_KEY not set, skipped"),
+    };
+    let api = BinanceSwap::testnet(Some(api_key), Some(secret_key));
+    api.ping().unwrap();
+    api.get_balance("USDT").unwrap();
+
+    let info = symbol_info(api.get_symbols());
+    let ticker = api.get_ticker(SYMBOL).unwrap();
+    let request = resting_bid(&info, ticker.bid.price);
+    let id = api.create_order(&request).unwrap();
+
+    let order = api.get_order(SYMBOL, &id).unwrap();
+    assert_eq!(order.status, OrderStatus::New);
+    let open = api.get_open_orders(SYMBOL).unwrap();
+    assert!(open.iter().any(|o| o.order_id == id));
+
+    assert!(api.cancel_all(SYMBOL).unwrap());
+    let order = api.get_order(SYMBOL, &id).unwrap();
+    assert_eq!(order.status, OrderStatus::Canceled);
+}